@@ -0,0 +1,285 @@
+//! Derive macros for [determinishtic](https://docs.rs/determinishtic).
+//!
+//! Use these through the `derive` feature of `determinishtic` rather than
+//! depending on this crate directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{Data, DeriveInput, Fields, LitInt, LitStr, parse_macro_input};
+
+/// Derive `determinishtic::ThinkOutput`, collecting prompt-facing hints from
+/// `#[think(...)]` field attributes.
+///
+/// Each field may carry any number of `example = "..."` values and a
+/// `constraint = "..."` describing what a good value looks like, along with
+/// rules that are enforced on the result: `min_length = n`,
+/// `max_length = n`, `non_empty`, `pattern = "regex"`, `min = x`, and
+/// `max = x`. Patterns are checked for syntax at compile time. Field
+/// names follow `#[serde(rename = "...")]` and `#[serde(rename_all = "...")]`
+/// so the hints line up with the JSON schema.
+///
+/// ```rust,ignore
+/// #[derive(Deserialize, JsonSchema, ThinkOutput)]
+/// struct Summary {
+///     /// A short summary of the changes.
+///     #[think(constraint = "1-3 sentences", example = "Fixes a crash when the config is empty.")]
+///     text: String,
+///     #[think(non_empty, max_length = 5, pattern = "^[a-z-]+$")]
+///     tags: Vec<String>,
+/// }
+/// ```
+#[proc_macro_derive(ThinkOutput, attributes(think))]
+pub fn derive_think_output(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "ThinkOutput can only be derived for structs with named fields",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "ThinkOutput can only be derived for structs with named fields",
+        ));
+    };
+    let rename_all = serde_rename_all(&input.attrs)?;
+
+    let mut hints = Vec::new();
+    for field in &fields.named {
+        let mut examples = Vec::new();
+        let mut constraint = None;
+        let mut rules = Vec::new();
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("think")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("example") {
+                    examples.push(meta.value()?.parse::<LitStr>()?);
+                    Ok(())
+                } else if meta.path.is_ident("constraint") {
+                    if constraint.is_some() {
+                        return Err(meta.error("duplicate `constraint`"));
+                    }
+                    constraint = Some(meta.value()?.parse::<LitStr>()?);
+                    Ok(())
+                } else if meta.path.is_ident("min_length") {
+                    let n: usize = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                    rules.push(quote!(::determinishtic::Rule::MinLength(#n)));
+                    Ok(())
+                } else if meta.path.is_ident("max_length") {
+                    let n: usize = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                    rules.push(quote!(::determinishtic::Rule::MaxLength(#n)));
+                    Ok(())
+                } else if meta.path.is_ident("non_empty") {
+                    rules.push(quote!(::determinishtic::Rule::NonEmpty));
+                    Ok(())
+                } else if meta.path.is_ident("pattern") {
+                    let pattern = meta.value()?.parse::<LitStr>()?;
+                    if let Err(err) = regex_syntax::Parser::new().parse(&pattern.value()) {
+                        return Err(syn::Error::new_spanned(
+                            &pattern,
+                            format!("invalid pattern: {err}"),
+                        ));
+                    }
+                    rules.push(quote!(::determinishtic::Rule::Pattern(#pattern)));
+                    Ok(())
+                } else if meta.path.is_ident("min") {
+                    let x = parse_number(meta.value()?)?;
+                    rules.push(quote!(::determinishtic::Rule::Minimum(#x)));
+                    Ok(())
+                } else if meta.path.is_ident("max") {
+                    let x = parse_number(meta.value()?)?;
+                    rules.push(quote!(::determinishtic::Rule::Maximum(#x)));
+                    Ok(())
+                } else {
+                    Err(meta.error(
+                        "expected `example`, `constraint`, `min_length`, `max_length`, \
+                         `non_empty`, `pattern`, `min`, or `max`",
+                    ))
+                }
+            })?;
+        }
+        if examples.is_empty() && constraint.is_none() && rules.is_empty() {
+            continue;
+        }
+
+        let ident = field.ident.as_ref().expect("named fields have names");
+        let name = match serde_rename(&field.attrs)? {
+            Some(name) => name,
+            None => rename_all.apply(ident.to_string().trim_start_matches("r#")),
+        };
+        let constraint = match constraint {
+            Some(constraint) => quote!(::std::option::Option::Some(#constraint)),
+            None => quote!(::std::option::Option::None),
+        };
+        hints.push(quote! {
+            ::determinishtic::FieldHint {
+                field: #name,
+                examples: &[#(#examples),*],
+                constraint: #constraint,
+                rules: &[#(#rules),*],
+            }
+        });
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::determinishtic::ThinkOutput for #ident #ty_generics #where_clause {
+            fn field_hints() -> ::std::vec::Vec<::determinishtic::FieldHint> {
+                ::std::vec![#(#hints),*]
+            }
+        }
+    })
+}
+
+/// A possibly negative integer or float literal, as an `f64`.
+fn parse_number(input: syn::parse::ParseStream<'_>) -> syn::Result<f64> {
+    let negative = input.parse::<Option<syn::Token![-]>>()?.is_some();
+    let x = if input.peek(syn::LitFloat) {
+        input.parse::<syn::LitFloat>()?.base10_parse::<f64>()?
+    } else {
+        input.parse::<LitInt>()?.base10_parse::<f64>()?
+    };
+    Ok(if negative { -x } else { x })
+}
+
+/// The field's `#[serde(rename = "...")]`, if any.
+///
+/// With separate names for serialization and deserialization, the
+/// deserialization name wins, since that is what the agent must produce.
+fn serde_rename(attrs: &[syn::Attribute]) -> syn::Result<Option<String>> {
+    let mut rename = None;
+    for attr in attrs.iter().filter(|a| a.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                if meta.input.peek(syn::Token![=]) {
+                    rename = Some(meta.value()?.parse::<LitStr>()?.value());
+                } else {
+                    meta.parse_nested_meta(|inner| {
+                        let value = inner.value()?.parse::<LitStr>()?.value();
+                        if inner.path.is_ident("deserialize") {
+                            rename = Some(value);
+                        }
+                        Ok(())
+                    })?;
+                }
+                Ok(())
+            } else {
+                skip_meta(&meta)
+            }
+        })?;
+    }
+    Ok(rename)
+}
+
+/// The container's `#[serde(rename_all = "...")]`.
+fn serde_rename_all(attrs: &[syn::Attribute]) -> syn::Result<RenameAll> {
+    let mut rename_all = RenameAll::None;
+    for attr in attrs.iter().filter(|a| a.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename_all") {
+                let rule = if meta.input.peek(syn::Token![=]) {
+                    Some(meta.value()?.parse::<LitStr>()?)
+                } else {
+                    let mut rule = None;
+                    meta.parse_nested_meta(|inner| {
+                        let value = inner.value()?.parse::<LitStr>()?;
+                        if inner.path.is_ident("deserialize") {
+                            rule = Some(value);
+                        }
+                        Ok(())
+                    })?;
+                    rule
+                };
+                if let Some(rule) = rule {
+                    rename_all = RenameAll::parse(&rule)?;
+                }
+                Ok(())
+            } else {
+                skip_meta(&meta)
+            }
+        })?;
+    }
+    Ok(rename_all)
+}
+
+/// Consume a serde attribute we don't care about.
+fn skip_meta(meta: &syn::meta::ParseNestedMeta<'_>) -> syn::Result<()> {
+    if meta.input.peek(syn::Token![=]) {
+        meta.value()?.parse::<syn::Lit>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        let content;
+        syn::parenthesized!(content in meta.input);
+        content.parse::<TokenStream2>()?;
+    }
+    Ok(())
+}
+
+/// A serde `rename_all` rule.
+enum RenameAll {
+    None,
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+impl RenameAll {
+    fn parse(rule: &LitStr) -> syn::Result<Self> {
+        Ok(match rule.value().as_str() {
+            "lowercase" => Self::Lower,
+            "UPPERCASE" => Self::Upper,
+            "PascalCase" => Self::Pascal,
+            "camelCase" => Self::Camel,
+            "snake_case" => Self::Snake,
+            "SCREAMING_SNAKE_CASE" => Self::ScreamingSnake,
+            "kebab-case" => Self::Kebab,
+            "SCREAMING-KEBAB-CASE" => Self::ScreamingKebab,
+            _ => return Err(syn::Error::new_spanned(rule, "unknown rename_all rule")),
+        })
+    }
+
+    /// Rename a snake_case field name.
+    fn apply(&self, field: &str) -> String {
+        let pascal = || {
+            field
+                .split('_')
+                .map(|word| {
+                    let mut chars = word.chars();
+                    match chars.next() {
+                        Some(first) => first.to_uppercase().chain(chars).collect(),
+                        None => String::new(),
+                    }
+                })
+                .collect::<String>()
+        };
+        match self {
+            Self::None | Self::Snake => field.to_string(),
+            Self::Lower => field.to_ascii_lowercase(),
+            Self::Upper => field.to_ascii_uppercase(),
+            Self::Pascal => pascal(),
+            Self::Camel => {
+                let pascal = pascal();
+                let mut chars = pascal.chars();
+                match chars.next() {
+                    Some(first) => first.to_lowercase().chain(chars).collect(),
+                    None => String::new(),
+                }
+            }
+            Self::ScreamingSnake => field.to_ascii_uppercase(),
+            Self::Kebab => field.replace('_', "-"),
+            Self::ScreamingKebab => field.to_ascii_uppercase().replace('_', "-"),
+        }
+    }
+}
@@ -0,0 +1,401 @@
+//! Python bindings for [determinishtic](https://docs.rs/determinishtic).
+//!
+//! Exposes connecting to an agent, building think blocks, and their
+//! outcomes to Python, as laid out in the Python bindings RFC. Think blocks
+//! go through the same session setup, `return_result` validation, and
+//! permission policy as they do from Rust.
+//!
+//! ```python
+//! from pydantic import BaseModel
+//! from determinishtic import Determinishtic
+//!
+//! class Summary(BaseModel):
+//!     title: str
+//!     points: list[str]
+//!
+//! d = await Determinishtic.connect(["npx", "-y", "@zed-industries/claude-code-acp"])
+//! summary = await d.think(Summary).text("Summarize").display(text).run()
+//! ```
+//!
+//! Build the module with `maturin develop`, which turns on the
+//! `extension-module` feature. PyO3 needs a Python interpreter to build,
+//! so without the `python` feature this crate is empty and the rest of the
+//! workspace builds without one. Tools written in Python are not supported
+//! yet.
+
+#![cfg(feature = "python")]
+
+use std::sync::Arc;
+
+use determinishtic::{AgentCommand, Determinishtic, Error, ThinkBuilder, ThinkOutcome};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use pyo3_async_runtimes::tokio::future_into_py;
+
+create_exception!(
+    determinishtic,
+    DeterminishticError,
+    PyException,
+    "A think block, or the connection to the agent, failed."
+);
+create_exception!(
+    determinishtic,
+    NoResult,
+    DeterminishticError,
+    "The agent stopped without returning a result."
+);
+create_exception!(
+    determinishtic,
+    Refused,
+    DeterminishticError,
+    "The agent refused the request."
+);
+create_exception!(
+    determinishtic,
+    Cancelled,
+    DeterminishticError,
+    "The session was cancelled before the agent returned a result."
+);
+create_exception!(
+    determinishtic,
+    MaxTurns,
+    DeterminishticError,
+    "The agent reached its turn limit before returning a result."
+);
+create_exception!(
+    determinishtic,
+    Timeout,
+    DeterminishticError,
+    "An operation did not finish in time."
+);
+
+/// A connection to an agent, the Python side of [`Determinishtic`].
+#[pyclass(frozen, name = "Determinishtic", module = "determinishtic")]
+pub struct PyDeterminishtic {
+    inner: Arc<Determinishtic>,
+}
+
+impl PyDeterminishtic {
+    /// Wrap an instance connected from Rust.
+    pub fn new(inner: Determinishtic) -> Self {
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+}
+
+#[pymethods]
+impl PyDeterminishtic {
+    /// Start the agent with `command`, its program followed by its
+    /// arguments, and connect to it.
+    #[staticmethod]
+    fn connect(py: Python<'_>, command: Vec<String>) -> PyResult<Bound<'_, PyAny>> {
+        let Some((program, args)) = command.split_first() else {
+            return Err(PyValueError::new_err("the agent's command line is empty"));
+        };
+        let agent = AgentCommand::new(program).args(args);
+        future_into_py(py, async move {
+            // Connecting holds the builder, which isn't `Sync`, across
+            // awaits, so the future can't move between threads; give it
+            // one of its own
+            let runtime = tokio::runtime::Handle::current();
+            let d = tokio::task::spawn_blocking(move || {
+                runtime.block_on(Determinishtic::builder().connect_command(agent))
+            })
+            .await
+            .map_err(|err| DeterminishticError::new_err(err.to_string()))?
+            .map_err(to_py_err)?;
+            Ok(PyDeterminishtic::new(d))
+        })
+    }
+
+    /// Start building a think block returning `output`: a pydantic model
+    /// class, a JSON Schema dictionary, or, by default, a string.
+    #[pyo3(signature = (output = None))]
+    fn think(&self, output: Option<&Bound<'_, PyAny>>) -> PyResult<PyThinkBuilder> {
+        Ok(PyThinkBuilder {
+            d: self.inner.clone(),
+            output: Output::from_py(output)?,
+            segments: Vec::new(),
+            labels: Vec::new(),
+            model: None,
+        })
+    }
+}
+
+/// What a think block returns, as given to [`PyDeterminishtic::think`].
+enum Output {
+    /// A string, as `d.think::<String>()` returns.
+    Text,
+    /// Plain Python values matching a JSON Schema.
+    Schema(serde_json::Value),
+    /// An instance of a pydantic model class.
+    Model {
+        schema: serde_json::Value,
+        class: Py<PyAny>,
+    },
+}
+
+impl Output {
+    fn from_py(output: Option<&Bound<'_, PyAny>>) -> PyResult<Self> {
+        let Some(output) = output else {
+            return Ok(Output::Text);
+        };
+        if output.hasattr("model_json_schema")? {
+            let schema = output.call_method0("model_json_schema")?;
+            return Ok(Output::Model {
+                schema: to_json(&schema)?,
+                class: output.clone().unbind(),
+            });
+        }
+        if output.is_instance_of::<PyDict>() {
+            return Ok(Output::Schema(to_json(output)?));
+        }
+        Err(PyTypeError::new_err(
+            "the output must be a pydantic model class or a JSON Schema dictionary",
+        ))
+    }
+
+    /// Turn the accepted result into what Python gets back.
+    fn convert(&self, py: Python<'_>, value: &serde_json::Value) -> PyResult<Py<PyAny>> {
+        let value = from_json(py, value)?;
+        match self {
+            Output::Model { class, .. } => class.call_method1(py, "model_validate", (value,)),
+            Output::Text | Output::Schema(_) => Ok(value),
+        }
+    }
+
+    fn clone_ref(&self, py: Python<'_>) -> Self {
+        match self {
+            Output::Text => Output::Text,
+            Output::Schema(schema) => Output::Schema(schema.clone()),
+            Output::Model { schema, class } => Output::Model {
+                schema: schema.clone(),
+                class: class.clone_ref(py),
+            },
+        }
+    }
+}
+
+/// A piece of the prompt, added by one of the builder's methods.
+#[derive(Clone)]
+enum Segment {
+    Text(String),
+    Textln(String),
+    Display(String),
+    Context {
+        label: String,
+        value: serde_json::Value,
+    },
+}
+
+/// A think block being built from Python, the Python side of
+/// [`ThinkBuilder`].
+///
+/// Each method adds to the builder and returns it, so calls chain as they
+/// do in Rust. Nothing is sent until [`run`](Self::run) or
+/// [`outcome`](Self::outcome) is awaited.
+#[pyclass(name = "ThinkBuilder", module = "determinishtic")]
+pub struct PyThinkBuilder {
+    d: Arc<Determinishtic>,
+    output: Output,
+    segments: Vec<Segment>,
+    labels: Vec<(String, String)>,
+    model: Option<String>,
+}
+
+#[pymethods]
+impl PyThinkBuilder {
+    /// Add literal text to the prompt.
+    fn text<'py>(mut slf: PyRefMut<'py, Self>, text: String) -> PyRefMut<'py, Self> {
+        slf.segments.push(Segment::Text(text));
+        slf
+    }
+
+    /// Add literal text followed by a newline.
+    fn textln<'py>(mut slf: PyRefMut<'py, Self>, text: String) -> PyRefMut<'py, Self> {
+        slf.segments.push(Segment::Textln(text));
+        slf
+    }
+
+    /// Add `str(value)` to the prompt.
+    fn display<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        value: &Bound<'py, PyAny>,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let text = value.str()?.to_string();
+        slf.segments.push(Segment::Display(text));
+        Ok(slf)
+    }
+
+    /// Add `value` as a labeled JSON section, as `context` does in Rust.
+    fn context<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        label: String,
+        value: &Bound<'py, PyAny>,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let value = to_json(value)?;
+        slf.segments.push(Segment::Context { label, value });
+        Ok(slf)
+    }
+
+    /// Tag the think block with `key` set to `value`.
+    fn label<'py>(mut slf: PyRefMut<'py, Self>, key: String, value: String) -> PyRefMut<'py, Self> {
+        slf.labels.push((key, value));
+        slf
+    }
+
+    /// Run the think block on a specific model.
+    fn model<'py>(mut slf: PyRefMut<'py, Self>, model: String) -> PyRefMut<'py, Self> {
+        slf.model = Some(model);
+        slf
+    }
+
+    /// Run the think block, returning its output.
+    fn run<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let outcome = self.build().outcome();
+        let output = self.output.clone_ref(py);
+        future_into_py(py, async move {
+            let outcome = outcome.await.map_err(to_py_err)?;
+            Python::with_gil(|py| output.convert(py, &outcome.output))
+        })
+    }
+
+    /// Run the think block, returning its output along with a record of
+    /// how it was produced.
+    fn outcome<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let outcome = self.build().outcome();
+        let output = self.output.clone_ref(py);
+        future_into_py(py, async move {
+            let outcome = outcome.await.map_err(to_py_err)?;
+            Python::with_gil(|py| PyOutcome::new(py, &output, &outcome))
+        })
+    }
+}
+
+impl PyThinkBuilder {
+    /// The Rust think block this builder describes.
+    fn build(&self) -> ThinkBuilder<'static, serde_json::Value> {
+        let mut think = match &self.output {
+            Output::Text => self.d.think::<String>().dynamic_output(),
+            Output::Schema(schema) | Output::Model { schema, .. } => self
+                .d
+                .think::<serde_json::Value>()
+                .output_schema(schema.clone()),
+        };
+        for segment in &self.segments {
+            think = match segment {
+                Segment::Text(text) => think.text(text),
+                Segment::Textln(text) => think.textln(text),
+                Segment::Display(text) => think.display(text),
+                Segment::Context { label, value } => think.context(label, value),
+            };
+        }
+        for (key, value) in &self.labels {
+            think = think.label(key, value);
+        }
+        if let Some(model) = &self.model {
+            think = think.model(model);
+        }
+        think
+    }
+}
+
+/// The output of a think block along with a record of how it was
+/// produced, the Python side of [`ThinkOutcome`].
+///
+/// Permission records and provenance are plain dictionaries, in the same
+/// form they are stored in.
+#[pyclass(frozen, name = "Outcome", module = "determinishtic")]
+pub struct PyOutcome {
+    /// The think block's output.
+    #[pyo3(get)]
+    output: Py<PyAny>,
+    /// Every permission request the agent made, and how it was decided.
+    #[pyo3(get)]
+    permissions: Py<PyAny>,
+    /// Where the result came from.
+    #[pyo3(get)]
+    provenance: Py<PyAny>,
+    /// How many calls to each tool had invalid arguments.
+    #[pyo3(get)]
+    invalid_tool_calls: Py<PyAny>,
+}
+
+impl PyOutcome {
+    fn new(
+        py: Python<'_>,
+        output: &Output,
+        outcome: &ThinkOutcome<serde_json::Value>,
+    ) -> PyResult<Self> {
+        Ok(Self {
+            output: output.convert(py, &outcome.output)?,
+            permissions: from_serialize(py, &outcome.permissions)?,
+            provenance: from_serialize(py, outcome.provenance())?,
+            invalid_tool_calls: from_serialize(py, &outcome.invalid_tool_calls)?,
+        })
+    }
+}
+
+/// Report `err` as the matching Python exception, with the messages of
+/// its sources appended.
+fn to_py_err(err: Error) -> PyErr {
+    let mut message = err.to_string();
+    let mut source = std::error::Error::source(&err);
+    while let Some(cause) = source {
+        message.push_str(&format!(": {cause}"));
+        source = cause.source();
+    }
+    match err {
+        Error::NoResult => NoResult::new_err(message),
+        Error::Refused => Refused::new_err(message),
+        Error::Cancelled => Cancelled::new_err(message),
+        Error::MaxTurns => MaxTurns::new_err(message),
+        Error::Timeout { .. } | Error::InitializeTimeout { .. } => Timeout::new_err(message),
+        _ => DeterminishticError::new_err(message),
+    }
+}
+
+/// `value` as JSON, through Python's `json` module.
+fn to_json(value: &Bound<'_, PyAny>) -> PyResult<serde_json::Value> {
+    let json: String = value
+        .py()
+        .import("json")?
+        .call_method1("dumps", (value,))?
+        .extract()?;
+    serde_json::from_str(&json).map_err(|err| PyValueError::new_err(err.to_string()))
+}
+
+/// `value` as Python values, through Python's `json` module.
+fn from_json(py: Python<'_>, value: &serde_json::Value) -> PyResult<Py<PyAny>> {
+    Ok(py
+        .import("json")?
+        .call_method1("loads", (value.to_string(),))?
+        .unbind())
+}
+
+/// `value`'s serde representation as Python values.
+fn from_serialize(py: Python<'_>, value: &impl serde::Serialize) -> PyResult<Py<PyAny>> {
+    let value =
+        serde_json::to_value(value).map_err(|err| PyValueError::new_err(err.to_string()))?;
+    from_json(py, &value)
+}
+
+/// The `determinishtic` Python module.
+#[pymodule]
+#[pyo3(name = "determinishtic")]
+fn determinishtic_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyDeterminishtic>()?;
+    m.add_class::<PyThinkBuilder>()?;
+    m.add_class::<PyOutcome>()?;
+    let py = m.py();
+    m.add("DeterminishticError", py.get_type::<DeterminishticError>())?;
+    m.add("NoResult", py.get_type::<NoResult>())?;
+    m.add("Refused", py.get_type::<Refused>())?;
+    m.add("Cancelled", py.get_type::<Cancelled>())?;
+    m.add("MaxTurns", py.get_type::<MaxTurns>())?;
+    m.add("Timeout", py.get_type::<Timeout>())?;
+    Ok(())
+}
@@ -0,0 +1,109 @@
+//! Think blocks run from Python against the scripted mock agent.
+//!
+//! These embed an interpreter, so run them with the `python` feature (not
+//! `extension-module`) and with the interpreter's shared library on the
+//! library path.
+
+#![cfg(feature = "python")]
+
+use std::ffi::CString;
+
+use determinishtic::testing::{MockAgent, Turn};
+use determinishtic_py::PyDeterminishtic;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use serde_json::json;
+
+/// Run `script` with `d` bound to a connection to `agent`, returning what
+/// it leaves in `result`.
+fn run_script(agent: &MockAgent, script: &str) -> PyResult<String> {
+    let runtime = pyo3_async_runtimes::tokio::get_runtime();
+    let d = runtime.block_on(agent.connect()).unwrap();
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| {
+        let globals = PyDict::new(py);
+        globals.set_item("d", Bound::new(py, PyDeterminishtic::new(d))?)?;
+        let script = CString::new(script).unwrap();
+        py.run(&script, Some(&globals), None)?;
+        globals.get_item("result")?.unwrap().str()?.extract()
+    })
+}
+
+#[test]
+fn returns_a_pydantic_model() {
+    let agent = MockAgent::new().turn(
+        Turn::new()
+            .expect_prompt("Summarize the notes")
+            .return_result(json!({ "title": "Notes", "points": ["one", "two"] })),
+    );
+
+    let result = run_script(
+        &agent,
+        r#"
+import asyncio
+from pydantic import BaseModel
+
+class Summary(BaseModel):
+    title: str
+    points: list[str]
+
+async def main():
+    return await d.think(Summary).text("Summarize the notes").run()
+
+summary = asyncio.run(main())
+assert isinstance(summary, Summary)
+result = summary.title + ": " + ", ".join(summary.points)
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(result, "Notes: one, two");
+    agent.verify();
+}
+
+#[test]
+fn outcomes_carry_provenance() {
+    let agent = MockAgent::new().turn(Turn::new().return_result(json!("hello")));
+
+    let result = run_script(
+        &agent,
+        r#"
+import asyncio
+
+async def main():
+    return await d.think().text("Say hello").label("notebook", "demo").outcome()
+
+outcome = asyncio.run(main())
+result = outcome.output + " " + outcome.provenance["labels"]["notebook"]
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(result, "hello demo");
+    agent.verify();
+}
+
+#[test]
+fn errors_raise_their_own_exceptions() {
+    let agent = MockAgent::new().turn(Turn::new());
+
+    let result = run_script(
+        &agent,
+        r#"
+import asyncio
+
+async def main():
+    return await d.think().text("Say hello").run()
+
+try:
+    asyncio.run(main())
+    result = "no error"
+except Exception as err:
+    result = type(err).__name__ + " " + type(err).__mro__[1].__name__
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(result, "NoResult DeterminishticError");
+    agent.verify();
+}
@@ -0,0 +1,34 @@
+//! Example: Serve think blocks to another process over JSON-RPC on stdio.
+//!
+//! The process that starts this one sends `think` requests on its standard
+//! input and receives results, and `tool/call` requests for its own tools,
+//! on its standard output. See `Sidecar` for the protocol. Logs go to
+//! standard error so they don't mix with the protocol.
+//!
+//! Usage:
+//!   cargo run --example sidecar --features sidecar
+//!   cargo run --example sidecar --features sidecar -- npx -y @zed-industries/codex-acp
+
+use agent_client_protocol_tokio::AcpAgent;
+use determinishtic::{Determinishtic, Sidecar};
+use tracing_subscriber::EnvFilter;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
+        .init();
+
+    // The agent's command line, or Claude Code if none is given
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let agent = if args.is_empty() {
+        AcpAgent::zed_claude_code()
+    } else {
+        AcpAgent::from_args(args)?
+    };
+    let d = Determinishtic::new(agent).await?;
+
+    Sidecar::new(&d).serve_stdio().await?;
+    Ok(())
+}
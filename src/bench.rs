@@ -0,0 +1,224 @@
+//! Benchmarking agents against a suite of prompts.
+//!
+//! A [`Suite`] is a list of cases, each a prompt plus a validator for the
+//! typed output. Running the suite against several agents produces a
+//! [`Report`] comparing their accuracy, latency, token use, and failures.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use determinishtic::bench::Suite;
+//!
+//! let suite = Suite::<u32>::new("arithmetic")
+//!     .case("sum", "What is 17 + 25?", |n| *n == 42)
+//!     .case("product", "What is 6 * 7?", |n| *n == 42);
+//!
+//! let claude = Determinishtic::new(AcpAgent::zed_claude_code()).await?;
+//! let gemini = Determinishtic::new(AcpAgent::google_gemini()).await?;
+//!
+//! let report = suite.run(&[("claude-code", &claude), ("gemini", &gemini)]).await;
+//! println!("{report}");
+//! ```
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use agent_client_protocol::Agent;
+use agent_client_protocol::role::{HasPeer, Role};
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::Determinishtic;
+
+/// A named collection of benchmark cases producing outputs of type `T`.
+pub struct Suite<T> {
+    name: String,
+    cases: Vec<Case<T>>,
+}
+
+struct Case<T> {
+    name: String,
+    prompt: String,
+    validate: Box<dyn Fn(&T) -> bool + Send + Sync>,
+}
+
+/// Results of running a [`Suite`] against one or more agents.
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// The suite's name.
+    pub suite: String,
+
+    /// One entry per agent, in the order the agents were given.
+    pub agents: Vec<AgentReport>,
+}
+
+/// Results for a single agent.
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentReport {
+    /// The name the agent was given when running the suite.
+    pub agent: String,
+
+    /// Number of cases run.
+    pub cases: usize,
+
+    /// Number of cases whose output passed validation.
+    pub passed: usize,
+
+    /// Total wall-clock time across all cases.
+    pub total_latency: Duration,
+
+    /// Estimated tokens across all cases (prompt plus output, about four characters per token).
+    pub estimated_tokens: u64,
+
+    /// Cases that failed, with the reason.
+    pub failures: Vec<Failure>,
+}
+
+/// A case that did not pass.
+#[derive(Debug, Clone, Serialize)]
+pub struct Failure {
+    /// The case's name.
+    pub case: String,
+
+    /// Why the case failed: the error, or a note that validation rejected the output.
+    pub reason: String,
+}
+
+impl AgentReport {
+    /// Fraction of cases that passed, from 0.0 to 1.0 (zero if there were none).
+    pub fn accuracy(&self) -> f64 {
+        if self.cases == 0 {
+            0.0
+        } else {
+            self.passed as f64 / self.cases as f64
+        }
+    }
+
+    /// Mean wall-clock time per case (zero if there were none).
+    pub fn mean_latency(&self) -> Duration {
+        if self.cases == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(self.total_latency.as_secs_f64() / self.cases as f64)
+        }
+    }
+}
+
+impl<T> Suite<T>
+where
+    T: Send + Serialize + JsonSchema + DeserializeOwned + 'static,
+{
+    /// Create an empty suite.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            cases: Vec::new(),
+        }
+    }
+
+    /// Add a case: the prompt to send and a validator for the output.
+    pub fn case(
+        mut self,
+        name: impl Into<String>,
+        prompt: impl Into<String>,
+        validate: impl Fn(&T) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.cases.push(Case {
+            name: name.into(),
+            prompt: prompt.into(),
+            validate: Box::new(validate),
+        });
+        self
+    }
+
+    /// Run every case against each agent in turn.
+    ///
+    /// Cases run sequentially so that latencies are comparable. Errors from
+    /// individual cases are recorded as failures rather than aborting the run.
+    pub async fn run<R>(&self, agents: &[(&str, &Determinishtic<R>)]) -> Report
+    where
+        R: Role + HasPeer<Agent>,
+    {
+        let mut reports = Vec::with_capacity(agents.len());
+        for &(agent, d) in agents {
+            reports.push(self.run_agent(agent, d).await);
+        }
+        Report {
+            suite: self.name.clone(),
+            agents: reports,
+        }
+    }
+
+    async fn run_agent<R>(&self, agent: &str, d: &Determinishtic<R>) -> AgentReport
+    where
+        R: Role + HasPeer<Agent>,
+    {
+        let mut report = AgentReport {
+            agent: agent.to_string(),
+            cases: self.cases.len(),
+            passed: 0,
+            total_latency: Duration::ZERO,
+            estimated_tokens: 0,
+            failures: Vec::new(),
+        };
+
+        for case in &self.cases {
+            let start = Instant::now();
+            let result = d.think::<T>().text(&case.prompt).await;
+            report.total_latency += start.elapsed();
+
+            let mut characters = case.prompt.len();
+            let failure = match result {
+                Ok(output) => {
+                    characters += serde_json::to_string(&output).map_or(0, |s| s.len());
+                    if (case.validate)(&output) {
+                        None
+                    } else {
+                        Some("output rejected by validator".to_string())
+                    }
+                }
+                Err(err) => Some(err.to_string()),
+            };
+            report.estimated_tokens += (characters / 4) as u64;
+
+            match failure {
+                None => report.passed += 1,
+                Some(reason) => report.failures.push(Failure {
+                    case: case.name.clone(),
+                    reason,
+                }),
+            }
+        }
+
+        report
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Suite: {}", self.suite)?;
+        writeln!(
+            f,
+            "{:<20} {:>9} {:>12} {:>10} {:>8}",
+            "agent", "accuracy", "mean latency", "~tokens", "failures"
+        )?;
+        for agent in &self.agents {
+            writeln!(
+                f,
+                "{:<20} {:>8.1}% {:>11.2}s {:>10} {:>8}",
+                agent.agent,
+                agent.accuracy() * 100.0,
+                agent.mean_latency().as_secs_f64(),
+                agent.estimated_tokens,
+                agent.failures.len(),
+            )?;
+        }
+        for agent in &self.agents {
+            for failure in &agent.failures {
+                writeln!(f, "{}: {}: {}", agent.agent, failure.case, failure.reason)?;
+            }
+        }
+        Ok(())
+    }
+}
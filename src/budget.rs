@@ -0,0 +1,40 @@
+//! Keeping prompts within a token budget.
+
+/// How important a prompt segment is when the prompt must be trimmed.
+///
+/// Set with [`ThinkBuilder::text_with_priority`](crate::ThinkBuilder::text_with_priority);
+/// every other segment is [`Normal`](Self::Normal). When a prompt exceeds its
+/// [token budget](crate::ThinkBuilder::token_budget), [`Low`](Self::Low)
+/// segments are dropped first, oldest first, then `Normal` ones, largest
+/// first. [`High`](Self::High) segments and tool references are never dropped.
+///
+/// Not to be confused with [`ThinkBuilder::priority`](crate::ThinkBuilder::priority),
+/// which orders think blocks in a [`Scheduler`](crate::Scheduler).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Background material that can go first, such as long reference documents.
+    Low,
+    /// The default for segments added without a priority.
+    #[default]
+    Normal,
+    /// Material the task can't do without; never trimmed.
+    High,
+}
+
+/// Roughly how many tokens `text` will cost.
+///
+/// Uses the same four-characters-per-token heuristic as [`bench`](crate::bench),
+/// which is close enough to keep a prompt under an agent's limit.
+pub(crate) fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Appended to a trimmed prompt so the agent knows something is missing.
+pub(crate) fn omission_note(omitted: usize) -> String {
+    let sections = if omitted == 1 {
+        "section was"
+    } else {
+        "sections were"
+    };
+    format!("(Note: {omitted} lower-priority {sections} omitted to fit the prompt budget.)\n")
+}
@@ -0,0 +1,179 @@
+//! What the connected agent supports.
+
+use agent_client_protocol::schema::{
+    InitializeResponse, NewSessionResponse, ProtocolVersion, SessionConfigKind,
+    SessionConfigOptionCategory, SessionConfigSelectOptions,
+};
+use agent_client_protocol::{McpAcpTransport, MetaCapabilityExt};
+use serde::Serialize;
+
+use crate::think::SAMPLING_META_KEY;
+
+/// A summary of what the connected agent supports, from
+/// [`Determinishtic::capabilities`](crate::Determinishtic::capabilities).
+///
+/// Flags derived from initialization are `false` when they are unknown, which
+/// is the case for instances created with
+/// [`from_connection`](crate::Determinishtic::from_connection).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Capabilities {
+    /// The ACP version agreed at initialization, or `None` if unknown.
+    pub protocol_version: Option<ProtocolVersion>,
+
+    /// The agent can reach MCP servers over ACP, so think block tools work
+    /// without an HTTP bridge.
+    pub mcp_over_acp: bool,
+
+    /// The agent can connect to MCP servers over HTTP.
+    pub mcp_http: bool,
+
+    /// Prompts may include images.
+    pub images: bool,
+
+    /// Prompts may include audio.
+    pub audio: bool,
+
+    /// Prompts may include embedded resources.
+    pub embedded_context: bool,
+
+    /// The agent has opted into honoring
+    /// [`temperature`](crate::ThinkBuilder::temperature) and
+    /// [`seed`](crate::ThinkBuilder::seed); see
+    /// [`Determinishtic::supports_sampling`](crate::Determinishtic::supports_sampling).
+    pub sampling: bool,
+
+    /// Session modes accepted by [`ThinkBuilder::mode`](crate::ThinkBuilder::mode).
+    pub modes: Vec<Mode>,
+
+    /// Models accepted by [`ThinkBuilder::model`](crate::ThinkBuilder::model).
+    pub models: Vec<Model>,
+}
+
+/// A session mode the agent offers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Mode {
+    /// The identifier to pass to [`ThinkBuilder::mode`](crate::ThinkBuilder::mode).
+    pub id: String,
+
+    /// Human-readable name.
+    pub name: String,
+
+    /// Optional description of what the mode allows.
+    pub description: Option<String>,
+
+    /// Whether new sessions start in this mode.
+    pub current: bool,
+}
+
+/// A model the agent offers, as listed by [`Determinishtic::models`](crate::Determinishtic::models).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Model {
+    /// The identifier to pass to [`ThinkBuilder::model`](crate::ThinkBuilder::model).
+    pub id: String,
+
+    /// Human-readable name.
+    pub name: String,
+
+    /// Optional description, e.g. of the model's cost or quality tier.
+    pub description: Option<String>,
+
+    /// Whether this is the model new sessions use by default.
+    pub current: bool,
+}
+
+impl Capabilities {
+    /// Combine what the agent reported at initialization with what it
+    /// offered for a new session.
+    pub(crate) fn new(
+        initialize: Option<&InitializeResponse>,
+        session: &NewSessionResponse,
+    ) -> Self {
+        let agent = initialize.map(|init| &init.agent_capabilities);
+        Self {
+            protocol_version: initialize.map(|init| init.protocol_version.clone()),
+            mcp_over_acp: initialize.is_some_and(|init| init.has_meta_capability(McpAcpTransport)),
+            mcp_http: agent.is_some_and(|agent| agent.mcp_capabilities.http),
+            images: agent.is_some_and(|agent| agent.prompt_capabilities.image),
+            audio: agent.is_some_and(|agent| agent.prompt_capabilities.audio),
+            embedded_context: agent.is_some_and(|agent| agent.prompt_capabilities.embedded_context),
+            sampling: supports_sampling(initialize),
+            modes: Mode::from_session(session),
+            models: Model::from_session(session),
+        }
+    }
+}
+
+/// Whether the agent has opted into the sampling parameters convention.
+pub(crate) fn supports_sampling(initialize: Option<&InitializeResponse>) -> bool {
+    initialize
+        .and_then(|init| init.agent_capabilities.meta.as_ref())
+        .is_some_and(|meta| meta.contains_key(SAMPLING_META_KEY))
+}
+
+impl Mode {
+    /// Extract the session modes advertised in a new session response.
+    fn from_session(response: &NewSessionResponse) -> Vec<Mode> {
+        let Some(modes) = &response.modes else {
+            return Vec::new();
+        };
+        modes
+            .available_modes
+            .iter()
+            .map(|mode| Mode {
+                id: mode.id.to_string(),
+                name: mode.name.clone(),
+                description: mode.description.clone(),
+                current: mode.id == modes.current_mode_id,
+            })
+            .collect()
+    }
+}
+
+impl Model {
+    /// Extract the models advertised in a new session response.
+    ///
+    /// Agents list models either in the dedicated `models` field or as a
+    /// config option in the "model" category; we accept both.
+    fn from_session(response: &NewSessionResponse) -> Vec<Model> {
+        if let Some(models) = &response.models {
+            return models
+                .available_models
+                .iter()
+                .map(|model| Model {
+                    id: model.model_id.to_string(),
+                    name: model.name.clone(),
+                    description: model.description.clone(),
+                    current: model.model_id == models.current_model_id,
+                })
+                .collect();
+        }
+
+        let Some(option) = response
+            .config_options
+            .iter()
+            .flatten()
+            .find(|option| matches!(option.category, Some(SessionConfigOptionCategory::Model)))
+        else {
+            return Vec::new();
+        };
+        let SessionConfigKind::Select(select) = &option.kind else {
+            return Vec::new();
+        };
+        let options: Vec<_> = match &select.options {
+            SessionConfigSelectOptions::Ungrouped(options) => options.iter().collect(),
+            SessionConfigSelectOptions::Grouped(groups) => {
+                groups.iter().flat_map(|group| &group.options).collect()
+            }
+            _ => Vec::new(),
+        };
+        options
+            .into_iter()
+            .map(|option| Model {
+                id: option.value.to_string(),
+                name: option.name.clone(),
+                description: option.description.clone(),
+                current: option.value == select.current_value,
+            })
+            .collect()
+    }
+}
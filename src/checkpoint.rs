@@ -0,0 +1,43 @@
+//! Points in a session where the host decides how the agent continues.
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// The host's answer at a [checkpoint](crate::ThinkBuilder::checkpoint).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckpointDecision {
+    /// Carry on as submitted.
+    Proceed,
+    /// Revise the submission according to the feedback, then submit it again.
+    Revise(String),
+}
+
+/// The description given to the agent for every checkpoint tool.
+pub(crate) const CHECKPOINT_DESCRIPTION: &str = "Submit your work for review before continuing. \
+     Wait for the response: if `proceed` is true, carry on; otherwise revise \
+     according to `feedback` and submit again.";
+
+/// What the agent sees when a checkpoint returns.
+#[derive(Debug, Serialize, JsonSchema)]
+pub(crate) struct CheckpointOutput {
+    /// Whether to carry on as submitted.
+    proceed: bool,
+    /// What to change before submitting again.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    feedback: Option<String>,
+}
+
+impl From<CheckpointDecision> for CheckpointOutput {
+    fn from(decision: CheckpointDecision) -> Self {
+        match decision {
+            CheckpointDecision::Proceed => CheckpointOutput {
+                proceed: true,
+                feedback: None,
+            },
+            CheckpointDecision::Revise(feedback) => CheckpointOutput {
+                proceed: false,
+                feedback: Some(feedback),
+            },
+        }
+    }
+}
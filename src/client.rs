@@ -0,0 +1,427 @@
+//! Handlers for requests the agent makes of the client.
+//!
+//! ACP agents may ask the client to read and write files (for instance to
+//! see unsaved editor buffers) or to run commands in a terminal. We only
+//! advertise these capabilities when the host supplies a handler, so agents
+//! that can't use them fall back to their own tools.
+
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use agent_client_protocol::role::Role;
+use agent_client_protocol::schema::{
+    ClientCapabilities, CreateTerminalRequest, CreateTerminalResponse, FileSystemCapabilities,
+    KillTerminalRequest, KillTerminalResponse, ReadTextFileRequest, ReadTextFileResponse,
+    ReleaseTerminalRequest, ReleaseTerminalResponse, RequestPermissionRequest,
+    TerminalOutputRequest, TerminalOutputResponse, WaitForTerminalExitRequest,
+    WaitForTerminalExitResponse, WriteTextFileRequest, WriteTextFileResponse,
+};
+use agent_client_protocol::{BoxFuture, ConnectionTo, Dispatch, JsonRpcResponse, Responder};
+use tracing::debug;
+
+use crate::completion::Completion;
+use crate::guardrails::Guardrails;
+use crate::permissions::{Decision, Permissions};
+use crate::redaction::LogRedaction;
+use crate::runtime::Spawner;
+use crate::security::SecurityProfile;
+use crate::transcripts::TranscriptStore;
+
+/// Runs commands on behalf of the agent.
+///
+/// Register an implementation with
+/// [`DeterminishticBuilder::terminal`](crate::DeterminishticBuilder::terminal)
+/// to advertise the `terminal` client capability. Each method corresponds to
+/// one of the ACP `terminal/*` requests.
+pub trait TerminalHandler: Send + Sync + 'static {
+    /// Start a command and return an id for the new terminal.
+    fn create_terminal(
+        &self,
+        request: CreateTerminalRequest,
+    ) -> BoxFuture<'_, Result<CreateTerminalResponse, agent_client_protocol::Error>>;
+
+    /// Return the output captured so far, and the exit status if the command has finished.
+    fn terminal_output(
+        &self,
+        request: TerminalOutputRequest,
+    ) -> BoxFuture<'_, Result<TerminalOutputResponse, agent_client_protocol::Error>>;
+
+    /// Wait for the command to exit.
+    fn wait_for_terminal_exit(
+        &self,
+        request: WaitForTerminalExitRequest,
+    ) -> BoxFuture<'_, Result<WaitForTerminalExitResponse, agent_client_protocol::Error>>;
+
+    /// Kill the command, keeping the terminal around so its output can still be read.
+    fn kill_terminal(
+        &self,
+        request: KillTerminalRequest,
+    ) -> BoxFuture<'_, Result<KillTerminalResponse, agent_client_protocol::Error>>;
+
+    /// Kill the command if it is still running and forget the terminal.
+    fn release_terminal(
+        &self,
+        request: ReleaseTerminalRequest,
+    ) -> BoxFuture<'_, Result<ReleaseTerminalResponse, agent_client_protocol::Error>>;
+}
+
+type Handler<Req, Resp> = Arc<
+    dyn Fn(Req) -> BoxFuture<'static, Result<Resp, agent_client_protocol::Error>> + Send + Sync,
+>;
+
+/// The client-side handlers registered on a [`DeterminishticBuilder`](crate::DeterminishticBuilder).
+#[derive(Clone, Default)]
+pub(crate) struct ClientHandlers {
+    read_text_file: Option<Handler<ReadTextFileRequest, ReadTextFileResponse>>,
+    write_text_file: Option<Handler<WriteTextFileRequest, WriteTextFileResponse>>,
+    terminal: Option<Arc<dyn TerminalHandler>>,
+    permission:
+        Option<Arc<dyn Fn(RequestPermissionRequest) -> BoxFuture<'static, Decision> + Send + Sync>>,
+    permissions: Permissions,
+    /// Called with session messages that no other handler matched.
+    unhandled: Option<Handler<Dispatch, ()>>,
+    /// Called after every think block.
+    complete: Option<Arc<dyn Fn(Completion) -> BoxFuture<'static, ()> + Send + Sync>>,
+    /// Where think blocks' transcripts are kept.
+    transcripts: Option<Arc<dyn TranscriptStore>>,
+    /// The agent's working directory, if not the current directory.
+    working_dir: Option<PathBuf>,
+    /// Starts background tasks, if not `tokio::spawn`.
+    spawner: Option<Arc<dyn Spawner>>,
+    profile: SecurityProfile,
+    /// Titles of tool calls that may be allowed under [`SecurityProfile::Strict`].
+    allowlist: Vec<String>,
+    /// Rules every think block must follow.
+    guardrails: Vec<Guardrails>,
+    log_redaction: LogRedaction,
+    /// Whether to leave the tool call's description and arguments out of
+    /// permission records.
+    omit_permission_details: bool,
+}
+
+impl ClientHandlers {
+    pub(crate) fn set_read_text_file<F, Fut>(&mut self, func: F)
+    where
+        F: Fn(ReadTextFileRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<ReadTextFileResponse, agent_client_protocol::Error>>
+            + Send
+            + 'static,
+    {
+        self.read_text_file = Some(Arc::new(move |request| Box::pin(func(request))));
+    }
+
+    pub(crate) fn set_write_text_file<F, Fut>(&mut self, func: F)
+    where
+        F: Fn(WriteTextFileRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<WriteTextFileResponse, agent_client_protocol::Error>>
+            + Send
+            + 'static,
+    {
+        self.write_text_file = Some(Arc::new(move |request| Box::pin(func(request))));
+    }
+
+    pub(crate) fn set_permission<F, Fut>(&mut self, func: F)
+    where
+        F: Fn(RequestPermissionRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Decision> + Send + 'static,
+    {
+        self.permission = Some(Arc::new(move |request| Box::pin(func(request))));
+    }
+
+    pub(crate) fn set_unhandled<F, Fut>(&mut self, func: F)
+    where
+        F: Fn(Dispatch) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), agent_client_protocol::Error>> + Send + 'static,
+    {
+        self.unhandled = Some(Arc::new(move |dispatch| Box::pin(func(dispatch))));
+    }
+
+    pub(crate) fn set_complete<F, Fut>(&mut self, func: F)
+    where
+        F: Fn(Completion) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.complete = Some(Arc::new(move |completion| Box::pin(func(completion))));
+    }
+
+    pub(crate) fn set_transcript_store(&mut self, store: impl TranscriptStore + 'static) {
+        self.transcripts = Some(Arc::new(store));
+    }
+
+    pub(crate) fn transcript_store(&self) -> Option<Arc<dyn TranscriptStore>> {
+        self.transcripts.clone()
+    }
+
+    pub(crate) fn set_working_dir(&mut self, dir: PathBuf) {
+        self.working_dir = Some(dir);
+    }
+
+    /// The working directory for new sessions: the one configured, or else
+    /// the current directory, or `/` where there is none.
+    pub(crate) fn working_dir(&self) -> PathBuf {
+        self.working_dir.clone().unwrap_or_else(|| {
+            std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/"))
+        })
+    }
+
+    pub(crate) fn set_spawner(&mut self, spawner: impl Spawner) {
+        self.spawner = Some(Arc::new(spawner));
+    }
+
+    /// The spawner for background tasks: the one configured, or else
+    /// [`TokioSpawner`](crate::TokioSpawner) when the `tokio` feature is on.
+    pub(crate) fn spawner(&self) -> Option<Arc<dyn Spawner>> {
+        #[cfg(feature = "tokio")]
+        if self.spawner.is_none() {
+            return Some(Arc::new(crate::TokioSpawner));
+        }
+        self.spawner.clone()
+    }
+
+    pub(crate) fn set_terminal(&mut self, terminal: impl TerminalHandler) {
+        self.terminal = Some(Arc::new(terminal));
+    }
+
+    pub(crate) fn set_permissions(&mut self, permissions: Permissions) {
+        self.permissions = permissions;
+    }
+
+    pub(crate) fn permissions(&self) -> &Permissions {
+        &self.permissions
+    }
+
+    pub(crate) fn set_profile(&mut self, profile: SecurityProfile) {
+        self.profile = profile;
+    }
+
+    pub(crate) fn set_permission_details(&mut self, enabled: bool) {
+        self.omit_permission_details = !enabled;
+    }
+
+    pub(crate) fn permission_details(&self) -> bool {
+        !self.omit_permission_details
+    }
+
+    pub(crate) fn set_log_redaction(&mut self, redaction: LogRedaction) {
+        self.log_redaction = redaction;
+    }
+
+    /// How content appears in logs, taking the profile into account.
+    pub(crate) fn log_redaction(&self) -> LogRedaction {
+        if self.profile.redacts_logs() {
+            LogRedaction::omit()
+        } else {
+            self.log_redaction
+        }
+    }
+
+    pub(crate) fn allow_permission(&mut self, title: String) {
+        self.allowlist.push(title);
+    }
+
+    pub(crate) fn add_guardrails(&mut self, guardrails: Guardrails) {
+        self.guardrails.push(guardrails);
+    }
+
+    pub(crate) fn guardrails(&self) -> &[Guardrails] {
+        &self.guardrails
+    }
+
+    /// The guardrail profile keeping the agent from the tool titled
+    /// `title`, if any.
+    pub(crate) fn denied_by(&self, title: Option<&str>) -> Option<&str> {
+        let title = title?;
+        self.guardrails
+            .iter()
+            .find(|guardrails| guardrails.denies(title))
+            .map(Guardrails::name)
+    }
+
+    /// Drop whatever the profile forbids.
+    ///
+    /// Called once the builder is finished, so the order of builder calls
+    /// doesn't matter.
+    pub(crate) fn enforce_profile(&mut self) {
+        if self.profile == SecurityProfile::Strict {
+            self.read_text_file = None;
+            self.write_text_file = None;
+            self.terminal = None;
+        }
+    }
+
+    /// Whether the profile lets a tool call with this title be allowed at all.
+    pub(crate) fn may_allow(&self, title: Option<&str>) -> bool {
+        match self.profile {
+            SecurityProfile::Standard => true,
+            SecurityProfile::Strict => {
+                title.is_some_and(|title| self.allowlist.iter().any(|allowed| allowed == title))
+            }
+        }
+    }
+
+    /// The capabilities to declare during initialization.
+    pub(crate) fn capabilities(&self) -> ClientCapabilities {
+        ClientCapabilities::new()
+            .fs(FileSystemCapabilities::new()
+                .read_text_file(self.read_text_file.is_some())
+                .write_text_file(self.write_text_file.is_some()))
+            .terminal(self.terminal.is_some())
+    }
+
+    /// Ask the host whether the agent may use a tool.
+    ///
+    /// Returns `None` if the host registered no handler.
+    pub(crate) async fn request_permission(
+        &self,
+        request: RequestPermissionRequest,
+    ) -> Option<Decision> {
+        match &self.permission {
+            Some(handler) => Some(handler(request).await),
+            None => None,
+        }
+    }
+
+    /// Whether a completion hook is registered.
+    pub(crate) fn wants_completion(&self) -> bool {
+        self.complete.is_some()
+    }
+
+    /// Tell the host that a think block has finished.
+    pub(crate) async fn complete(&self, completion: Completion) {
+        if let Some(handler) = &self.complete {
+            handler(completion).await;
+        }
+    }
+
+    /// Pass a message no other handler matched to the host, or ignore it.
+    pub(crate) async fn unhandled(
+        &self,
+        dispatch: Dispatch,
+    ) -> Result<(), agent_client_protocol::Error> {
+        match &self.unhandled {
+            Some(handler) => handler(dispatch).await,
+            None => {
+                debug!(method = dispatch.method(), "ignoring unhandled session message");
+                Ok(())
+            }
+        }
+    }
+
+    pub(crate) fn read_text_file<R: Role>(
+        &self,
+        cx: &ConnectionTo<R>,
+        request: ReadTextFileRequest,
+        responder: Responder<ReadTextFileResponse>,
+    ) -> Result<(), agent_client_protocol::Error> {
+        debug!(path = ?request.path, "agent requested to read a file");
+        match &self.read_text_file {
+            Some(handler) => respond_later(cx, responder, handler(request)),
+            None => unsupported(responder),
+        }
+    }
+
+    pub(crate) fn write_text_file<R: Role>(
+        &self,
+        cx: &ConnectionTo<R>,
+        request: WriteTextFileRequest,
+        responder: Responder<WriteTextFileResponse>,
+    ) -> Result<(), agent_client_protocol::Error> {
+        debug!(path = ?request.path, "agent requested to write a file");
+        match &self.write_text_file {
+            Some(handler) => respond_later(cx, responder, handler(request)),
+            None => unsupported(responder),
+        }
+    }
+
+    pub(crate) fn create_terminal<R: Role>(
+        &self,
+        cx: &ConnectionTo<R>,
+        request: CreateTerminalRequest,
+        responder: Responder<CreateTerminalResponse>,
+    ) -> Result<(), agent_client_protocol::Error> {
+        debug!(command = ?request.command, "agent requested a terminal");
+        match self.terminal.clone() {
+            Some(terminal) => respond_later(cx, responder, async move {
+                terminal.create_terminal(request).await
+            }),
+            None => unsupported(responder),
+        }
+    }
+
+    pub(crate) fn terminal_output<R: Role>(
+        &self,
+        cx: &ConnectionTo<R>,
+        request: TerminalOutputRequest,
+        responder: Responder<TerminalOutputResponse>,
+    ) -> Result<(), agent_client_protocol::Error> {
+        match self.terminal.clone() {
+            Some(terminal) => respond_later(cx, responder, async move {
+                terminal.terminal_output(request).await
+            }),
+            None => unsupported(responder),
+        }
+    }
+
+    pub(crate) fn wait_for_terminal_exit<R: Role>(
+        &self,
+        cx: &ConnectionTo<R>,
+        request: WaitForTerminalExitRequest,
+        responder: Responder<WaitForTerminalExitResponse>,
+    ) -> Result<(), agent_client_protocol::Error> {
+        match self.terminal.clone() {
+            Some(terminal) => respond_later(cx, responder, async move {
+                terminal.wait_for_terminal_exit(request).await
+            }),
+            None => unsupported(responder),
+        }
+    }
+
+    pub(crate) fn kill_terminal<R: Role>(
+        &self,
+        cx: &ConnectionTo<R>,
+        request: KillTerminalRequest,
+        responder: Responder<KillTerminalResponse>,
+    ) -> Result<(), agent_client_protocol::Error> {
+        match self.terminal.clone() {
+            Some(terminal) => respond_later(cx, responder, async move {
+                terminal.kill_terminal(request).await
+            }),
+            None => unsupported(responder),
+        }
+    }
+
+    pub(crate) fn release_terminal<R: Role>(
+        &self,
+        cx: &ConnectionTo<R>,
+        request: ReleaseTerminalRequest,
+        responder: Responder<ReleaseTerminalResponse>,
+    ) -> Result<(), agent_client_protocol::Error> {
+        match self.terminal.clone() {
+            Some(terminal) => respond_later(cx, responder, async move {
+                terminal.release_terminal(request).await
+            }),
+            None => unsupported(responder),
+        }
+    }
+}
+
+/// Run the handler off the session loop and respond when it finishes.
+///
+/// Handlers may take a while (e.g. waiting for a command to exit), and the
+/// agent may send further requests in the meantime, such as killing the
+/// command it is waiting on.
+fn respond_later<R: Role, T: JsonRpcResponse>(
+    cx: &ConnectionTo<R>,
+    responder: Responder<T>,
+    response: impl Future<Output = Result<T, agent_client_protocol::Error>> + Send + 'static,
+) -> Result<(), agent_client_protocol::Error> {
+    cx.spawn(async move { responder.respond_with_result(response.await) })
+}
+
+/// Reject a request for a capability we did not advertise.
+fn unsupported<T: JsonRpcResponse>(
+    responder: Responder<T>,
+) -> Result<(), agent_client_protocol::Error> {
+    responder.respond_with_error(agent_client_protocol::Error::method_not_found())
+}
@@ -0,0 +1,69 @@
+//! Telling the host that a think block has finished.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::Error;
+use crate::permissions::PermissionRecord;
+use crate::provenance::Provenance;
+use crate::think::ThinkOutcome;
+
+/// A finished think block, as passed to
+/// [`DeterminishticBuilder::on_complete`](crate::DeterminishticBuilder::on_complete).
+///
+/// The output itself stays with the caller; this carries what telemetry and
+/// notifications usually need.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// The think block's [fingerprint](crate::ThinkBuilder::fingerprint).
+    pub fingerprint: String,
+
+    /// The think block's [labels](crate::ThinkBuilder::label).
+    pub labels: BTreeMap<String, String>,
+
+    /// How the output was produced, if the think block succeeded.
+    pub provenance: Option<Provenance>,
+
+    /// Every permission request the agent made, if the think block succeeded.
+    pub permissions: Vec<PermissionRecord>,
+
+    /// Why the think block failed, if it did.
+    pub error: Option<String>,
+
+    /// Time from when the think block was awaited until it finished,
+    /// including any wait for a [`Scheduler`](crate::Scheduler).
+    pub elapsed: Duration,
+}
+
+impl Completion {
+    pub(crate) fn new<T>(
+        fingerprint: String,
+        labels: BTreeMap<String, String>,
+        result: &Result<ThinkOutcome<T>, Error>,
+        elapsed: Duration,
+    ) -> Self {
+        match result {
+            Ok(outcome) => Self {
+                fingerprint,
+                labels,
+                provenance: Some(outcome.provenance().clone()),
+                permissions: outcome.permissions.clone(),
+                error: None,
+                elapsed,
+            },
+            Err(err) => Self {
+                fingerprint,
+                labels,
+                provenance: None,
+                permissions: Vec::new(),
+                error: Some(err.to_string()),
+                elapsed,
+            },
+        }
+    }
+
+    /// Whether the think block succeeded.
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
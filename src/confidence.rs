@@ -0,0 +1,176 @@
+//! Asking the agent how confident it is in a think block's result.
+
+use agent_client_protocol::role::{HasPeer, Role};
+use agent_client_protocol::{Agent, BoxFuture, NullRun, RunWithConnectionTo};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, ThinkBuilder, ThinkOutcome};
+
+/// How many times a result may be sent back for low confidence, unless
+/// configured.
+const DEFAULT_RETRIES: u32 = 2;
+
+/// Tells the agent how to fill in its confidence.
+pub(crate) const CONFIDENCE_GUIDANCE: &str = "Along with the result, report how confident \
+    you are that it is correct, as a probability from 0.0 to 1.0. Be calibrated: of all the \
+    results you give a confidence of 0.8, about 80% should turn out correct. Don't round up \
+    to sound sure; a low confidence with a clear rationale is more useful than a false one.";
+
+/// How sure the agent is that a result is correct, returned alongside it
+/// by a think block built with [`ThinkBuilder::with_confidence`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Confidence {
+    /// Probability that the result is correct, from 0.0 (certainly wrong)
+    /// to 1.0 (certainly right).
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub score: f32,
+
+    /// Why the agent is as confident as it is, including anything it was
+    /// unsure about.
+    pub rationale: String,
+}
+
+/// A think block that reports its confidence in its result, created with
+/// [`ThinkBuilder::with_confidence`].
+///
+/// Await it for the output and the [`Confidence`], or call
+/// [`outcome`](Self::outcome).
+pub struct Confident<'bound, Output, R: Role = Agent, Run: RunWithConnectionTo<R> = NullRun>
+where
+    R: HasPeer<Agent>,
+{
+    think: ThinkBuilder<'bound, Output, R, Run>,
+}
+
+/// What a think block requires of the agent's confidence.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Elicitation {
+    /// Results with a lower confidence are sent back.
+    pub(crate) min_confidence: Option<f32>,
+    /// How many results may be sent back before the think block fails.
+    pub(crate) retries: u32,
+}
+
+impl Default for Elicitation {
+    fn default() -> Self {
+        Self {
+            min_confidence: None,
+            retries: DEFAULT_RETRIES,
+        }
+    }
+}
+
+/// A result as the agent returns it when asked for its confidence.
+#[derive(Deserialize)]
+struct Envelope {
+    result: serde_json::Value,
+    confidence: Confidence,
+}
+
+/// The schema for a result with the agent's confidence alongside it, given
+/// the schema for the result alone.
+pub(crate) fn envelope_schema(mut result: serde_json::Value) -> serde_json::Value {
+    let mut envelope = schemars::schema_for!(Confidence).to_value();
+    let confidence = serde_json::json!({
+        "description": "How confident you are that the result is correct.",
+        "type": "object",
+        "properties": envelope["properties"].take(),
+        "required": envelope["required"].take(),
+    });
+    // References in the result's schema point at the root
+    let defs = result
+        .as_object_mut()
+        .and_then(|schema| schema.remove("$defs"));
+    let meta = result
+        .as_object_mut()
+        .and_then(|schema| schema.remove("$schema"));
+    let mut schema = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "result": result,
+            "confidence": confidence,
+        },
+        "required": ["result", "confidence"],
+    });
+    if let Some(meta) = meta {
+        schema["$schema"] = meta;
+    }
+    if let Some(defs) = defs {
+        schema["$defs"] = defs;
+    }
+    schema
+}
+
+/// Split a result returned with the agent's confidence into the two,
+/// rejecting a confidence that isn't a probability.
+pub(crate) fn split(
+    value: &serde_json::Value,
+) -> Result<(serde_json::Value, Confidence), serde_json::Error> {
+    let Envelope { result, confidence } = Envelope::deserialize(value)?;
+    if !(0.0..=1.0).contains(&confidence.score) {
+        return Err(serde::de::Error::custom(format!(
+            "the confidence score {} is not between 0.0 and 1.0",
+            confidence.score
+        )));
+    }
+    Ok((result, confidence))
+}
+
+impl<'bound, Output, R: Role, Run: RunWithConnectionTo<R>> Confident<'bound, Output, R, Run>
+where
+    R: HasPeer<Agent>,
+    Output: Send + DeserializeOwned + 'static,
+    Run: Send,
+{
+    pub(crate) fn new(think: ThinkBuilder<'bound, Output, R, Run>) -> Self {
+        Self { think }
+    }
+
+    /// Send results the agent is less than `score` confident in back to
+    /// it, asking it to look into its doubts and try again. Once it has
+    /// been sent back as many times as [`retries`](Self::retries) allows,
+    /// the think block fails with [`Error::LowConfidence`].
+    pub fn min_confidence(mut self, score: f32) -> Self {
+        self.think.elicitation_mut().min_confidence = Some(score);
+        self
+    }
+
+    /// Send results back for low confidence at most `n` times before the
+    /// think block fails. The default is 2; with 0, the first result below
+    /// the [minimum](Self::min_confidence) fails it.
+    pub fn retries(mut self, n: u32) -> Self {
+        self.think.elicitation_mut().retries = n;
+        self
+    }
+
+    /// Run the think block, returning the output along with a record of
+    /// how it was produced, as [`ThinkBuilder::outcome`] does.
+    /// [`ThinkOutcome::confidence`] holds the agent's confidence.
+    pub fn outcome(self) -> BoxFuture<'bound, Result<ThinkOutcome<Output>, Error>> {
+        self.think.outcome()
+    }
+}
+
+impl<'bound, Output, R: Role, Run: RunWithConnectionTo<R>> IntoFuture
+    for Confident<'bound, Output, R, Run>
+where
+    R: HasPeer<Agent>,
+    Output: Send + DeserializeOwned + 'static,
+    Run: Send,
+{
+    type Output = Result<(Output, Confidence), Error>;
+
+    type IntoFuture = BoxFuture<'bound, Result<(Output, Confidence), Error>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move {
+            let outcome = self.outcome().await?;
+            let confidence = outcome
+                .confidence
+                .expect("think blocks built with with_confidence report it");
+            Ok((outcome.output, confidence))
+        })
+    }
+}
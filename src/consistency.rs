@@ -0,0 +1,175 @@
+//! Sampling a think block several times and voting on the answer.
+
+use agent_client_protocol::role::{HasPeer, Role};
+use agent_client_protocol::{Agent, BoxFuture};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{Delivery, Error, ThinkBuilder, ThinkOutcome};
+
+/// A think block run several times, keeping the answer most runs agree on,
+/// created with [`ThinkBuilder::self_consistency`].
+///
+/// Await it for the modal output, as you would the builder, or call
+/// [`outcome`](Self::outcome) for the samples and agreement rate too.
+pub struct SelfConsistency<'bound, Output, R: Role = Agent>
+where
+    R: HasPeer<Agent>,
+{
+    think: ThinkBuilder<'bound, Output, R>,
+    samples: usize,
+    min_agreement: f64,
+}
+
+/// The answer most samples of a think block agreed on, returned by
+/// [`SelfConsistency::outcome`].
+#[derive(Debug, Clone)]
+pub struct Consensus<T> {
+    /// The outcome of the first sample that gave the winning answer.
+    pub outcome: ThinkOutcome<T>,
+
+    /// The fraction of samples that gave the winning answer, from 0 to 1.
+    /// Failed samples count as disagreeing.
+    pub agreement: f64,
+
+    /// Every sample, in the order they were started.
+    pub samples: Vec<Sample>,
+}
+
+/// One run of a sampled think block.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    /// The result as the agent returned it, if the run succeeded.
+    pub output: Option<serde_json::Value>,
+
+    /// Why the run failed, if it did.
+    pub error: Option<String>,
+
+    /// Whether the run gave the winning answer.
+    pub agrees: bool,
+}
+
+impl<T> Consensus<T> {
+    /// Whether every sample gave the winning answer.
+    pub fn unanimous(&self) -> bool {
+        self.samples.iter().all(|sample| sample.agrees)
+    }
+}
+
+impl<'bound, Output, R: Role> SelfConsistency<'bound, Output, R>
+where
+    R: HasPeer<Agent>,
+    Output: Send + JsonSchema + DeserializeOwned + 'static,
+{
+    pub(crate) fn new(think: ThinkBuilder<'bound, Output, R>, samples: usize) -> Self {
+        Self {
+            think,
+            samples: samples.max(1),
+            min_agreement: 0.0,
+        }
+    }
+
+    /// Fail with [`Error::Disagreement`] unless at least `fraction` of the
+    /// samples give the winning answer; with `1.0` they must be unanimous.
+    /// By default the most common answer wins however few gave it.
+    pub fn min_agreement(mut self, fraction: f64) -> Self {
+        self.min_agreement = fraction;
+        self
+    }
+
+    /// Run every sample and vote, returning the winning outcome along with
+    /// all the samples and the agreement rate.
+    ///
+    /// Samples fail independently: the vote is among those that succeed,
+    /// and only if none do does this fail, with the first sample's error.
+    /// Ties go to the answer given first.
+    pub fn outcome(self) -> BoxFuture<'bound, Result<Consensus<Output>, Error>> {
+        let mut think = self.think;
+        let sinks = think.take_sinks();
+        let mut runs: Vec<_> = (1..self.samples)
+            .map(|_| think.duplicate().outcome())
+            .collect();
+        runs.insert(0, think.outcome());
+        let samples = self.samples;
+        let min_agreement = self.min_agreement;
+
+        Box::pin(async move {
+            let results = futures::future::join_all(runs).await;
+            let answers: Vec<Option<&serde_json::Value>> = results
+                .iter()
+                .map(|result| result.as_ref().ok().map(|outcome| &outcome.accepted))
+                .collect();
+
+            // The first answer with the most votes
+            let mut winner: Option<(usize, usize)> = None;
+            for (index, answer) in answers.iter().enumerate() {
+                if answer.is_none() {
+                    continue;
+                }
+                let votes = answers.iter().filter(|other| *other == answer).count();
+                if winner.is_none_or(|(_, most)| votes > most) {
+                    winner = Some((index, votes));
+                }
+            }
+            let Some((index, votes)) = winner else {
+                warn!(samples, "every sample failed");
+                return Err(results
+                    .into_iter()
+                    .find_map(Result::err)
+                    .unwrap_or(Error::NoResult));
+            };
+
+            let agreement = votes as f64 / samples as f64;
+            debug!(samples, votes, agreement, "samples voted");
+            let samples = results
+                .iter()
+                .zip(&answers)
+                .map(|(result, answer)| Sample {
+                    output: answer.cloned(),
+                    error: result.as_ref().err().map(ToString::to_string),
+                    agrees: *answer == answers[index],
+                })
+                .collect();
+            if agreement < min_agreement {
+                warn!(agreement, min_agreement, "samples disagreed");
+                return Err(Error::Disagreement {
+                    agreement,
+                    required: min_agreement,
+                });
+            }
+
+            let Some(Ok(outcome)) = results.into_iter().nth(index) else {
+                unreachable!("the winning sample succeeded");
+            };
+            for sink in &sinks {
+                debug!("delivering result to sink");
+                sink.deliver(Delivery {
+                    output: outcome.accepted.clone(),
+                    provenance: outcome.provenance().clone(),
+                })
+                .await?;
+            }
+            Ok(Consensus {
+                outcome,
+                agreement,
+                samples,
+            })
+        })
+    }
+}
+
+impl<'bound, Output, R: Role> IntoFuture for SelfConsistency<'bound, Output, R>
+where
+    R: HasPeer<Agent>,
+    Output: Send + JsonSchema + DeserializeOwned + 'static,
+{
+    type Output = Result<Output, Error>;
+
+    type IntoFuture = BoxFuture<'bound, Result<Output, Error>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move { Ok(self.outcome().await?.outcome.output) })
+    }
+}
@@ -0,0 +1,128 @@
+//! Predicting what a think block will cost before running it.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+/// Per-model token prices, for [`ThinkBuilder::estimate_cost`](crate::ThinkBuilder::estimate_cost).
+///
+/// Prices are in US dollars per million tokens. Start from [`new`](Self::new)
+/// and add the models you use, or from [`builtin`](Self::builtin) for
+/// Anthropic's published list prices. Models are matched by exact name, then
+/// by the longest configured name the model starts with, so
+/// `"claude-sonnet-4"` also prices `"claude-sonnet-4-20250514"`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pricing {
+    models: HashMap<String, ModelPrice>,
+    default_model: Option<String>,
+    output_tokens: u64,
+}
+
+/// The price of one model, in US dollars per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ModelPrice {
+    /// Price per million input (prompt) tokens.
+    pub input: f64,
+
+    /// Price per million output tokens.
+    pub output: f64,
+}
+
+/// What a think block is expected to cost, from [`ThinkBuilder::estimate_cost`](crate::ThinkBuilder::estimate_cost).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CostEstimate {
+    /// The model the estimate is for: the think block's
+    /// [model](crate::ThinkBuilder::model), or the pricing's default.
+    pub model: Option<String>,
+
+    /// Estimated tokens in the prompt, tool definitions, and output schema.
+    pub input_tokens: u64,
+
+    /// The output tokens assumed by the [`Pricing`].
+    pub output_tokens: u64,
+
+    /// Estimated cost in US dollars, or `None` if the model has no price.
+    pub cost: Option<f64>,
+}
+
+impl Pricing {
+    /// An empty table, assuming 1,000 output tokens per think block.
+    pub fn new() -> Self {
+        Self {
+            models: HashMap::new(),
+            default_model: None,
+            output_tokens: 1_000,
+        }
+    }
+
+    /// Anthropic's published list prices for current Claude models.
+    ///
+    /// Prices change; check them against your provider's pricing page and
+    /// override any that differ with [`model`](Self::model).
+    pub fn builtin() -> Self {
+        Self::new()
+            .model("claude-opus-4-5", 5.0, 25.0)
+            .model("claude-opus-4", 15.0, 75.0)
+            .model("claude-sonnet-4-5", 3.0, 15.0)
+            .model("claude-sonnet-4", 3.0, 15.0)
+            .model("claude-3-7-sonnet", 3.0, 15.0)
+            .model("claude-haiku-4-5", 1.0, 5.0)
+            .model("claude-3-5-haiku", 0.8, 4.0)
+    }
+
+    /// Price a model, in US dollars per million input and output tokens.
+    pub fn model(mut self, name: impl Into<String>, input: f64, output: f64) -> Self {
+        self.models
+            .insert(name.into(), ModelPrice { input, output });
+        self
+    }
+
+    /// The model to assume for think blocks that don't choose one, usually
+    /// the agent's default.
+    pub fn default_model(mut self, name: impl Into<String>) -> Self {
+        self.default_model = Some(name.into());
+        self
+    }
+
+    /// Assume each think block produces `tokens` output tokens.
+    ///
+    /// Output length can't be predicted from the prompt, so this is a
+    /// planning figure; agents that reason at length before answering
+    /// produce many more tokens than the result itself.
+    pub fn output_tokens(mut self, tokens: u64) -> Self {
+        self.output_tokens = tokens;
+        self
+    }
+
+    /// The price of `model`, if the table has one.
+    pub fn price(&self, model: &str) -> Option<ModelPrice> {
+        if let Some(price) = self.models.get(model) {
+            return Some(*price);
+        }
+        self.models
+            .iter()
+            .filter(|(name, _)| model.starts_with(name.as_str()))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, price)| *price)
+    }
+
+    pub(crate) fn estimate(&self, model: Option<&str>, input_tokens: u64) -> CostEstimate {
+        let model = model.or(self.default_model.as_deref());
+        let cost = model.and_then(|model| self.price(model)).map(|price| {
+            (input_tokens as f64 * price.input + self.output_tokens as f64 * price.output)
+                / 1_000_000.0
+        });
+        CostEstimate {
+            model: model.map(str::to_string),
+            input_tokens,
+            output_tokens: self.output_tokens,
+            cost,
+        }
+    }
+}
+
+impl Default for Pricing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
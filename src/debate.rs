@@ -0,0 +1,263 @@
+//! Settling a question by having two agents debate it.
+//!
+//! A [`Debate`] asks two of an instance's
+//! [named agents](crate::DeterminishticBuilder::connect_agents) the same
+//! question. Each then reads the other's answer, critiques it, and revises
+//! its own, for a number of rounds. Finally a judge reads the whole debate
+//! and picks the better answer, or combines the two, as a typed output.
+//! Answers that survive a critic are easier to trust than a single agent's
+//! first attempt, at the cost of `2 + 2 * rounds + 1` think blocks.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use determinishtic::debate::Debate;
+//!
+//! let d = Determinishtic::builder()
+//!     .connect_agents([("claude", claude), ("gemini", gemini)])
+//!     .await?;
+//!
+//! let question = format!("Should we shard the orders table before the launch?\n\n{design}");
+//! let outcome = Debate::new(question, "claude", "gemini")
+//!     .rounds(2)
+//!     .run::<Decision>(&d)
+//!     .await?;
+//! println!("{}", outcome.transcript);
+//! println!("decision: {:?}", outcome.output);
+//! ```
+
+use std::fmt;
+
+use agent_client_protocol::Agent;
+use agent_client_protocol::role::{HasPeer, Role};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{Determinishtic, Error, ThinkBuilder};
+
+/// Two agents answering a question, critiquing each other's answers, and a
+/// judge deciding between them.
+#[derive(Debug, Clone)]
+pub struct Debate {
+    question: String,
+    agents: [String; 2],
+    rounds: usize,
+    /// The agent that judges the debate, or the default agent if `None`.
+    judge: Option<String>,
+}
+
+/// The judge's decision, along with the debate it was based on.
+#[derive(Debug, Clone, Serialize)]
+pub struct DebateOutcome<T> {
+    /// What the judge decided.
+    pub output: T,
+
+    /// Everything the debaters said.
+    pub transcript: DebateTranscript,
+}
+
+/// Everything said in a debate, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DebateTranscript {
+    /// The question debated.
+    pub question: String,
+
+    /// What was said, round by round, the first agent first in each round.
+    pub statements: Vec<Statement>,
+}
+
+/// One thing a debater said.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Statement {
+    /// The round it was said in: 0 for the opening answers, then 1 onwards.
+    pub round: usize,
+
+    /// The agent that said it.
+    pub agent: String,
+
+    /// The agent's critique of the other agent's answer from the round
+    /// before; empty in round 0.
+    pub critique: String,
+
+    /// The agent's answer as of this round.
+    pub answer: String,
+}
+
+/// A debater's turn after the opening round.
+#[derive(Debug, Deserialize, JsonSchema)]
+struct Rebuttal {
+    /// What is wrong with, or missing from, the other agent's answer.
+    critique: String,
+
+    /// Your complete answer, revised where the other agent's answer or your
+    /// own critique showed it could be better.
+    answer: String,
+}
+
+impl Debate {
+    /// A debate about `question` between the agents named `first` and
+    /// `second`. The question should carry all the context the agents need.
+    ///
+    /// Defaults to one round of critiques, judged by the default agent.
+    pub fn new(
+        question: impl fmt::Display,
+        first: impl Into<String>,
+        second: impl Into<String>,
+    ) -> Self {
+        Self {
+            question: question.to_string(),
+            agents: [first.into(), second.into()],
+            rounds: 1,
+            judge: None,
+        }
+    }
+
+    /// Number of rounds of critiques after the opening answers. With zero,
+    /// the judge decides between the opening answers alone.
+    pub fn rounds(mut self, rounds: usize) -> Self {
+        self.rounds = rounds;
+        self
+    }
+
+    /// Have the agent named `agent` judge the debate, rather than the
+    /// instance's default agent.
+    pub fn judge(mut self, agent: impl Into<String>) -> Self {
+        self.judge = Some(agent.into());
+        self
+    }
+
+    /// Run the debate on `d`'s agents and have the judge decide.
+    ///
+    /// Both agents take each round at the same time. Every think block is
+    /// [labelled](crate::ThinkBuilder::label) with `debate_round` and
+    /// `debate_role`, so the debate can also be followed in stored
+    /// [transcripts](crate::TranscriptStore). Fails if any think block
+    /// does, and with [`Error::UnknownAgent`], before any runs, if `d` has
+    /// no agent with one of the names given.
+    pub async fn run<T, R>(&self, d: &Determinishtic<R>) -> Result<DebateOutcome<T>, Error>
+    where
+        T: Send + JsonSchema + DeserializeOwned + 'static,
+        R: Role + HasPeer<Agent>,
+    {
+        let mut transcript = DebateTranscript {
+            question: self.question.clone(),
+            statements: Vec::new(),
+        };
+
+        let [first, second] = &self.agents;
+        let judge = match &self.judge {
+            Some(agent) => d.try_think_on(agent)?,
+            None => d.think(),
+        };
+        let (a, b): (String, String) = futures::try_join!(
+            self.open(d.try_think_on(first)?),
+            self.open(d.try_think_on(second)?)
+        )?;
+        let mut answers = [a, b];
+        for (agent, answer) in self.agents.iter().zip(&answers) {
+            transcript.statements.push(Statement {
+                round: 0,
+                agent: agent.clone(),
+                critique: String::new(),
+                answer: answer.clone(),
+            });
+        }
+
+        for round in 1..=self.rounds {
+            let (a, b): (Rebuttal, Rebuttal) = futures::try_join!(
+                self.rebut(d.think_on(first), round, &answers[0], &answers[1]),
+                self.rebut(d.think_on(second), round, &answers[1], &answers[0]),
+            )?;
+            debug!(round, "debate round finished");
+            answers = [a.answer.clone(), b.answer.clone()];
+            for (agent, rebuttal) in self.agents.iter().zip([a, b]) {
+                transcript.statements.push(Statement {
+                    round,
+                    agent: agent.clone(),
+                    critique: rebuttal.critique,
+                    answer: rebuttal.answer,
+                });
+            }
+        }
+
+        let output = judge
+            .label("debate_round", (self.rounds + 1).to_string())
+            .label("debate_role", "judge")
+            .textln("You are judging a debate between two agents.")
+            .textln("Read the question and everything both agents said. Decide which final")
+            .textln("answer is better supported, or combine the strongest points of both, and")
+            .textln("return the result. Weigh the critiques: an answer whose flaws were pointed")
+            .textln("out and not fixed should not win.")
+            .textln("")
+            .text(&format!(
+                "<question>\n{}\n</question>\n\n<debate>\n{transcript}</debate>\n",
+                self.question
+            ))
+            .await?;
+
+        Ok(DebateOutcome { output, transcript })
+    }
+
+    /// Ask for an agent's opening answer.
+    async fn open<R>(&self, think: ThinkBuilder<'_, String, R>) -> Result<String, Error>
+    where
+        R: Role + HasPeer<Agent>,
+    {
+        think
+            .label("debate_round", "0")
+            .label("debate_role", "debater")
+            .textln("Answer the following question. Another agent is answering it too, and")
+            .textln("you will each critique the other's answer, so be thorough and give reasons.")
+            .textln("")
+            .text(&format!("<question>\n{}\n</question>\n", self.question))
+            .await
+    }
+
+    /// Ask an agent to critique the other's answer and revise its own.
+    async fn rebut<R>(
+        &self,
+        think: ThinkBuilder<'_, Rebuttal, R>,
+        round: usize,
+        own: &str,
+        other: &str,
+    ) -> Result<Rebuttal, Error>
+    where
+        R: Role + HasPeer<Agent>,
+    {
+        think
+            .label("debate_round", round.to_string())
+            .label("debate_role", "debater")
+            .textln("You are debating a question with another agent.")
+            .textln("Critique the other agent's answer: point out errors, gaps, and weak")
+            .textln("reasoning, and say what it gets right that yours does not. Then give your")
+            .textln("complete answer again, revised in light of both. Don't concede a point")
+            .textln("just to agree, and don't hold on to one that was shown to be wrong.")
+            .textln("")
+            .text(&format!(
+                "<question>\n{}\n</question>\n\n<your_answer>\n{own}\n</your_answer>\n\n\
+                 <other_answer>\n{other}\n</other_answer>\n",
+                self.question
+            ))
+            .await
+    }
+}
+
+impl fmt::Display for DebateTranscript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for statement in &self.statements {
+            writeln!(
+                f,
+                "<statement round=\"{}\" agent=\"{}\">",
+                statement.round, statement.agent
+            )?;
+            if !statement.critique.is_empty() {
+                writeln!(f, "<critique>\n{}\n</critique>", statement.critique)?;
+            }
+            writeln!(f, "<answer>\n{}\n</answer>", statement.answer)?;
+            writeln!(f, "</statement>")?;
+        }
+        Ok(())
+    }
+}
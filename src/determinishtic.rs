@@ -1,20 +1,47 @@
 //! The main Determinishtic struct that wraps a ConnectTo component.
 
 use agent_client_protocol::{
-    Agent, Client, ConnectionTo, ConnectTo,
+    Agent, Client, ConnectionTo, ConnectTo, Dispatch,
     role::{HasPeer, Role},
-    schema::{InitializeRequest, InitializeResponse, ProtocolVersion},
+    schema::{
+        InitializeRequest, InitializeResponse, NewSessionRequest, NewSessionResponse,
+        ProtocolVersion, ReadTextFileRequest, ReadTextFileResponse, RequestPermissionRequest,
+        WriteTextFileRequest,
+        WriteTextFileResponse,
+    },
 };
-use agent_client_protocol_conductor::{AgentOnly, ConductorImpl, McpBridgeMode};
+#[cfg(feature = "conductor")]
+use agent_client_protocol::{Conductor, DynConnectTo};
+#[cfg(feature = "conductor")]
+use agent_client_protocol_conductor::{ConductorImpl, McpBridgeMode, ProxiesAndAgent};
 use schemars::JsonSchema;
 use serde::de::DeserializeOwned;
-use tokio::sync::oneshot;
-use tokio::task::JoinHandle;
-use tracing::{debug, info, instrument};
+use futures::future::{AbortHandle, Abortable, Either};
+use tokio::sync::{oneshot, watch};
+use tracing::{Instrument, debug, info, instrument};
 
+#[cfg(feature = "conductor")]
+use std::ffi::{OsStr, OsString};
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
 use std::sync::Arc;
+#[cfg(feature = "tokio")]
+use std::time::Duration;
+use crate::capabilities::{self, Capabilities};
+use crate::client::{ClientHandlers, TerminalHandler};
+use crate::completion::Completion;
+use crate::guardrails::Guardrails;
+use crate::permissions::{Decision, Permissions};
+use crate::preamble::Preamble;
+use crate::scheduler::Scheduler;
+use crate::redaction::LogRedaction;
+use crate::runtime::Spawner;
+use crate::security::SecurityProfile;
 use crate::think::ThinkObserver;
-use crate::ThinkBuilder;
+use crate::transcripts::TranscriptStore;
+use crate::usage::{Usage, UsageObserver, UsageStats};
+use crate::{Model, ThinkBuilder, ToolSet};
 
 /// The main entry point for determinishtic operations.
 ///
@@ -28,13 +55,28 @@ use crate::ThinkBuilder;
 ///
 /// When created via `new`, the connection runs in a background task and is cancelled
 /// when `Determinishtic` is dropped.
+///
+/// An instance can also manage several named agents, connected with
+/// [`DeterminishticBuilder::connect_agents`] and picked with
+/// [`think_on`](Self::think_on).
 pub struct Determinishtic<R: Role = Agent>
 where
     R: HasPeer<Agent>,
 {
-    cx: ConnectionTo<R>,
-    task: Option<JoinHandle<Result<(), agent_client_protocol::Error>>>,
+    /// The agents think blocks run on; the first is the default.
+    agents: Vec<AgentLink<R>>,
     observer: Option<Arc<dyn ThinkObserver>>,
+    /// Handlers for requests the agent makes of the client.
+    client: Arc<ClientHandlers>,
+    scheduler: Option<Scheduler>,
+    preamble: Preamble,
+    /// Totals across think blocks, for [`stats`](Self::stats).
+    usage: Arc<Usage>,
+    /// Tools offered in every think block, from [`register_tools`](Self::register_tools).
+    tools: ToolSet<R>,
+    /// The session opened to see what the default agent offers, kept so
+    /// it is only opened once.
+    probe: tokio::sync::OnceCell<NewSessionResponse>,
 }
 
 impl<R: Role> Determinishtic<R>
@@ -59,19 +101,244 @@ where
     ///     Ok(result)
     /// }
     /// ```
+    ///
+    /// To handle client requests (file reads, terminals) arriving during
+    /// think blocks on this connection, use [`DeterminishticBuilder::attach`].
     pub fn from_connection(cx: ConnectionTo<R>) -> Self {
-        Self { cx, task: None, observer: None }
+        DeterminishticBuilder::default().attach(cx)
     }
 
     /// Start building a think block.
     ///
     /// Returns a [`ThinkBuilder`] that can be used to compose the prompt
     /// and register tools. The builder is consumed when awaited.
+    ///
+    /// On an instance with several [named agents](DeterminishticBuilder::connect_agents),
+    /// the think block runs on the first one.
     pub fn think<'bound, Output>(&self) -> ThinkBuilder<'bound, Output, R>
     where
         Output: Send + JsonSchema + DeserializeOwned + 'static,
     {
-        ThinkBuilder::new(self.cx.clone(), self.observer.clone())
+        self.think_with(self.default_agent())
+    }
+
+    /// Start building a think block that runs on the agent named `agent`.
+    ///
+    /// The think block shares this instance's observer, scheduler,
+    /// preamble, registered tools, and client handlers with every other
+    /// agent's, and its usage counts toward the same [`stats`](Self::stats).
+    /// It is [labelled](ThinkBuilder::label) with `agent` set to the agent's
+    /// name, so [`label_stats`](Self::label_stats) gives each agent's share.
+    ///
+    /// ```rust,ignore
+    /// let draft: String = d.think_on("fast").text("Draft a reply to").display(&mail).await?;
+    /// let reply: String = d.think_on("deep").text("Polish this reply").display(&draft).await?;
+    ///
+    /// let deep = d.label_stats("agent", "deep");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no agent named `agent`; see
+    /// [`agent_names`](Self::agent_names). Use
+    /// [`try_think_on`](Self::try_think_on) when the name comes from
+    /// configuration or other input.
+    pub fn think_on<'bound, Output>(&self, agent: &str) -> ThinkBuilder<'bound, Output, R>
+    where
+        Output: Send + JsonSchema + DeserializeOwned + 'static,
+    {
+        self.try_think_on(agent).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Like [`think_on`](Self::think_on), failing with
+    /// [`Error::UnknownAgent`](crate::Error::UnknownAgent) if there is no
+    /// agent named `agent`.
+    pub fn try_think_on<'bound, Output>(
+        &self,
+        agent: &str,
+    ) -> Result<ThinkBuilder<'bound, Output, R>, crate::Error>
+    where
+        Output: Send + JsonSchema + DeserializeOwned + 'static,
+    {
+        let link = self
+            .agents
+            .iter()
+            .find(|link| link.name.as_deref() == Some(agent))
+            .ok_or_else(|| crate::Error::UnknownAgent {
+                name: agent.to_string(),
+            })?;
+        Ok(self.think_with(link).label("agent", agent))
+    }
+
+    /// The names of the agents connected with
+    /// [`DeterminishticBuilder::connect_agents`], default first. Empty for
+    /// an instance with a single, unnamed agent.
+    pub fn agent_names(&self) -> impl Iterator<Item = &str> {
+        self.agents.iter().filter_map(|link| link.name.as_deref())
+    }
+
+    fn think_with<'bound, Output>(&self, link: &AgentLink<R>) -> ThinkBuilder<'bound, Output, R>
+    where
+        Output: Send + JsonSchema + DeserializeOwned + 'static,
+    {
+        let observer = self
+            .observer
+            .as_ref()
+            .map(|observer| observer.for_think().unwrap_or_else(|| observer.clone()));
+        let observer = UsageObserver::new(observer, self.usage.clone());
+        let preamble = self
+            .client
+            .guardrails()
+            .iter()
+            .filter_map(Guardrails::preamble_text)
+            .fold(self.preamble.clone(), Preamble::with_context);
+        let mut think = ThinkBuilder::new(
+            link.cx.clone(),
+            Some(Arc::new(observer)),
+            link.closed.clone(),
+            self.client.clone(),
+            link.initialize.as_ref().and_then(|i| i.agent_info.clone()),
+            self.scheduler.clone(),
+            preamble,
+        );
+        for name in self.client.guardrails().iter().flat_map(Guardrails::denied_tools) {
+            think = think.without_tool(name);
+        }
+        if self.tools.is_empty() {
+            think
+        } else {
+            think.tools(&self.tools)
+        }
+    }
+
+    /// Make every tool in `tools` available in every think block started
+    /// from this instance, without mentioning them in the prompt.
+    ///
+    /// Use this for cross-cutting tools, such as logging or progress
+    /// reporting, that shouldn't have to be added at every call site. A
+    /// tool set passed to [`ThinkBuilder::tools`] replaces registered tools
+    /// with the same name. Calling this more than once merges the sets.
+    ///
+    /// ```rust,ignore
+    /// let mut d = Determinishtic::new(agent).await?;
+    /// d.register_tools(&ToolSet::new().tool("report_progress", "Report progress to the operator", report));
+    /// ```
+    pub fn register_tools(&mut self, tools: &ToolSet<R>) {
+        self.tools = std::mem::take(&mut self.tools).extend(tools);
+    }
+
+    /// Prepend `fragment` to every think block started from this instance.
+    ///
+    /// Use this for context every prompt should carry, such as an
+    /// organization's glossary, tone guide, or domain definitions, so call
+    /// sites can stick to the task. Fragments follow the
+    /// [preamble](DeterminishticBuilder::preamble), in the order they were
+    /// added, and like it are never trimmed to fit a
+    /// [token budget](ThinkBuilder::token_budget).
+    ///
+    /// ```rust,ignore
+    /// let d = Determinishtic::new(AcpAgent::zed_claude_code())
+    ///     .await?
+    ///     .with_context(include_str!("glossary.md"))
+    ///     .with_context("Write for an audience of on-call engineers.");
+    /// ```
+    pub fn with_context(mut self, fragment: impl std::fmt::Display) -> Self {
+        self.preamble = std::mem::take(&mut self.preamble).with_context(&fragment.to_string());
+        self
+    }
+
+    /// Totals across every think block run on this instance so far.
+    ///
+    /// ```rust,ignore
+    /// let stats = d.stats();
+    /// println!("{} prompts, ~{} tokens, {} failures", stats.prompts, stats.estimated_tokens, stats.failures);
+    /// ```
+    pub fn stats(&self) -> UsageStats {
+        self.usage.snapshot()
+    }
+
+    /// Totals across the think blocks run on this instance with the
+    /// [label](ThinkBuilder::label) `key` set to `value`.
+    ///
+    /// ```rust,ignore
+    /// let tenant = d.label_stats("tenant", "acme");
+    /// println!("acme used ~{} tokens", tenant.estimated_tokens);
+    /// ```
+    pub fn label_stats(&self, key: &str, value: &str) -> UsageStats {
+        self.usage.snapshot_label(key, value)
+    }
+
+    /// Start counting from zero again, returning the totals up to now.
+    ///
+    /// Useful for reporting usage per interval: call this on a timer and
+    /// publish what it returns. Per-label totals start from zero too.
+    pub fn reset_stats(&self) -> UsageStats {
+        self.usage.reset()
+    }
+
+    /// Whether the agent honors [`temperature`](ThinkBuilder::temperature) and
+    /// [`seed`](ThinkBuilder::seed).
+    ///
+    /// ACP has no standard sampling capability, so this relies on a
+    /// determinishtic convention: agents that honor the parameters opt in by
+    /// advertising a `sampling` key in their capabilities' `_meta`. Agents
+    /// that don't know the convention never do, so `false` means "not
+    /// known to", not "known not to". It is also `false` if this instance
+    /// was created with [`from_connection`](Self::from_connection) and so
+    /// never saw the agent's capabilities.
+    pub fn supports_sampling(&self) -> bool {
+        capabilities::supports_sampling(self.default_agent().initialize.as_ref())
+    }
+
+    /// Summarize what the agent supports, so callers can branch on it
+    /// instead of discovering missing features through errors.
+    ///
+    /// Agents report modes and models when a session is created, so the
+    /// first call opens a session with no tools to find out; the answer is
+    /// kept for the life of the instance, so later calls, and
+    /// [`models`](Self::models), don't open another.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let capabilities = d.capabilities().await?;
+    /// let think = d.think::<Review>().text("Review this change");
+    /// let think = if capabilities.modes.iter().any(|m| m.id == "plan") {
+    ///     think.mode("plan")
+    /// } else {
+    ///     think
+    /// };
+    /// ```
+    pub async fn capabilities(&self) -> Result<Capabilities, crate::Error> {
+        let session = self.probe_session().await?;
+        Ok(Capabilities::new(
+            self.default_agent().initialize.as_ref(),
+            session,
+        ))
+    }
+
+    /// List the models the agent offers.
+    ///
+    /// Shares [`capabilities`](Self::capabilities)' probe, so only the
+    /// first of either opens a session. Returns an empty list if the agent
+    /// doesn't support model selection.
+    pub async fn models(&self) -> Result<Vec<Model>, crate::Error> {
+        Ok(self.capabilities().await?.models)
+    }
+
+    /// Create a session with no tools, just to see what the agent offers,
+    /// the first time this is called.
+    async fn probe_session(&self) -> Result<&NewSessionResponse, crate::Error> {
+        self.probe
+            .get_or_try_init(async || {
+                Ok(self
+                    .default_agent()
+                    .cx
+                    .send_request_to(Agent, NewSessionRequest::new(self.client.working_dir()))
+                    .block_task()
+                    .await?)
+            })
+            .await
     }
 
     /// Attach an observer that will receive all session updates
@@ -79,6 +346,11 @@ where
     pub fn set_observer(&mut self, observer: Arc<dyn ThinkObserver>) {
         self.observer = Some(observer);
     }
+
+    /// The agent [`think`](Self::think) and the capability queries use.
+    fn default_agent(&self) -> &AgentLink<R> {
+        &self.agents[0]
+    }
 }
 
 impl Determinishtic<Agent> {
@@ -88,15 +360,688 @@ impl Determinishtic<Agent> {
     /// The component will be used to communicate with an LLM agent.
     ///
     /// For use inside proxies where you already have a connection, use
-    /// [`from_connection`](Self::from_connection) instead.
+    /// [`from_connection`](Self::from_connection) instead. To configure the
+    /// connection, use [`builder`](Self::builder).
+    ///
+    /// Only available with the `conductor` feature, which is on by default.
+    #[cfg(feature = "conductor")]
     #[instrument(name = "Determinishtic::new", skip_all)]
     pub async fn new(
         component: impl ConnectTo<Client> + 'static,
     ) -> Result<Self, crate::Error> {
+        Self::builder().connect(component).await
+    }
+
+    /// Start configuring a connection, e.g. to handle requests the agent
+    /// makes of the client.
+    pub fn builder() -> DeterminishticBuilder {
+        DeterminishticBuilder::default()
+    }
+}
+
+/// Configures how [`Determinishtic`] connects to an agent.
+///
+/// Created via [`Determinishtic::builder`].
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let d = Determinishtic::builder()
+///     .on_read_text_file(async |request: ReadTextFileRequest| {
+///         let content = tokio::fs::read_to_string(&request.path)
+///             .await
+///             .map_err(|e| agent_client_protocol::Error::internal_error().data(e.to_string()))?;
+///         Ok(ReadTextFileResponse::new(content))
+///     })
+///     .connect(AcpAgent::zed_claude_code())
+///     .await?;
+/// ```
+pub struct DeterminishticBuilder {
+    client: ClientHandlers,
+    #[cfg(feature = "conductor")]
+    conductor_name: String,
+    #[cfg(feature = "conductor")]
+    mcp_bridge_mode: McpBridgeMode,
+    #[cfg(feature = "conductor")]
+    proxies: Vec<DynConnectTo<Conductor>>,
+    /// Extra arguments for an agent started with `connect_command`.
+    #[cfg(feature = "conductor")]
+    agent_args: Vec<OsString>,
+    /// Extra environment variables for an agent started with `connect_command`.
+    #[cfg(feature = "conductor")]
+    agent_env: Vec<(OsString, OsString)>,
+    /// Called with the lifecycle events of an agent started with `connect_command`.
+    #[cfg(feature = "conductor")]
+    agent_events: Vec<crate::process::EventHandler>,
+    /// The end of the agent's stderr, for an agent started with `connect_command`.
+    #[cfg(feature = "conductor")]
+    agent_stderr: Option<crate::process::StderrTail>,
+    #[cfg(feature = "tokio")]
+    initialize_timeout: Duration,
+    min_protocol_version: ProtocolVersion,
+    scheduler: Option<Scheduler>,
+    preamble: Preamble,
+}
+
+// Only derivable without the conductor's settings
+#[cfg_attr(not(feature = "conductor"), allow(clippy::derivable_impls))]
+impl Default for DeterminishticBuilder {
+    fn default() -> Self {
+        Self {
+            client: ClientHandlers::default(),
+            #[cfg(feature = "conductor")]
+            conductor_name: "determinishtic-conductor".to_string(),
+            #[cfg(feature = "conductor")]
+            mcp_bridge_mode: McpBridgeMode::default(),
+            #[cfg(feature = "conductor")]
+            proxies: Vec::new(),
+            #[cfg(feature = "conductor")]
+            agent_args: Vec::new(),
+            #[cfg(feature = "conductor")]
+            agent_env: Vec::new(),
+            #[cfg(feature = "conductor")]
+            agent_events: Vec::new(),
+            #[cfg(feature = "conductor")]
+            agent_stderr: None,
+            #[cfg(feature = "tokio")]
+            initialize_timeout: DEFAULT_INITIALIZE_TIMEOUT,
+            min_protocol_version: ProtocolVersion::V1,
+            scheduler: None,
+            preamble: Preamble::default(),
+        }
+    }
+}
+
+impl DeterminishticBuilder {
+    /// Let the agent read text files through the client.
+    ///
+    /// Advertises the `fs.readTextFile` capability. Agents use this to see
+    /// the host's view of a file, which may differ from what is on disk.
+    pub fn on_read_text_file<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(ReadTextFileRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<ReadTextFileResponse, agent_client_protocol::Error>>
+            + Send
+            + 'static,
+    {
+        self.client.set_read_text_file(handler);
+        self
+    }
+
+    /// Let the agent write text files through the client.
+    ///
+    /// Advertises the `fs.writeTextFile` capability.
+    pub fn on_write_text_file<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(WriteTextFileRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<WriteTextFileResponse, agent_client_protocol::Error>>
+            + Send
+            + 'static,
+    {
+        self.client.set_write_text_file(handler);
+        self
+    }
+
+    /// Let the agent run commands through the client.
+    ///
+    /// Advertises the `terminal` capability.
+    pub fn terminal(mut self, terminal: impl TerminalHandler) -> Self {
+        self.client.set_terminal(terminal);
+        self
+    }
+
+    /// Decide the agent's permission requests yourself.
+    ///
+    /// Agents ask before using tools that may have side effects, such as
+    /// editing files or running commands. By default every request is
+    /// allowed; supply a handler to show the request to a human (in a TUI or
+    /// editor, say) and relay their decision. Answering
+    /// [`Decision::AllowAlways`] or [`Decision::RejectAlways`] is remembered
+    /// for the rest of the think block's session, so the handler is not asked
+    /// again about the same tool.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let d = Determinishtic::builder()
+    ///     .on_permission(async |request: RequestPermissionRequest| {
+    ///         let title = request.tool_call.fields.title.unwrap_or_default();
+    ///         if ask_user(&format!("Allow {title}?")).await {
+    ///             Decision::AllowAlways
+    ///         } else {
+    ///             Decision::RejectOnce
+    ///         }
+    ///     })
+    ///     .connect(AcpAgent::zed_claude_code())
+    ///     .await?;
+    /// ```
+    pub fn on_permission<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(RequestPermissionRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Decision> + Send + 'static,
+    {
+        self.client.set_permission(handler);
+        self
+    }
+
+    /// Choose whether permission records carry the description and
+    /// arguments the agent gave for each tool call.
+    ///
+    /// On by default, so hosts can show a faithful consent dialog from
+    /// [`ThinkObserver::on_permission_decision`] or
+    /// [`ThinkOutcome::permissions`](crate::ThinkOutcome::permissions). Turn
+    /// it off when records are stored somewhere the arguments must not go;
+    /// the offered options are kept either way.
+    pub fn permission_details(mut self, enabled: bool) -> Self {
+        self.client.set_permission_details(enabled);
+        self
+    }
+
+    /// Put the transcript of every think block in `store` when it finishes,
+    /// whether it succeeded or not.
+    ///
+    /// Transcripts are keyed by the think ID in each result's
+    /// [`Provenance`](crate::Provenance). If a transcript can't be stored, a
+    /// warning is logged and the think block's result is returned anyway.
+    /// Transcripts hold prompts and results in full, whatever the
+    /// [log redaction](Self::log_redaction).
+    pub fn transcript_store(mut self, store: impl TranscriptStore + 'static) -> Self {
+        self.client.set_transcript_store(store);
+        self
+    }
+
+    /// Decide permission requests by the kind of tool the agent wants to use.
+    ///
+    /// Rules are consulted before the [`on_permission`](Self::on_permission)
+    /// handler, which only sees requests for kinds without an allow or deny
+    /// rule. Replaces any rules set earlier.
+    pub fn permissions(mut self, permissions: Permissions) -> Self {
+        self.client.set_permissions(permissions);
+        self
+    }
+
+    /// Call `hook` after every think block, successful or not.
+    ///
+    /// Use it to fan out notifications or telemetry without wrapping every
+    /// await site. The think block's result is returned only once the hook
+    /// has finished, so spawn a task for anything slow.
+    ///
+    /// ```rust,ignore
+    /// let d = Determinishtic::builder()
+    ///     .on_complete(async |completion: Completion| {
+    ///         if let Some(error) = &completion.error {
+    ///             alerts.send(format!("think block {} failed: {error}", completion.fingerprint)).await;
+    ///         }
+    ///     })
+    ///     .connect(AcpAgent::zed_claude_code())
+    ///     .await?;
+    /// ```
+    pub fn on_complete<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn(Completion) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.client.set_complete(hook);
+        self
+    }
+
+    /// Handle session messages the crate doesn't, instead of ignoring them.
+    ///
+    /// Permission requests, session notifications, and the file system and
+    /// terminal requests are handled already; anything else the agent sends
+    /// during a think block, such as extension requests and notifications,
+    /// is passed to `handler`. Requests carry a responder, which the handler
+    /// must use to answer them.
+    ///
+    /// ```rust,ignore
+    /// let d = Determinishtic::builder()
+    ///     .on_unhandled(async |dispatch: Dispatch| {
+    ///         tracing::info!(method = dispatch.method(), "agent sent an extension message");
+    ///         Ok(())
+    ///     })
+    ///     .connect(AcpAgent::zed_claude_code())
+    ///     .await?;
+    /// ```
+    pub fn on_unhandled<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(Dispatch) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), agent_client_protocol::Error>> + Send + 'static,
+    {
+        self.client.set_unhandled(handler);
+        self
+    }
+
+    /// Choose how much to trust the agent.
+    ///
+    /// Defaults to [`SecurityProfile::Standard`]. With
+    /// [`SecurityProfile::Strict`], permission requests are rejected unless
+    /// allowlisted with [`allow_permission`](Self::allow_permission), the
+    /// file system and terminal handlers are disabled, and prompts are kept
+    /// out of logs. The profile can't be changed afterwards, so no think
+    /// block can loosen it.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let d = Determinishtic::builder()
+    ///     .security_profile(SecurityProfile::Strict)
+    ///     .allow_permission("Read")
+    ///     .connect(AcpAgent::zed_claude_code())
+    ///     .await?;
+    /// ```
+    pub fn security_profile(mut self, profile: SecurityProfile) -> Self {
+        self.client.set_profile(profile);
+        self
+    }
+
+    /// Choose how prompts, results, and session updates appear in logs.
+    ///
+    /// Defaults to [`LogRedaction::full`]. What is sent to the agent is
+    /// unaffected. Under [`SecurityProfile::Strict`], content is left out of
+    /// logs whatever the policy.
+    pub fn log_redaction(mut self, redaction: LogRedaction) -> Self {
+        self.client.set_log_redaction(redaction);
+        self
+    }
+
+    /// Under [`SecurityProfile::Strict`], let permission requests for tool
+    /// calls titled `title` through.
+    ///
+    /// Allowlisted requests are decided as usual: by the
+    /// [`on_permission`](Self::on_permission) handler if there is one, and
+    /// otherwise allowed. Has no effect under [`SecurityProfile::Standard`].
+    pub fn allow_permission(mut self, title: impl Into<String>) -> Self {
+        self.client.allow_permission(title.into());
+        self
+    }
+
+    /// Apply a profile of [`Guardrails`] to every think block.
+    ///
+    /// The profile's preamble is added to every prompt, its denied tools
+    /// are kept from the agent, and results that break its rules fail with
+    /// [`Error::GuardrailViolation`](crate::Error::GuardrailViolation).
+    /// Call this again to apply several profiles; a result must satisfy
+    /// them all. Like the [security profile](Self::security_profile),
+    /// guardrails can't be changed once the instance is created.
+    ///
+    /// ```rust,ignore
+    /// let d = Determinishtic::builder()
+    ///     .guardrails(Guardrails::new("no-secrets").forbid(r"AKIA[0-9A-Z]{16}"))
+    ///     .guardrails(customer_facing)
+    ///     .connect(agent)
+    ///     .await?;
+    /// ```
+    pub fn guardrails(mut self, guardrails: Guardrails) -> Self {
+        self.client.add_guardrails(guardrails);
+        self
+    }
+
+    /// Run background tasks, such as the connection to the agent and
+    /// spawned think blocks, with `spawner` instead of `tokio::spawn`.
+    ///
+    /// Use this to drive the instance from another executor; without the
+    /// `tokio` feature, connecting fails unless a spawner is set. See
+    /// [`Spawner`] for an example.
+    pub fn spawner(mut self, spawner: impl Spawner) -> Self {
+        self.client.set_spawner(spawner);
+        self
+    }
+
+    /// Give up on an agent that hasn't answered the initialization
+    /// handshake after `timeout`, failing with
+    /// [`Error::InitializeTimeout`](crate::Error::InitializeTimeout).
+    ///
+    /// Defaults to two minutes, long enough for `npx` to download an agent
+    /// on first use. Only available with the `tokio` feature; without it
+    /// the handshake is awaited for as long as the connection lasts.
+    #[cfg(feature = "tokio")]
+    pub fn initialize_timeout(mut self, timeout: Duration) -> Self {
+        self.initialize_timeout = timeout;
+        self
+    }
+
+    /// Accept agents that answer the initialization handshake with any ACP
+    /// version from `version` up to [`ProtocolVersion::LATEST`], the version
+    /// this crate requests.
+    ///
+    /// Defaults to version 1. An agent answering with a version outside the
+    /// range fails the connection with
+    /// [`Error::IncompatibleProtocolVersion`](crate::Error::IncompatibleProtocolVersion);
+    /// the version agreed on is reported by
+    /// [`Capabilities::protocol_version`](crate::Capabilities::protocol_version).
+    pub fn min_protocol_version(mut self, version: ProtocolVersion) -> Self {
+        self.min_protocol_version = version;
+        self
+    }
+
+    /// Have the agent work in `dir`.
+    ///
+    /// This is the working directory of every session the agent opens,
+    /// against which it resolves relative paths and runs commands, and of
+    /// an agent process started with [`connect_command`](Self::connect_command)
+    /// that doesn't set its own. It defaults to this process's current
+    /// directory, or `/` where there is none, as on `wasm32`.
+    pub fn working_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.client.set_working_dir(dir.into());
+        self
+    }
+
+    /// Queue think blocks so they run within the scheduler's limits.
+    ///
+    /// Without a scheduler, every think block starts as soon as it is awaited.
+    /// Keep a clone of the scheduler to watch its
+    /// [metrics](Scheduler::metrics).
+    pub fn scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    /// Replace the instructions placed before every prompt.
+    ///
+    /// ```rust,ignore
+    /// let d = Determinishtic::builder()
+    ///     .preamble(Preamble::custom(
+    ///         "Erledige die folgende Aufgabe und rufe danach `return_result` mit dem Ergebnis auf.",
+    ///     ))
+    ///     .connect(AcpAgent::zed_claude_code())
+    ///     .await?;
+    /// ```
+    pub fn preamble(mut self, preamble: Preamble) -> Self {
+        self.preamble = preamble;
+        self
+    }
+
+    /// Set the name the conductor reports for itself (used in logs and traces).
+    ///
+    /// Defaults to `determinishtic-conductor`.
+    #[cfg(feature = "conductor")]
+    pub fn conductor_name(mut self, name: impl Into<String>) -> Self {
+        self.conductor_name = name.into();
+        self
+    }
+
+    /// Choose how the conductor bridges our MCP servers to the agent.
+    ///
+    /// Defaults to [`McpBridgeMode::Http`], which works with any agent that
+    /// supports HTTP MCP servers. Use [`McpBridgeMode::Stdio`] for agents that
+    /// only support stdio MCP servers.
+    #[cfg(feature = "conductor")]
+    pub fn mcp_bridge_mode(mut self, mode: McpBridgeMode) -> Self {
+        self.mcp_bridge_mode = mode;
+        self
+    }
+
+    /// Insert a proxy between us and the agent.
+    ///
+    /// Proxies see every message in both directions and may rewrite them,
+    /// which is how policies such as logging or access control are layered
+    /// onto an agent. Proxies are chained in the order they are added, the
+    /// first being closest to us.
+    #[cfg(feature = "conductor")]
+    pub fn proxy(mut self, proxy: impl ConnectTo<Conductor> + 'static) -> Self {
+        self.proxies.push(DynConnectTo::new(proxy));
+        self
+    }
+
+    /// Pass `arg` to an agent started with
+    /// [`connect_command`](Self::connect_command), after the command's own
+    /// arguments.
+    ///
+    /// Use this to point a deployment's agent at its own configuration
+    /// without changing the command, which may be shared.
+    #[cfg(feature = "conductor")]
+    pub fn agent_arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.agent_args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    /// Set an environment variable for an agent started with
+    /// [`connect_command`](Self::connect_command), such as `HTTPS_PROXY`.
+    ///
+    /// The agent otherwise inherits this process's environment, and
+    /// variables set here override those set on the command.
+    #[cfg(feature = "conductor")]
+    pub fn agent_env(mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> Self {
+        self.agent_env
+            .push((key.as_ref().to_owned(), value.as_ref().to_owned()));
+        self
+    }
+
+    /// Call `handler` when an agent started with
+    /// [`connect_command`](Self::connect_command) starts, exits, or is
+    /// restarted.
+    ///
+    /// Supervisors can use this to alert on an agent that keeps dying. See
+    /// [`AgentCommand::on_event`](crate::AgentCommand::on_event).
+    ///
+    /// ```rust,ignore
+    /// let d = Determinishtic::builder()
+    ///     .on_agent_event(|event| {
+    ///         if let AgentEvent::Exited { code, success: false, .. } = event {
+    ///             alerts.agent_died(*code);
+    ///         }
+    ///     })
+    ///     .connect_command(agent.clone())
+    ///     .await?;
+    /// ```
+    #[cfg(feature = "conductor")]
+    pub fn on_agent_event(
+        mut self,
+        handler: impl Fn(&crate::AgentEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.agent_events.push(Arc::new(handler));
+        self
+    }
+
+    /// Use an existing connection instead of creating one.
+    ///
+    /// Like [`Determinishtic::from_connection`], no background task is spawned
+    /// and no initialization is performed, since the owner of the connection
+    /// has already done so. This means the capabilities configured here are
+    /// not advertised to the agent; the handlers still answer any matching
+    /// requests the agent sends during think blocks.
+    pub fn attach<R>(mut self, cx: ConnectionTo<R>) -> Determinishtic<R>
+    where
+        R: Role + HasPeer<Agent>,
+    {
+        self.client.enforce_profile();
+        Determinishtic {
+            agents: vec![AgentLink {
+                name: None,
+                cx,
+                task: None,
+                closed: None,
+                initialize: None,
+            }],
+            observer: None,
+            client: Arc::new(self.client),
+            scheduler: self.scheduler,
+            preamble: self.preamble,
+            usage: Arc::default(),
+            tools: ToolSet::default(),
+            probe: tokio::sync::OnceCell::new(),
+        }
+    }
+
+    /// Connect to the agent and initialize it.
+    ///
+    /// Like [`Determinishtic::new`], this spawns a background task running a
+    /// conductor that bridges the agent to our MCP servers.
+    ///
+    /// Only available with the `conductor` feature, which is on by default;
+    /// without it, use [`connect_direct`](Self::connect_direct).
+    #[cfg(feature = "conductor")]
+    #[instrument(name = "DeterminishticBuilder::connect", skip_all)]
+    pub async fn connect(
+        mut self,
+        component: impl ConnectTo<Client> + 'static,
+    ) -> Result<Determinishtic, crate::Error> {
+        let proxies = std::mem::take(&mut self.proxies);
+        let conductor = ConductorImpl::new_agent(
+            self.conductor_name.clone(),
+            ProxiesAndAgent::new(component).proxies(proxies),
+            self.mcp_bridge_mode.clone(),
+        );
+        self.connect_direct(conductor).await
+    }
+
+    /// Start `command` as the agent, with the [arguments](Self::agent_arg),
+    /// [environment](Self::agent_env),
+    /// [working directory](Self::working_dir), and
+    /// [event handlers](Self::on_agent_event) configured here, and connect
+    /// to it as [`connect`](Self::connect) does.
+    #[cfg(feature = "conductor")]
+    pub async fn connect_command(
+        mut self,
+        command: crate::AgentCommand,
+    ) -> Result<Determinishtic, crate::Error> {
+        let mut command = command.configure(
+            self.agent_args.iter().map(OsString::as_os_str),
+            self.agent_env
+                .iter()
+                .map(|(key, value)| (key.as_os_str(), value.as_os_str())),
+            &self.agent_events,
+            &self.client.working_dir(),
+        );
+        self.agent_stderr = Some(command.capture_stderr());
+        self.connect(command).await
+    }
+
+    /// Connect to `component` directly, without interposing a conductor.
+    ///
+    /// The component must speak MCP-over-ACP itself, since MCP servers are
+    /// offered to it via `acp:` URLs rather than bridged to HTTP. This is
+    /// how a build without the `conductor` feature connects.
+    #[instrument(name = "DeterminishticBuilder::connect_direct", skip_all)]
+    pub async fn connect_direct(
+        mut self,
+        component: impl ConnectTo<Client> + 'static,
+    ) -> Result<Determinishtic, crate::Error> {
+        self.client.enforce_profile();
+        let spawner = self.connection_spawner()?;
+        let link = self.link(&*spawner, None, component).await?;
+        Ok(self.into_instance(vec![link]))
+    }
+
+    /// Connect to several agents, each through its own conductor, and
+    /// manage them from one instance.
+    ///
+    /// Think blocks pick an agent by name with
+    /// [`Determinishtic::think_on`]; [`Determinishtic::think`] uses the
+    /// first one. The agents share everything configured here, as well as
+    /// the instance's registered tools and usage totals, but each has its
+    /// own connection: one that fails or closes only fails the think blocks
+    /// running on it. The agents are connected concurrently, and if any
+    /// can't be, those already connected are closed again.
+    ///
+    /// [Proxies](Self::proxy) can only be used with a single agent, since
+    /// each is put in front of exactly one.
+    ///
+    /// ```rust,ignore
+    /// let d = Determinishtic::builder()
+    ///     .connect_agents([
+    ///         ("fast", AcpAgent::from_str("gemini --experimental-acp")?),
+    ///         ("deep", AcpAgent::zed_claude_code()),
+    ///     ])
+    ///     .await?;
+    /// ```
+    ///
+    /// Only available with the `conductor` feature, which is on by default;
+    /// without it, use [`connect_agents_direct`](Self::connect_agents_direct).
+    #[cfg(feature = "conductor")]
+    #[instrument(name = "DeterminishticBuilder::connect_agents", skip_all)]
+    pub async fn connect_agents<N, C>(
+        mut self,
+        agents: impl IntoIterator<Item = (N, C)>,
+    ) -> Result<Determinishtic, crate::Error>
+    where
+        N: Into<String>,
+        C: ConnectTo<Client> + 'static,
+    {
+        let agents: Vec<_> = agents.into_iter().collect();
+        if agents.len() > 1 && !self.proxies.is_empty() {
+            return Err(crate::Error::connection(
+                "proxies can't be shared between several agents",
+            ));
+        }
+        let mut proxies = std::mem::take(&mut self.proxies);
+        let conductors: Vec<_> = agents
+            .into_iter()
+            .map(|(name, component)| {
+                let conductor = ConductorImpl::new_agent(
+                    self.conductor_name.clone(),
+                    ProxiesAndAgent::new(component).proxies(std::mem::take(&mut proxies)),
+                    self.mcp_bridge_mode.clone(),
+                );
+                (name, conductor)
+            })
+            .collect();
+        self.connect_agents_direct(conductors).await
+    }
+
+    /// Like [`connect_agents`](Self::connect_agents), but connecting to
+    /// each agent directly, as [`connect_direct`](Self::connect_direct) does.
+    #[instrument(name = "DeterminishticBuilder::connect_agents_direct", skip_all)]
+    pub async fn connect_agents_direct<N, C>(
+        mut self,
+        agents: impl IntoIterator<Item = (N, C)>,
+    ) -> Result<Determinishtic, crate::Error>
+    where
+        N: Into<String>,
+        C: ConnectTo<Client> + 'static,
+    {
+        let agents: Vec<(String, C)> = agents
+            .into_iter()
+            .map(|(name, component)| (name.into(), component))
+            .collect();
+        if agents.is_empty() {
+            return Err(crate::Error::connection("no agents to connect to"));
+        }
+        for (i, (name, _)) in agents.iter().enumerate() {
+            if agents[..i].iter().any(|(earlier, _)| earlier == name) {
+                return Err(crate::Error::connection(format!(
+                    "more than one agent is named `{name}`"
+                )));
+            }
+        }
+        self.client.enforce_profile();
+        let spawner = self.connection_spawner()?;
+        let links = futures::future::try_join_all(agents.into_iter().map(|(name, component)| {
+            let span = tracing::info_span!("agent", agent = %name);
+            self.link(&*spawner, Some(name), component).instrument(span)
+        }))
+        .await?;
+        Ok(self.into_instance(links))
+    }
+
+    /// The spawner for connection tasks, which is required.
+    fn connection_spawner(&self) -> Result<Arc<dyn Spawner>, crate::Error> {
+        self.client.spawner().ok_or_else(|| {
+            crate::Error::connection(
+                "no spawner for the connection task: enable the `tokio` feature or set one with `spawner`",
+            )
+        })
+    }
+
+    /// Start a task running the connection to `component` and initialize
+    /// the agent.
+    async fn link(
+        &self,
+        spawner: &dyn Spawner,
+        name: Option<String>,
+        component: impl ConnectTo<Client> + 'static,
+    ) -> Result<AgentLink<Agent>, crate::Error> {
         debug!("spawning connection task");
         let (tx, rx) = oneshot::channel();
+        let (closed_tx, closed) = watch::channel(());
+        let (ended_tx, ended) = oneshot::channel();
 
-        let task = tokio::spawn(async move {
+        let (task, registration) = AbortHandle::new_pair();
+        // Stop the connection task if the handshake fails or is abandoned
+        let mut task = StopOnDrop(Some(task));
+        let connection = async move {
+            // Dropped when the connection ends, waking any think blocks in progress
+            let _closed_tx = closed_tx;
             Client
                 .builder()
                 .with_spawned(|cx| async move {
@@ -105,34 +1050,170 @@ impl Determinishtic<Agent> {
                     // Keep running until the connection closes
                     std::future::pending::<Result<(), agent_client_protocol::Error>>().await
                 })
-                .connect_to(ConductorImpl::new_agent(
-                    "determinishtic-conductor",
-                    AgentOnly(component),
-                    McpBridgeMode::default(),
-                ))
+                .connect_to(component)
                 .await
-        });
+        };
+        spawner.spawn(Box::pin(async move {
+            let detail = match Abortable::new(connection, registration).await {
+                Ok(Ok(())) => "the agent closed the connection".to_string(),
+                Ok(Err(err)) => {
+                    debug!(%err, "connection task ended");
+                    error_detail(&err)
+                }
+                Err(_) => return,
+            };
+            // Nobody is listening once the handshake is over
+            let _ = ended_tx.send(detail);
+        }));
 
-        let cx = rx.await.map_err(|_| crate::Error::ConnectionClosed)?;
-        info!("connection established");
+        let mut ended = std::pin::pin!(ended);
+        let handshake = async {
+            let cx = match futures::future::select(rx, ended.as_mut()).await {
+                Either::Left((Ok(cx), _)) => cx,
+                Either::Left((Err(_), ended)) => return Err(handshake_failed(ended.await)),
+                Either::Right((detail, _)) => return Err(handshake_failed(detail)),
+            };
+            info!("connection established");
 
-        // FIXME: we should check that it supports MCP-over-ACP
-        let InitializeResponse { .. } = cx
-            .send_request(InitializeRequest::new(ProtocolVersion::LATEST))
-            .block_task()
-            .await?;
+            // FIXME: we should check that it supports MCP-over-ACP
+            let initialize = cx
+                .send_request(
+                    InitializeRequest::new(ProtocolVersion::LATEST)
+                        .client_capabilities(self.client.capabilities()),
+                )
+                .block_task();
+            match futures::future::select(std::pin::pin!(initialize), ended.as_mut()).await {
+                Either::Left((Ok(initialize), _)) => Ok((cx, initialize)),
+                // If the connection is ending, the agent never answered
+                Either::Left((Err(source), ended)) => match ended_soon(ended).await {
+                    Some(detail) => Err(handshake_failed(detail)),
+                    None => Err(crate::Error::Initialization { source }),
+                },
+                Either::Right((detail, _)) => Err(handshake_failed(detail)),
+            }
+        };
+        #[cfg(feature = "tokio")]
+        let handshake = async {
+            let after = self.initialize_timeout;
+            tokio::time::timeout(after, handshake)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(crate::Error::InitializeTimeout {
+                        after,
+                        #[cfg(feature = "conductor")]
+                        stderr: self.agent_stderr.as_ref().and_then(|tail| tail.get()),
+                        #[cfg(not(feature = "conductor"))]
+                        stderr: None,
+                    })
+                })
+        };
+        let (cx, initialize) = handshake.await?;
+        let agent = initialize.protocol_version.clone();
+        if agent < self.min_protocol_version || agent > ProtocolVersion::LATEST {
+            return Err(crate::Error::IncompatibleProtocolVersion {
+                agent,
+                minimum: self.min_protocol_version.clone(),
+                latest: ProtocolVersion::LATEST,
+            });
+        }
+        debug!(protocol_version = %agent, "protocol version agreed");
+
+        Ok(AgentLink {
+            name,
+            cx,
+            task: task.0.take(),
+            closed: Some(closed),
+            initialize: Some(initialize),
+        })
+    }
 
-        Ok(Self { cx, task: Some(task), observer: None })
+    /// An instance running think blocks on `agents`, the first by default.
+    fn into_instance(self, agents: Vec<AgentLink<Agent>>) -> Determinishtic {
+        Determinishtic {
+            agents,
+            observer: None,
+            client: Arc::new(self.client),
+            scheduler: self.scheduler,
+            preamble: self.preamble,
+            usage: Arc::default(),
+            tools: ToolSet::default(),
+            probe: tokio::sync::OnceCell::new(),
+        }
     }
 }
 
-impl<R: Role> Drop for Determinishtic<R>
-where
-    R: HasPeer<Agent>,
-{
+/// How long to wait for the agent to answer `initialize`, unless configured.
+#[cfg(feature = "tokio")]
+const DEFAULT_INITIALIZE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A handshake failure explained by `detail`, or by the connection task
+/// having been stopped if it sent none.
+fn handshake_failed(
+    detail: Result<String, oneshot::error::RecvError>,
+) -> crate::Error {
+    crate::Error::HandshakeFailed {
+        detail: detail.unwrap_or_else(|_| "the connection task was stopped".to_string()),
+    }
+}
+
+/// How long the connection task has to report that it ended after a
+/// request failed, for the failure to be put down to the connection.
+#[cfg(feature = "tokio")]
+const ENDED_GRACE: Duration = Duration::from_millis(200);
+
+/// Why the connection ended, if it has or does within moments, as it does
+/// when a request failed because the agent went away rather than because
+/// the agent answered with an error.
+async fn ended_soon(
+    ended: Pin<&mut oneshot::Receiver<String>>,
+) -> Option<Result<String, oneshot::error::RecvError>> {
+    #[cfg(feature = "tokio")]
+    return tokio::time::timeout(ENDED_GRACE, ended).await.ok();
+    #[cfg(not(feature = "tokio"))]
+    futures::FutureExt::now_or_never(ended)
+}
+
+/// The most specific description in `err`, whose data may nest the errors
+/// of the tasks it passed through.
+fn error_detail(err: &agent_client_protocol::Error) -> String {
+    let mut data = err.data.as_ref();
+    while let Some(serde_json::Value::Object(map)) = data {
+        data = map.get("data");
+    }
+    match data {
+        Some(serde_json::Value::String(detail)) => detail.clone(),
+        _ => err.message.clone(),
+    }
+}
+
+/// A connection to one agent.
+struct AgentLink<R: Role> {
+    /// The agent's name, if it was connected as one of several.
+    name: Option<String>,
+    cx: ConnectionTo<R>,
+    /// Stops the background connection task, if we spawned one.
+    task: Option<AbortHandle>,
+    /// Signals (by closing) when the background connection task ends.
+    closed: Option<watch::Receiver<()>>,
+    /// The agent's response to initialization, if we performed it.
+    initialize: Option<InitializeResponse>,
+}
+
+impl<R: Role> Drop for AgentLink<R> {
     fn drop(&mut self) {
         if let Some(ref task) = self.task {
             task.abort();
         }
     }
 }
+
+/// Stops a connection task when dropped, until it is taken out.
+struct StopOnDrop(Option<AbortHandle>);
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        if let Some(task) = self.0.take() {
+            task.abort();
+        }
+    }
+}
@@ -0,0 +1,541 @@
+//! Code edits proposed by the agent, and applying them safely.
+
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+/// Lines of unchanged context shown around each change in a diff.
+const CONTEXT_LINES: usize = 3;
+
+/// A set of edits to source files, for code-mod think blocks.
+///
+/// Use this as the output type of a think block to have the agent propose
+/// changes as data rather than making them itself. Review the edits in Rust,
+/// preview them with [`dry_run`](Self::dry_run), then write them with
+/// [`apply_to`](Self::apply_to). Both refuse any path that would land
+/// outside the root directory, whether through `..`, an absolute path, or a
+/// symlink.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let edits: CodeEdits = d.think()
+///     .text("Replace every use of the deprecated `old_api` in")
+///     .display(&file)
+///     .await?;
+///
+/// println!("{}", edits.dry_run(&repo)?);
+/// if confirm()? {
+///     edits.apply_to(&repo)?;
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+pub struct CodeEdits {
+    /// The edits to make. Edits to the same file must not overlap; line
+    /// numbers always refer to the file before any edit is made.
+    pub edits: Vec<CodeEdit>,
+}
+
+/// A replacement of a range of lines in one file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct CodeEdit {
+    /// The file to edit, relative to the project root, e.g. `src/lib.rs`.
+    pub path: String,
+
+    /// The first line to replace, counting from 1.
+    pub start_line: usize,
+
+    /// The last line to replace, inclusive. To insert without replacing
+    /// anything, set this to `start_line - 1`; the new lines then go before
+    /// `start_line`.
+    pub end_line: usize,
+
+    /// The text that replaces the lines. Empty to delete them.
+    pub replacement: String,
+}
+
+impl CodeEdits {
+    /// A unified diff of the edits, without writing anything.
+    ///
+    /// Fails if any path escapes `root`, any range is out of bounds, or two
+    /// edits to the same file overlap.
+    pub fn dry_run(&self, root: impl AsRef<Path>) -> Result<String, Error> {
+        let files = self.plan(root.as_ref())?;
+        Ok(files.iter().map(FilePlan::diff).collect())
+    }
+
+    /// Write the edits to the files under `root` and return the diff.
+    ///
+    /// Every edit is checked before any file is written, so an invalid edit
+    /// leaves the tree untouched. Files that don't exist are created, along
+    /// with their parent directories.
+    pub fn apply_to(&self, root: impl AsRef<Path>) -> Result<String, Error> {
+        let files = self.plan(root.as_ref())?;
+        for file in &files {
+            if let Some(parent) = file.full_path.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|err| Error::edit_io(&file.path, "cannot create directory", err))?;
+            }
+            write_file(&file.full_path, &file.new_contents(), !file.exists)
+                .map_err(|err| Error::edit_io(&file.path, "cannot write file", err))?;
+        }
+        Ok(files.iter().map(FilePlan::diff).collect())
+    }
+
+    /// Check every edit and work out the resulting file contents.
+    fn plan(&self, root: &Path) -> Result<Vec<FilePlan>, Error> {
+        let root = root
+            .canonicalize()
+            .map_err(|err| Error::edit_io(&root.display().to_string(), "invalid root", err))?;
+
+        // Group by where the edits land, so `a.rs` and `./a.rs` are checked
+        // against each other and written once
+        let mut by_path: BTreeMap<PathBuf, Vec<&CodeEdit>> = BTreeMap::new();
+        for edit in &self.edits {
+            by_path
+                .entry(resolve(&root, &edit.path)?)
+                .or_default()
+                .push(edit);
+        }
+
+        by_path
+            .into_iter()
+            .map(|(full_path, edits)| FilePlan::new(&root, full_path, edits))
+            .collect()
+    }
+}
+
+/// The edits to one file, checked against its current contents.
+struct FilePlan {
+    path: String,
+    full_path: PathBuf,
+    /// Whether the file exists yet.
+    exists: bool,
+    /// The file's lines, each with its line ending.
+    lines: Vec<String>,
+    /// The edits as 0-based half-open line ranges, sorted and non-overlapping.
+    changes: Vec<Change>,
+}
+
+struct Change {
+    start: usize,
+    end: usize,
+    replacement: Vec<String>,
+}
+
+impl FilePlan {
+    fn new(root: &Path, full_path: PathBuf, mut edits: Vec<&CodeEdit>) -> Result<Self, Error> {
+        let path = &relative_name(root, &full_path);
+        let (exists, contents) = match std::fs::read_to_string(&full_path) {
+            Ok(contents) => (true, contents),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (false, String::new()),
+            Err(err) => return Err(Error::edit_io(path, "cannot read file", err)),
+        };
+        let lines = split_lines(&contents);
+
+        edits.sort_by_key(|edit| (edit.start_line, edit.end_line));
+        let mut changes: Vec<Change> = Vec::with_capacity(edits.len());
+        for edit in edits {
+            if edit.start_line == 0
+                || edit.start_line > lines.len() + 1
+                || edit.end_line + 1 < edit.start_line
+                || edit.end_line > lines.len()
+            {
+                return Err(Error::edit(
+                    path,
+                    format!(
+                        "lines {}-{} are out of range for a file with {} lines",
+                        edit.start_line,
+                        edit.end_line,
+                        lines.len()
+                    ),
+                ));
+            }
+            let change = Change {
+                start: edit.start_line - 1,
+                end: edit.end_line,
+                replacement: split_lines(&edit.replacement)
+                    .into_iter()
+                    .map(|line| {
+                        if line.ends_with('\n') {
+                            line
+                        } else {
+                            line + "\n"
+                        }
+                    })
+                    .collect(),
+            };
+            if let Some(previous) = changes.last()
+                && (change.start < previous.end
+                    || (change.start == previous.start && change.start == change.end))
+            {
+                return Err(Error::edit(
+                    path,
+                    format!("edits overlap at line {}", edit.start_line),
+                ));
+            }
+            changes.push(change);
+        }
+
+        Ok(Self {
+            path: path.to_string(),
+            full_path,
+            exists,
+            lines,
+            changes,
+        })
+    }
+
+    fn new_contents(&self) -> String {
+        let mut out = String::new();
+        let mut line = 0;
+        for change in &self.changes {
+            self.lines[line..change.start]
+                .iter()
+                .chain(&change.replacement)
+                .for_each(|l| push_line(&mut out, l));
+            line = change.end;
+        }
+        self.lines[line..]
+            .iter()
+            .for_each(|l| push_line(&mut out, l));
+        out
+    }
+
+    /// The unified diff for this file, or nothing if the edits change nothing.
+    fn diff(&self) -> String {
+        let mut hunks = String::new();
+        // How many more lines the new file has than the old one, so far
+        let mut offset: isize = 0;
+
+        let mut i = 0;
+        while i < self.changes.len() {
+            // Group changes whose context would touch into one hunk
+            let mut j = i + 1;
+            while j < self.changes.len()
+                && self.changes[j].start <= self.changes[j - 1].end + 2 * CONTEXT_LINES
+            {
+                j += 1;
+            }
+            let group = &self.changes[i..j];
+
+            let old_start = group[0].start.saturating_sub(CONTEXT_LINES);
+            let old_end = (group[j - i - 1].end + CONTEXT_LINES).min(self.lines.len());
+            let mut body = String::new();
+            let mut line = old_start;
+            let mut added = 0;
+            let mut removed = 0;
+            for change in group {
+                push_lines(&mut body, ' ', &self.lines[line..change.start]);
+                push_lines(&mut body, '-', &self.lines[change.start..change.end]);
+                push_lines(&mut body, '+', &change.replacement);
+                removed += change.end - change.start;
+                added += change.replacement.len();
+                line = change.end;
+            }
+            push_lines(&mut body, ' ', &self.lines[line..old_end]);
+
+            let old_count = old_end - old_start;
+            let new_count = old_count - removed + added;
+            let new_start = old_start.saturating_add_signed(offset);
+            if removed > 0 || added > 0 {
+                hunks.push_str(&format!(
+                    "@@ -{} +{} @@\n",
+                    hunk_range(old_start, old_count),
+                    hunk_range(new_start, new_count)
+                ));
+                hunks.push_str(&body);
+            }
+            offset += added as isize - removed as isize;
+            i = j;
+        }
+
+        if hunks.is_empty() {
+            return hunks;
+        }
+        let old_name = if self.exists {
+            format!("a/{}", self.path)
+        } else {
+            "/dev/null".to_string()
+        };
+        format!("--- {old_name}\n+++ b/{}\n{hunks}", self.path)
+    }
+}
+
+/// Resolve `path` under `root`, which must be canonical, refusing anything
+/// that would escape it.
+pub(crate) fn resolve(root: &Path, path: &str) -> Result<PathBuf, Error> {
+    let relative = Path::new(path);
+    if path.is_empty() {
+        return Err(Error::edit(path, "the path is empty"));
+    }
+    for component in relative.components() {
+        match component {
+            Component::Normal(_) | Component::CurDir => {}
+            Component::ParentDir => {
+                return Err(Error::edit(path, "the path may not contain `..`"));
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(Error::edit(path, "the path must be relative to the root"));
+            }
+        }
+    }
+
+    // A symlink anywhere along the way could lead outside the root, even
+    // one whose target doesn't exist yet, so walk down from the root
+    // without following any
+    let mut full_path = root.to_path_buf();
+    let mut components = relative
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .peekable();
+    if components.peek().is_none() {
+        return Err(Error::edit(path, "the path is not a file"));
+    }
+    while let Some(component) = components.next() {
+        full_path.push(component);
+        let metadata = match std::fs::symlink_metadata(&full_path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                full_path.extend(components);
+                return Ok(full_path);
+            }
+            Err(err) => return Err(Error::edit_io(path, "cannot resolve path", err)),
+        };
+        if metadata.file_type().is_symlink() {
+            return Err(Error::edit(path, "the path goes through a symbolic link"));
+        }
+        let last = components.peek().is_none();
+        if last && !metadata.is_file() {
+            return Err(Error::edit(path, "the path is not a file"));
+        }
+        if !last && !metadata.is_dir() {
+            return Err(Error::edit(path, "a parent of the path is not a directory"));
+        }
+    }
+    Ok(full_path)
+}
+
+/// The path of `full_path` under `root`, separated by `/`, as diffs and
+/// errors name it.
+fn relative_name(root: &Path, full_path: &Path) -> String {
+    full_path
+        .strip_prefix(root)
+        .unwrap_or(full_path)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Write `contents` to a path [`resolve`] accepted, without following a
+/// symlink put there since: a new file must not exist at all, and an
+/// existing one is opened without following links where the platform
+/// allows.
+pub(crate) fn write_file(full_path: &Path, contents: &str, create: bool) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true);
+    if create {
+        options.create_new(true);
+    } else {
+        options.truncate(true);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(libc::O_NOFOLLOW);
+    }
+    options.open(full_path)?.write_all(contents.as_bytes())
+}
+
+/// Split `text` into lines, each keeping its line ending.
+fn split_lines(text: &str) -> Vec<String> {
+    text.split_inclusive('\n').map(str::to_string).collect()
+}
+
+/// Append `line`, first ending the previous line if it had no line ending.
+fn push_line(out: &mut String, line: &str) {
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+    out.push_str(line);
+}
+
+/// Append `lines` to a diff, each marked with `prefix`.
+fn push_lines(out: &mut String, prefix: char, lines: &[String]) {
+    for line in lines {
+        out.push(prefix);
+        out.push_str(line);
+        if !line.ends_with('\n') {
+            out.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+}
+
+/// A hunk header range; empty ranges name the line before them.
+fn hunk_range(start: usize, count: usize) -> String {
+    if count == 0 {
+        format!("{start},0")
+    } else {
+        format!("{},{count}", start + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh, canonical directory to use as a root.
+    fn temp_root(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "determinishtic-edits-{name}-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    #[test]
+    fn resolves_paths_inside_the_root() {
+        let root = temp_root("inside");
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src/lib.rs"), "").unwrap();
+        assert_eq!(
+            resolve(&root, "src/lib.rs").unwrap(),
+            root.join("src/lib.rs")
+        );
+        assert_eq!(
+            resolve(&root, "./src/new.rs").unwrap(),
+            root.join("src/new.rs")
+        );
+        assert_eq!(resolve(&root, "a/b/c.rs").unwrap(), root.join("a/b/c.rs"));
+    }
+
+    #[test]
+    fn rejects_parent_components() {
+        let root = temp_root("parent");
+        std::fs::create_dir(root.join("src")).unwrap();
+        assert!(resolve(&root, "../outside.rs").is_err());
+        assert!(resolve(&root, "src/../../outside.rs").is_err());
+    }
+
+    #[test]
+    fn rejects_absolute_paths() {
+        let root = temp_root("absolute");
+        assert!(resolve(&root, "/etc/passwd").is_err());
+        let inside = root.join("file.rs");
+        assert!(resolve(&root, inside.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn rejects_directories_and_empty_paths() {
+        let root = temp_root("directories");
+        std::fs::create_dir(root.join("src")).unwrap();
+        assert!(resolve(&root, "").is_err());
+        assert!(resolve(&root, ".").is_err());
+        assert!(resolve(&root, "src").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinked_directories() {
+        let root = temp_root("symlinked-dir");
+        let outside = temp_root("symlinked-dir-target");
+        std::os::unix::fs::symlink(&outside, root.join("out")).unwrap();
+        assert!(resolve(&root, "out/file.rs").is_err());
+        assert!(resolve(&root, "out/new/file.rs").is_err());
+
+        // Even a link that stays inside the root is refused
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::os::unix::fs::symlink(root.join("src"), root.join("alias")).unwrap();
+        assert!(resolve(&root, "alias/lib.rs").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_dangling_symlinks() {
+        let root = temp_root("dangling");
+        let outside = temp_root("dangling-target");
+        let target = outside.join("planted");
+        std::os::unix::fs::symlink(&target, root.join("out")).unwrap();
+        assert!(resolve(&root, "out").is_err());
+        assert!(resolve(&root, "out/file.rs").is_err());
+
+        let edits = CodeEdits {
+            edits: vec![CodeEdit {
+                path: "out".to_string(),
+                start_line: 1,
+                end_line: 0,
+                replacement: "planted\n".to_string(),
+            }],
+        };
+        assert!(edits.apply_to(&root).is_err());
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn spellings_of_one_path_are_one_file() {
+        let root = temp_root("spellings");
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src/a.rs"), "one\ntwo\nthree\n").unwrap();
+        let edit = |path: &str, line, replacement: &str| CodeEdit {
+            path: path.to_string(),
+            start_line: line,
+            end_line: line,
+            replacement: replacement.to_string(),
+        };
+
+        let overlapping = CodeEdits {
+            edits: vec![edit("src/a.rs", 2, "2\n"), edit("./src//a.rs", 2, "II\n")],
+        };
+        assert!(overlapping.apply_to(&root).is_err());
+
+        let apart = CodeEdits {
+            edits: vec![edit("src/a.rs", 1, "1\n"), edit("./src//a.rs", 3, "3\n")],
+        };
+        let diff = apart.apply_to(&root).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(root.join("src/a.rs")).unwrap(),
+            "1\ntwo\n3\n"
+        );
+        assert!(
+            diff.starts_with("--- a/src/a.rs\n+++ b/src/a.rs\n"),
+            "{diff}"
+        );
+    }
+
+    #[test]
+    fn spellings_of_one_new_path_create_it_once() {
+        let root = temp_root("new-spellings");
+        let edit = |path: &str, replacement: &str| CodeEdit {
+            path: path.to_string(),
+            start_line: 1,
+            end_line: 0,
+            replacement: replacement.to_string(),
+        };
+        let edits = CodeEdits {
+            edits: vec![edit("new.rs", "a\n"), edit("./new.rs", "b\n")],
+        };
+        assert!(edits.apply_to(&root).is_err());
+        assert!(!root.join("new.rs").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writing_never_follows_a_symlink() {
+        let root = temp_root("write");
+        let outside = temp_root("write-target");
+        let target = outside.join("planted");
+        std::os::unix::fs::symlink(&target, root.join("new.rs")).unwrap();
+        assert!(write_file(&root.join("new.rs"), "x", true).is_err());
+        assert!(write_file(&root.join("new.rs"), "x", false).is_err());
+        assert!(!target.exists());
+    }
+}
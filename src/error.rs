@@ -1,27 +1,326 @@
-//! Error types for patchwork.
+//! Error types for determinishtic.
 
+use std::time::Duration;
+
+use agent_client_protocol::schema::ProtocolVersion;
 use thiserror::Error;
 
-/// Errors that can occur during patchwork operations.
+/// Errors that can occur during determinishtic operations.
+///
+/// Each variant is a distinct class of failure, so callers can decide how to
+/// react (retry, fall back to another agent, give up) by matching on it.
+/// Where a lower-level error caused the failure, it is available through
+/// [`std::error::Error::source`]. New variants may be added as the crate
+/// grows, so matches need a catch-all arm.
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum Error {
-    /// Error from the underlying ACP connection.
-    #[error("connection error: {0}")]
-    Connection(#[from] agent_client_protocol::Error),
+    /// The connection to the agent could not be established or was lost.
+    #[error("connection error: {message}")]
+    Connection {
+        /// What went wrong.
+        message: String,
+        /// The underlying protocol error, if there was one.
+        #[source]
+        source: Option<agent_client_protocol::Error>,
+    },
+
+    /// The agent rejected the initialization handshake.
+    #[error("failed to initialize the agent")]
+    Initialization {
+        /// The agent's error.
+        #[source]
+        source: agent_client_protocol::Error,
+    },
+
+    /// The agent did not answer the initialization handshake in time.
+    ///
+    /// See [`DeterminishticBuilder::initialize_timeout`](crate::DeterminishticBuilder::initialize_timeout).
+    #[error("the agent did not respond to initialization within {after:?}{}", stderr_note(.stderr))]
+    InitializeTimeout {
+        /// How long we waited.
+        after: Duration,
+        /// What the agent had written to standard error, for agents started
+        /// with an [`AgentCommand`](crate::AgentCommand) that wrote any.
+        stderr: Option<String>,
+    },
+
+    /// The agent chose a version of ACP this client can't speak.
+    ///
+    /// See [`DeterminishticBuilder::min_protocol_version`](crate::DeterminishticBuilder::min_protocol_version).
+    #[error(
+        "the agent chose ACP version {agent}, but versions {minimum} to {latest} are supported"
+    )]
+    IncompatibleProtocolVersion {
+        /// The version the agent answered with.
+        agent: ProtocolVersion,
+        /// The oldest version accepted.
+        minimum: ProtocolVersion,
+        /// The version requested, the newest this client speaks.
+        latest: ProtocolVersion,
+    },
+
+    /// The connection ended before the initialization handshake finished,
+    /// for instance because the agent process failed to start or exited.
+    #[error("the initialization handshake failed: {detail}")]
+    HandshakeFailed {
+        /// Why the connection ended, including what the agent wrote to
+        /// standard error where that is available.
+        detail: String,
+    },
+
+    /// A think block was asked to run on an agent the instance doesn't
+    /// have.
+    ///
+    /// See [`Determinishtic::agent_names`](crate::Determinishtic::agent_names).
+    #[error("no agent named `{name}`")]
+    UnknownAgent {
+        /// The name asked for.
+        name: String,
+    },
+
+    /// The agent responded to a request with an error, or sent something
+    /// we could not make sense of.
+    #[error("protocol error")]
+    Protocol {
+        /// The protocol error.
+        #[source]
+        source: agent_client_protocol::Error,
+    },
+
+    /// A tool invoked directly (e.g. via [`ToolSet::invoke`](crate::ToolSet::invoke)) failed.
+    #[error("tool `{tool}` failed")]
+    ToolFailure {
+        /// The name of the tool.
+        tool: String,
+        /// The error the tool returned.
+        #[source]
+        source: agent_client_protocol::Error,
+    },
+
+    /// A tool was called with arguments that don't fit its input schema.
+    #[error("invalid arguments to tool `{tool}`{}: {message}", location(.pointer))]
+    ToolArguments {
+        /// The name of the tool.
+        tool: String,
+        /// A JSON pointer to the offending field, such as `/items/0/name`;
+        /// empty if the arguments as a whole are at fault.
+        pointer: String,
+        /// What is wrong with the arguments.
+        message: String,
+    },
+
+    /// A value could not be deserialized.
+    #[error("failed to deserialize {context}: {source}")]
+    Deserialization {
+        /// What was being deserialized.
+        context: String,
+        /// The deserialization error.
+        #[source]
+        source: serde_json::Error,
+    },
+
+    /// An operation did not finish in time.
+    #[error("{operation} timed out after {after:?}")]
+    Timeout {
+        /// What timed out.
+        operation: String,
+        /// How long we waited.
+        after: Duration,
+    },
+
+    /// A [`Memory`](crate::Memory) failed to store or retrieve entries.
+    #[error("memory store failed: {source}")]
+    Memory {
+        /// The store's error.
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// A [`ResultSink`](crate::ResultSink) failed to store a result.
+    #[error("result sink failed: {source}")]
+    Sink {
+        /// The sink's error.
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// A [`TranscriptStore`](crate::TranscriptStore) failed.
+    #[error("transcript store failed: {source}")]
+    TranscriptStore {
+        /// The store's error.
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// A prompt template could not be filled in.
+    #[error("prompt `{name}` failed: {message}")]
+    Prompt {
+        /// The name of the prompt.
+        name: String,
+        /// What went wrong.
+        message: String,
+    },
+
+    /// A tool's request for a completion through a
+    /// [`Sampler`](crate::Sampler) failed.
+    #[error("sampling failed: {message}")]
+    Sampling {
+        /// What went wrong.
+        message: String,
+    },
+
+    /// A [`CodeEdits`](crate::CodeEdits) edit was invalid or could not be
+    /// written.
+    #[error("cannot edit `{path}`: {message}")]
+    Edit {
+        /// The path of the file, as given in the edit.
+        path: String,
+        /// What went wrong.
+        message: String,
+        /// The I/O error, if there was one.
+        #[source]
+        source: Option<std::io::Error>,
+    },
+
+    /// A `git` command run on the caller's behalf failed.
+    #[error("git failed: {message}")]
+    Git {
+        /// What went wrong, usually git's own error message.
+        message: String,
+    },
 
-    /// Error deserializing the LLM's response.
-    #[error("failed to deserialize response: {0}")]
-    Deserialization(#[from] serde_json::Error),
+    /// The [judge](crate::ThinkBuilder::judged_by) rejected every result
+    /// the agent was allowed to submit.
+    #[error("the judge rejected the result {attempts} times; last critique: {critique}")]
+    JudgeRejected {
+        /// How many results the judge rejected.
+        attempts: u32,
+        /// Why the judge rejected the last one.
+        critique: String,
+    },
+
+    /// A result broke a rule of one of the instance's
+    /// [guardrail profiles](crate::Guardrails).
+    #[error("the result violates the `{rule}` guardrail of profile `{profile}`: {details}")]
+    GuardrailViolation {
+        /// The name of the profile the rule belongs to.
+        profile: String,
+        /// The rule broken: a forbidden pattern, or the name of a check.
+        rule: String,
+        /// What was wrong with the result.
+        details: String,
+    },
+
+    /// The agent stayed less confident in its result than the
+    /// [minimum](crate::Confident::min_confidence) required.
+    #[error("the agent's confidence of {confidence} is below the required {required}")]
+    LowConfidence {
+        /// The confidence the agent gave its last result.
+        confidence: f32,
+        /// The confidence required.
+        required: f32,
+    },
+
+    /// Fewer of a think block's [samples](crate::ThinkBuilder::self_consistency)
+    /// agreed on an answer than required.
+    #[error("only {agreement:.2} of the samples agreed on an answer, but {required:.2} was required")]
+    Disagreement {
+        /// The fraction of samples that gave the most common answer.
+        agreement: f64,
+        /// The fraction required.
+        required: f64,
+    },
+
+    /// The session was cancelled before the agent returned a result.
+    #[error("the session was cancelled")]
+    Cancelled,
+
+    /// The agent refused to carry out the request.
+    #[error("the agent refused the request")]
+    Refused,
+
+    /// The agent ran out of turns before returning a result.
+    ///
+    /// See [`ThinkBuilder::continue_on_max_turns`](crate::ThinkBuilder::continue_on_max_turns).
+    #[error("the agent reached its turn limit before returning a result")]
+    MaxTurns,
 
     /// The LLM did not call return_result.
     #[error("LLM did not return a result")]
     NoResult,
+}
+
+impl Error {
+    /// A connection error with no underlying protocol error.
+    pub(crate) fn connection(message: impl Into<String>) -> Self {
+        Error::Connection {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    /// A [`ResultSink`](crate::ResultSink) failure caused by `source`.
+    pub fn sink(source: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Error::Sink {
+            source: source.into(),
+        }
+    }
 
-    /// Error from a tool invocation.
-    #[error("tool error: {0}")]
-    Tool(String),
+    /// A [`TranscriptStore`](crate::TranscriptStore) failure caused by `source`.
+    pub fn transcript_store(source: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Error::TranscriptStore {
+            source: source.into(),
+        }
+    }
+
+    /// A rejected edit to `path`.
+    pub(crate) fn edit(path: &str, message: impl Into<String>) -> Self {
+        Error::Edit {
+            path: path.to_string(),
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    /// An edit to `path` that failed with an I/O error.
+    pub(crate) fn edit_io(path: &str, message: &str, source: std::io::Error) -> Self {
+        Error::Edit {
+            path: path.to_string(),
+            message: format!("{message}: {source}"),
+            source: Some(source),
+        }
+    }
+}
+
+impl From<agent_client_protocol::Error> for Error {
+    fn from(source: agent_client_protocol::Error) -> Self {
+        Error::Protocol { source }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(source: serde_json::Error) -> Self {
+        Error::Deserialization {
+            context: "JSON value".to_string(),
+            source,
+        }
+    }
+}
+
+/// What the agent wrote to stderr, for [`Error::InitializeTimeout`].
+fn stderr_note(stderr: &Option<String>) -> String {
+    match stderr {
+        Some(stderr) => format!("; the agent wrote to stderr:\n{stderr}"),
+        None => String::new(),
+    }
+}
 
-    /// The connection was closed before we could get the context.
-    #[error("connection closed")]
-    ConnectionClosed,
+/// Where in a tool's arguments an error is, for [`Error::ToolArguments`].
+fn location(pointer: &str) -> String {
+    if pointer.is_empty() {
+        String::new()
+    } else {
+        format!(" at `{pointer}`")
+    }
 }
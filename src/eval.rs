@@ -0,0 +1,229 @@
+//! Evaluation helpers for using an LLM as a judge.
+
+use std::fmt::Display;
+
+use agent_client_protocol::Agent;
+use agent_client_protocol::role::{HasPeer, Role};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{Determinishtic, Error, Judgement};
+
+/// The result of scoring a subject against a rubric with [`Determinishtic::score`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Score {
+    /// Overall score, from 0.0 (fails the rubric entirely) to 1.0 (fully satisfies it).
+    pub score: f32,
+
+    /// Why the subject received this overall score.
+    pub rationale: String,
+
+    /// Scores for each individual criterion in the rubric.
+    pub criteria: Vec<CriterionScore>,
+}
+
+/// The score for a single rubric criterion.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct CriterionScore {
+    /// The criterion being scored, as named in the rubric.
+    pub name: String,
+
+    /// Score for this criterion, from 0.0 to 1.0.
+    pub score: f32,
+
+    /// Why the subject received this score for the criterion.
+    pub rationale: String,
+}
+
+/// Which of two candidates a judge preferred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub enum Preference {
+    /// The first candidate (`a`) is better.
+    A,
+    /// The second candidate (`b`) is better.
+    B,
+    /// Neither candidate is clearly better.
+    Tie,
+}
+
+/// The result of a pairwise comparison with [`Determinishtic::compare`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Comparison {
+    /// The reconciled preference across both presentation orders.
+    pub preference: Preference,
+
+    /// The judge's rationale for each presentation order, `a` first and then `b` first.
+    pub rationale: String,
+
+    /// Whether the judge reached the same verdict in both orders.
+    ///
+    /// When the verdicts disagree the judge was swayed by position, and the
+    /// preference is reported as [`Preference::Tie`].
+    pub consistent: bool,
+}
+
+/// A single judgement of two candidates in a fixed order.
+#[derive(Debug, Deserialize, JsonSchema)]
+struct Verdict {
+    /// Which candidate better satisfies the criteria.
+    winner: Winner,
+
+    /// Why that candidate was chosen.
+    rationale: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum Winner {
+    First,
+    Second,
+    Tie,
+}
+
+impl<R: Role> Determinishtic<R>
+where
+    R: HasPeer<Agent>,
+{
+    /// Score `subject` against a rubric, using the agent as a judge.
+    ///
+    /// The rubric is free-form text; list the criteria you care about and
+    /// the agent scores each one as well as the subject overall. Scores are
+    /// clamped to the range 0.0 to 1.0.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let score = d.score(
+    ///     "- accuracy: the summary contains no claims absent from the source\n\
+    ///      - brevity: the summary is at most three sentences",
+    ///     &summary,
+    /// ).await?;
+    /// if score.score < 0.7 {
+    ///     println!("rejected: {}", score.rationale);
+    /// }
+    /// ```
+    pub async fn score(&self, rubric: impl Display, subject: impl Display) -> Result<Score, Error> {
+        let mut score: Score = self
+            .think()
+            .textln("You are evaluating a subject against a rubric.")
+            .textln(
+                "Score each criterion in the rubric from 0.0 (not met at all) to 1.0 (fully met),",
+            )
+            .textln("then give an overall score on the same scale. Justify every score briefly.")
+            .textln("")
+            .text(&format!(
+                "<rubric>\n{rubric}\n</rubric>\n\n<subject>\n{subject}\n</subject>\n"
+            ))
+            .await?;
+
+        score.score = score.score.clamp(0.0, 1.0);
+        for criterion in &mut score.criteria {
+            criterion.score = criterion.score.clamp(0.0, 1.0);
+        }
+        Ok(score)
+    }
+
+    /// Decide whether `subject` meets `criteria`, using the agent as a
+    /// judge, and if not, say what to change.
+    ///
+    /// This is a ready-made judge for
+    /// [`ThinkBuilder::judged_by`](crate::ThinkBuilder::judged_by).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let summary: String = d.think()
+    ///     .text("Summarize")
+    ///     .display(&report)
+    ///     .judged_by(|summary: &String| {
+    ///         d.assess("- at most three sentences\n- no figures absent from the report", summary.clone())
+    ///     })
+    ///     .await?;
+    /// ```
+    pub async fn assess(
+        &self,
+        criteria: impl Display,
+        subject: impl Display,
+    ) -> Result<Judgement, Error> {
+        self.think()
+            .textln("You are checking a subject against some criteria.")
+            .textln("Accept it only if it meets every criterion. Otherwise reject it, and in")
+            .textln("your critique name each criterion it misses and say how to fix it.")
+            .textln("")
+            .text(&format!(
+                "<criteria>\n{criteria}\n</criteria>\n\n<subject>\n{subject}\n</subject>\n"
+            ))
+            .await
+    }
+
+    /// Compare two candidates against some criteria, using the agent as a judge.
+    ///
+    /// LLM judges tend to favor whichever candidate is presented first (or
+    /// last), so the comparison is run in both orders and the verdicts are
+    /// reconciled. If the two verdicts disagree, the result is a
+    /// [`Preference::Tie`] and [`Comparison::consistent`] is `false`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let comparison = d.compare(&old_summary, &new_summary, "Which summary is more accurate?").await?;
+    /// match comparison.preference {
+    ///     Preference::A => println!("keep the old prompt"),
+    ///     Preference::B => println!("ship the new prompt"),
+    ///     Preference::Tie => println!("no clear winner"),
+    /// }
+    /// ```
+    pub async fn compare(
+        &self,
+        a: impl Display,
+        b: impl Display,
+        criteria: impl Display,
+    ) -> Result<Comparison, Error> {
+        let (a, b, criteria) = (a.to_string(), b.to_string(), criteria.to_string());
+
+        let (forward, reversed) =
+            futures::try_join!(self.judge(&a, &b, &criteria), self.judge(&b, &a, &criteria),)?;
+
+        let forward_preference = match forward.winner {
+            Winner::First => Preference::A,
+            Winner::Second => Preference::B,
+            Winner::Tie => Preference::Tie,
+        };
+        let reversed_preference = match reversed.winner {
+            Winner::First => Preference::B,
+            Winner::Second => Preference::A,
+            Winner::Tie => Preference::Tie,
+        };
+
+        let consistent = forward_preference == reversed_preference;
+        let preference = if consistent {
+            forward_preference
+        } else {
+            Preference::Tie
+        };
+
+        Ok(Comparison {
+            preference,
+            rationale: format!(
+                "With A shown first: {}\n\nWith B shown first: {}",
+                forward.rationale, reversed.rationale
+            ),
+            consistent,
+        })
+    }
+
+    /// Ask the judge to pick between two candidates in the given order.
+    async fn judge(&self, first: &str, second: &str, criteria: &str) -> Result<Verdict, Error> {
+        self.think()
+            .textln("You are comparing two candidates against some criteria.")
+            .textln("Decide which candidate better satisfies the criteria, or declare a tie")
+            .textln("if neither is clearly better. Judge on substance, not on the order of presentation.")
+            .textln("")
+            .text(&format!(
+                "<criteria>\n{criteria}\n</criteria>\n\n\
+                 <first>\n{first}\n</first>\n\n\
+                 <second>\n{second}\n</second>\n"
+            ))
+            .await
+    }
+}
@@ -0,0 +1,199 @@
+//! Few-shot examples chosen per think block from a larger pool.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+/// A worked example: an input and the output it should produce.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Example<T> {
+    /// The input, as it would appear in a prompt.
+    pub input: String,
+
+    /// The output the agent should return for this input.
+    pub output: T,
+
+    /// Tags for selecting examples by topic, e.g. the language of a snippet.
+    pub tags: Vec<String>,
+}
+
+/// A pool of examples from which each think block picks the few most
+/// relevant to its input.
+///
+/// Few-shot examples help agents match the expected output, but a prompt
+/// carrying every example gets long and expensive. Keep them all in a pool
+/// and pass a handful to [`ThinkBuilder::examples`](crate::ThinkBuilder::examples):
+/// [`select`](Self::select) ranks examples by how similar their input is to
+/// a query, and [`tagged`](Self::tagged) narrows the pool to a topic first.
+///
+/// Similarity defaults to word overlap between the inputs, which is cheap
+/// and works well when examples share vocabulary with the task. Swap in
+/// something smarter, such as embeddings, with
+/// [`similarity`](Self::similarity).
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let pool = ExamplePool::new()
+///     .example("fn main() { let x = 1 }", Review::approve())
+///     .example_tagged(["unsafe"], "unsafe { *ptr = 0 }", Review::reject("unchecked write"));
+///
+/// let review: Review = d.think()
+///     .examples(pool.select(&diff, 3))
+///     .text("Review this diff")
+///     .display(&diff)
+///     .await?;
+/// ```
+pub struct ExamplePool<T> {
+    examples: Vec<Example<T>>,
+    similarity: Arc<Similarity>,
+}
+
+/// Scores how relevant an example's input is to a query.
+type Similarity = dyn Fn(&str, &str) -> f64 + Send + Sync;
+
+impl<T> ExamplePool<T> {
+    /// Create an empty pool using word-overlap similarity.
+    pub fn new() -> Self {
+        Self {
+            examples: Vec::new(),
+            similarity: Arc::new(word_overlap),
+        }
+    }
+
+    /// Add an example.
+    pub fn example(self, input: impl fmt::Display, output: T) -> Self {
+        self.example_tagged(std::iter::empty::<String>(), input, output)
+    }
+
+    /// Add an example with tags.
+    pub fn example_tagged(
+        mut self,
+        tags: impl IntoIterator<Item = impl Into<String>>,
+        input: impl fmt::Display,
+        output: T,
+    ) -> Self {
+        self.push(Example {
+            input: input.to_string(),
+            output,
+            tags: tags.into_iter().map(Into::into).collect(),
+        });
+        self
+    }
+
+    /// Add an example to an existing pool, for instance one that grows as
+    /// results are reviewed.
+    pub fn push(&mut self, example: Example<T>) {
+        self.examples.push(example);
+    }
+
+    /// Rank examples with `similarity(query, input)`, where higher scores
+    /// mean more relevant.
+    pub fn similarity(
+        mut self,
+        similarity: impl Fn(&str, &str) -> f64 + Send + Sync + 'static,
+    ) -> Self {
+        self.similarity = Arc::new(similarity);
+        self
+    }
+
+    /// Number of examples in the pool.
+    pub fn len(&self) -> usize {
+        self.examples.len()
+    }
+
+    /// Whether the pool has no examples.
+    pub fn is_empty(&self) -> bool {
+        self.examples.is_empty()
+    }
+
+    /// Every example, in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = &Example<T>> {
+        self.examples.iter()
+    }
+
+    /// The `k` examples whose input is most similar to `query`, most
+    /// similar first. Ties keep the order the examples were added.
+    pub fn select(&self, query: &str, k: usize) -> Vec<&Example<T>> {
+        rank(self.examples.iter(), &*self.similarity, query, k)
+    }
+
+    /// The examples carrying `tag`, narrowed further with
+    /// [`TaggedExamples::select`].
+    pub fn tagged<'a>(&'a self, tag: &'a str) -> TaggedExamples<'a, T> {
+        TaggedExamples { pool: self, tag }
+    }
+}
+
+impl<T> Default for ExamplePool<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ExamplePool<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExamplePool")
+            .field("examples", &self.examples)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The examples in an [`ExamplePool`] carrying a tag.
+///
+/// Returned by [`ExamplePool::tagged`].
+pub struct TaggedExamples<'a, T> {
+    pool: &'a ExamplePool<T>,
+    tag: &'a str,
+}
+
+impl<'a, T> TaggedExamples<'a, T> {
+    /// Every example with the tag, in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = &'a Example<T>> + use<'a, T> {
+        let tag = self.tag;
+        self.pool
+            .examples
+            .iter()
+            .filter(move |example| example.tags.iter().any(|t| t == tag))
+    }
+
+    /// The `k` examples with the tag whose input is most similar to
+    /// `query`, most similar first.
+    pub fn select(&self, query: &str, k: usize) -> Vec<&'a Example<T>> {
+        rank(self.iter(), &*self.pool.similarity, query, k)
+    }
+}
+
+fn rank<'a, T>(
+    examples: impl Iterator<Item = &'a Example<T>>,
+    similarity: &Similarity,
+    query: &str,
+    k: usize,
+) -> Vec<&'a Example<T>> {
+    let mut scored: Vec<(f64, &Example<T>)> = examples
+        .map(|example| (similarity(query, &example.input), example))
+        .collect();
+    // A stable sort keeps insertion order among ties
+    scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    scored
+        .into_iter()
+        .take(k)
+        .map(|(_, example)| example)
+        .collect()
+}
+
+/// Jaccard similarity of the sets of lowercase words in `a` and `b`.
+fn word_overlap(a: &str, b: &str) -> f64 {
+    let words = |text: &str| -> HashSet<String> {
+        text.split(|c: char| !c.is_alphanumeric() && c != '_')
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect()
+    };
+    let (a, b) = (words(a), words(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
@@ -0,0 +1,292 @@
+//! Prompt A/B experiments.
+//!
+//! An [`Experiment`] holds two or more variants of a prompt (or any other
+//! value that parameterizes a think block) for the same task. Each unit of
+//! work is assigned to a variant deterministically by a key, so the same
+//! input always sees the same variant across runs, and the outcome of each
+//! trial is recorded per variant for later comparison.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use determinishtic::experiments::Experiment;
+//!
+//! let experiment = Experiment::new("summarize")
+//!     .variant("terse", "Summarize in one sentence:")
+//!     .variant("detailed", "Summarize the key points of this document:");
+//!
+//! for path in files {
+//!     let contents = std::fs::read_to_string(&path)?;
+//!     let summary: Result<String, _> = experiment
+//!         .run_validated(
+//!             path.display(),
+//!             async |instructions| d.think().text(instructions).display(&contents).await,
+//!             |summary| !summary.is_empty(),
+//!         )
+//!         .await;
+//! }
+//!
+//! for report in experiment.report() {
+//!     println!("{}: {:.0}% pass, {:?} mean latency", report.name, report.pass_rate() * 100.0, report.mean_latency);
+//! }
+//! ```
+
+use std::fmt::Display;
+use std::sync::Mutex;
+#[cfg(feature = "tokio")]
+use std::sync::{Arc, atomic::AtomicU64, atomic::Ordering};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::Error;
+use crate::hash::stable_hash;
+
+#[cfg(feature = "tokio")]
+tokio::task_local! {
+    /// The tokens used by think blocks in the trial running in this task.
+    static TRIAL_TOKENS: Arc<AtomicU64>;
+}
+
+/// Count `tokens` used by a think block toward the trial it ran in, if any.
+pub(crate) fn count_tokens(tokens: u64) {
+    #[cfg(feature = "tokio")]
+    let _ = TRIAL_TOKENS.try_with(|trial| trial.fetch_add(tokens, Ordering::Relaxed));
+    #[cfg(not(feature = "tokio"))]
+    let _ = tokens;
+}
+
+/// A set of variants competing on the same task.
+pub struct Experiment<V> {
+    name: String,
+    variants: Vec<Variant<V>>,
+}
+
+struct Variant<V> {
+    name: String,
+    weight: u32,
+    value: V,
+    trials: Mutex<Vec<Trial>>,
+}
+
+/// The outcome of running one unit of work with a variant.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Trial {
+    /// Whether the output was produced and passed validation.
+    pub passed: bool,
+
+    /// Wall-clock time taken by the trial.
+    pub latency: Duration,
+
+    /// Tokens consumed by the trial, if known.
+    ///
+    /// Trials run with [`Experiment::run`] or
+    /// [`run_validated`](Experiment::run_validated) count the
+    /// [estimated tokens](crate::ThinkOutcome::estimated_tokens) of every
+    /// think block awaited in them; this is `None` without the `tokio`
+    /// feature, or if no think block ran.
+    pub tokens: Option<u64>,
+}
+
+/// Aggregated outcomes for a single variant.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VariantReport {
+    /// The variant's name.
+    pub name: String,
+
+    /// Number of trials recorded.
+    pub trials: usize,
+
+    /// Number of trials that passed.
+    pub passed: usize,
+
+    /// Mean latency across all trials (zero if there were none).
+    pub mean_latency: Duration,
+
+    /// Mean tokens across the trials that reported a token count.
+    pub mean_tokens: Option<f64>,
+}
+
+impl VariantReport {
+    /// Fraction of trials that passed, from 0.0 to 1.0 (zero if there were none).
+    pub fn pass_rate(&self) -> f64 {
+        if self.trials == 0 {
+            0.0
+        } else {
+            self.passed as f64 / self.trials as f64
+        }
+    }
+}
+
+impl<V> Experiment<V> {
+    /// Create an experiment with no variants.
+    ///
+    /// The name is mixed into variant assignment, so two experiments over the
+    /// same keys split traffic independently.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            variants: Vec::new(),
+        }
+    }
+
+    /// Register a variant with weight 1.
+    pub fn variant(self, name: impl Into<String>, value: V) -> Self {
+        self.weighted_variant(name, 1, value)
+    }
+
+    /// Register a variant that receives `weight` shares of the traffic.
+    ///
+    /// A variant with weight 0 is never assigned but can still have trials
+    /// recorded against it manually.
+    pub fn weighted_variant(mut self, name: impl Into<String>, weight: u32, value: V) -> Self {
+        self.variants.push(Variant {
+            name: name.into(),
+            weight,
+            value,
+            trials: Mutex::new(Vec::new()),
+        });
+        self
+    }
+
+    /// The experiment's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Deterministically pick a variant for `key`.
+    ///
+    /// Returns the variant's name and value. The assignment depends only on
+    /// the experiment name, the key, and the registered variants and weights,
+    /// so it is stable across runs and processes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no variant has a non-zero weight.
+    pub fn assign(&self, key: impl Display) -> (&str, &V) {
+        let variant = self.assign_variant(&key.to_string());
+        (&variant.name, &variant.value)
+    }
+
+    /// Run `work` with the variant assigned to `key` and record the outcome.
+    ///
+    /// The trial passes if `work` returns `Ok`.
+    pub async fn run<T>(
+        &self,
+        key: impl Display,
+        work: impl AsyncFnOnce(&V) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        self.run_validated(key, work, |_| true).await
+    }
+
+    /// Like [`run`](Self::run), but the trial only passes if `validate` accepts the output.
+    ///
+    /// The trial's tokens are those of the think blocks `work` awaits; think
+    /// blocks it [spawns](crate::ThinkBuilder::spawn) onto other tasks are
+    /// not counted.
+    pub async fn run_validated<T>(
+        &self,
+        key: impl Display,
+        work: impl AsyncFnOnce(&V) -> Result<T, Error>,
+        validate: impl FnOnce(&T) -> bool,
+    ) -> Result<T, Error> {
+        let variant = self.assign_variant(&key.to_string());
+        let start = Instant::now();
+        #[cfg(feature = "tokio")]
+        let (result, tokens) = {
+            let counted = Arc::new(AtomicU64::new(0));
+            let result = TRIAL_TOKENS
+                .scope(counted.clone(), work(&variant.value))
+                .await;
+            let tokens = counted.load(Ordering::Relaxed);
+            (result, (tokens > 0).then_some(tokens))
+        };
+        #[cfg(not(feature = "tokio"))]
+        let (result, tokens) = (work(&variant.value).await, None);
+        let latency = start.elapsed();
+
+        let passed = result.as_ref().is_ok_and(validate);
+        variant.record(Trial {
+            passed,
+            latency,
+            tokens,
+        });
+        result
+    }
+
+    /// Record a trial against the named variant.
+    ///
+    /// Use this when you drive the work yourself, e.g. to supply your own
+    /// token counts.
+    /// Returns `false` if there is no variant with that name.
+    pub fn record(&self, variant: &str, trial: Trial) -> bool {
+        match self.variants.iter().find(|v| v.name == variant) {
+            Some(v) => {
+                v.record(trial);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Summarize the recorded trials for each variant, in registration order.
+    pub fn report(&self) -> Vec<VariantReport> {
+        self.variants.iter().map(Variant::report).collect()
+    }
+
+    fn assign_variant(&self, key: &str) -> &Variant<V> {
+        let total: u64 = self.variants.iter().map(|v| u64::from(v.weight)).sum();
+        assert!(
+            total > 0,
+            "experiment `{}` has no variants with a non-zero weight",
+            self.name
+        );
+
+        // The name and key are separated so ("ab", "c") and ("a", "bc") differ
+        let bytes = self.name.bytes().chain([0]).chain(key.bytes());
+        let mut point = stable_hash(bytes) % total;
+        for variant in &self.variants {
+            let weight = u64::from(variant.weight);
+            if point < weight {
+                return variant;
+            }
+            point -= weight;
+        }
+        unreachable!("point is always less than the total weight")
+    }
+}
+
+impl<V> Variant<V> {
+    fn record(&self, trial: Trial) {
+        self.trials
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(trial);
+    }
+
+    fn report(&self) -> VariantReport {
+        let trials = self
+            .trials
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let passed = trials.iter().filter(|t| t.passed).count();
+        let mean_latency = if trials.is_empty() {
+            Duration::ZERO
+        } else {
+            let total: f64 = trials.iter().map(|t| t.latency.as_secs_f64()).sum();
+            Duration::from_secs_f64(total / trials.len() as f64)
+        };
+        let tokens: Vec<u64> = trials.iter().filter_map(|t| t.tokens).collect();
+        let mean_tokens =
+            (!tokens.is_empty()).then(|| tokens.iter().sum::<u64>() as f64 / tokens.len() as f64);
+
+        VariantReport {
+            name: self.name.clone(),
+            trials: trials.len(),
+            passed,
+            mean_latency,
+            mean_tokens,
+        }
+    }
+}
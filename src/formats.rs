@@ -0,0 +1,40 @@
+//! Lenient parsing for common value types in think block outputs.
+//!
+//! With the `chrono` or `uuid` feature enabled, dates, times, and UUIDs can
+//! appear in output and tool input types like any other field: their schemas
+//! are provided, and the prompt spells out the format expected (ISO 8601 for
+//! dates and times, hyphenated UUIDs).
+//!
+//! Models don't always follow the format to the letter, though. A date-time
+//! without a timezone, a space instead of `T`, or a UUID wrapped in braces
+//! would fail strict deserialization even though the meaning is clear. The
+//! functions here are for use with `#[serde(deserialize_with = "...")]` and
+//! accept those variations. The field keeps its own type, so its schema and
+//! serialization are unchanged.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use chrono::{DateTime, NaiveDate, Utc};
+//! use uuid::Uuid;
+//!
+//! #[derive(Deserialize, JsonSchema)]
+//! struct Incident {
+//!     #[serde(deserialize_with = "determinishtic::formats::uuid::deserialize")]
+//!     id: Uuid,
+//!     #[serde(deserialize_with = "determinishtic::formats::datetime::deserialize")]
+//!     started: DateTime<Utc>,
+//!     #[serde(
+//!         deserialize_with = "determinishtic::formats::date::deserialize_option",
+//!         default
+//!     )]
+//!     resolved_on: Option<NaiveDate>,
+//! }
+//! ```
+
+#[cfg(feature = "chrono")]
+pub mod date;
+#[cfg(feature = "chrono")]
+pub mod datetime;
+#[cfg(feature = "uuid")]
+pub mod uuid;
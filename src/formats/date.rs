@@ -0,0 +1,67 @@
+//! Lenient [`NaiveDate`] fields.
+//!
+//! Use with
+//! `#[serde(deserialize_with = "determinishtic::formats::date::deserialize")]`,
+//! or [`deserialize_option`] for `Option<NaiveDate>`. Accepts ISO 8601
+//! (`2024-05-01`), `2024/05/01`, or a full date-time
+//! (`2024-05-01T13:45:00Z`), whose time is ignored.
+
+use std::fmt;
+
+use chrono::NaiveDate;
+use serde::{Deserializer, de};
+
+/// Deserialize from any of the formats listed in the [module docs](self).
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDate, D::Error> {
+    deserializer
+        .deserialize_any(Visitor { optional: false })?
+        .ok_or_else(|| de::Error::custom("expected a date"))
+}
+
+/// Deserialize an `Option<NaiveDate>`, also accepting `null` and empty
+/// strings as `None`.
+///
+/// Combine with `#[serde(default)]` to also accept a missing field.
+pub fn deserialize_option<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<NaiveDate>, D::Error> {
+    deserializer.deserialize_any(Visitor { optional: true })
+}
+
+/// Parse a date in any of the accepted formats.
+fn parse(text: &str) -> Option<NaiveDate> {
+    let text = text.trim();
+    ["%Y-%m-%d", "%Y/%m/%d"].iter().find_map(|format| {
+        let (date, rest) = NaiveDate::parse_and_remainder(text, format).ok()?;
+        (rest.is_empty() || rest.starts_with(['T', 't', ' '])).then_some(date)
+    })
+}
+
+struct Visitor {
+    optional: bool,
+}
+
+impl de::Visitor<'_> for Visitor {
+    type Value = Option<NaiveDate>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an ISO 8601 date")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        if self.optional && value.trim().is_empty() {
+            return Ok(None);
+        }
+        parse(value)
+            .map(Some)
+            .ok_or_else(|| E::invalid_value(de::Unexpected::Str(value), &self))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        if self.optional {
+            Ok(None)
+        } else {
+            Err(E::invalid_type(de::Unexpected::Unit, &self))
+        }
+    }
+}
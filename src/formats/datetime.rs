@@ -0,0 +1,120 @@
+//! Lenient [`DateTime<Utc>`] fields.
+//!
+//! Use with
+//! `#[serde(deserialize_with = "determinishtic::formats::datetime::deserialize")]`,
+//! or [`deserialize_option`] for `Option<DateTime<Utc>>`. Accepts any of:
+//!
+//! - RFC 3339 with an offset (`2024-05-01T13:45:00+02:00`), converted to UTC
+//! - RFC 2822 (`Wed, 01 May 2024 13:45:00 +0000`)
+//! - a date and time without an offset (`2024-05-01 13:45`), taken as UTC
+//! - a bare date (`2024-05-01`), taken as midnight UTC
+//! - a Unix timestamp in seconds, as a number
+
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use serde::{Deserializer, de};
+
+/// Date-time layouts without an offset, tried in order.
+const NAIVE_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+];
+
+/// Deserialize from any of the formats listed in the [module docs](self).
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    deserializer
+        .deserialize_any(Visitor { optional: false })?
+        .ok_or_else(|| de::Error::custom("expected a date-time"))
+}
+
+/// Deserialize an `Option<DateTime<Utc>>`, also accepting `null` and empty
+/// strings as `None`.
+///
+/// Combine with `#[serde(default)]` to also accept a missing field.
+pub fn deserialize_option<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<DateTime<Utc>>, D::Error> {
+    deserializer.deserialize_any(Visitor { optional: true })
+}
+
+/// Parse a date-time in any of the accepted formats.
+fn parse(text: &str) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    if let Ok(value) = DateTime::parse_from_rfc3339(text) {
+        return Some(value.to_utc());
+    }
+    if let Ok(value) = DateTime::parse_from_rfc2822(text) {
+        return Some(value.to_utc());
+    }
+    // Models often mark UTC with a suffix on an otherwise naive date-time
+    let naive = text
+        .strip_suffix(['Z', 'z'])
+        .or_else(|| text.strip_suffix("UTC"))
+        .unwrap_or(text)
+        .trim_end();
+    NAIVE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(naive, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(naive, "%Y-%m-%d")
+                .ok()
+                .map(|date| date.and_time(NaiveTime::MIN))
+        })
+        .map(|value| value.and_utc())
+}
+
+struct Visitor {
+    optional: bool,
+}
+
+impl de::Visitor<'_> for Visitor {
+    type Value = Option<DateTime<Utc>>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an ISO 8601 date-time")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        if self.optional && value.trim().is_empty() {
+            return Ok(None);
+        }
+        parse(value)
+            .map(Some)
+            .ok_or_else(|| E::invalid_value(de::Unexpected::Str(value), &self))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+        DateTime::from_timestamp(value, 0)
+            .map(Some)
+            .ok_or_else(|| E::invalid_value(de::Unexpected::Signed(value), &self))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        i64::try_from(value)
+            .ok()
+            .and_then(|secs| DateTime::from_timestamp(secs, 0))
+            .map(Some)
+            .ok_or_else(|| E::invalid_value(de::Unexpected::Unsigned(value), &self))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+        let secs = value.floor();
+        let nanos = ((value - secs) * 1e9) as u32;
+        Some(secs)
+            .filter(|secs| secs.is_finite() && (i64::MIN as f64..i64::MAX as f64).contains(secs))
+            .and_then(|secs| DateTime::from_timestamp(secs as i64, nanos))
+            .map(Some)
+            .ok_or_else(|| E::invalid_value(de::Unexpected::Float(value), &self))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        if self.optional {
+            Ok(None)
+        } else {
+            Err(E::invalid_type(de::Unexpected::Unit, &self))
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! Lenient [`Uuid`] fields.
+//!
+//! Use with
+//! `#[serde(deserialize_with = "determinishtic::formats::uuid::deserialize")]`,
+//! or [`deserialize_option`] for `Option<Uuid>`. Accepts hyphenated UUIDs
+//! (`67e55044-10b1-426f-9247-bb680e5fe0c8`) in either case, as well as
+//! UUIDs without hyphens, in braces, or as a `urn:uuid:` URN, ignoring
+//! surrounding whitespace.
+
+use std::fmt;
+
+use serde::{Deserializer, de};
+use uuid::Uuid;
+
+/// Deserialize from any of the formats listed in the [module docs](self).
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Uuid, D::Error> {
+    deserializer
+        .deserialize_any(Visitor { optional: false })?
+        .ok_or_else(|| de::Error::custom("expected a UUID"))
+}
+
+/// Deserialize an `Option<Uuid>`, also accepting `null` and empty
+/// strings as `None`.
+///
+/// Combine with `#[serde(default)]` to also accept a missing field.
+pub fn deserialize_option<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Uuid>, D::Error> {
+    deserializer.deserialize_any(Visitor { optional: true })
+}
+
+struct Visitor {
+    optional: bool,
+}
+
+impl de::Visitor<'_> for Visitor {
+    type Value = Option<Uuid>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a UUID")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        let trimmed = value.trim();
+        if self.optional && trimmed.is_empty() {
+            return Ok(None);
+        }
+        Uuid::try_parse(trimmed)
+            .map(Some)
+            .map_err(|_| E::invalid_value(de::Unexpected::Str(value), &self))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        if self.optional {
+            Ok(None)
+        } else {
+            Err(E::invalid_type(de::Unexpected::Unit, &self))
+        }
+    }
+}
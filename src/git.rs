@@ -0,0 +1,438 @@
+//! Tools for inspecting a git repository.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use agent_client_protocol::role::Role;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::redaction::LogRedaction;
+use crate::{Error, ToolSet};
+
+/// Commits listed by `git_log` unless the agent asks for a different number.
+const DEFAULT_LOG_COUNT: usize = 20;
+
+/// The most commits `git_log` lists at once.
+const MAX_LOG_COUNT: usize = 200;
+
+/// Changed files listed by [`GitSummary`] before it is cut off.
+const SUMMARY_CHANGES: usize = 50;
+
+/// Git tools for think blocks that review code or write changelogs.
+///
+/// [`toolset`](Self::toolset) offers the agent `git_status`, `git_log`,
+/// `git_blame`, and `git_diff` for one repository. They only read; the
+/// agent can also commit if you opt in with
+/// [`allow_commit`](Self::allow_commit). Output longer than
+/// [`max_output`](Self::max_output) is cut off.
+///
+/// The tools run the `git` executable, which must be on the `PATH`.
+/// Requires the `git` feature.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let git = GitTools::new(".").toolset();
+///
+/// let notes: ReleaseNotes = d.think()
+///     .git_context(".")
+///     .text("Write release notes for the commits since the last tag")
+///     .tools(&git)
+///     .await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct GitTools {
+    repo: PathBuf,
+    commit: bool,
+    max_output: usize,
+}
+
+/// The state of a repository, as included by
+/// [`ThinkBuilder::git_context`](crate::ThinkBuilder::git_context).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GitSummary {
+    /// The checked out branch, or `None` if `HEAD` is detached.
+    pub branch: Option<String>,
+
+    /// The abbreviated hash and subject of the `HEAD` commit, or `None` if
+    /// there are no commits yet.
+    pub head: Option<String>,
+
+    /// The branch's upstream, if it has one.
+    pub upstream: Option<String>,
+
+    /// Commits on the branch that its upstream doesn't have.
+    pub ahead: usize,
+
+    /// Commits on the upstream that the branch doesn't have.
+    pub behind: usize,
+
+    /// Changed and untracked files, in `git status --short` format.
+    pub changes: Vec<String>,
+
+    /// Whether more files changed than are listed.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct StatusInput {}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct LogInput {
+    /// A revision or range, e.g. `v1.2.0..HEAD`. Defaults to `HEAD`.
+    #[serde(default)]
+    revision: Option<String>,
+
+    /// Only list commits that touch this path.
+    #[serde(default)]
+    path: Option<String>,
+
+    /// How many commits to list (default 20, at most 200).
+    #[serde(default)]
+    max_count: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct BlameInput {
+    /// The file to blame, relative to the repository root.
+    path: String,
+
+    /// The first line to blame, counting from 1.
+    #[serde(default)]
+    start_line: Option<usize>,
+
+    /// The last line to blame.
+    #[serde(default)]
+    end_line: Option<usize>,
+
+    /// Blame the file as of this revision instead of the working tree.
+    #[serde(default)]
+    revision: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct DiffInput {
+    /// A revision or range to diff, e.g. `HEAD~3` or `main...feature`.
+    /// Without one, shows uncommitted changes.
+    #[serde(default)]
+    revision: Option<String>,
+
+    /// Show staged changes rather than unstaged ones. Ignored when a
+    /// revision is given.
+    #[serde(default)]
+    staged: bool,
+
+    /// Only show changes to this path.
+    #[serde(default)]
+    path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct CommitInput {
+    /// The commit message.
+    message: String,
+
+    /// Files to stage before committing, relative to the repository root.
+    /// Already staged changes are committed too.
+    #[serde(default)]
+    paths: Vec<String>,
+}
+
+impl GitTools {
+    /// Read-only tools for the repository at `repo`.
+    ///
+    /// Output is cut off after 50,000 characters by default.
+    pub fn new(repo: impl Into<PathBuf>) -> Self {
+        Self {
+            repo: repo.into(),
+            commit: false,
+            max_output: 50_000,
+        }
+    }
+
+    /// Also offer a `git_commit` tool, which stages the given files and
+    /// commits them.
+    pub fn allow_commit(mut self) -> Self {
+        self.commit = true;
+        self
+    }
+
+    /// Cut off each tool's output after `chars` characters.
+    pub fn max_output(mut self, chars: usize) -> Self {
+        self.max_output = chars;
+        self
+    }
+
+    /// The state of the repository: branch, `HEAD`, upstream, and changed
+    /// files.
+    ///
+    /// Runs `git` synchronously.
+    pub fn summary(&self) -> Result<GitSummary, Error> {
+        summary(&self.repo)
+    }
+
+    /// A tool set containing the git tools, to pass to
+    /// [`ThinkBuilder::tools`](crate::ThinkBuilder::tools).
+    pub fn toolset<R: Role>(self) -> ToolSet<R> {
+        let commit = self.commit;
+        let git = Arc::new(self);
+        let mut tools = ToolSet::default()
+            .tool(
+                "git_status",
+                "Show the current branch and which files have changed, in `git status --short` format.",
+                {
+                    let git = git.clone();
+                    move |_: StatusInput| {
+                        let git = git.clone();
+                        async move {
+                            git.run(vec!["status".into(), "--short".into(), "--branch".into()])
+                                .await
+                        }
+                    }
+                },
+            )
+            .tool(
+                "git_log",
+                "List commits, newest first, one per line: hash, date, author, and subject.",
+                {
+                    let git = git.clone();
+                    move |input: LogInput| {
+                        let git = git.clone();
+                        async move { git.log(input).await }
+                    }
+                },
+            )
+            .tool(
+                "git_blame",
+                "Show who last changed each line of a file, and in which commit.",
+                {
+                    let git = git.clone();
+                    move |input: BlameInput| {
+                        let git = git.clone();
+                        async move { git.blame(input).await }
+                    }
+                },
+            )
+            .tool(
+                "git_diff",
+                "Show changes as a unified diff: uncommitted changes, or those in a revision range.",
+                {
+                    let git = git.clone();
+                    move |input: DiffInput| {
+                        let git = git.clone();
+                        async move { git.diff(input).await }
+                    }
+                },
+            );
+        if commit {
+            tools = tools.tool(
+                "git_commit",
+                "Stage the given files and commit them with a message. Returns the new commit.",
+                move |input: CommitInput| {
+                    let git = git.clone();
+                    async move { git.commit(input).await }
+                },
+            );
+        }
+        tools
+    }
+
+    async fn log(&self, input: LogInput) -> Result<String, agent_client_protocol::Error> {
+        let count = input
+            .max_count
+            .unwrap_or(DEFAULT_LOG_COUNT)
+            .clamp(1, MAX_LOG_COUNT);
+        let mut args = vec![
+            "log".to_string(),
+            "--date=short".to_string(),
+            "--format=%h %ad %an: %s".to_string(),
+            format!("--max-count={count}"),
+        ];
+        if let Some(revision) = input.revision {
+            args.push(revision_arg(revision)?);
+        }
+        args.push("--".to_string());
+        args.extend(input.path);
+        self.run(args).await
+    }
+
+    async fn blame(&self, input: BlameInput) -> Result<String, agent_client_protocol::Error> {
+        let mut args = vec!["blame".to_string(), "--date=short".to_string()];
+        match (input.start_line, input.end_line) {
+            (Some(start), Some(end)) => args.push(format!("-L{start},{end}")),
+            (Some(start), None) => args.push(format!("-L{start},")),
+            (None, Some(end)) => args.push(format!("-L1,{end}")),
+            (None, None) => {}
+        }
+        if let Some(revision) = input.revision {
+            args.push(revision_arg(revision)?);
+        }
+        args.push("--".to_string());
+        args.push(input.path);
+        self.run(args).await
+    }
+
+    async fn diff(&self, input: DiffInput) -> Result<String, agent_client_protocol::Error> {
+        let mut args = vec!["diff".to_string()];
+        match input.revision {
+            Some(revision) => args.push(revision_arg(revision)?),
+            None if input.staged => args.push("--cached".to_string()),
+            None => {}
+        }
+        args.push("--".to_string());
+        args.extend(input.path);
+        self.run(args).await
+    }
+
+    async fn commit(&self, input: CommitInput) -> Result<String, agent_client_protocol::Error> {
+        if !input.paths.is_empty() {
+            let mut args = vec!["add".to_string(), "--".to_string()];
+            args.extend(input.paths);
+            self.run(args).await?;
+        }
+        self.run(vec![
+            "commit".to_string(),
+            "--message".to_string(),
+            input.message,
+        ])
+        .await?;
+        self.run(vec![
+            "log".to_string(),
+            "-1".to_string(),
+            "--format=%h %s".to_string(),
+        ])
+        .await
+    }
+
+    /// Run `git` in the repository, returning its output.
+    async fn run(&self, args: Vec<String>) -> Result<String, agent_client_protocol::Error> {
+        // Arguments carry what the agent sent, such as commit messages
+        match LogRedaction::for_tool_call().text(&args.join(" ")) {
+            Some(args) => debug!(repo = %self.repo.display(), args, "running git"),
+            None => debug!(
+                repo = %self.repo.display(),
+                subcommand = args.first().map(String::as_str),
+                "running git"
+            ),
+        }
+        let output = tokio::process::Command::new("git")
+            .arg("-C")
+            .arg(&self.repo)
+            .args(&args)
+            .output()
+            .await
+            .map_err(|err| {
+                agent_client_protocol::Error::internal_error()
+                    .data(format!("could not run git: {err}"))
+            })?;
+        if !output.status.success() {
+            return Err(agent_client_protocol::Error::invalid_params().data(format!(
+                "git {} failed: {}",
+                args[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(truncate(
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            self.max_output,
+        ))
+    }
+}
+
+/// Check that a revision the agent supplied can't be taken as an option.
+fn revision_arg(revision: String) -> Result<String, agent_client_protocol::Error> {
+    if revision.starts_with('-') {
+        return Err(agent_client_protocol::Error::invalid_params()
+            .data(format!("`{revision}` is not a revision")));
+    }
+    Ok(revision)
+}
+
+fn truncate(mut text: String, max: usize) -> String {
+    if let Some((cut, _)) = text.char_indices().nth(max) {
+        text.truncate(cut);
+        text.push_str("\n[output truncated]\n");
+    }
+    text
+}
+
+/// Summarize the repository at `repo` by running `git status` and `git log`.
+pub(crate) fn summary(repo: &Path) -> Result<GitSummary, Error> {
+    let git = |args: &[&str]| -> Result<String, Error> {
+        let output = std::process::Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args(args)
+            .output()
+            .map_err(|err| Error::Git {
+                message: format!("could not run git: {err}"),
+            })?;
+        if !output.status.success() {
+            return Err(Error::Git {
+                message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+
+    let status = git(&["status", "--porcelain=v1", "--branch"])?;
+    let mut lines = status.lines();
+    let mut summary = GitSummary {
+        branch: None,
+        head: None,
+        upstream: None,
+        ahead: 0,
+        behind: 0,
+        changes: Vec::new(),
+        truncated: false,
+    };
+    if let Some(header) = lines.next().and_then(|line| line.strip_prefix("## ")) {
+        parse_branch_header(header, &mut summary);
+    }
+    for line in lines {
+        if summary.changes.len() == SUMMARY_CHANGES {
+            summary.truncated = true;
+            break;
+        }
+        summary.changes.push(line.to_string());
+    }
+    // Fails when there are no commits yet
+    summary.head = git(&["log", "-1", "--format=%h %s"])
+        .ok()
+        .map(|head| head.trim().to_string())
+        .filter(|head| !head.is_empty());
+    Ok(summary)
+}
+
+/// Parse the `## ...` line of `git status --branch`, such as
+/// `main...origin/main [ahead 1, behind 2]`, `HEAD (no branch)`, or
+/// `No commits yet on main`.
+fn parse_branch_header(header: &str, summary: &mut GitSummary) {
+    if header.starts_with("HEAD (no branch)") {
+        return;
+    }
+    let header = header
+        .strip_prefix("No commits yet on ")
+        .or_else(|| header.strip_prefix("Initial commit on "))
+        .unwrap_or(header);
+    let (names, tracking) = match header.split_once(" [") {
+        Some((names, tracking)) => (names, tracking.trim_end_matches(']')),
+        None => (header, ""),
+    };
+    match names.split_once("...") {
+        Some((branch, upstream)) => {
+            summary.branch = Some(branch.to_string());
+            summary.upstream = Some(upstream.to_string());
+        }
+        None => summary.branch = Some(names.to_string()),
+    }
+    for part in tracking.split(", ") {
+        if let Some(n) = part.strip_prefix("ahead ") {
+            summary.ahead = n.parse().unwrap_or(0);
+        } else if let Some(n) = part.strip_prefix("behind ") {
+            summary.behind = n.parse().unwrap_or(0);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! Named bundles of rules every think block on an instance must follow.
+
+use std::fmt;
+use std::sync::Arc;
+
+use regex_automata::meta::Regex;
+use serde_json::Value;
+use tracing::warn;
+
+use crate::Error;
+
+/// Decides whether a result is acceptable, returning what is wrong with it
+/// if not.
+type Check = Arc<dyn Fn(&Value) -> Result<(), String> + Send + Sync>;
+
+/// A named set of guardrails, applied to every think block run through an
+/// instance.
+///
+/// A profile bundles the rules a class of work must follow, such as
+/// "customer-facing" or "untrusted-input": patterns results must not
+/// contain, checks results must pass, instructions every prompt must
+/// carry, and tools the agent must not use. Apply profiles with
+/// [`DeterminishticBuilder::guardrails`](crate::DeterminishticBuilder::guardrails);
+/// they are fixed when the instance is created, so no think block can
+/// loosen them.
+///
+/// A result that breaks a rule is sent back to the agent to correct, as
+/// one of the wrong shape is; it is never returned,
+/// [delivered](crate::ThinkBuilder::deliver_to), or
+/// [salvaged](crate::PartialResult). If the agent never returns a result
+/// that passes, the think block fails with [`Error::GuardrailViolation`],
+/// naming the profile and rule.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let customer_facing = Guardrails::new("customer-facing")
+///     .preamble("Never promise refunds, discounts, or delivery dates.")
+///     .forbid(r"(?i)\b(refund|discount) guaranteed\b")
+///     .forbid(r"\b\d{4}[ -]?\d{4}[ -]?\d{4}[ -]?\d{4}\b")
+///     .check("no internal links", |result| {
+///         if result.to_string().contains("intranet.") {
+///             return Err("links to the intranet".to_string());
+///         }
+///         Ok(())
+///     })
+///     .deny_tool("Bash");
+///
+/// let d = Determinishtic::builder()
+///     .guardrails(customer_facing)
+///     .connect(agent)
+///     .await?;
+/// ```
+#[derive(Clone)]
+pub struct Guardrails {
+    name: String,
+    /// Instructions added to every prompt.
+    preamble: Option<String>,
+    /// Patterns no string in a result may match, as given and compiled.
+    forbidden: Vec<(String, Regex)>,
+    /// Named checks every result must pass.
+    checks: Vec<(String, Check)>,
+    /// Tools the agent may not use.
+    denied_tools: Vec<String>,
+}
+
+impl Guardrails {
+    /// An empty profile called `name`, which violations are reported under.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            preamble: None,
+            forbidden: Vec::new(),
+            checks: Vec::new(),
+            denied_tools: Vec::new(),
+        }
+    }
+
+    /// The profile's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Add `text` to the [preamble](crate::Preamble) of every think block,
+    /// whichever preamble the instance uses, even
+    /// [none](crate::Preamble::none). Calling this again adds to the text.
+    pub fn preamble(mut self, text: impl fmt::Display) -> Self {
+        let text = text.to_string();
+        self.preamble = Some(match self.preamble.take() {
+            Some(preamble) => format!("{preamble}\n{text}"),
+            None => text,
+        });
+        self
+    }
+
+    /// Reject results with any string, at any depth, matching the regular
+    /// expression `pattern`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pattern` is not a valid regular expression, so a
+    /// mistyped rule can't silently let everything through.
+    pub fn forbid(mut self, pattern: &str) -> Self {
+        let regex = Regex::new(pattern)
+            .unwrap_or_else(|err| panic!("invalid guardrail pattern `{pattern}`: {err}"));
+        self.forbidden.push((pattern.to_string(), regex));
+        self
+    }
+
+    /// Reject results for which `check` returns an error, reported as a
+    /// violation of the rule called `name` with the error as its details.
+    ///
+    /// The check receives the result as the agent returned it, as JSON.
+    pub fn check<F>(mut self, name: impl Into<String>, check: F) -> Self
+    where
+        F: Fn(&Value) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.checks.push((name.into(), Arc::new(check)));
+        self
+    }
+
+    /// Keep the tool named `name` from the agent.
+    ///
+    /// Tools of that name are left out of [`ToolSet`](crate::ToolSet)s, as
+    /// with [`ThinkBuilder::without_tool`](crate::ThinkBuilder::without_tool),
+    /// tools added with [`ThinkBuilder::tool`](crate::ThinkBuilder::tool) or
+    /// [`define_tool`](crate::ThinkBuilder::define_tool) under that name are
+    /// not served, and the agent's permission requests for tool calls
+    /// titled `name` are rejected.
+    ///
+    /// Tools of the agent's own, and those of MCP servers the agent
+    /// connects to itself, are only covered when it asks permission to run
+    /// them; one that runs them without asking can't be stopped from here,
+    /// so configure such agents to ask, or not to offer the tool at all.
+    pub fn deny_tool(mut self, name: impl Into<String>) -> Self {
+        self.denied_tools.push(name.into());
+        self
+    }
+
+    pub(crate) fn preamble_text(&self) -> Option<&str> {
+        self.preamble.as_deref()
+    }
+
+    pub(crate) fn denied_tools(&self) -> impl Iterator<Item = &str> {
+        self.denied_tools.iter().map(String::as_str)
+    }
+
+    /// Whether the agent may use the tool titled `title`.
+    pub(crate) fn denies(&self, title: &str) -> bool {
+        self.denied_tools.iter().any(|denied| denied == title)
+    }
+
+    /// Check a result against every rule, failing on the first it breaks.
+    pub(crate) fn check_result(&self, result: &Value) -> Result<(), Error> {
+        for (pattern, regex) in &self.forbidden {
+            if let Some(location) = find_match(result, regex, &mut String::new()) {
+                warn!(profile = %self.name, pattern, "result violates a guardrail");
+                return Err(self.violation(pattern, format!("{location} matches")));
+            }
+        }
+        for (name, check) in &self.checks {
+            if let Err(details) = check(result) {
+                warn!(profile = %self.name, rule = %name, "result violates a guardrail");
+                return Err(self.violation(name, details));
+            }
+        }
+        Ok(())
+    }
+
+    fn violation(&self, rule: &str, details: String) -> Error {
+        Error::GuardrailViolation {
+            profile: self.name.clone(),
+            rule: rule.to_string(),
+            details,
+        }
+    }
+}
+
+impl fmt::Debug for Guardrails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Guardrails")
+            .field("name", &self.name)
+            .field("preamble", &self.preamble)
+            .field(
+                "forbidden",
+                &self
+                    .forbidden
+                    .iter()
+                    .map(|(pattern, _)| pattern)
+                    .collect::<Vec<_>>(),
+            )
+            .field(
+                "checks",
+                &self.checks.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            )
+            .field("denied_tools", &self.denied_tools)
+            .finish()
+    }
+}
+
+/// Where in `value` a string matches `regex`, described for an error
+/// message; `path` is the JSON pointer to `value`.
+fn find_match(value: &Value, regex: &Regex, path: &mut String) -> Option<String> {
+    match value {
+        Value::String(text) if regex.is_match(text) => Some(if path.is_empty() {
+            "the result".to_string()
+        } else {
+            format!("`{path}`")
+        }),
+        Value::Array(items) => items.iter().enumerate().find_map(|(index, item)| {
+            let len = path.len();
+            path.push_str(&format!("/{index}"));
+            let found = find_match(item, regex, path);
+            path.truncate(len);
+            found
+        }),
+        Value::Object(fields) => fields.iter().find_map(|(key, field)| {
+            let len = path.len();
+            path.push('/');
+            path.push_str(&key.replace('~', "~0").replace('/', "~1"));
+            let found = find_match(field, regex, path);
+            path.truncate(len);
+            found
+        }),
+        _ => None,
+    }
+}
@@ -0,0 +1,257 @@
+//! Think blocks running in the background.
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use agent_client_protocol::schema::{
+    RequestPermissionRequest, SessionNotification, SessionUpdate, StopReason,
+};
+use futures::FutureExt;
+use futures::channel::oneshot;
+use futures::future::{AbortHandle, Abortable, Aborted};
+
+use crate::Error;
+use crate::permissions::PermissionRecord;
+use crate::runtime::Spawner;
+use crate::think::{ThinkObserver, ThinkOutcome};
+
+/// A think block running in the background.
+///
+/// Returned by [`ThinkBuilder::spawn`](crate::ThinkBuilder::spawn). Awaiting
+/// the handle yields the think block's output, just as awaiting the builder
+/// would have. Dropping the handle detaches the think block, which keeps
+/// running to completion; use [`abort`](Self::abort) to stop it.
+pub struct ThinkHandle<T> {
+    result: oneshot::Receiver<TaskResult<T>>,
+    abort: AbortHandle,
+    finished: Arc<AtomicBool>,
+    progress: Arc<ProgressObserver>,
+}
+
+/// Aborts a think block without owning its [`ThinkHandle`].
+pub(crate) struct TaskAbortHandle {
+    abort: AbortHandle,
+    finished: Arc<AtomicBool>,
+}
+
+impl TaskAbortHandle {
+    pub(crate) fn abort(&self) {
+        self.abort.abort();
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Acquire)
+    }
+}
+
+/// How the spawned task ended: with the think block's result, aborted, or
+/// with a panic.
+type TaskResult<T> = Result<Result<Result<ThinkOutcome<T>, Error>, Aborted>, Box<dyn Any + Send>>;
+
+/// A snapshot of how far a background think block has got.
+///
+/// Returned by [`ThinkHandle::progress`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThinkProgress {
+    /// Time since the think block was spawned.
+    pub elapsed: Duration,
+
+    /// Prompts sent to the agent, including nudges and continuations.
+    pub prompts: usize,
+
+    /// Session notifications received from the agent.
+    pub updates: usize,
+
+    /// Tool calls the agent has reported starting.
+    pub tool_calls: usize,
+
+    /// Permission requests the agent has made.
+    pub permission_requests: usize,
+
+    /// Whether the session has stopped.
+    pub stopped: bool,
+}
+
+impl<T> ThinkHandle<T>
+where
+    T: Send + 'static,
+{
+    pub(crate) fn spawn(
+        spawner: &dyn Spawner,
+        future: impl Future<Output = Result<ThinkOutcome<T>, Error>> + Send + 'static,
+        progress: Arc<ProgressObserver>,
+    ) -> Self {
+        let (abort, registration) = AbortHandle::new_pair();
+        let (tx, result) = oneshot::channel();
+        let finished = Arc::new(AtomicBool::new(false));
+        let done = finished.clone();
+        spawner.spawn(Box::pin(async move {
+            let result = AssertUnwindSafe(Abortable::new(future, registration))
+                .catch_unwind()
+                .await;
+            done.store(true, Ordering::Release);
+            // Nobody is waiting if the handle was dropped
+            let _ = tx.send(result);
+        }));
+        Self {
+            result,
+            abort,
+            finished,
+            progress,
+        }
+    }
+
+    /// Stop the think block.
+    ///
+    /// Awaiting the handle afterwards returns [`Error::Cancelled`], unless the
+    /// think block had already finished.
+    pub fn abort(&self) {
+        self.abort.abort();
+    }
+
+    /// A handle that can abort the think block after this one is consumed.
+    pub(crate) fn abort_handle(&self) -> TaskAbortHandle {
+        TaskAbortHandle {
+            abort: self.abort.clone(),
+            finished: self.finished.clone(),
+        }
+    }
+
+    /// Whether the think block has finished, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Acquire)
+    }
+
+    /// How far the think block has got.
+    pub fn progress(&self) -> ThinkProgress {
+        self.progress.snapshot()
+    }
+
+    /// Wait for the think block, returning its full [`ThinkOutcome`].
+    pub async fn outcome(self) -> Result<ThinkOutcome<T>, Error> {
+        join(self.result.await)
+    }
+}
+
+impl<T> Future for ThinkHandle<T> {
+    type Output = Result<T, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.result)
+            .poll(cx)
+            .map(|result| join(result).map(|outcome| outcome.output))
+    }
+}
+
+/// Flatten the result of the spawned task.
+fn join<T>(result: Result<TaskResult<T>, oneshot::Canceled>) -> Result<ThinkOutcome<T>, Error> {
+    match result {
+        Ok(Ok(Ok(result))) => result,
+        Ok(Err(panic)) => std::panic::resume_unwind(panic),
+        // Aborted, or dropped by the executor before it finished
+        Ok(Ok(Err(Aborted))) | Err(oneshot::Canceled) => Err(Error::Cancelled),
+    }
+}
+
+/// Counts what happens in a background think block, forwarding everything
+/// to the observer configured on the [`Determinishtic`](crate::Determinishtic)
+/// instance, if any.
+pub(crate) struct ProgressObserver {
+    inner: Option<Arc<dyn ThinkObserver>>,
+    started: Instant,
+    prompts: AtomicUsize,
+    updates: AtomicUsize,
+    tool_calls: AtomicUsize,
+    permission_requests: AtomicUsize,
+    stopped: AtomicBool,
+}
+
+impl ProgressObserver {
+    pub(crate) fn new(inner: Option<Arc<dyn ThinkObserver>>) -> Self {
+        Self {
+            inner,
+            started: Instant::now(),
+            prompts: AtomicUsize::new(0),
+            updates: AtomicUsize::new(0),
+            tool_calls: AtomicUsize::new(0),
+            permission_requests: AtomicUsize::new(0),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    fn snapshot(&self) -> ThinkProgress {
+        ThinkProgress {
+            elapsed: self.started.elapsed(),
+            prompts: self.prompts.load(Ordering::Relaxed),
+            updates: self.updates.load(Ordering::Relaxed),
+            tool_calls: self.tool_calls.load(Ordering::Relaxed),
+            permission_requests: self.permission_requests.load(Ordering::Relaxed),
+            stopped: self.stopped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl ThinkObserver for ProgressObserver {
+    fn on_start(&self, labels: &BTreeMap<String, String>) {
+        if let Some(inner) = &self.inner {
+            inner.on_start(labels);
+        }
+    }
+
+    fn on_prompt(&self, prompt: &str) {
+        self.prompts.fetch_add(1, Ordering::Relaxed);
+        self.stopped.store(false, Ordering::Relaxed);
+        if let Some(inner) = &self.inner {
+            inner.on_prompt(prompt);
+        }
+    }
+
+    fn on_notification(&self, notification: &SessionNotification) {
+        self.updates.fetch_add(1, Ordering::Relaxed);
+        if let SessionUpdate::ToolCall(_) = notification.update {
+            self.tool_calls.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(inner) = &self.inner {
+            inner.on_notification(notification);
+        }
+    }
+
+    fn on_reasoning(&self, text: &str) {
+        if let Some(inner) = &self.inner {
+            inner.on_reasoning(text);
+        }
+    }
+
+    fn on_permission_request(&self, request: &RequestPermissionRequest) {
+        self.permission_requests.fetch_add(1, Ordering::Relaxed);
+        if let Some(inner) = &self.inner {
+            inner.on_permission_request(request);
+        }
+    }
+
+    fn on_permission_decision(&self, record: &PermissionRecord) {
+        if let Some(inner) = &self.inner {
+            inner.on_permission_decision(record);
+        }
+    }
+
+    fn on_stop(&self, reason: &StopReason) {
+        self.stopped.store(true, Ordering::Relaxed);
+        if let Some(inner) = &self.inner {
+            inner.on_stop(reason);
+        }
+    }
+
+    fn on_finish(&self, error: Option<&Error>) {
+        if let Some(inner) = &self.inner {
+            inner.on_finish(error);
+        }
+    }
+}
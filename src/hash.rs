@@ -0,0 +1,30 @@
+//! A hash that stays the same across Rust releases.
+
+/// FNV-1a over `bytes`.
+///
+/// Fingerprints, experiment buckets, and redacted log values are compared
+/// across processes and builds, so they can't use
+/// `std::hash::DefaultHasher`, whose output may change with any Rust release.
+pub(crate) fn stable_hash(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    const OFFSET: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    let mut hash = OFFSET;
+    for byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(PRIME);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_fnv_1a() {
+        assert_eq!(stable_hash("".bytes()), 0xcbf29ce484222325);
+        assert_eq!(stable_hash("a".bytes()), 0xaf63dc4c8601ec8c);
+        assert_eq!(stable_hash("foobar".bytes()), 0x85944171f73967e8);
+    }
+}
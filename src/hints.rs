@@ -0,0 +1,270 @@
+//! Prompt-facing hints attached to output fields.
+
+use regex_automata::meta::Regex;
+use schemars::JsonSchema;
+use serde_json::{Map, Value};
+use tracing::warn;
+
+/// An output type with per-field hints for the agent: examples of good
+/// values and constraints on them.
+///
+/// Derive it with `#[derive(ThinkOutput)]` (requires the `derive` feature)
+/// and annotate fields with `#[think(example = "...", constraint = "...")]`,
+/// then call [`ThinkBuilder::output_hints`](crate::ThinkBuilder::output_hints)
+/// to fold the hints into the schema the agent sees. Writing the guidance
+/// once, next to the field, saves repeating it in prose at every call site.
+///
+/// Fields can also carry [`Rule`]s, such as `#[think(max_length = 200)]`.
+/// These are added to the schema too, and are enforced: a result that
+/// breaks one is rejected and the agent is asked to fix it.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[derive(Deserialize, JsonSchema, ThinkOutput)]
+/// struct Review {
+///     /// What the change does.
+///     #[think(constraint = "1-3 sentences")]
+///     summary: String,
+///     #[think(example = "\"low\"", example = "\"high\"")]
+///     risk: Risk,
+///     #[think(non_empty, max_length = 5)]
+///     findings: Vec<Finding>,
+///     #[think(min = 0, max = 10)]
+///     score: u8,
+/// }
+///
+/// let review: Review = d.think()
+///     .text("Review this diff")
+///     .display(&diff)
+///     .output_hints()
+///     .describe_output()
+///     .await?;
+/// ```
+pub trait ThinkOutput: JsonSchema {
+    /// The hints for each annotated field, in declaration order.
+    fn field_hints() -> Vec<FieldHint>;
+}
+
+/// Hints for one field of a [`ThinkOutput`] type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldHint {
+    /// The field's name in the JSON schema.
+    pub field: &'static str,
+
+    /// Example values. Each is parsed as JSON if possible, so `"42"` is the
+    /// number 42 unless the field is a string; otherwise it is used as a
+    /// string.
+    pub examples: &'static [&'static str],
+
+    /// What a good value looks like, e.g. `1-3 sentences`.
+    pub constraint: Option<&'static str>,
+
+    /// Rules the value must follow.
+    pub rules: &'static [Rule],
+}
+
+/// A rule an output field must follow, checked when the agent returns a
+/// result.
+///
+/// Declared with `#[think(...)]` field attributes when deriving
+/// [`ThinkOutput`]; the attribute for each rule is given below.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum Rule {
+    /// `min_length = n`: a string has at least `n` characters, or an array
+    /// at least `n` items.
+    MinLength(usize),
+
+    /// `max_length = n`: a string has at most `n` characters, or an array
+    /// at most `n` items.
+    MaxLength(usize),
+
+    /// `non_empty`: a string or array is not empty.
+    NonEmpty,
+
+    /// `pattern = "..."`: a string matches the regular expression somewhere;
+    /// anchor it with `^` and `$` to match the whole string.
+    Pattern(&'static str),
+
+    /// `min = x`: a number is at least `x`.
+    Minimum(f64),
+
+    /// `max = x`: a number is at most `x`.
+    Maximum(f64),
+}
+
+/// Fold hints into an object schema: examples become the field's
+/// `examples`, the constraint is appended to its description, and rules
+/// become the matching JSON schema keywords.
+///
+/// Hints for fields the schema doesn't have are ignored.
+pub(crate) fn apply(schema: &mut Value, hints: &[FieldHint]) {
+    let Some(properties) = schema.get_mut("properties").and_then(Value::as_object_mut) else {
+        return;
+    };
+    for hint in hints {
+        let Some(Value::Object(property)) = properties.get_mut(hint.field) else {
+            continue;
+        };
+        let is_string = property.get("type").and_then(Value::as_str) == Some("string");
+        if !hint.examples.is_empty() {
+            let examples = hint
+                .examples
+                .iter()
+                .map(|example| match serde_json::from_str(example) {
+                    Ok(value) if !is_string || matches!(value, Value::String(_)) => value,
+                    _ => Value::String(example.to_string()),
+                })
+                .collect();
+            property.insert("examples".to_string(), Value::Array(examples));
+        }
+        if let Some(constraint) = hint.constraint {
+            let description = match property.get("description").and_then(Value::as_str) {
+                Some(description) => {
+                    format!("{} Constraint: {constraint}.", description.trim_end())
+                }
+                None => format!("Constraint: {constraint}."),
+            };
+            property.insert("description".to_string(), Value::String(description));
+        }
+        for rule in hint.rules {
+            rule.annotate(property);
+        }
+    }
+}
+
+impl Rule {
+    /// Add the JSON schema keyword expressing this rule.
+    fn annotate(&self, property: &mut Map<String, Value>) {
+        let is_array = has_type(property, "array");
+        let (min_key, max_key) = if is_array {
+            ("minItems", "maxItems")
+        } else {
+            ("minLength", "maxLength")
+        };
+        let (key, value) = match *self {
+            Rule::MinLength(n) => (min_key, Value::from(n)),
+            Rule::MaxLength(n) => (max_key, Value::from(n)),
+            Rule::NonEmpty => {
+                if property.get(min_key).and_then(Value::as_u64).unwrap_or(0) > 0 {
+                    return;
+                }
+                (min_key, Value::from(1))
+            }
+            Rule::Pattern(pattern) => ("pattern", Value::from(pattern)),
+            Rule::Minimum(x) => ("minimum", Value::from(x)),
+            Rule::Maximum(x) => ("maximum", Value::from(x)),
+        };
+        property.insert(key.to_string(), value);
+    }
+
+    /// Why `value` breaks this rule, if it does.
+    ///
+    /// Values of a type the rule doesn't apply to pass; deserialization
+    /// has already checked the type.
+    fn check(&self, field: &str, value: &Value, regex: Option<&Regex>) -> Option<String> {
+        let length = match value {
+            Value::String(s) => Some((s.chars().count(), "characters")),
+            Value::Array(items) => Some((items.len(), "items")),
+            _ => None,
+        };
+        match *self {
+            Rule::MinLength(n) => {
+                let (len, unit) = length?;
+                (len < n).then(|| format!("`{field}` must have at least {n} {unit} (it has {len})"))
+            }
+            Rule::MaxLength(n) => {
+                let (len, unit) = length?;
+                (len > n).then(|| format!("`{field}` must have at most {n} {unit} (it has {len})"))
+            }
+            Rule::NonEmpty => {
+                let (len, _) = length?;
+                (len == 0).then(|| format!("`{field}` must not be empty"))
+            }
+            Rule::Pattern(pattern) => {
+                let text = value.as_str()?;
+                (!regex?.is_match(text))
+                    .then(|| format!("`{field}` must match the pattern `{pattern}`"))
+            }
+            Rule::Minimum(min) => {
+                let x = value.as_f64()?;
+                (x < min).then(|| format!("`{field}` must be at least {min} (it is {value})"))
+            }
+            Rule::Maximum(max) => {
+                let x = value.as_f64()?;
+                (x > max).then(|| format!("`{field}` must be at most {max} (it is {value})"))
+            }
+        }
+    }
+}
+
+fn has_type(property: &Map<String, Value>, ty: &str) -> bool {
+    match property.get("type") {
+        Some(Value::String(t)) => t == ty,
+        Some(Value::Array(types)) => types.iter().any(|t| t == ty),
+        _ => false,
+    }
+}
+
+/// The [`Rule`]s of an output type, ready to check results against.
+#[derive(Clone, Default)]
+pub(crate) struct Rules {
+    fields: Vec<FieldRules>,
+}
+
+#[derive(Clone)]
+struct FieldRules {
+    field: &'static str,
+    rules: Vec<(Rule, Option<Regex>)>,
+}
+
+impl Rules {
+    /// Collect the rules from a type's hints, compiling any patterns.
+    ///
+    /// A pattern that doesn't compile is logged and skipped.
+    pub(crate) fn new(hints: &[FieldHint]) -> Self {
+        let fields = hints
+            .iter()
+            .filter(|hint| !hint.rules.is_empty())
+            .map(|hint| FieldRules {
+                field: hint.field,
+                rules: hint
+                    .rules
+                    .iter()
+                    .filter_map(|&rule| match rule {
+                        Rule::Pattern(pattern) => match Regex::new(pattern) {
+                            Ok(regex) => Some((rule, Some(regex))),
+                            Err(err) => {
+                                warn!(field = hint.field, pattern, %err, "ignoring invalid pattern");
+                                None
+                            }
+                        },
+                        _ => Some((rule, None)),
+                    })
+                    .collect(),
+            })
+            .collect();
+        Self { fields }
+    }
+
+    /// Check a result, describing every rule it breaks.
+    pub(crate) fn check(&self, result: &Value) -> Result<(), String> {
+        let violations: Vec<String> =
+            self.fields
+                .iter()
+                .filter_map(|field| Some((field, result.get(field.field)?)))
+                .filter(|(_, value)| !value.is_null())
+                .flat_map(|(field, value)| {
+                    field.rules.iter().filter_map(move |(rule, regex)| {
+                        rule.check(field.field, value, regex.as_ref())
+                    })
+                })
+                .collect();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations.join("; "))
+        }
+    }
+}
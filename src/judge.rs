@@ -0,0 +1,125 @@
+//! Checking a think block's results with a judge before accepting them.
+
+use agent_client_protocol::role::{HasPeer, Role};
+use agent_client_protocol::{Agent, BoxFuture, NullRun, RunWithConnectionTo};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::think::RawSessionFn;
+use crate::{Error, ThinkBuilder, ThinkOutcome};
+
+/// How many times a judge may reject results, unless configured.
+const DEFAULT_RETRIES: u32 = 2;
+
+/// A judge's decision on a think block's result, returned by the judge
+/// given to [`ThinkBuilder::judged_by`].
+///
+/// Use it as the output of the judge's own think block, or build one with
+/// [`accept`](Self::accept) and [`reject`](Self::reject) in code.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Judgement {
+    /// Whether the result is good enough to keep.
+    pub accepted: bool,
+
+    /// What is wrong with the result and how to fix it. This is shown to
+    /// the agent when the result is rejected.
+    pub critique: String,
+}
+
+impl Judgement {
+    /// Keep the result.
+    pub fn accept() -> Self {
+        Self {
+            accepted: true,
+            critique: String::new(),
+        }
+    }
+
+    /// Send the result back to the agent with `critique`.
+    pub fn reject(critique: impl Into<String>) -> Self {
+        Self {
+            accepted: false,
+            critique: critique.into(),
+        }
+    }
+}
+
+/// A think block whose results are checked by a judge before they are
+/// accepted, created with [`ThinkBuilder::judged_by`].
+///
+/// Await it for the output, as you would the builder, or call
+/// [`outcome`](Self::outcome).
+pub struct Judged<'bound, Output, R: Role = Agent, Run: RunWithConnectionTo<R> = NullRun>
+where
+    R: HasPeer<Agent>,
+{
+    think: ThinkBuilder<'bound, Output, R, Run>,
+    judge: Judge<'bound, Output>,
+}
+
+/// Decides whether a result is good enough.
+type Check<'bound, Output> =
+    Box<dyn Fn(&Output) -> BoxFuture<'bound, Result<Judgement, Error>> + Send + Sync + 'bound>;
+
+/// The judge a think block's results are checked with.
+pub(crate) struct Judge<'bound, Output> {
+    pub(crate) check: Check<'bound, Output>,
+    /// How many results may be sent back before the think block fails.
+    pub(crate) retries: u32,
+}
+
+impl<'bound, Output, R: Role, Run: RunWithConnectionTo<R>> Judged<'bound, Output, R, Run>
+where
+    R: HasPeer<Agent>,
+    Output: Send + DeserializeOwned + 'static,
+    Run: Send,
+{
+    pub(crate) fn new<J, F>(think: ThinkBuilder<'bound, Output, R, Run>, judge: J) -> Self
+    where
+        J: Fn(&Output) -> F + Send + Sync + 'bound,
+        F: IntoFuture<Output = Result<Judgement, Error>>,
+        F::IntoFuture: Send + 'bound,
+    {
+        Self {
+            think,
+            judge: Judge {
+                check: Box::new(move |output| Box::pin(judge(output).into_future())),
+                retries: DEFAULT_RETRIES,
+            },
+        }
+    }
+
+    /// Let the judge send results back at most `n` times before the think
+    /// block fails with [`Error::JudgeRejected`]. The default is 2.
+    pub fn retries(mut self, n: u32) -> Self {
+        self.judge.retries = n;
+        self
+    }
+
+    /// Run the think block, returning the accepted output along with a
+    /// record of how it was produced, as
+    /// [`ThinkBuilder::outcome`](ThinkBuilder::outcome) does. Results the
+    /// judge sent back count towards
+    /// [`Provenance::retries`](crate::Provenance::retries).
+    pub fn outcome(self) -> BoxFuture<'bound, Result<ThinkOutcome<Output>, Error>> {
+        let outcome = self.think.run(None::<RawSessionFn<R>>, Some(self.judge));
+        Box::pin(async move { outcome.await.map_err(Error::from) })
+    }
+}
+
+impl<'bound, Output, R: Role, Run: RunWithConnectionTo<R>> IntoFuture
+    for Judged<'bound, Output, R, Run>
+where
+    R: HasPeer<Agent>,
+    Output: Send + DeserializeOwned + 'static,
+    Run: Send,
+{
+    type Output = Result<Output, Error>;
+
+    type IntoFuture = BoxFuture<'bound, Result<Output, Error>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move { Ok(self.outcome().await?.output) })
+    }
+}
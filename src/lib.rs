@@ -20,10 +20,142 @@
 //!     .await?;
 //! ```
 
+pub mod bench;
+mod budget;
+mod capabilities;
+mod checkpoint;
+mod client;
+mod completion;
+mod confidence;
+mod consistency;
+mod cost;
+pub mod debate;
 mod determinishtic;
+mod edits;
 mod error;
+mod eval;
+mod examples;
+pub mod experiments;
+#[cfg(any(feature = "chrono", feature = "uuid"))]
+pub mod formats;
+#[cfg(feature = "git")]
+mod git;
+mod guardrails;
+mod handle;
+mod hash;
+mod hints;
+mod judge;
+mod lint;
+mod manifest;
+mod mcp;
+mod memory;
+#[cfg(feature = "tui")]
+mod monitor;
+pub mod optimize;
+mod output;
+mod partial;
+mod patch;
+mod permissions;
+mod pipeline;
+mod plan;
+mod preamble;
+#[cfg(feature = "tokio")]
+mod process;
+mod prompts;
+mod provenance;
+mod redaction;
+mod resources;
+mod retry;
+mod runtime;
+mod sampling;
+mod scheduler;
+mod schema;
+mod scope;
+mod search;
+mod security;
+#[cfg(feature = "sidecar")]
+mod sidecar;
+mod sink;
+#[cfg(feature = "sqlx")]
+mod sql;
+mod strictness;
+#[cfg(feature = "testing")]
+pub mod testing;
 mod think;
+mod tool_gate;
+mod tool_output;
+mod toolset;
+mod transcripts;
+mod usage;
+#[cfg(feature = "wire-debug")]
+mod wire;
 
-pub use determinishtic::Determinishtic;
+pub use budget::Priority;
+pub use capabilities::{Capabilities, Mode, Model};
+pub use checkpoint::CheckpointDecision;
+pub use client::TerminalHandler;
+pub use completion::Completion;
+pub use confidence::{Confidence, Confident};
+pub use consistency::{Consensus, Sample, SelfConsistency};
+pub use cost::{CostEstimate, ModelPrice, Pricing};
+pub use determinishtic::{Determinishtic, DeterminishticBuilder};
+pub use edits::{CodeEdit, CodeEdits};
 pub use error::Error;
-pub use think::{ThinkBuilder, ThinkObserver};
+pub use eval::{Comparison, CriterionScore, Preference, Score};
+pub use examples::{Example, ExamplePool, TaggedExamples};
+#[cfg(feature = "git")]
+pub use git::{GitSummary, GitTools};
+pub use guardrails::Guardrails;
+pub use handle::{ThinkHandle, ThinkProgress};
+pub use hints::{FieldHint, Rule, ThinkOutput};
+pub use judge::{Judged, Judgement};
+pub use lint::LintWarning;
+pub use manifest::{ManifestEntry, ManifestSink, RunManifest};
+pub use memory::{InMemoryStore, Memory, Recollection};
+#[cfg(feature = "tui")]
+pub use monitor::Monitor;
+pub use output::BinaryOutput;
+pub use partial::PartialResult;
+pub use patch::{PatchConflict, PatchResult, PatchTool, PatchedFile};
+pub use permissions::{DecidedBy, Decision, PermissionRecord, Permissions};
+pub use pipeline::{ThinkMap, ThinkStreamExt};
+pub use plan::Plan;
+pub use preamble::Preamble;
+#[cfg(feature = "tokio")]
+pub use process::{AgentCommand, AgentEvent};
+pub use prompts::{PromptArgument, PromptTemplate};
+pub use provenance::Provenance;
+pub use redaction::LogRedaction;
+pub use resources::{Resource, ResourceContents, ResourceProvider};
+pub use retry::Attempt;
+pub use runtime::Spawner;
+#[cfg(feature = "tokio")]
+pub use runtime::TokioSpawner;
+pub use sampling::Sampler;
+pub use scheduler::{Scheduler, SchedulerMetrics};
+pub use scope::{OnFailure, ThinkScope};
+pub use search::{Searcher, Snippet};
+pub use security::SecurityProfile;
+#[cfg(feature = "sidecar")]
+pub use sidecar::Sidecar;
+pub use sink::{Delivery, JsonLinesSink, ResultSink};
+#[cfg(feature = "sqlx")]
+pub use sql::SqlTools;
+pub use strictness::ArgumentStrictness;
+pub use think::{ThinkBuilder, ThinkObserver, ThinkOutcome};
+pub use tool_gate::ToolGate;
+pub use tool_output::ToolOutput;
+pub use toolset::ToolSet;
+pub use transcripts::{
+    FileTranscriptStore, InMemoryTranscriptStore, StoredTranscript, TranscriptEntry, TranscriptStore,
+};
+pub use usage::UsageStats;
+#[cfg(feature = "wire-debug")]
+pub use wire::WireCapture;
+
+#[cfg(feature = "conductor")]
+pub use agent_client_protocol_conductor::McpBridgeMode;
+
+/// Derive [`ThinkOutput`] from `#[think(...)]` field attributes.
+#[cfg(feature = "derive")]
+pub use determinishtic_macros::ThinkOutput;
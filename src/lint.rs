@@ -0,0 +1,188 @@
+//! Static checks on a think block's prompt and tools.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use crate::prompts;
+
+/// A likely mistake in a think block, found by [`ThinkBuilder::lint`](crate::ThinkBuilder::lint).
+///
+/// None of these stop a think block from running, but each tends to make
+/// agents behave worse than the author intended.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LintWarning {
+    /// A tool was registered but the prompt never mentions it, so the agent
+    /// has no hint of when to use it.
+    UnreferencedTool {
+        /// The tool's name.
+        name: String,
+    },
+
+    /// The prompt mentions a tool by name in plain text without embedding a
+    /// reference to it, which agents pick up less reliably.
+    UnmarkedToolName {
+        /// The tool's name.
+        name: String,
+    },
+
+    /// The prompt has nothing in it beyond the preamble.
+    EmptyPrompt,
+
+    /// Two tools share a name, so one of them can never be called.
+    DuplicateTool {
+        /// The shared name.
+        name: String,
+    },
+
+    /// The output schema is an object with no fields, so the agent has
+    /// nothing to fill in.
+    EmptyOutputSchema,
+
+    /// A template has a placeholder with no value
+    /// [bound](crate::ThinkBuilder::bind) to it, so the think block will
+    /// fail.
+    UnboundPlaceholder {
+        /// The placeholder's name.
+        name: String,
+    },
+
+    /// A template has a brace that is neither part of a placeholder nor
+    /// doubled, so the think block will fail.
+    MalformedTemplate {
+        /// What is wrong with it.
+        message: String,
+    },
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LintWarning::UnreferencedTool { name } => {
+                write!(
+                    f,
+                    "tool `{name}` is registered but never mentioned in the prompt"
+                )
+            }
+            LintWarning::UnmarkedToolName { name } => write!(
+                f,
+                "tool `{name}` is named in the prompt text; use `.tool()` to embed a reference instead"
+            ),
+            LintWarning::EmptyPrompt => write!(f, "the prompt is empty apart from the preamble"),
+            LintWarning::DuplicateTool { name } => {
+                write!(f, "more than one tool is named `{name}`")
+            }
+            LintWarning::EmptyOutputSchema => write!(f, "the output schema has no fields"),
+            LintWarning::UnboundPlaceholder { name } => {
+                write!(f, "template placeholder `{{{name}}}` has no bound value")
+            }
+            LintWarning::MalformedTemplate { message } => {
+                write!(f, "malformed template: {message}")
+            }
+        }
+    }
+}
+
+/// What the lints look at, gathered from a think block.
+pub(crate) struct LintInput<'a> {
+    /// The text of every segment, and the labels of context sections.
+    pub(crate) texts: Vec<&'a str>,
+    /// Tools referenced with `<mcp_tool>` markup.
+    pub(crate) references: Vec<&'a str>,
+    /// Tools registered directly on the think block, in registration order.
+    pub(crate) tools: Vec<&'a str>,
+    /// Tools from tool sets, which are meant to be available without mention.
+    pub(crate) toolset: Vec<&'a str>,
+    pub(crate) output_schema: &'a serde_json::Value,
+    /// f-string style templates in the prompt.
+    pub(crate) templates: Vec<&'a str>,
+    /// Values bound to template placeholders.
+    pub(crate) bindings: &'a BTreeMap<String, String>,
+}
+
+impl LintInput<'_> {
+    pub(crate) fn check(&self) -> Vec<LintWarning> {
+        let mut warnings = Vec::new();
+
+        if self.texts.iter().all(|t| t.trim().is_empty()) && self.references.is_empty() {
+            warnings.push(LintWarning::EmptyPrompt);
+        }
+
+        let mut seen = HashSet::new();
+        let mut duplicates = HashSet::new();
+        for &name in self
+            .tools
+            .iter()
+            .chain(&self.toolset)
+            .chain(&["return_result"])
+        {
+            if !seen.insert(name) && duplicates.insert(name) {
+                warnings.push(LintWarning::DuplicateTool {
+                    name: name.to_string(),
+                });
+            }
+        }
+
+        let mut checked = HashSet::new();
+        for &name in self.tools.iter().chain(&self.toolset) {
+            if !checked.insert(name) || self.references.contains(&name) {
+                continue;
+            }
+            let named = self.texts.iter().any(|text| mentions(text, name));
+            if named {
+                warnings.push(LintWarning::UnmarkedToolName {
+                    name: name.to_string(),
+                });
+            } else if self.tools.contains(&name) {
+                warnings.push(LintWarning::UnreferencedTool {
+                    name: name.to_string(),
+                });
+            }
+        }
+
+        if is_empty_object(self.output_schema) {
+            warnings.push(LintWarning::EmptyOutputSchema);
+        }
+
+        let mut unbound = Vec::new();
+        for template in &self.templates {
+            let filled = prompts::fill(template, |name| {
+                if !self.bindings.contains_key(name) && !unbound.iter().any(|n| n == name) {
+                    unbound.push(name.to_string());
+                }
+                Ok(String::new())
+            });
+            if let Err(message) = filled {
+                warnings.push(LintWarning::MalformedTemplate { message });
+            }
+        }
+        warnings.extend(
+            unbound
+                .into_iter()
+                .map(|name| LintWarning::UnboundPlaceholder { name }),
+        );
+
+        warnings
+    }
+}
+
+/// Whether `text` contains `name` as a whole word.
+fn mentions(text: &str, name: &str) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    text.match_indices(name).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + name.len()..].chars().next();
+        !before.is_some_and(is_word) && !after.is_some_and(is_word)
+    })
+}
+
+/// Whether a schema describes an object that can't hold anything.
+fn is_empty_object(schema: &serde_json::Value) -> bool {
+    schema.get("type").and_then(|t| t.as_str()) == Some("object")
+        && schema
+            .get("properties")
+            .and_then(|p| p.as_object())
+            .is_none_or(|p| p.is_empty())
+        && schema
+            .get("additionalProperties")
+            .is_none_or(|a| a == &serde_json::Value::Bool(false))
+}
@@ -0,0 +1,190 @@
+//! Tracking which items of a batch have completed, so it can be resumed.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use agent_client_protocol::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::runtime::blocking;
+use crate::{Delivery, Error, ResultSink};
+
+/// A record of the items in a batch job that have completed, kept in a
+/// JSON Lines file so a restarted job can skip them.
+///
+/// Each entry stores an item's key, the [fingerprint] of the think block
+/// that processed it, and the result. An item counts as completed only if
+/// its fingerprint matches, so changing the prompt, tools, or output type
+/// reruns it. Pass a manifest to [`ThinkMap::resume_from`] to have a
+/// pipeline skip completed items, or use [`sink`](Self::sink) and
+/// [`completed`](Self::completed) directly in a loop of your own.
+///
+/// Clones share the same entries and file.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let manifest = RunManifest::open("summaries.manifest.jsonl")?;
+/// println!("{} documents already done", manifest.len());
+///
+/// let mut summaries = futures::stream::iter(docs)
+///     .think_map(&d, |doc: Doc, think| think.text("Summarize").display(&doc.body))
+///     .resume_from(&manifest, |doc| doc.path.display().to_string());
+/// ```
+///
+/// [fingerprint]: crate::ThinkBuilder::fingerprint
+/// [`ThinkMap::resume_from`]: crate::ThinkMap::resume_from
+#[derive(Debug, Clone)]
+pub struct RunManifest {
+    entries: Arc<Mutex<HashMap<String, ManifestEntry>>>,
+    file: Arc<Mutex<File>>,
+}
+
+/// A completed item in a [`RunManifest`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// The item's key.
+    pub key: String,
+
+    /// The fingerprint of the think block that processed the item.
+    pub fingerprint: String,
+
+    /// The result, as the agent returned it.
+    pub output: serde_json::Value,
+
+    /// When the result was accepted.
+    pub completed_at: SystemTime,
+}
+
+impl RunManifest {
+    /// Open the manifest at `path`, loading any entries already in it, or
+    /// create it if it doesn't exist.
+    ///
+    /// If an item was recorded more than once, the last entry wins. Lines
+    /// that can't be read, such as one cut short by a crash, are skipped, so
+    /// those items run again.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let mut entries = HashMap::new();
+        match File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line.map_err(Error::sink)?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    match serde_json::from_str::<ManifestEntry>(&line) {
+                        Ok(entry) => {
+                            entries.insert(entry.key.clone(), entry);
+                        }
+                        // Most likely cut short by a crash; the item just reruns
+                        Err(err) => warn!(%err, "ignoring unreadable manifest entry"),
+                    }
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(Error::sink(err)),
+        }
+        debug!(path = %path.display(), completed = entries.len(), "opened run manifest");
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(Error::sink)?;
+        // Start on a fresh line if the last one was cut short
+        if !ends_with_newline(&mut file).map_err(Error::sink)? {
+            file.write_all(b"\n").map_err(Error::sink)?;
+        }
+        Ok(Self {
+            entries: Arc::new(Mutex::new(entries)),
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// The stored result for `key`, if the item completed with a think block
+    /// whose fingerprint is `fingerprint`.
+    pub fn completed(&self, key: &str, fingerprint: &str) -> Option<serde_json::Value> {
+        self.get(key)
+            .filter(|entry| entry.fingerprint == fingerprint)
+            .map(|entry| entry.output)
+    }
+
+    /// The entry for `key`, whatever its fingerprint.
+    pub fn get(&self, key: &str) -> Option<ManifestEntry> {
+        self.lock().get(key).cloned()
+    }
+
+    /// How many items have completed.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Whether no items have completed.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// A [`ResultSink`] that records a think block's result under `key`.
+    pub fn sink(&self, key: impl Into<String>) -> ManifestSink {
+        ManifestSink {
+            manifest: self.clone(),
+            key: key.into(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, ManifestEntry>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Records results in a [`RunManifest`], created by [`RunManifest::sink`].
+#[derive(Debug, Clone)]
+pub struct ManifestSink {
+    manifest: RunManifest,
+    key: String,
+}
+
+impl ResultSink for ManifestSink {
+    fn deliver(&self, delivery: Delivery) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            let entry = ManifestEntry {
+                key: self.key.clone(),
+                fingerprint: delivery.provenance.prompt_fingerprint,
+                output: delivery.output,
+                completed_at: delivery.provenance.finished_at,
+            };
+            let mut line = serde_json::to_vec(&entry).map_err(Error::sink)?;
+            line.push(b'\n');
+            let file = self.manifest.file.clone();
+            blocking(move || {
+                let mut file = file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                file.write_all(&line)?;
+                file.sync_data()
+            })
+            .await
+            .map_err(Error::sink)?;
+            self.manifest.lock().insert(entry.key.clone(), entry);
+            Ok(())
+        })
+    }
+}
+
+/// Whether `file` is empty or ends with a newline.
+fn ends_with_newline(file: &mut File) -> std::io::Result<bool> {
+    if file.metadata()?.len() == 0 {
+        return Ok(true);
+    }
+    file.seek(SeekFrom::End(-1))?;
+    let mut last = [0];
+    file.read_exact(&mut last)?;
+    Ok(last[0] == b'\n')
+}
@@ -0,0 +1,387 @@
+//! The MCP server that serves [`ToolSet`](crate::ToolSet)s, prompts, and
+//! resources to the agent.
+//!
+//! The builder in `agent_client_protocol` only serves tools, and only returns
+//! their output as JSON text, so tool sets are served by this server
+//! instead, which can also hand the agent any [`ToolOutput`].
+
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+use agent_client_protocol::mcp_server::{McpConnectionTo, McpServerConnect};
+use agent_client_protocol::role::{self, Role};
+use agent_client_protocol::{ByteStreams, ConnectTo, DynConnectTo};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use futures::future::Either;
+use rmcp::ErrorData;
+use rmcp::model::{
+    CallToolRequestParams, CallToolResult, GetPromptRequestParams, GetPromptResult, Implementation,
+    ListPromptsResult, ListResourcesResult, ListToolsResult, PaginatedRequestParams, Prompt,
+    PromptArgument, PromptMessage, PromptMessageRole, ProtocolVersion, RawResource,
+    ReadResourceRequestParams, ReadResourceResult, ServerCapabilities, ServerInfo,
+};
+use rmcp::service::RequestContext;
+use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+use tracing::{debug, warn};
+
+use crate::prompts::PromptTemplate;
+use crate::redaction::LogRedaction;
+use crate::resources::{ResourceContents, Resources};
+use crate::sampling::Sampler;
+use crate::tool_gate::ToolGate;
+use crate::tool_output::ToolOutput;
+use crate::toolset::ErasedTool;
+use crate::{Error, Resource};
+
+/// How many bytes may be in flight between the MCP client and server.
+const BUFFER_SIZE: usize = 8192;
+
+/// Serves tools and resources over MCP-over-ACP.
+pub(crate) struct Server<R> {
+    name: String,
+    contents: Arc<Contents>,
+    phantom: PhantomData<fn() -> R>,
+}
+
+/// Everything a [`Server`] offers.
+struct Contents {
+    tools: Vec<ServedTool>,
+    prompts: Vec<PromptTemplate>,
+    resources: Resources,
+    gate: ToolGate,
+    invalid_calls: InvalidCalls,
+    redaction: LogRedaction,
+}
+
+/// How many times each tool was called with arguments that didn't fit its
+/// input schema, shared between a think block and its server.
+#[derive(Clone, Default)]
+pub(crate) struct InvalidCalls(Arc<Mutex<BTreeMap<String, u32>>>);
+
+impl InvalidCalls {
+    fn record(&self, tool: &str) {
+        let mut counts = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        *counts.entry(tool.to_string()).or_default() += 1;
+    }
+
+    /// The counts so far, by tool name.
+    pub(crate) fn counts(&self) -> BTreeMap<String, u32> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+}
+
+/// A tool along with the model advertised for it in `tools/list`.
+struct ServedTool {
+    model: rmcp::model::Tool,
+    tool: Arc<dyn ErasedTool>,
+}
+
+impl<R> Server<R> {
+    pub(crate) fn new(
+        name: &str,
+        tools: &[Arc<dyn ErasedTool>],
+        prompts: Vec<PromptTemplate>,
+        resources: Resources,
+        gate: ToolGate,
+        invalid_calls: InvalidCalls,
+        redaction: LogRedaction,
+    ) -> Self {
+        let tools = tools
+            .iter()
+            .map(|tool| ServedTool {
+                model: tool.model(),
+                tool: tool.clone(),
+            })
+            .collect();
+        Self {
+            name: name.to_string(),
+            contents: Arc::new(Contents {
+                tools,
+                prompts,
+                resources,
+                gate,
+                invalid_calls,
+                redaction,
+            }),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<R: Role> McpServerConnect<R> for Server<R> {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn connect(&self, _cx: McpConnectionTo<R>) -> DynConnectTo<role::mcp::Client> {
+        DynConnectTo::new(Connection {
+            contents: self.contents.clone(),
+        })
+    }
+}
+
+/// One agent's connection to a [`Server`].
+struct Connection {
+    contents: Arc<Contents>,
+}
+
+impl ConnectTo<role::mcp::Client> for Connection {
+    async fn connect_to(
+        self,
+        client: impl ConnectTo<role::mcp::Server>,
+    ) -> Result<(), agent_client_protocol::Error> {
+        // rmcp speaks over a byte stream, so bridge it to the ACP client
+        let (server_stream, client_stream) = tokio::io::duplex(BUFFER_SIZE);
+        let (server_read, server_write) = tokio::io::split(server_stream);
+        let (client_read, client_write) = tokio::io::split(client_stream);
+
+        let run_client = async {
+            let streams = ByteStreams::new(client_write.compat_write(), client_read.compat());
+            // The client ends when the agent disconnects, which is not an error
+            let _ = ConnectTo::<role::mcp::Client>::connect_to(streams, client).await;
+            Ok(())
+        };
+        let gate = self.contents.gate.clone();
+        let run_server = async {
+            let running = rmcp::ServiceExt::serve(self, (server_read, server_write))
+                .await
+                .map_err(agent_client_protocol::Error::into_internal_error)?;
+            // Keep the gate able to reach the agent while it is connected
+            let _attached = gate.attach(running.peer().clone());
+            running
+                .waiting()
+                .await
+                .map(|_| ())
+                .map_err(agent_client_protocol::Error::into_internal_error)
+        };
+        futures::future::try_join(run_client, run_server).await?;
+        Ok(())
+    }
+}
+
+impl rmcp::ServerHandler for Connection {
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParams,
+        context: RequestContext<rmcp::RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let Some(served) = self
+            .contents
+            .tools
+            .iter()
+            .find(|t| t.model.name == request.name)
+            .filter(|t| self.contents.gate.allows(t.tool.as_ref()))
+        else {
+            return Err(ErrorData::invalid_params(
+                format!("tool `{}` not found", request.name),
+                None,
+            ));
+        };
+        debug!(tool_name = %request.name, "tool invoked by agent");
+
+        let input = serde_json::Value::Object(request.arguments.unwrap_or_default());
+        let call = self
+            .contents
+            .redaction
+            .scope_tool_call(served.tool.call(input, Sampler::new(context.peer.clone())));
+        let cancelled = std::pin::pin!(context.ct.cancelled());
+        match futures::future::select(call, cancelled).await {
+            Either::Left((Ok(ToolOutput::Json(value)), _))
+                if served.model.output_schema.is_some() =>
+            {
+                Ok(CallToolResult::structured(value))
+            }
+            Either::Left((Ok(output), _)) => Ok(CallToolResult::success(output.into_contents())),
+            Either::Left((Err(err @ Error::ToolArguments { .. }), _)) => {
+                self.contents.invalid_calls.record(&request.name);
+                Err(usage_error(err, &served.model))
+            }
+            Either::Left((Err(err), _)) => Err(to_rmcp_error(err)),
+            Either::Right(_) => Err(ErrorData::internal_error("operation cancelled", None)),
+        }
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<rmcp::RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        Ok(ListToolsResult::with_all_items(
+            self.contents
+                .tools
+                .iter()
+                .filter(|t| self.contents.gate.allows(t.tool.as_ref()))
+                .map(|t| t.model.clone())
+                .collect(),
+        ))
+    }
+
+    async fn list_prompts(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<rmcp::RoleServer>,
+    ) -> Result<ListPromptsResult, ErrorData> {
+        Ok(ListPromptsResult::with_all_items(
+            self.contents.prompts.iter().map(prompt_model).collect(),
+        ))
+    }
+
+    async fn get_prompt(
+        &self,
+        request: GetPromptRequestParams,
+        _context: RequestContext<rmcp::RoleServer>,
+    ) -> Result<GetPromptResult, ErrorData> {
+        let Some(prompt) = self
+            .contents
+            .prompts
+            .iter()
+            .find(|p| p.name == request.name)
+        else {
+            return Err(ErrorData::invalid_params(
+                format!("prompt `{}` not found", request.name),
+                None,
+            ));
+        };
+        debug!(prompt = %request.name, "prompt requested by agent");
+        let arguments = request.arguments.unwrap_or_default();
+        let text = prompt
+            .render(|name| arguments.get(name).and_then(|value| value.as_str()))
+            .map_err(|message| ErrorData::invalid_params(message, None))?;
+        let mut result =
+            GetPromptResult::new(vec![PromptMessage::new_text(PromptMessageRole::User, text)]);
+        if let Some(description) = &prompt.description {
+            result = result.with_description(description);
+        }
+        Ok(result)
+    }
+
+    async fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<rmcp::RoleServer>,
+    ) -> Result<ListResourcesResult, ErrorData> {
+        let resources = self
+            .contents
+            .resources
+            .list()
+            .await
+            .map_err(to_rmcp_error)?;
+        Ok(ListResourcesResult::with_all_items(
+            resources.into_iter().map(resource_model).collect(),
+        ))
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParams,
+        _context: RequestContext<rmcp::RoleServer>,
+    ) -> Result<ReadResourceResult, ErrorData> {
+        debug!(uri = %request.uri, "resource read by agent");
+        let Some((mime_type, contents)) = self
+            .contents
+            .resources
+            .read(&request.uri)
+            .await
+            .map_err(to_rmcp_error)?
+        else {
+            return Err(ErrorData::resource_not_found(
+                format!("no resource at `{}`", request.uri),
+                None,
+            ));
+        };
+        let contents = match contents {
+            ResourceContents::Text(text) => rmcp::model::ResourceContents::text(text, request.uri),
+            ResourceContents::Blob(data) => {
+                rmcp::model::ResourceContents::blob(STANDARD.encode(data), request.uri)
+            }
+        };
+        let contents = match mime_type {
+            Some(mime_type) => contents.with_mime_type(mime_type),
+            None => contents,
+        };
+        Ok(ReadResourceResult::new(vec![contents]))
+    }
+
+    fn get_info(&self) -> ServerInfo {
+        let capabilities = ServerCapabilities::builder()
+            .enable_tools()
+            .enable_tool_list_changed()
+            .enable_prompts()
+            .enable_resources()
+            .build();
+        ServerInfo::new(capabilities)
+            .with_server_info(Implementation::default())
+            .with_protocol_version(ProtocolVersion::default())
+    }
+}
+
+/// The listing entry for `prompt`.
+fn prompt_model(prompt: &PromptTemplate) -> Prompt {
+    let arguments = prompt
+        .arguments
+        .iter()
+        .map(|argument| {
+            let model = PromptArgument::new(&argument.name).with_required(argument.required);
+            match &argument.description {
+                Some(description) => model.with_description(description),
+                None => model,
+            }
+        })
+        .collect();
+    Prompt::new(&prompt.name, prompt.description.as_ref(), Some(arguments))
+}
+
+/// The listing entry for `resource`.
+fn resource_model(resource: Resource) -> rmcp::model::Resource {
+    let mut model = RawResource::new(resource.uri, resource.name);
+    model.description = resource.description;
+    model.mime_type = resource.mime_type;
+    rmcp::model::AnnotateAble::no_annotation(model)
+}
+
+/// Report a tool call with bad arguments to the agent, along with the
+/// tool's input schema, so it can correct the call.
+fn usage_error(error: Error, tool: &rmcp::model::Tool) -> ErrorData {
+    let message = error.to_string();
+    let Error::ToolArguments {
+        pointer,
+        message: problem,
+        ..
+    } = error
+    else {
+        return to_rmcp_error(error);
+    };
+    warn!(tool = %tool.name, %pointer, %problem, "tool called with invalid arguments");
+    ErrorData::invalid_params(
+        message,
+        Some(serde_json::json!({
+            "tool": tool.name,
+            "pointer": pointer,
+            "problem": problem,
+            "inputSchema": tool.input_schema,
+        })),
+    )
+}
+
+/// Report a failed request to the agent.
+fn to_rmcp_error(error: Error) -> ErrorData {
+    match error {
+        Error::ToolFailure { source, .. } => ErrorData {
+            code: rmcp::model::ErrorCode(source.code.into()),
+            message: source.message.into(),
+            data: source.data,
+        },
+        Error::Deserialization { .. } | Error::ToolArguments { .. } => {
+            ErrorData::invalid_params(error.to_string(), None)
+        }
+        other => ErrorData::internal_error(other.to_string(), None),
+    }
+}
@@ -0,0 +1,184 @@
+//! Recalling relevant earlier results into new prompts.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+use agent_client_protocol::BoxFuture;
+
+use crate::Error;
+
+/// How many memories [`ThinkBuilder::with_memory`](crate::ThinkBuilder::with_memory)
+/// recalls unless told otherwise.
+pub(crate) const DEFAULT_RECALL_LIMIT: usize = 3;
+
+/// Dimensions of the vectors produced by [`InMemoryStore`]'s default embedding.
+const HASHED_DIMENSIONS: usize = 512;
+
+/// A store of text that can be searched by similarity.
+///
+/// Think blocks recall from a memory with
+/// [`ThinkBuilder::with_memory`](crate::ThinkBuilder::with_memory), which
+/// adds the entries most relevant to a query to the prompt. Store results
+/// worth remembering with [`store`](Self::store) as they are produced.
+///
+/// [`InMemoryStore`] is a simple implementation for a single process.
+/// Implement this trait to back memories with a vector database instead.
+pub trait Memory: Send + Sync {
+    /// Store a piece of text.
+    fn store(&self, text: String) -> BoxFuture<'_, Result<(), Error>>;
+
+    /// Return up to `limit` stored entries most similar to `query`, most
+    /// similar first.
+    fn retrieve(
+        &self,
+        query: String,
+        limit: usize,
+    ) -> BoxFuture<'_, Result<Vec<Recollection>, Error>>;
+}
+
+/// An entry returned by [`Memory::retrieve`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recollection {
+    /// The stored text.
+    pub text: String,
+
+    /// How similar the entry is to the query; higher is more similar. The
+    /// scale depends on the store.
+    pub score: f64,
+}
+
+/// A [`Memory`] kept in process, ranked by cosine similarity.
+///
+/// By default text is embedded by hashing its lowercase words into a
+/// fixed-size vector, which needs no model and finds entries that share
+/// vocabulary with the query. For semantic matches, supply real embeddings
+/// with [`with_embedder`](Self::with_embedder).
+///
+/// Clones share the same entries.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let memory = InMemoryStore::new();
+/// for file in files {
+///     let summary: String = d.think()
+///         .with_memory(&memory, &file.name)
+///         .text("Summarize")
+///         .display(&file.contents)
+///         .await?;
+///     memory.store(format!("{}: {summary}", file.name)).await?;
+/// }
+/// ```
+#[derive(Clone)]
+pub struct InMemoryStore {
+    entries: Arc<Mutex<Vec<Entry>>>,
+    embed: Arc<Embedder>,
+}
+
+/// A stored text and its embedding.
+type Entry = (String, Vec<f32>);
+
+/// Turns text into a vector.
+type Embedder = dyn Fn(&str) -> Vec<f32> + Send + Sync;
+
+impl InMemoryStore {
+    /// Create an empty store using the default hashed word embedding.
+    pub fn new() -> Self {
+        Self::with_embedder(hashed_words)
+    }
+
+    /// Create an empty store that embeds text with `embed`.
+    ///
+    /// Every vector it returns should have the same length.
+    pub fn with_embedder(embed: impl Fn(&str) -> Vec<f32> + Send + Sync + 'static) -> Self {
+        Self {
+            entries: Arc::default(),
+            embed: Arc::new(embed),
+        }
+    }
+
+    /// Number of stored entries.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Whether nothing has been stored.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Entry>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for InMemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for InMemoryStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InMemoryStore")
+            .field("entries", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Memory for InMemoryStore {
+    fn store(&self, text: String) -> BoxFuture<'_, Result<(), Error>> {
+        let vector = (self.embed)(&text);
+        self.lock().push((text, vector));
+        Box::pin(async { Ok(()) })
+    }
+
+    fn retrieve(
+        &self,
+        query: String,
+        limit: usize,
+    ) -> BoxFuture<'_, Result<Vec<Recollection>, Error>> {
+        let query = (self.embed)(&query);
+        let mut recollections: Vec<Recollection> = self
+            .lock()
+            .iter()
+            .map(|(text, vector)| Recollection {
+                text: text.clone(),
+                score: cosine(&query, vector),
+            })
+            .filter(|recollection| recollection.score > 0.0)
+            .collect();
+        recollections.sort_by(|a, b| b.score.total_cmp(&a.score));
+        recollections.truncate(limit);
+        Box::pin(async { Ok(recollections) })
+    }
+}
+
+/// Cosine similarity, or zero if either vector is all zeros.
+fn cosine(a: &[f32], b: &[f32]) -> f64 {
+    let dot: f64 = a
+        .iter()
+        .zip(b)
+        .map(|(x, y)| f64::from(*x) * f64::from(*y))
+        .sum();
+    let norm = |v: &[f32]| v.iter().map(|x| f64::from(*x).powi(2)).sum::<f64>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
+
+/// Count each lowercase word into one of a fixed number of buckets.
+fn hashed_words(text: &str) -> Vec<f32> {
+    let mut vector = vec![0.0; HASHED_DIMENSIONS];
+    for word in text.split(|c: char| !c.is_alphanumeric() && c != '_') {
+        if word.is_empty() {
+            continue;
+        }
+        let mut hasher = DefaultHasher::new();
+        word.to_lowercase().hash(&mut hasher);
+        vector[hasher.finish() as usize % HASHED_DIMENSIONS] += 1.0;
+    }
+    vector
+}
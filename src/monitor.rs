@@ -0,0 +1,386 @@
+//! A live terminal view of running think blocks.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use agent_client_protocol::schema::{ContentBlock, SessionNotification, SessionUpdate, StopReason};
+use ratatui::Frame;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, Row, Table, Wrap};
+
+use crate::think::ThinkObserver;
+use crate::{Error, budget};
+
+/// How often the view is redrawn.
+const REFRESH: Duration = Duration::from_millis(200);
+
+/// How much of a think block's streamed text is kept for display.
+const TEXT_LIMIT: usize = 4000;
+
+/// How many finished think blocks stay listed below the running ones.
+const FINISHED_LIMIT: usize = 5;
+
+/// A live terminal view of the think blocks running on a
+/// [`Determinishtic`](crate::Determinishtic) instance: which are active,
+/// the text the most recent one is streaming, the tools being called, and
+/// estimated token usage.
+///
+/// Attach the monitor's [`observer`](Self::observer) to the instance, then
+/// [`run`](Self::run) the work under it. Press `q` to close the view; the
+/// work carries on without it.
+///
+/// This type is only available with the `tui` feature.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let monitor = Monitor::new();
+/// let mut d = Determinishtic::new(agent).await?;
+/// d.set_observer(monitor.observer());
+///
+/// let summaries = monitor
+///     .run(stream::iter(docs).think_map(&d, summarize).try_collect::<Vec<_>>())
+///     .await??;
+/// ```
+#[derive(Clone, Default)]
+pub struct Monitor {
+    state: Arc<Mutex<State>>,
+    next_id: Arc<AtomicU64>,
+}
+
+/// Everything the view shows.
+#[derive(Default)]
+struct State {
+    /// Think blocks in the order they started; finished ones are pruned.
+    blocks: Vec<ThinkState>,
+    finished: u64,
+    failed: u64,
+    estimated_tokens: u64,
+    tool_calls: u64,
+}
+
+struct ThinkState {
+    id: u64,
+    labels: BTreeMap<String, String>,
+    started: Instant,
+    /// How long the think block took, once it has finished.
+    elapsed: Option<Duration>,
+    /// The text the agent has streamed, trimmed to [`TEXT_LIMIT`].
+    text: String,
+    /// The title of the tool call the agent made most recently.
+    last_tool: Option<String>,
+    tool_calls: u64,
+    estimated_tokens: u64,
+    status: Status,
+}
+
+enum Status {
+    Running,
+    Stopped(StopReason),
+    Finished,
+    Failed(String),
+}
+
+impl Monitor {
+    /// A monitor with no think blocks yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The observer to attach with
+    /// [`Determinishtic::set_observer`](crate::Determinishtic::set_observer).
+    pub fn observer(&self) -> Arc<dyn ThinkObserver> {
+        Arc::new(self.clone())
+    }
+
+    /// Show the monitor in the terminal until `work` finishes, and return
+    /// its output.
+    ///
+    /// Takes over the terminal while the view is open and restores it
+    /// afterwards. Fails only if the terminal can't be drawn to.
+    pub async fn run<T>(&self, work: impl Future<Output = T>) -> std::io::Result<T> {
+        let mut work = std::pin::pin!(work);
+        let mut terminal = ratatui::try_init()?;
+        let started = Instant::now();
+        let result = loop {
+            if let Err(err) = terminal.draw(|frame| self.draw(frame, started)) {
+                break Err(err);
+            }
+            match event::poll(Duration::ZERO) {
+                Ok(true) => match event::read() {
+                    Ok(Event::Key(key))
+                        if key.kind == KeyEventKind::Press && key.code == KeyCode::Char('q') =>
+                    {
+                        break Ok(None);
+                    }
+                    Ok(_) => {}
+                    Err(err) => break Err(err),
+                },
+                Ok(false) => {}
+                Err(err) => break Err(err),
+            }
+            let tick = std::pin::pin!(tokio::time::sleep(REFRESH));
+            if let futures::future::Either::Left((output, _)) =
+                futures::future::select(work.as_mut(), tick).await
+            {
+                break Ok(Some(output));
+            }
+        };
+        ratatui::restore();
+        match result? {
+            Some(output) => Ok(output),
+            None => Ok(work.await),
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame, started: Instant) {
+        let state = self.lock();
+        let running = state.blocks.iter().filter(|block| !block.is_done()).count();
+        let [summary, table, text] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Percentage(40),
+            Constraint::Fill(1),
+        ])
+        .areas(frame.area());
+
+        frame.render_widget(
+            Paragraph::new(format!(
+                " {running} running · {} finished · {} failed · ~{} tokens · {} tool calls · {}s   (q to close)",
+                state.finished,
+                state.failed,
+                state.estimated_tokens,
+                state.tool_calls,
+                started.elapsed().as_secs()
+            ))
+            .style(Style::new().add_modifier(Modifier::BOLD)),
+            summary,
+        );
+
+        let rows = state.blocks.iter().map(|block| {
+            let (status, color) = match &block.status {
+                Status::Running => ("running".to_string(), Color::Yellow),
+                Status::Stopped(reason) => (format!("stopped: {reason:?}"), Color::Yellow),
+                Status::Finished => ("done".to_string(), Color::Green),
+                Status::Failed(error) => (format!("failed: {error}"), Color::Red),
+            };
+            let labels: Vec<String> = block
+                .labels
+                .iter()
+                .map(|(key, value)| format!("{key}={value}"))
+                .collect();
+            Row::new([
+                format!("#{}", block.id),
+                labels.join(" "),
+                format!("{}s", block.elapsed().as_secs()),
+                block.tool_calls.to_string(),
+                block.last_tool.clone().unwrap_or_default(),
+                format!("~{}", block.estimated_tokens),
+                status,
+            ])
+            .style(Style::new().fg(color))
+        });
+        frame.render_widget(
+            Table::new(
+                rows,
+                [
+                    Constraint::Length(6),
+                    Constraint::Fill(1),
+                    Constraint::Length(6),
+                    Constraint::Length(6),
+                    Constraint::Fill(1),
+                    Constraint::Length(8),
+                    Constraint::Fill(1),
+                ],
+            )
+            .header(
+                Row::new([
+                    "",
+                    "labels",
+                    "time",
+                    "tools",
+                    "last tool",
+                    "tokens",
+                    "status",
+                ])
+                .style(Style::new().add_modifier(Modifier::UNDERLINED)),
+            )
+            .block(Block::bordered().title(" think blocks ")),
+            table,
+        );
+
+        let latest = state.blocks.iter().rev().find(|block| !block.is_done());
+        let (title, body) = match latest {
+            Some(block) => (format!(" #{} ", block.id), block.text.as_str()),
+            None => (" idle ".to_string(), ""),
+        };
+        // Show the end of the text, where the agent is writing
+        let height = text.height.saturating_sub(2) as usize;
+        let lines: Vec<Line> = body.lines().map(Line::raw).collect();
+        let skip = lines.len().saturating_sub(height);
+        frame.render_widget(
+            Paragraph::new(lines[skip..].to_vec())
+                .wrap(Wrap { trim: false })
+                .block(Block::bordered().title(title)),
+            text,
+        );
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl ThinkObserver for Monitor {
+    fn for_think(&self) -> Option<Arc<dyn ThinkObserver>> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        Some(Arc::new(ThinkMonitor {
+            id,
+            state: self.state.clone(),
+        }))
+    }
+}
+
+/// Records one think block into a [`Monitor`].
+struct ThinkMonitor {
+    id: u64,
+    state: Arc<Mutex<State>>,
+}
+
+impl ThinkMonitor {
+    /// Apply `update` to the shared state and this think block's entry.
+    fn update(&self, update: impl FnOnce(&mut State, Option<usize>)) {
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let index = state.blocks.iter().position(|block| block.id == self.id);
+        update(&mut state, index);
+    }
+}
+
+impl ThinkObserver for ThinkMonitor {
+    fn on_start(&self, labels: &BTreeMap<String, String>) {
+        self.update(|state, _| {
+            state.blocks.push(ThinkState {
+                id: self.id,
+                labels: labels.clone(),
+                started: Instant::now(),
+                elapsed: None,
+                text: String::new(),
+                last_tool: None,
+                tool_calls: 0,
+                estimated_tokens: 0,
+                status: Status::Running,
+            });
+        });
+    }
+
+    fn on_prompt(&self, prompt: &str) {
+        let tokens = budget::estimate_tokens(prompt) as u64;
+        self.update(|state, index| {
+            state.estimated_tokens += tokens;
+            if let Some(index) = index {
+                let block = &mut state.blocks[index];
+                block.estimated_tokens += tokens;
+                block.status = Status::Running;
+            }
+        });
+    }
+
+    fn on_notification(&self, notification: &SessionNotification) {
+        self.update(|state, index| {
+            let Some(index) = index else {
+                return;
+            };
+            match &notification.update {
+                SessionUpdate::AgentMessageChunk(chunk) => {
+                    if let ContentBlock::Text(text) = &chunk.content {
+                        let tokens = budget::estimate_tokens(&text.text) as u64;
+                        state.estimated_tokens += tokens;
+                        let block = &mut state.blocks[index];
+                        block.estimated_tokens += tokens;
+                        block.push_text(&text.text);
+                    }
+                }
+                SessionUpdate::AgentThoughtChunk(chunk) => {
+                    if let ContentBlock::Text(text) = &chunk.content {
+                        let tokens = budget::estimate_tokens(&text.text) as u64;
+                        state.estimated_tokens += tokens;
+                        state.blocks[index].estimated_tokens += tokens;
+                    }
+                }
+                SessionUpdate::ToolCall(call) => {
+                    state.tool_calls += 1;
+                    let block = &mut state.blocks[index];
+                    block.tool_calls += 1;
+                    block.last_tool = Some(call.title.clone());
+                    block.push_text(&format!("\n[{}]\n", call.title));
+                }
+                _ => {}
+            }
+        });
+    }
+
+    fn on_stop(&self, reason: &StopReason) {
+        self.update(|state, index| {
+            if let Some(index) = index {
+                state.blocks[index].status = Status::Stopped(*reason);
+            }
+        });
+    }
+
+    fn on_finish(&self, error: Option<&Error>) {
+        self.update(|state, index| {
+            match error {
+                Some(_) => state.failed += 1,
+                None => state.finished += 1,
+            }
+            if let Some(index) = index {
+                let block = &mut state.blocks[index];
+                block.elapsed = Some(block.started.elapsed());
+                block.status = match error {
+                    Some(error) => Status::Failed(error.to_string()),
+                    None => Status::Finished,
+                };
+            }
+            // Keep every running think block and only the latest finished ones
+            let mut finished = state.blocks.iter().filter(|block| block.is_done()).count();
+            state.blocks.retain(|block| {
+                if block.is_done() && finished > FINISHED_LIMIT {
+                    finished -= 1;
+                    return false;
+                }
+                true
+            });
+        });
+    }
+}
+
+impl ThinkState {
+    fn is_done(&self) -> bool {
+        matches!(self.status, Status::Finished | Status::Failed(_))
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.elapsed.unwrap_or_else(|| self.started.elapsed())
+    }
+
+    fn push_text(&mut self, text: &str) {
+        self.text.push_str(text);
+        if self.text.len() > TEXT_LIMIT {
+            let mut cut = self.text.len() - TEXT_LIMIT;
+            while !self.text.is_char_boundary(cut) {
+                cut += 1;
+            }
+            self.text.drain(..cut);
+        }
+    }
+}
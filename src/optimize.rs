@@ -0,0 +1,354 @@
+//! Searching for better prompt instructions against labeled cases.
+//!
+//! An [`Optimizer`] starts from the instructions of a think block and a set
+//! of cases, each an input with the output it should produce. It scores the
+//! instructions on the cases, asks the agent to propose rewrites that fix the
+//! failures, scores each rewrite the same way, and reports which variant did
+//! best. Nothing is changed automatically: review the winning instructions
+//! and paste them into your code.
+//!
+//! Every candidate is run on every case, so an optimization costs
+//! `cases * (1 + rounds * candidates)` think blocks plus one proposal per
+//! round. Start with a handful of representative cases.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use determinishtic::optimize::Optimizer;
+//!
+//! let optimizer = Optimizer::new("Classify the sentiment of this review:")
+//!     .case("refund", "Broke after a day, I want a refund.", Sentiment::Negative)
+//!     .case("sarcasm", "Oh great, another update that logs me out.", Sentiment::Negative)
+//!     .case("praise", "Does exactly what it says.", Sentiment::Positive)
+//!     .rounds(2);
+//!
+//! let report = optimizer
+//!     .run(&d, async |instructions, review| {
+//!         d.think().text(instructions).display(&review).await
+//!     })
+//!     .await?;
+//! println!("{report}");
+//! println!("best instructions: {}", report.best().instructions);
+//! ```
+
+use std::fmt;
+
+use agent_client_protocol::Agent;
+use agent_client_protocol::role::{HasPeer, Role};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::bench::Failure;
+use crate::{Determinishtic, Error};
+
+/// How many failing cases are shown to the agent when it proposes rewrites.
+const FAILURES_SHOWN: usize = 5;
+
+/// Searches for instructions that make a think block pass more of its cases.
+pub struct Optimizer<T> {
+    instructions: String,
+    cases: Vec<Case<T>>,
+    rounds: usize,
+    candidates: usize,
+}
+
+struct Case<T> {
+    name: String,
+    input: String,
+    /// The expected output as JSON, shown to the agent when the case fails.
+    expected: Option<serde_json::Value>,
+    validate: Box<dyn Fn(&T) -> bool + Send + Sync>,
+}
+
+/// Results of running an [`Optimizer`].
+#[derive(Debug, Clone, Serialize)]
+pub struct OptimizationReport {
+    /// Every variant evaluated, starting with the original instructions and
+    /// then in the order they were proposed.
+    pub candidates: Vec<Candidate>,
+
+    /// Index into [`candidates`](Self::candidates) of the variant that
+    /// passed the most cases. Ties go to the earlier variant, so the
+    /// original instructions win unless a rewrite does strictly better.
+    pub best: usize,
+}
+
+/// One variant of the instructions and how it did.
+#[derive(Debug, Clone, Serialize)]
+pub struct Candidate {
+    /// The instructions passed to the think block.
+    pub instructions: String,
+
+    /// The round that proposed this variant; 0 for the original.
+    pub round: usize,
+
+    /// Number of cases run.
+    pub cases: usize,
+
+    /// Number of cases whose output was correct.
+    pub passed: usize,
+
+    /// Cases that failed, with the reason.
+    pub failures: Vec<Failure>,
+}
+
+/// The rewrites proposed by the agent in one round.
+#[derive(Debug, Deserialize, JsonSchema)]
+struct Proposal {
+    /// Complete rewritten instructions, each a drop-in replacement for the
+    /// current ones.
+    rewrites: Vec<String>,
+}
+
+impl Candidate {
+    /// Fraction of cases that passed, from 0.0 to 1.0 (zero if there were none).
+    pub fn accuracy(&self) -> f64 {
+        if self.cases == 0 {
+            0.0
+        } else {
+            self.passed as f64 / self.cases as f64
+        }
+    }
+}
+
+impl OptimizationReport {
+    /// The variant that passed the most cases.
+    pub fn best(&self) -> &Candidate {
+        &self.candidates[self.best]
+    }
+
+    /// The original instructions and how they did.
+    pub fn original(&self) -> &Candidate {
+        &self.candidates[0]
+    }
+
+    /// Whether some rewrite did better than the original instructions.
+    pub fn improved(&self) -> bool {
+        self.best != 0
+    }
+}
+
+impl<T> Optimizer<T>
+where
+    T: Send + 'static,
+{
+    /// Start from the instructions currently used by the think block.
+    ///
+    /// Defaults to one round of three rewrites.
+    pub fn new(instructions: impl Into<String>) -> Self {
+        Self {
+            instructions: instructions.into(),
+            cases: Vec::new(),
+            rounds: 1,
+            candidates: 3,
+        }
+    }
+
+    /// Add a labeled case: the think block should produce `expected` for `input`.
+    pub fn case(self, name: impl Into<String>, input: impl fmt::Display, expected: T) -> Self
+    where
+        T: PartialEq + Serialize + Sync,
+    {
+        let json = serde_json::to_value(&expected).ok();
+        self.push(name, input, json, move |output| *output == expected)
+    }
+
+    /// Add a case checked by a validator rather than compared with an
+    /// expected output, for tasks with more than one right answer.
+    ///
+    /// `description` tells the agent what a correct output looks like when
+    /// it proposes rewrites.
+    pub fn case_validated(
+        self,
+        name: impl Into<String>,
+        input: impl fmt::Display,
+        description: impl Into<String>,
+        validate: impl Fn(&T) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        let description = serde_json::Value::String(description.into());
+        self.push(name, input, Some(description), validate)
+    }
+
+    /// Number of rounds. Each round proposes rewrites of the best variant
+    /// so far, using the cases it still fails.
+    pub fn rounds(mut self, rounds: usize) -> Self {
+        self.rounds = rounds;
+        self
+    }
+
+    /// Number of rewrites to request per round.
+    pub fn candidates(mut self, candidates: usize) -> Self {
+        self.candidates = candidates;
+        self
+    }
+
+    fn push(
+        mut self,
+        name: impl Into<String>,
+        input: impl fmt::Display,
+        expected: Option<serde_json::Value>,
+        validate: impl Fn(&T) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.cases.push(Case {
+            name: name.into(),
+            input: input.to_string(),
+            expected,
+            validate: Box::new(validate),
+        });
+        self
+    }
+
+    /// Run the optimization.
+    ///
+    /// `think` runs the think block being optimized with the given
+    /// instructions and case input. Rewrites are proposed by `d`, which may
+    /// be a different (stronger) agent than the one `think` uses.
+    ///
+    /// Cases run sequentially, and errors from individual cases count as
+    /// failures. Stops early once a variant passes every case. Fails only
+    /// if the agent can't propose rewrites.
+    pub async fn run<R>(
+        &self,
+        d: &Determinishtic<R>,
+        think: impl AsyncFn(&str, &str) -> Result<T, Error>,
+    ) -> Result<OptimizationReport, Error>
+    where
+        R: Role + HasPeer<Agent>,
+    {
+        let original = self.evaluate(&self.instructions, 0, &think).await;
+        let mut report = OptimizationReport {
+            candidates: vec![original],
+            best: 0,
+        };
+
+        for round in 1..=self.rounds {
+            let best = report.best();
+            if best.failures.is_empty() {
+                break;
+            }
+            let rewrites = self.propose(d, best).await?;
+            debug!(round, count = rewrites.len(), "proposed rewrites");
+            for instructions in rewrites {
+                let candidate = self.evaluate(&instructions, round, &think).await;
+                if candidate.passed > report.best().passed {
+                    report.best = report.candidates.len();
+                }
+                report.candidates.push(candidate);
+            }
+        }
+
+        Ok(report)
+    }
+
+    async fn evaluate(
+        &self,
+        instructions: &str,
+        round: usize,
+        think: &impl AsyncFn(&str, &str) -> Result<T, Error>,
+    ) -> Candidate {
+        let mut candidate = Candidate {
+            instructions: instructions.to_string(),
+            round,
+            cases: self.cases.len(),
+            passed: 0,
+            failures: Vec::new(),
+        };
+
+        for case in &self.cases {
+            let failure = match think(instructions, &case.input).await {
+                Ok(output) if (case.validate)(&output) => None,
+                Ok(_) => Some("output rejected by validator".to_string()),
+                Err(err) => Some(err.to_string()),
+            };
+            match failure {
+                None => candidate.passed += 1,
+                Some(reason) => candidate.failures.push(Failure {
+                    case: case.name.clone(),
+                    reason,
+                }),
+            }
+        }
+
+        debug!(
+            round,
+            passed = candidate.passed,
+            cases = candidate.cases,
+            "evaluated candidate"
+        );
+        candidate
+    }
+
+    /// Ask the agent for rewrites of `current` that fix its failures.
+    async fn propose<R>(
+        &self,
+        d: &Determinishtic<R>,
+        current: &Candidate,
+    ) -> Result<Vec<String>, Error>
+    where
+        R: Role + HasPeer<Agent>,
+    {
+        let mut failures = String::new();
+        for failure in current.failures.iter().take(FAILURES_SHOWN) {
+            let Some(case) = self.cases.iter().find(|case| case.name == failure.case) else {
+                continue;
+            };
+            failures.push_str(&format!(
+                "<case name=\"{}\">\n<input>\n{}\n</input>\n",
+                case.name, case.input
+            ));
+            if let Some(expected) = &case.expected {
+                failures.push_str(&format!("<expected>\n{expected}\n</expected>\n"));
+            }
+            failures.push_str(&format!(
+                "<problem>\n{}\n</problem>\n</case>\n",
+                failure.reason
+            ));
+        }
+
+        let proposal: Proposal = d
+            .think()
+            .textln("You are improving the instructions of a prompt sent to an AI agent.")
+            .textln("The instructions are followed by an input, and the agent must produce a")
+            .textln("structured output. With the current instructions, some inputs give wrong")
+            .textln("outputs. Propose rewritten instructions that fix these failures without")
+            .textln("breaking other inputs. Each rewrite must be complete, must not mention")
+            .textln("the specific example inputs, and should try a different approach.")
+            .textln("")
+            .text(&format!(
+                "Propose {} rewrites.\n\n<instructions>\n{}\n</instructions>\n\n\
+                 Passed {} of {} cases. Failures:\n\n{failures}",
+                self.candidates, current.instructions, current.passed, current.cases
+            ))
+            .await?;
+
+        Ok(proposal
+            .rewrites
+            .into_iter()
+            .map(|rewrite| rewrite.trim().to_string())
+            .filter(|rewrite| !rewrite.is_empty())
+            .take(self.candidates)
+            .collect())
+    }
+}
+
+impl fmt::Display for OptimizationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<4} {:>5} {:>9}  instructions",
+            "", "round", "accuracy"
+        )?;
+        for (i, candidate) in self.candidates.iter().enumerate() {
+            let marker = if i == self.best { "*" } else { "" };
+            let first_line = candidate.instructions.lines().next().unwrap_or("");
+            writeln!(
+                f,
+                "{marker:<4} {:>5} {:>8.1}%  {first_line}",
+                candidate.round,
+                candidate.accuracy() * 100.0,
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+//! Output types with special handling.
+
+use std::borrow::Cow;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::{Deserialize, Serialize};
+
+/// Binary content produced by the LLM, such as an image or an SVG badge.
+///
+/// Use this as the output type of a think block to have the agent return
+/// base64-encoded content via `return_result`; the library decodes it for you.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let badge: BinaryOutput = d.think()
+///     .text("Generate an SVG badge that says")
+///     .display(&label)
+///     .await?;
+/// std::fs::write("badge.svg", &badge.data)?;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "BinaryOutputWire", into = "BinaryOutputWire")]
+pub struct BinaryOutput {
+    /// The decoded content.
+    pub data: Vec<u8>,
+
+    /// The MIME type of the content (e.g., `image/svg+xml`).
+    pub mime_type: String,
+}
+
+/// The JSON representation of [`BinaryOutput`] exchanged with the LLM.
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "BinaryOutput")]
+struct BinaryOutputWire {
+    /// The content, encoded as standard base64.
+    base64: String,
+
+    /// The MIME type of the content (e.g., `image/png` or `image/svg+xml`).
+    mime_type: String,
+}
+
+impl TryFrom<BinaryOutputWire> for BinaryOutput {
+    type Error = String;
+
+    fn try_from(wire: BinaryOutputWire) -> Result<Self, Self::Error> {
+        let BinaryOutputWire {
+            base64,
+            mut mime_type,
+        } = wire;
+
+        // Models sometimes return a data URI rather than bare base64.
+        let mut encoded = base64.as_str();
+        if let Some(rest) = encoded.strip_prefix("data:")
+            && let Some((media, payload)) = rest.split_once(";base64,")
+        {
+            if mime_type.is_empty() {
+                mime_type = media.to_string();
+            }
+            encoded = payload;
+        }
+
+        let encoded: String = encoded.chars().filter(|c| !c.is_whitespace()).collect();
+        let data = STANDARD
+            .decode(encoded)
+            .map_err(|err| format!("invalid base64 content: {err}"))?;
+
+        Ok(BinaryOutput { data, mime_type })
+    }
+}
+
+impl From<BinaryOutput> for BinaryOutputWire {
+    fn from(output: BinaryOutput) -> Self {
+        BinaryOutputWire {
+            base64: STANDARD.encode(output.data),
+            mime_type: output.mime_type,
+        }
+    }
+}
+
+impl JsonSchema for BinaryOutput {
+    fn schema_name() -> Cow<'static, str> {
+        BinaryOutputWire::schema_name()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        BinaryOutputWire::json_schema(generator)
+    }
+}
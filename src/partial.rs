@@ -0,0 +1,123 @@
+//! Salvaging what the agent produced when a think block fails.
+
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+
+use crate::Error;
+
+/// A think block that failed, along with whatever the agent produced before
+/// it did.
+///
+/// Returned by [`ThinkBuilder::outcome_or_partial`](crate::ThinkBuilder::outcome_or_partial).
+/// When the agent is cancelled, runs out of turns, loses its connection, or
+/// simply stops without calling `return_result`, its last message often
+/// already contains a usable answer. If that message holds JSON matching the
+/// output type, it is parsed into [`output`](Self::output); the caller
+/// decides whether to keep it.
+///
+/// Converts into the underlying [`Error`], so `?` works in functions that
+/// return `Result<_, Error>`.
+pub struct PartialResult<T> {
+    /// Why the think block failed.
+    pub error: Error,
+
+    /// The output parsed from the agent's last message, if it contained
+    /// JSON matching the output type.
+    pub output: Option<T>,
+
+    /// The text of the agent's last message, empty if it sent none.
+    pub message: String,
+}
+
+impl<T: DeserializeOwned> PartialResult<T> {
+    /// A failure, salvaging what we can from the agent's last message.
+    ///
+    /// Nothing is salvaged from a think block that failed a guardrail, and
+    /// only output that `allowed` accepts, as JSON, is kept.
+    pub(crate) fn salvage(
+        error: Error,
+        message: String,
+        allowed: impl Fn(&serde_json::Value) -> bool,
+    ) -> Self {
+        let output = match error {
+            Error::GuardrailViolation { .. } => None,
+            _ => parse_output(&message)
+                .filter(|(_, value)| allowed(value))
+                .map(|(output, _)| output),
+        };
+        Self {
+            error,
+            output,
+            message,
+        }
+    }
+}
+
+impl<T> fmt::Debug for PartialResult<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PartialResult")
+            .field("error", &self.error)
+            .field("output", &self.output)
+            .field("message", &self.message)
+            .finish()
+    }
+}
+
+impl<T> fmt::Display for PartialResult<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl<T> std::error::Error for PartialResult<T>
+where
+    T: fmt::Debug,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl<T> From<PartialResult<T>> for Error {
+    fn from(partial: PartialResult<T>) -> Self {
+        partial.error
+    }
+}
+
+/// Find the last JSON value in `message` that deserializes into `T`,
+/// along with the value.
+///
+/// Agents tend to wrap their answer in prose or a fenced code block, so
+/// rather than parsing the whole message we try every `{` or `[` as a
+/// starting point and keep the last value that fits. A value that fits is
+/// skipped over, so that nothing nested inside it is taken instead; one that
+/// doesn't is searched for nested values.
+fn parse_output<T: DeserializeOwned>(message: &str) -> Option<(T, serde_json::Value)> {
+    if let Ok(value) = serde_json::from_str::<serde_json::Value>(message.trim())
+        && let Ok(output) = T::deserialize(&value)
+    {
+        return Some((output, value));
+    }
+    let mut found = None;
+    let mut start = 0;
+    while let Some(offset) = message[start..].find(['{', '[']) {
+        start += offset;
+        let mut values =
+            serde_json::Deserializer::from_str(&message[start..]).into_iter::<serde_json::Value>();
+        match values.next() {
+            Some(Ok(value)) => match T::deserialize(&value) {
+                Ok(output) => {
+                    found = Some((output, value));
+                    start += values.byte_offset();
+                }
+                Err(_) => start += 1,
+            },
+            _ => start += 1,
+        }
+    }
+    found
+}
@@ -0,0 +1,889 @@
+//! A tool for applying unified diffs from the agent.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use agent_client_protocol::role::Role;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::edits::{resolve, write_file};
+use crate::runtime::blocking;
+use crate::{Error, ToolSet};
+
+/// Context lines a hunk may ignore at each end unless told otherwise.
+const DEFAULT_MAX_FUZZ: usize = 2;
+
+/// Lines of the file shown in a conflict, to help the agent fix the hunk.
+const CONFLICT_EXCERPT_LINES: usize = 10;
+
+/// An `apply_patch` tool that lets the agent edit files under a root
+/// directory by sending unified diffs.
+///
+/// Hunks are matched against the files' current contents, so they still
+/// apply when line numbers have drifted. If a hunk's context doesn't match
+/// exactly, up to [`max_fuzz`](Self::max_fuzz) context lines at each end of
+/// the hunk may be ignored. A patch is applied all or nothing: if any hunk
+/// conflicts, no file is written, and the agent receives a [`PatchResult`]
+/// listing each conflict with the lines actually found, so it can fix the
+/// patch and try again.
+///
+/// Paths are resolved like those of [`CodeEdits`](crate::CodeEdits): any
+/// path that would escape the root is a conflict. The `a/` and `b/` prefixes
+/// written by `git diff` are removed.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let patch = PatchTool::new("./checkout").toolset();
+///
+/// let summary: String = d.think()
+///     .text("Fix the failing test described below by editing the code.")
+///     .display(&failure)
+///     .tools(&patch)
+///     .await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct PatchTool {
+    root: PathBuf,
+    max_fuzz: usize,
+}
+
+/// What happened when a patch was applied.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct PatchResult {
+    /// Whether the patch was applied. If any hunk conflicts, no file is
+    /// changed.
+    pub applied: bool,
+
+    /// The files the patch changes, with how each hunk matched.
+    pub files: Vec<PatchedFile>,
+
+    /// The hunks that could not be applied.
+    pub conflicts: Vec<PatchConflict>,
+}
+
+/// A file changed by a patch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct PatchedFile {
+    /// The file's path, relative to the root.
+    pub path: String,
+
+    /// Whether the patch creates the file.
+    pub created: bool,
+
+    /// Whether the patch deletes the file.
+    pub deleted: bool,
+
+    /// How many hunks applied.
+    pub hunks: usize,
+
+    /// How many hunks only applied after ignoring some of their context.
+    pub fuzzed_hunks: usize,
+}
+
+/// A part of a patch that could not be applied.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct PatchConflict {
+    /// The file's path, relative to the root.
+    pub path: String,
+
+    /// The hunk that failed, counting from 1, or `None` if the problem is
+    /// with the file itself.
+    pub hunk: Option<usize>,
+
+    /// What went wrong.
+    pub message: String,
+
+    /// The lines of the file where the hunk was expected, if it exists.
+    pub found: Option<String>,
+}
+
+/// Input to the `apply_patch` tool.
+#[derive(Debug, Deserialize, JsonSchema)]
+struct PatchInput {
+    /// A unified diff, as written by `git diff` or `diff -u`, with paths
+    /// relative to the project root. Use `/dev/null` as the old path to
+    /// create a file and as the new path to delete one. The line counts in
+    /// each `@@` header must match the hunk's lines.
+    patch: String,
+}
+
+impl PatchTool {
+    /// A tool that applies patches to the files under `root`.
+    ///
+    /// Up to 2 context lines at each end of a hunk may be ignored by default.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            max_fuzz: DEFAULT_MAX_FUZZ,
+        }
+    }
+
+    /// Let hunks ignore up to `lines` context lines at each end when their
+    /// context doesn't match. Zero requires every context line to match.
+    pub fn max_fuzz(mut self, lines: usize) -> Self {
+        self.max_fuzz = lines;
+        self
+    }
+
+    /// Apply `patch`, writing the changed files only if every hunk applies.
+    ///
+    /// Conflicts are reported in the result; this fails only if the patch
+    /// can't be parsed, the root doesn't exist, or a file can't be written.
+    /// New contents are written beside their files and only moved into
+    /// place once every file has been, so a file that can't be written
+    /// leaves the others unchanged too.
+    pub fn apply(&self, patch: &str) -> Result<PatchResult, Error> {
+        let root = self
+            .root
+            .canonicalize()
+            .map_err(|err| Error::edit_io(&self.root.display().to_string(), "invalid root", err))?;
+        let file_patches = parse(patch).map_err(|message| Error::edit("patch", message))?;
+
+        let mut result = PatchResult {
+            applied: false,
+            files: Vec::new(),
+            conflicts: Vec::new(),
+        };
+        let mut writes: Vec<PendingWrite> = Vec::new();
+        for file_patch in &file_patches {
+            match self.apply_file(&root, file_patch) {
+                // Each section is applied to the file as it is on disk, so a
+                // second one would undo the first
+                Ok((file, write)) if writes.iter().any(|w| w.full_path == write.full_path) => {
+                    result.conflicts.push(PatchConflict {
+                        path: file.path,
+                        hunk: None,
+                        message: "the patch changes this file in more than one section; put all \
+                                  of its hunks under one `---`/`+++` header"
+                            .to_string(),
+                        found: None,
+                    });
+                }
+                Ok((file, write)) => {
+                    result.files.push(file);
+                    writes.push(write);
+                }
+                Err(conflicts) => result.conflicts.extend(conflicts),
+            }
+        }
+        if !result.conflicts.is_empty() {
+            debug!(conflicts = result.conflicts.len(), "patch not applied");
+            return Ok(result);
+        }
+
+        write_all(&writes)?;
+        debug!(files = result.files.len(), "patch applied");
+        result.applied = true;
+        Ok(result)
+    }
+
+    /// A tool set containing the `apply_patch` tool, to pass to
+    /// [`ThinkBuilder::tools`](crate::ThinkBuilder::tools).
+    pub fn toolset<R: Role>(self) -> ToolSet<R> {
+        let tool = Arc::new(self);
+        ToolSet::default().tool(
+            "apply_patch",
+            "Edit files by applying a unified diff. Either every hunk applies or no file is \
+             changed; conflicts are reported with the lines actually found, so you can fix the \
+             patch and try again.",
+            move |input: PatchInput| {
+                let tool = tool.clone();
+                async move {
+                    blocking(move || tool.apply(&input.patch))
+                        .await
+                        .map_err(|err| {
+                            agent_client_protocol::Error::invalid_params().data(err.to_string())
+                        })
+                }
+            },
+        )
+    }
+
+    /// Apply one file's hunks in memory, returning what to write or every
+    /// conflict found.
+    fn apply_file(
+        &self,
+        root: &Path,
+        file_patch: &FilePatch,
+    ) -> Result<(PatchedFile, PendingWrite), Vec<PatchConflict>> {
+        let path = file_patch.path().to_string();
+        let conflict = |hunk: Option<usize>, message: String, found: Option<String>| {
+            vec![PatchConflict {
+                path: path.clone(),
+                hunk,
+                message,
+                found,
+            }]
+        };
+
+        let full_path = resolve(root, &path).map_err(|err| match err {
+            Error::Edit { message, .. } => conflict(None, message, None),
+            other => conflict(None, other.to_string(), None),
+        })?;
+        let created = file_patch.old_path.is_none();
+        let deleted = file_patch.new_path.is_none();
+        let contents = match std::fs::read_to_string(&full_path) {
+            Ok(_) if created => {
+                return Err(conflict(
+                    None,
+                    "the patch creates the file, but it already exists".to_string(),
+                    None,
+                ));
+            }
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound && created => String::new(),
+            Err(err) => return Err(conflict(None, format!("cannot read file: {err}"), None)),
+        };
+
+        let mut lines: Vec<String> = contents.split_inclusive('\n').map(str::to_string).collect();
+        let line_ending = if lines.first().is_some_and(|line| line.ends_with("\r\n")) {
+            "\r\n"
+        } else {
+            "\n"
+        };
+
+        let mut conflicts = Vec::new();
+        let mut fuzzed_hunks = 0;
+        // Where the next hunk may start, and how far hunks have drifted
+        let mut next = 0;
+        let mut offset: isize = 0;
+        for (index, hunk) in file_patch.hunks.iter().enumerate() {
+            let expected = hunk
+                .old_start
+                .saturating_sub(1)
+                .saturating_add_signed(offset)
+                .max(next);
+            let Some((start, fuzz)) = self.locate(&lines, hunk, expected, next) else {
+                let excerpt_end = (expected + CONFLICT_EXCERPT_LINES).min(lines.len());
+                let found = (expected < lines.len()).then(|| lines[expected..excerpt_end].concat());
+                conflicts.extend(conflict(
+                    Some(index + 1),
+                    format!(
+                        "hunk `@@ -{},{} +{},{} @@` does not match the file",
+                        hunk.old_start,
+                        hunk.old_lines().count(),
+                        hunk.new_start,
+                        hunk.new_lines().count()
+                    ),
+                    found,
+                ));
+                continue;
+            };
+            if fuzz > 0 {
+                fuzzed_hunks += 1;
+            }
+
+            // Replace the matched lines, leaving the context that fuzz ignored
+            let (leading, trailing) = hunk.trimmed_context(fuzz);
+            let matched = hunk.old_lines().count() - leading - trailing;
+            let new: Vec<&Line> = hunk.new_lines().collect();
+            let mut new: Vec<String> = new[leading..new.len() - trailing]
+                .iter()
+                .map(|line| format!("{}{line_ending}", line.text))
+                .collect();
+            if hunk.no_newline_at_end
+                && start + matched == lines.len()
+                && let Some(last) = new.last_mut()
+            {
+                last.truncate(last.len() - line_ending.len());
+            }
+            let added = new.len();
+            lines.splice(start..start + matched, new);
+            next = start + added;
+            offset = start as isize - (hunk.old_start.saturating_sub(1) + leading) as isize
+                + added as isize
+                - matched as isize;
+        }
+        if !conflicts.is_empty() {
+            return Err(conflicts);
+        }
+
+        let new_contents = lines.concat();
+        if deleted && !new_contents.is_empty() {
+            return Err(conflict(
+                None,
+                "the patch deletes the file, but does not remove all of its lines".to_string(),
+                None,
+            ));
+        }
+        let file = PatchedFile {
+            path: path.clone(),
+            created,
+            deleted,
+            hunks: file_patch.hunks.len(),
+            fuzzed_hunks,
+        };
+        let write = PendingWrite {
+            full_path,
+            path,
+            created,
+            contents: (!deleted).then_some(new_contents),
+        };
+        Ok((file, write))
+    }
+
+    /// Find where `hunk` applies, searching outward from `expected` but not
+    /// before `earliest`, ignoring more context each round up to the fuzz
+    /// limit. Returns the start line and the fuzz used.
+    fn locate(
+        &self,
+        lines: &[String],
+        hunk: &Hunk,
+        expected: usize,
+        earliest: usize,
+    ) -> Option<(usize, usize)> {
+        let old: Vec<&Line> = hunk.old_lines().collect();
+        let max_fuzz = self.max_fuzz.min(hunk.max_fuzz());
+        for fuzz in 0..=max_fuzz {
+            let (leading, trailing) = hunk.trimmed_context(fuzz);
+            let wanted = &old[leading..old.len() - trailing];
+            if wanted.len() > lines.len() {
+                return None;
+            }
+            let matches_at = |start: usize| {
+                start >= earliest
+                    && start + wanted.len() <= lines.len()
+                    && wanted
+                        .iter()
+                        .zip(&lines[start..])
+                        .all(|(want, have)| want.text == have.trim_end_matches(['\r', '\n']))
+            };
+            let expected = expected + leading;
+            let last = lines.len() - wanted.len();
+            for distance in 0..=lines.len() {
+                if let Some(start) = expected.checked_sub(distance)
+                    && matches_at(start)
+                {
+                    return Some((start, fuzz));
+                }
+                if distance > 0 && expected + distance <= last && matches_at(expected + distance) {
+                    return Some((expected + distance, fuzz));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Make every write in `writes`, or none of them.
+///
+/// Each file's new contents go to a temporary file beside it, and each file
+/// to delete is moved aside; if any of that fails, it is all undone. Only
+/// then are the temporary files renamed over their targets and the moved
+/// files removed.
+fn write_all(writes: &[PendingWrite]) -> Result<(), Error> {
+    let mut staged: Vec<(&PendingWrite, PathBuf)> = Vec::new();
+    for write in writes {
+        match write.stage() {
+            Ok(temp) => staged.push((write, temp)),
+            Err(err) => {
+                for (write, temp) in staged.iter().rev() {
+                    let _ = match write.contents {
+                        Some(_) => std::fs::remove_file(temp),
+                        None => std::fs::rename(temp, &write.full_path),
+                    };
+                }
+                return Err(err);
+            }
+        }
+    }
+    for (write, temp) in staged {
+        match write.contents {
+            Some(_) => std::fs::rename(&temp, &write.full_path)
+                .map_err(|err| Error::edit_io(&write.path, "cannot write file", err))?,
+            None => std::fs::remove_file(&temp)
+                .map_err(|err| Error::edit_io(&write.path, "cannot delete file", err))?,
+        }
+    }
+    Ok(())
+}
+
+/// A path beside `full_path` for staging a write to it.
+fn staging_path(full_path: &Path) -> PathBuf {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let name = full_path.file_name().unwrap_or_default().to_string_lossy();
+    full_path.with_file_name(format!(
+        ".{name}.{}-{}.patch",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// A file to write once every hunk is known to apply.
+struct PendingWrite {
+    full_path: PathBuf,
+    path: String,
+    /// Whether the file is new.
+    created: bool,
+    /// The new contents, or `None` to delete the file.
+    contents: Option<String>,
+}
+
+impl PendingWrite {
+    /// Write the new contents to a temporary file beside the file, or move
+    /// the file aside if it is to be deleted, returning the path used.
+    fn stage(&self) -> Result<PathBuf, Error> {
+        let temp = staging_path(&self.full_path);
+        let Some(contents) = &self.contents else {
+            std::fs::rename(&self.full_path, &temp)
+                .map_err(|err| Error::edit_io(&self.path, "cannot delete file", err))?;
+            return Ok(temp);
+        };
+        if self.created {
+            if let Some(parent) = self.full_path.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|err| Error::edit_io(&self.path, "cannot create directory", err))?;
+            }
+            // Renaming would replace a file that has appeared since
+            if std::fs::symlink_metadata(&self.full_path).is_ok() {
+                return Err(Error::edit(&self.path, "the file already exists"));
+            }
+        }
+        let staged = write_file(&temp, contents, true).and_then(|()| {
+            if self.created {
+                return Ok(());
+            }
+            // Renaming over the file would otherwise reset its permissions
+            let permissions = std::fs::metadata(&self.full_path)?.permissions();
+            std::fs::set_permissions(&temp, permissions)
+        });
+        if let Err(err) = staged {
+            let _ = std::fs::remove_file(&temp);
+            return Err(Error::edit_io(&self.path, "cannot write file", err));
+        }
+        Ok(temp)
+    }
+}
+
+/// The hunks for one file.
+#[derive(Debug)]
+struct FilePatch {
+    /// The old path, or `None` if the file is created.
+    old_path: Option<String>,
+    /// The new path, or `None` if the file is deleted.
+    new_path: Option<String>,
+    hunks: Vec<Hunk>,
+}
+
+impl FilePatch {
+    fn path(&self) -> &str {
+        self.new_path
+            .as_deref()
+            .or(self.old_path.as_deref())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug)]
+struct Hunk {
+    old_start: usize,
+    new_start: usize,
+    lines: Vec<Line>,
+    /// Whether the new file's last line has no line ending.
+    no_newline_at_end: bool,
+}
+
+#[derive(Debug)]
+struct Line {
+    kind: LineKind,
+    text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineKind {
+    Context,
+    Removed,
+    Added,
+}
+
+impl Hunk {
+    /// The lines the hunk expects to find.
+    fn old_lines(&self) -> impl Iterator<Item = &Line> {
+        self.lines.iter().filter(|l| l.kind != LineKind::Added)
+    }
+
+    /// The lines the hunk leaves behind.
+    fn new_lines(&self) -> impl Iterator<Item = &Line> {
+        self.lines.iter().filter(|l| l.kind != LineKind::Removed)
+    }
+
+    /// How many context lines to ignore at the start and end for `fuzz`.
+    fn trimmed_context(&self, fuzz: usize) -> (usize, usize) {
+        let is_context = |l: &&Line| l.kind == LineKind::Context;
+        let leading = self.lines.iter().take_while(is_context).count();
+        let trailing = self.lines.iter().rev().take_while(is_context).count();
+        (leading.min(fuzz), trailing.min(fuzz))
+    }
+
+    /// The most fuzz that can make a difference to this hunk.
+    fn max_fuzz(&self) -> usize {
+        let (leading, trailing) = self.trimmed_context(usize::MAX);
+        leading.max(trailing)
+    }
+}
+
+/// Parse a unified diff into per-file hunks.
+fn parse(patch: &str) -> Result<Vec<FilePatch>, String> {
+    let lines: Vec<&str> = patch.lines().collect();
+    // A `---` line only starts a file if a `+++` line follows; otherwise it
+    // removes a line that starts with `--`
+    let is_file_header = |i: usize| {
+        lines[i].starts_with("--- ") && lines.get(i + 1).is_some_and(|l| l.starts_with("+++ "))
+    };
+
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        i += 1;
+        if is_file_header(i - 1) {
+            let old_path = patch_path(&line[4..], "a/");
+            let new_path = patch_path(&lines[i][4..], "b/");
+            i += 1;
+            if old_path.is_none() && new_path.is_none() {
+                return Err("a file cannot be both created and deleted".to_string());
+            }
+            files.push(FilePatch {
+                old_path,
+                new_path,
+                hunks: Vec::new(),
+            });
+        } else if let Some(header) = line.strip_prefix("@@ ") {
+            let Some(file) = files.last_mut() else {
+                return Err("a hunk comes before any `---`/`+++` file header".to_string());
+            };
+            let (old_start, mut old_left, new_start, mut new_left) =
+                parse_hunk_header(header).ok_or_else(|| format!("invalid hunk header `{line}`"))?;
+            let mut hunk = Hunk {
+                old_start,
+                new_start,
+                lines: Vec::new(),
+                no_newline_at_end: false,
+            };
+            // The body is exactly as long as the header says, so a removed
+            // `-- ` line followed by an added `++ ` one is not a file header
+            while old_left > 0 || new_left > 0 || lines.get(i).is_some_and(|l| l.starts_with('\\'))
+            {
+                let Some(body) = lines.get(i) else {
+                    return Err(format!("hunk `{line}` ends before all the lines it counts"));
+                };
+                let (kind, text) = match body.chars().next() {
+                    Some(' ') => (LineKind::Context, &body[1..]),
+                    Some('-') => (LineKind::Removed, &body[1..]),
+                    Some('+') => (LineKind::Added, &body[1..]),
+                    Some('\\') => {
+                        // "\ No newline at end of file" applies to the line before
+                        if hunk
+                            .lines
+                            .last()
+                            .is_some_and(|l| l.kind != LineKind::Removed)
+                        {
+                            hunk.no_newline_at_end = true;
+                        }
+                        i += 1;
+                        continue;
+                    }
+                    // Models often drop the space on blank context lines
+                    None => (LineKind::Context, ""),
+                    _ => {
+                        return Err(format!(
+                            "hunk `{line}` ends before all the lines it counts, at `{body}`"
+                        ));
+                    }
+                };
+                let (old, new) = match kind {
+                    LineKind::Context => (1, 1),
+                    LineKind::Removed => (1, 0),
+                    LineKind::Added => (0, 1),
+                };
+                if old > old_left || new > new_left {
+                    return Err(format!("hunk `{line}` has more lines than it counts"));
+                }
+                old_left -= old;
+                new_left -= new;
+                hunk.lines.push(Line {
+                    kind,
+                    text: text.to_string(),
+                });
+                i += 1;
+            }
+            if lines
+                .get(i)
+                .is_some_and(|l| (l.starts_with('-') || l.starts_with('+')) && !is_file_header(i))
+            {
+                return Err(format!("hunk `{line}` has more lines than it counts"));
+            }
+            if hunk.lines.iter().all(|l| l.kind == LineKind::Context) {
+                return Err(format!("hunk `{line}` changes nothing"));
+            }
+            file.hunks.push(hunk);
+        }
+        // Anything else (`diff --git`, `index`, commentary) is skipped
+    }
+    if files.is_empty() {
+        return Err("no file headers (`---`/`+++` lines) found".to_string());
+    }
+    Ok(files)
+}
+
+/// The path in a `---` or `+++` line, without `prefix` or a timestamp, or
+/// `None` for `/dev/null`.
+fn patch_path(text: &str, prefix: &str) -> Option<String> {
+    let path = text.split('\t').next().unwrap_or_default().trim();
+    if path == "/dev/null" {
+        return None;
+    }
+    Some(path.strip_prefix(prefix).unwrap_or(path).to_string())
+}
+
+/// The old start line and line count, then the new ones, in a hunk header
+/// such as `-12,5 +12,6 @@ fn main() {`. A range without a count has one
+/// line.
+fn parse_hunk_header(header: &str) -> Option<(usize, usize, usize, usize)> {
+    let mut ranges = header.split_whitespace();
+    let range = |range: Option<&str>, sign: char| -> Option<(usize, usize)> {
+        let range = range?.strip_prefix(sign)?;
+        let (start, count) = range.split_once(',').unwrap_or((range, "1"));
+        Some((start.parse().ok()?, count.parse().ok()?))
+    };
+    let (old_start, old_count) = range(ranges.next(), '-')?;
+    let (new_start, new_count) = range(ranges.next(), '+')?;
+    Some((old_start, old_count, new_start, new_count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh, canonical directory to use as a root.
+    fn temp_root(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "determinishtic-patch-{name}-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    fn read(root: &Path, path: &str) -> String {
+        std::fs::read_to_string(root.join(path)).unwrap()
+    }
+
+    #[test]
+    fn hunks_end_where_their_header_says() {
+        let root = temp_root("counts");
+        std::fs::write(
+            root.join("query.sql"),
+            "select 1;\n-- old note\nselect 2;\n",
+        )
+        .unwrap();
+        let patch = "--- a/query.sql\n+++ b/query.sql\n@@ -1,3 +1,3 @@\n select 1;\n--- old note\n+++ new note\n select 2;\n";
+
+        let result = PatchTool::new(&root).apply(patch).unwrap();
+
+        assert!(result.applied, "{result:?}");
+        assert_eq!(result.files.len(), 1);
+        assert_eq!(
+            read(&root, "query.sql"),
+            "select 1;\n++ new note\nselect 2;\n"
+        );
+    }
+
+    #[test]
+    fn rejects_hunks_that_miscount_their_lines() {
+        let short = "--- a/a.txt\n+++ b/a.txt\n@@ -1,3 +1,3 @@\n one\n-two\n+2\n";
+        assert!(parse(short).is_err());
+        let long = "--- a/a.txt\n+++ b/a.txt\n@@ -1,1 +1,1 @@\n-one\n+1\n-two\n+2\n";
+        assert!(parse(long).is_err());
+    }
+
+    #[test]
+    fn applies_hunks_whose_lines_have_drifted() {
+        let root = temp_root("drift");
+        std::fs::write(root.join("a.txt"), "new\nheader\none\ntwo\nthree\nfour\n").unwrap();
+        let patch = "--- a/a.txt\n+++ b/a.txt\n@@ -1,3 +1,3 @@\n one\n-two\n+2\n three\n\
+                     @@ -4,1 +4,1 @@\n-four\n+4\n";
+
+        let result = PatchTool::new(&root).apply(patch).unwrap();
+
+        assert!(result.applied, "{result:?}");
+        assert_eq!(result.files[0].fuzzed_hunks, 0);
+        assert_eq!(read(&root, "a.txt"), "new\nheader\none\n2\nthree\n4\n");
+    }
+
+    #[test]
+    fn fuzz_ignores_context_at_the_ends_of_a_hunk() {
+        let root = temp_root("fuzz");
+        let original = "one\ntwo\nthree\nfour\nfive\n";
+        std::fs::write(root.join("a.txt"), original).unwrap();
+        let patch =
+            "--- a/a.txt\n+++ b/a.txt\n@@ -1,5 +1,5 @@\n uno\n two\n-three\n+3\n four\n cinco\n";
+
+        let strict = PatchTool::new(&root).max_fuzz(0).apply(patch).unwrap();
+        assert!(!strict.applied);
+        assert_eq!(read(&root, "a.txt"), original);
+
+        let result = PatchTool::new(&root).apply(patch).unwrap();
+        assert!(result.applied, "{result:?}");
+        assert_eq!(result.files[0].fuzzed_hunks, 1);
+        assert_eq!(read(&root, "a.txt"), "one\ntwo\n3\nfour\nfive\n");
+    }
+
+    #[test]
+    fn conflicts_show_the_lines_found() {
+        let root = temp_root("excerpt");
+        std::fs::write(root.join("a.txt"), "one\ntwo\nthree\n").unwrap();
+        let patch = "--- a/a.txt\n+++ b/a.txt\n@@ -2,1 +2,1 @@\n-deux\n+2\n";
+
+        let result = PatchTool::new(&root).apply(patch).unwrap();
+
+        assert!(!result.applied);
+        assert_eq!(
+            result.conflicts,
+            [PatchConflict {
+                path: "a.txt".to_string(),
+                hunk: Some(1),
+                message: "hunk `@@ -2,1 +2,1 @@` does not match the file".to_string(),
+                found: Some("two\nthree\n".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn creates_and_deletes_files() {
+        let root = temp_root("create-delete");
+        std::fs::write(root.join("old.txt"), "gone\n").unwrap();
+        let patch = "--- /dev/null\n+++ b/src/new.txt\n@@ -0,0 +1,2 @@\n+hello\n+world\n\
+                     --- a/old.txt\n+++ /dev/null\n@@ -1,1 +0,0 @@\n-gone\n";
+
+        let result = PatchTool::new(&root).apply(patch).unwrap();
+
+        assert!(result.applied, "{result:?}");
+        assert!(result.files[0].created && result.files[1].deleted);
+        assert_eq!(read(&root, "src/new.txt"), "hello\nworld\n");
+        assert!(!root.join("old.txt").exists());
+
+        // Creating it again conflicts
+        let again = PatchTool::new(&root).apply(patch).unwrap();
+        assert!(!again.applied);
+    }
+
+    #[test]
+    fn keeps_a_missing_final_newline() {
+        let root = temp_root("newline");
+        std::fs::write(root.join("a.txt"), "one\ntwo").unwrap();
+        let patch = "--- a/a.txt\n+++ b/a.txt\n@@ -1,2 +1,2 @@\n one\n-two\n\\ No newline at end of file\n+2\n\\ No newline at end of file\n";
+
+        let result = PatchTool::new(&root).apply(patch).unwrap();
+
+        assert!(result.applied, "{result:?}");
+        assert_eq!(read(&root, "a.txt"), "one\n2");
+    }
+
+    #[test]
+    fn a_conflict_leaves_every_file_unchanged() {
+        let root = temp_root("all-or-nothing");
+        std::fs::write(root.join("a.txt"), "one\n").unwrap();
+        std::fs::write(root.join("b.txt"), "two\n").unwrap();
+        let patch = "--- a/a.txt\n+++ b/a.txt\n@@ -1 +1 @@\n-one\n+1\n\
+                     --- a/b.txt\n+++ b/b.txt\n@@ -1 +1 @@\n-deux\n+2\n";
+
+        let result = PatchTool::new(&root).apply(patch).unwrap();
+
+        assert!(!result.applied);
+        assert_eq!(result.conflicts.len(), 1);
+        assert_eq!(read(&root, "a.txt"), "one\n");
+        assert_eq!(read(&root, "b.txt"), "two\n");
+    }
+
+    #[test]
+    fn rejects_two_sections_for_one_file() {
+        let root = temp_root("sections");
+        std::fs::write(root.join("a.txt"), "one\ntwo\n").unwrap();
+        let patch = "--- a/a.txt\n+++ b/a.txt\n@@ -1 +1 @@\n-one\n+1\n\
+                     --- a/./a.txt\n+++ b/./a.txt\n@@ -2 +2 @@\n-two\n+2\n";
+
+        let result = PatchTool::new(&root).apply(patch).unwrap();
+
+        assert!(!result.applied);
+        assert_eq!(result.conflicts.len(), 1);
+        assert_eq!(read(&root, "a.txt"), "one\ntwo\n");
+    }
+
+    #[test]
+    fn a_failed_write_undoes_the_others() {
+        let root = temp_root("undo");
+        std::fs::write(root.join("a.txt"), "one\n").unwrap();
+        std::fs::write(root.join("b.txt"), "two\n").unwrap();
+        let writes = [
+            PendingWrite {
+                full_path: root.join("a.txt"),
+                path: "a.txt".to_string(),
+                created: false,
+                contents: Some("1\n".to_string()),
+            },
+            PendingWrite {
+                full_path: root.join("b.txt"),
+                path: "b.txt".to_string(),
+                created: false,
+                contents: None,
+            },
+            // Its directory is gone, so it can't be staged
+            PendingWrite {
+                full_path: root.join("gone/c.txt"),
+                path: "gone/c.txt".to_string(),
+                created: false,
+                contents: Some("3\n".to_string()),
+            },
+        ];
+
+        assert!(write_all(&writes).is_err());
+
+        assert_eq!(read(&root, "a.txt"), "one\n");
+        assert_eq!(read(&root, "b.txt"), "two\n");
+        assert_eq!(std::fs::read_dir(&root).unwrap().count(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_file_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = temp_root("permissions");
+        std::fs::write(root.join("run.sh"), "echo one\n").unwrap();
+        std::fs::set_permissions(root.join("run.sh"), std::fs::Permissions::from_mode(0o755))
+            .unwrap();
+        let patch = "--- a/run.sh\n+++ b/run.sh\n@@ -1 +1 @@\n-echo one\n+echo 1\n";
+
+        assert!(PatchTool::new(&root).apply(patch).unwrap().applied);
+
+        let mode = std::fs::metadata(root.join("run.sh"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o755);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn does_not_create_files_through_a_dangling_symlink() {
+        let base =
+            std::env::temp_dir().join(format!("determinishtic-patch-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        let (root, outside) = (base.join("root"), base.join("outside"));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(outside.join("planted"), root.join("out")).unwrap();
+
+        let result = PatchTool::new(&root)
+            .apply("--- /dev/null\n+++ b/out\n@@ -0,0 +1 @@\n+planted\n")
+            .unwrap();
+        assert!(!result.applied);
+        assert!(!outside.join("planted").exists());
+    }
+}
@@ -0,0 +1,300 @@
+//! Answering the agent's requests for permission to use its tools.
+
+use agent_client_protocol::schema::{
+    ContentBlock, PermissionOption, PermissionOptionKind, RequestPermissionOutcome,
+    RequestPermissionRequest, RequestPermissionResponse, SelectedPermissionOutcome,
+    ToolCallContent, ToolKind,
+};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::client::ClientHandlers;
+
+/// How to answer a permission request.
+///
+/// Returned by the handler registered with
+/// [`DeterminishticBuilder::on_permission`](crate::DeterminishticBuilder::on_permission).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Decision {
+    /// Allow this one tool call.
+    AllowOnce,
+    /// Allow this tool call, and similar ones for the rest of the session.
+    AllowAlways,
+    /// Reject this one tool call.
+    RejectOnce,
+    /// Reject this tool call, and similar ones for the rest of the session.
+    RejectAlways,
+    /// Answer neither way, cancelling the tool call.
+    Cancel,
+}
+
+impl Decision {
+    /// Option kinds that carry out this decision, most preferred first.
+    fn option_kinds(self) -> &'static [PermissionOptionKind] {
+        match self {
+            Decision::AllowOnce => &[
+                PermissionOptionKind::AllowOnce,
+                PermissionOptionKind::AllowAlways,
+            ],
+            Decision::AllowAlways => &[
+                PermissionOptionKind::AllowAlways,
+                PermissionOptionKind::AllowOnce,
+            ],
+            Decision::RejectOnce => &[
+                PermissionOptionKind::RejectOnce,
+                PermissionOptionKind::RejectAlways,
+            ],
+            Decision::RejectAlways => &[
+                PermissionOptionKind::RejectAlways,
+                PermissionOptionKind::RejectOnce,
+            ],
+            Decision::Cancel => &[],
+        }
+    }
+
+    fn is_sticky(self) -> bool {
+        matches!(self, Decision::AllowAlways | Decision::RejectAlways)
+    }
+}
+
+/// Rules deciding permission requests by the kind of tool the agent wants
+/// to use.
+///
+/// Register with
+/// [`DeterminishticBuilder::permissions`](crate::DeterminishticBuilder::permissions).
+/// Requests for kinds with no rule, or marked [`ask`](Self::ask), go to the
+/// [`on_permission`](crate::DeterminishticBuilder::on_permission) handler as usual.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let d = Determinishtic::builder()
+///     .permissions(
+///         Permissions::new()
+///             .allow(ToolKind::Read)
+///             .allow(ToolKind::Search)
+///             .deny(ToolKind::Execute)
+///             .ask(ToolKind::Edit),
+///     )
+///     .on_permission(async |request: RequestPermissionRequest| prompt_user(request).await)
+///     .connect(AcpAgent::zed_claude_code())
+///     .await?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Permissions {
+    rules: Vec<(ToolKind, Rule)>,
+}
+
+/// What a [`Permissions`] rule does with matching requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Rule {
+    Allow,
+    Deny,
+    Ask,
+}
+
+impl Permissions {
+    /// Create an empty rule set, which decides nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow every request to use tools of this kind.
+    ///
+    /// Under [`SecurityProfile::Strict`](crate::SecurityProfile::Strict), this
+    /// counts as allowlisting the kind.
+    pub fn allow(self, kind: ToolKind) -> Self {
+        self.rule(kind, Rule::Allow)
+    }
+
+    /// Reject every request to use tools of this kind.
+    pub fn deny(self, kind: ToolKind) -> Self {
+        self.rule(kind, Rule::Deny)
+    }
+
+    /// Leave requests to use tools of this kind to the
+    /// [`on_permission`](crate::DeterminishticBuilder::on_permission) handler.
+    ///
+    /// This is what happens to kinds without a rule; use it to override an
+    /// earlier rule, or to make the intent explicit.
+    pub fn ask(self, kind: ToolKind) -> Self {
+        self.rule(kind, Rule::Ask)
+    }
+
+    /// Set the rule for `kind`, replacing any earlier one.
+    fn rule(mut self, kind: ToolKind, rule: Rule) -> Self {
+        self.rules.retain(|(k, _)| *k != kind);
+        self.rules.push((kind, rule));
+        self
+    }
+
+    /// The rule for requests of this kind, if there is one.
+    pub(crate) fn rule_for(&self, kind: Option<ToolKind>) -> Option<Rule> {
+        let kind = kind?;
+        self.rules
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .map(|&(_, rule)| rule)
+    }
+}
+
+/// Who made a permission decision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DecidedBy {
+    /// The handler registered with
+    /// [`DeterminishticBuilder::on_permission`](crate::DeterminishticBuilder::on_permission).
+    Handler,
+    /// An earlier "always" decision for a similar tool call in the same session.
+    Remembered,
+    /// No handler was registered, so the request was allowed once.
+    Default,
+    /// A [`Permissions`] rule for the tool's kind.
+    Rule,
+    /// The [`SecurityProfile`](crate::SecurityProfile) rejected it without
+    /// asking the handler.
+    Profile,
+    /// A [`Guardrails`](crate::Guardrails) profile denied the tool.
+    Guardrails,
+}
+
+/// An entry in the audit log of permission decisions.
+///
+/// Available from [`ThinkOutcome::permissions`](crate::ThinkOutcome::permissions).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PermissionRecord {
+    /// The title of the tool call the agent asked about.
+    pub tool: Option<String>,
+    /// The kind of the tool call, if the agent said.
+    pub kind: Option<ToolKind>,
+    /// The text the agent gave to describe the tool call, for showing in a
+    /// consent dialog.
+    ///
+    /// `None` if the agent gave none, or if details were turned off with
+    /// [`DeterminishticBuilder::permission_details`](crate::DeterminishticBuilder::permission_details).
+    pub description: Option<String>,
+    /// The tool call's arguments as the agent reported them, under the same
+    /// conditions as [`description`](Self::description).
+    pub raw_input: Option<serde_json::Value>,
+    /// The options the agent offered, with their labels, in its order.
+    pub options: Vec<PermissionOption>,
+    /// The decision that was made.
+    pub decision: Decision,
+    /// Who made it.
+    pub decided_by: DecidedBy,
+    /// The id of the option sent back to the agent, or `None` if the request
+    /// was cancelled because no option matched the decision.
+    pub option: Option<String>,
+}
+
+/// Permission decisions made during a session.
+///
+/// "Always" decisions are remembered and reused for similar tool calls,
+/// i.e. those with the same title and kind. Every decision is recorded.
+#[derive(Debug, Default)]
+pub(crate) struct PermissionMemory {
+    remembered: Vec<(Option<String>, Option<ToolKind>, Decision)>,
+    records: Vec<PermissionRecord>,
+}
+
+impl PermissionMemory {
+    /// Decide on a request, asking the host's handler unless a rule or an
+    /// earlier "always" decision applies.
+    ///
+    /// The decision is added to the log and also returned.
+    pub(crate) async fn decide(
+        &mut self,
+        client: &ClientHandlers,
+        request: RequestPermissionRequest,
+    ) -> (RequestPermissionResponse, PermissionRecord) {
+        let title = request.tool_call.fields.title.clone();
+        let kind = request.tool_call.fields.kind;
+
+        let remembered = self
+            .remembered
+            .iter()
+            .find(|(t, k, _)| *t == title && *k == kind)
+            .map(|&(_, _, decision)| decision);
+        let (decision, decided_by) = match (client.permissions().rule_for(kind), remembered) {
+            _ if let Some(profile) = client.denied_by(title.as_deref()) => {
+                debug!(?title, profile, "rejecting permission request denied by guardrails");
+                (Decision::RejectOnce, DecidedBy::Guardrails)
+            }
+            (Some(Rule::Allow), _) => {
+                debug!(?kind, "allowing permission request by rule");
+                (Decision::AllowOnce, DecidedBy::Rule)
+            }
+            (Some(Rule::Deny), _) => {
+                debug!(?kind, "rejecting permission request by rule");
+                (Decision::RejectOnce, DecidedBy::Rule)
+            }
+            _ if !client.may_allow(title.as_deref()) => {
+                debug!("rejecting permission request not allowed by the security profile");
+                (Decision::RejectOnce, DecidedBy::Profile)
+            }
+            (_, Some(decision)) => {
+                debug!(?title, ?decision, "reusing remembered permission decision");
+                (decision, DecidedBy::Remembered)
+            }
+            (_, None) => match client.request_permission(request.clone()).await {
+                Some(decision) => {
+                    if decision.is_sticky() {
+                        self.remembered.push((title.clone(), kind, decision));
+                    }
+                    (decision, DecidedBy::Handler)
+                }
+                None => (Decision::AllowOnce, DecidedBy::Default),
+            },
+        };
+
+        let option = decision
+            .option_kinds()
+            .iter()
+            .find_map(|kind| request.options.iter().find(|option| option.kind == *kind));
+        let outcome = match option {
+            Some(option) => RequestPermissionOutcome::Selected(SelectedPermissionOutcome::new(
+                option.option_id.clone(),
+            )),
+            None => RequestPermissionOutcome::Cancelled,
+        };
+
+        let details = client.permission_details();
+        let record = PermissionRecord {
+            tool: title,
+            kind,
+            description: details.then(|| description(&request)).flatten(),
+            raw_input: details
+                .then(|| request.tool_call.fields.raw_input.clone())
+                .flatten(),
+            options: request.options.clone(),
+            decision,
+            decided_by,
+            option: option.map(|option| option.option_id.to_string()),
+        };
+        self.records.push(record.clone());
+        (RequestPermissionResponse::new(outcome), record)
+    }
+
+    /// Every decision made, in order.
+    pub(crate) fn into_records(self) -> Vec<PermissionRecord> {
+        self.records
+    }
+}
+
+/// The text content the agent attached to a permission request's tool call.
+fn description(request: &RequestPermissionRequest) -> Option<String> {
+    let text: Vec<&str> = request
+        .tool_call
+        .fields
+        .content
+        .iter()
+        .flatten()
+        .filter_map(|content| match content {
+            ToolCallContent::Content(content) => match &content.content {
+                ContentBlock::Text(text) => Some(text.text.as_str()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    (!text.is_empty()).then(|| text.join("\n"))
+}
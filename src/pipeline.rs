@@ -0,0 +1,219 @@
+//! Mapping streams of items through think blocks.
+
+use std::future::IntoFuture;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use agent_client_protocol::role::{HasPeer, Role};
+use agent_client_protocol::{Agent, BoxFuture, RunWithConnectionTo};
+use futures::Stream;
+use futures::stream::{FuturesOrdered, FuturesUnordered, StreamExt};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+
+use tracing::debug;
+
+use crate::{Determinishtic, Error, RunManifest, ThinkBuilder};
+
+/// Think blocks a [`ThinkMap`] runs at once unless told otherwise.
+const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Adds [`think_map`](Self::think_map) to streams.
+pub trait ThinkStreamExt: Stream + Sized {
+    /// Map each item through a think block, for ETL-style pipelines.
+    ///
+    /// `build` gets each item and a fresh builder from `d`, and returns the
+    /// think block to run for it. Up to 4 think blocks run at once (see
+    /// [`ThinkMap::concurrency`]), and no more items are pulled from the
+    /// stream until a slot frees up and the results are consumed, so a slow
+    /// agent holds back the source rather than piling up work. Results come
+    /// out in input order unless [`ThinkMap::unordered`] is set.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// use determinishtic::ThinkStreamExt;
+    ///
+    /// let mut records = rows
+    ///     .think_map(&d, |row: Row, think| think.text("Extract a contact from").display(&row.text))
+    ///     .concurrency(8)
+    ///     .unordered();
+    /// while let Some(contact) = records.next().await {
+    ///     sink.write(contact?).await?;
+    /// }
+    /// ```
+    fn think_map<'d, R, Output, Run, F>(
+        self,
+        d: &'d Determinishtic<R>,
+        build: F,
+    ) -> ThinkMap<'d, Self, F, R, Output>
+    where
+        R: Role + HasPeer<Agent>,
+        Output: Send + JsonSchema + DeserializeOwned + 'static,
+        Run: RunWithConnectionTo<R> + Send + 'd,
+        F: FnMut(Self::Item, ThinkBuilder<'d, Output, R>) -> ThinkBuilder<'d, Output, R, Run>,
+    {
+        ThinkMap {
+            source: Box::pin(self),
+            source_done: false,
+            d,
+            build,
+            concurrency: DEFAULT_CONCURRENCY,
+            in_flight: InFlight::Ordered(FuturesOrdered::new()),
+            resume: None,
+        }
+    }
+}
+
+impl<S: Stream> ThinkStreamExt for S {}
+
+/// A stream of think block results, created by
+/// [`ThinkStreamExt::think_map`].
+///
+/// Yields one `Result` per input item; a failed think block doesn't end
+/// the stream.
+pub struct ThinkMap<'d, S: Stream, F, R: Role, Output>
+where
+    R: HasPeer<Agent>,
+{
+    source: Pin<Box<S>>,
+    source_done: bool,
+    d: &'d Determinishtic<R>,
+    build: F,
+    concurrency: usize,
+    in_flight: InFlight<'d, Output>,
+    resume: Option<Resume<'d, S::Item>>,
+}
+
+/// Where a [`ThinkMap`] records completed items, and how it names them.
+struct Resume<'d, Item> {
+    manifest: RunManifest,
+    key: Box<dyn Fn(&Item) -> String + Send + 'd>,
+}
+
+/// The think blocks a [`ThinkMap`] is running.
+enum InFlight<'d, Output> {
+    Ordered(FuturesOrdered<BoxFuture<'d, Result<Output, Error>>>),
+    Unordered(FuturesUnordered<BoxFuture<'d, Result<Output, Error>>>),
+}
+
+impl<'d, S: Stream, F, R: Role, Output> ThinkMap<'d, S, F, R, Output>
+where
+    R: HasPeer<Agent>,
+{
+    /// Run up to `n` think blocks at once. Values below 1 are treated as 1.
+    pub fn concurrency(mut self, n: usize) -> Self {
+        self.concurrency = n.max(1);
+        self
+    }
+
+    /// Record each completed item in `manifest` under the key `key` gives it,
+    /// and skip items the manifest says are already done.
+    ///
+    /// A skipped item yields the result stored in the manifest instead of
+    /// running its think block. Items only count as done if their think
+    /// block has the same [fingerprint](ThinkBuilder::fingerprint) as when
+    /// they were recorded, so changing the prompt reruns them. Keys must be
+    /// unique and stable across runs, such as a file path or database ID.
+    pub fn resume_from(
+        mut self,
+        manifest: &RunManifest,
+        key: impl Fn(&S::Item) -> String + Send + 'd,
+    ) -> Self {
+        self.resume = Some(Resume {
+            manifest: manifest.clone(),
+            key: Box::new(key),
+        });
+        self
+    }
+
+    /// Yield results as soon as they are ready, rather than in input order.
+    ///
+    /// Call this before polling the stream.
+    pub fn unordered(mut self) -> Self {
+        if let InFlight::Ordered(futures) = &self.in_flight
+            && futures.is_empty()
+        {
+            self.in_flight = InFlight::Unordered(FuturesUnordered::new());
+        }
+        self
+    }
+}
+
+impl<Output> InFlight<'_, Output> {
+    fn len(&self) -> usize {
+        match self {
+            InFlight::Ordered(futures) => futures.len(),
+            InFlight::Unordered(futures) => futures.len(),
+        }
+    }
+}
+
+// `F` and `Output` are never pinned, and the source is boxed
+impl<S: Stream, F, R: Role, Output> Unpin for ThinkMap<'_, S, F, R, Output> where R: HasPeer<Agent> {}
+
+impl<'d, S, F, R, Output, Run> Stream for ThinkMap<'d, S, F, R, Output>
+where
+    S: Stream,
+    R: Role + HasPeer<Agent>,
+    Output: Send + JsonSchema + DeserializeOwned + 'static,
+    Run: RunWithConnectionTo<R> + Send + 'd,
+    F: FnMut(S::Item, ThinkBuilder<'d, Output, R>) -> ThinkBuilder<'d, Output, R, Run>,
+{
+    type Item = Result<Output, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        // Start think blocks for new items while there is room
+        while !this.source_done && this.in_flight.len() < this.concurrency {
+            match this.source.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    let key = this.resume.as_ref().map(|resume| (resume.key)(&item));
+                    let think = (this.build)(item, this.d.think());
+                    let think = match (&this.resume, key) {
+                        (Some(resume), Some(key)) => {
+                            match resume.manifest.completed(&key, &think.fingerprint()) {
+                                Some(output) => {
+                                    debug!(key, "skipping item completed in an earlier run");
+                                    Box::pin(async move {
+                                        serde_json::from_value(output).map_err(Error::from)
+                                    })
+                                }
+                                None => think.deliver_to(&resume.manifest.sink(key)).into_future(),
+                            }
+                        }
+                        _ => think.into_future(),
+                    };
+                    match &mut this.in_flight {
+                        InFlight::Ordered(futures) => futures.push_back(think),
+                        InFlight::Unordered(futures) => futures.push(think),
+                    }
+                }
+                Poll::Ready(None) => this.source_done = true,
+                Poll::Pending => break,
+            }
+        }
+
+        let next = match &mut this.in_flight {
+            InFlight::Ordered(futures) => futures.poll_next_unpin(cx),
+            InFlight::Unordered(futures) => futures.poll_next_unpin(cx),
+        };
+        match next {
+            Poll::Ready(None) if !this.source_done => Poll::Pending,
+            next => next,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let in_flight = self.in_flight.len();
+        if self.source_done {
+            return (in_flight, Some(in_flight));
+        }
+        let (lower, upper) = self.source.size_hint();
+        (
+            lower.saturating_add(in_flight),
+            upper.and_then(|upper| upper.checked_add(in_flight)),
+        )
+    }
+}
@@ -0,0 +1,179 @@
+//! Plan-then-execute: get a typed plan, vet it in Rust, then carry it out.
+
+use std::fmt::Display;
+use std::future::Future;
+
+use agent_client_protocol::Agent;
+use agent_client_protocol::RunWithConnectionTo;
+use agent_client_protocol::role::{HasPeer, Role};
+use tracing::debug;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::{Determinishtic, Error, ThinkBuilder};
+
+/// The name of the tool the agent submits its plan with in
+/// [`ThinkBuilder::plan_then_execute`].
+const SUBMIT_PLAN: &str = "submit_plan";
+
+/// The description given to the agent for the plan submission tool.
+const SUBMIT_PLAN_DESCRIPTION: &str = "Submit your plan before doing any of the work. \
+     Wait for the response: it lists the steps that were approved. Carry out \
+     only those, in order, and nothing else.";
+
+/// A task broken into steps by [`Determinishtic::plan`] or
+/// [`ThinkBuilder::plan_then_execute`].
+///
+/// The steps are plain data: drop the ones you don't want, reorder them, or
+/// show them to a human before passing the plan to
+/// [`ThinkBuilder::execute`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plan<Step> {
+    /// The task the plan is for, as given to [`Determinishtic::plan`].
+    pub task: String,
+
+    /// The steps, in the order the agent proposed them.
+    pub steps: Vec<Step>,
+}
+
+impl<Step> Plan<Step> {
+    /// Keep only the steps for which `approve` returns `true`.
+    pub fn retain(mut self, approve: impl FnMut(&Step) -> bool) -> Self {
+        self.steps.retain(approve);
+        self
+    }
+}
+
+impl<R: Role> Determinishtic<R>
+where
+    R: HasPeer<Agent>,
+{
+    /// Ask the agent to break `task` into steps, without carrying any of them out.
+    ///
+    /// `Step` is whatever shape you want each step in; its doc comments
+    /// reach the agent through the schema, so describe the fields you need
+    /// to vet a step (a risk level, the files it touches). Filter the plan in
+    /// Rust, then hand it to a second think block with
+    /// [`ThinkBuilder::execute`].
+    ///
+    /// Planning and execution are separate sessions, so the agent starts
+    /// the work without what it learned while planning. To plan and
+    /// execute in one session, use [`ThinkBuilder::plan_then_execute`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let plan: Plan<Step> = d.plan("Migrate the build from Make to Bazel").await?;
+    /// let plan = plan.retain(|step| step.risk != Risk::High);
+    ///
+    /// let report: Report = d.think()
+    ///     .execute(&plan)
+    ///     .tool("run_build", "Run the build", async |_: (), _cx| Ok(run_build()), tool_fn_mut!())
+    ///     .await?;
+    /// ```
+    pub async fn plan<Step>(&self, task: impl Display) -> Result<Plan<Step>, Error>
+    where
+        Step: Send + JsonSchema + DeserializeOwned + 'static,
+    {
+        let task = task.to_string();
+        let steps: Vec<Step> = self
+            .think()
+            .textln("Break the task below into a sequence of concrete steps.")
+            .textln("Do not carry out any of the steps yet; only plan them.")
+            .textln("")
+            .text(&format!("<task>\n{task}\n</task>\n"))
+            .await?;
+        Ok(Plan { task, steps })
+    }
+}
+
+impl<'bound, Output, R: Role, Run: RunWithConnectionTo<R>> ThinkBuilder<'bound, Output, R, Run>
+where
+    R: HasPeer<Agent>,
+    Output: Send + DeserializeOwned + 'static,
+{
+    /// Ask the agent to carry out an approved [`Plan`].
+    ///
+    /// The task and the remaining steps are added to the prompt, with an
+    /// instruction to perform only the listed steps. Steps removed from the
+    /// plan are not mentioned, so the agent can't be tempted to do them
+    /// anyway. Tools, context, and other segments can be added as usual.
+    pub fn execute<Step: Serialize>(self, plan: &Plan<Step>) -> Self {
+        self.textln("Carry out the following plan for the task below.")
+            .textln("These steps have been approved; perform them in order and do nothing else.")
+            .textln("")
+            .text(&format!("<task>\n{}\n</task>\n", plan.task))
+            .context("approved steps", &plan.steps)
+    }
+
+    /// Ask the agent to plan `task`, vet the plan with `approve`, and have
+    /// the agent carry out the approved steps, all in this think block's
+    /// session.
+    ///
+    /// The agent submits its steps through a `submit_plan` tool and waits
+    /// while `approve` decides which to keep, as with
+    /// [`checkpoint`](Self::checkpoint), so this is the place to ask a
+    /// human. The agent is then told which steps were approved and carries
+    /// out only those, keeping whatever it learned while planning. Steps
+    /// removed from the plan are not mentioned again.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let report: Report = d.think()
+    ///     .plan_then_execute("Migrate the build from Make to Bazel", async |plan: Plan<Step>| {
+    ///         plan.retain(|step| step.risk != Risk::High)
+    ///     })
+    ///     .tool("run_build", "Run the build", async |_: (), _cx| Ok(run_build()), tool_fn_mut!())
+    ///     .await?;
+    /// ```
+    pub fn plan_then_execute<Step, F, Fut>(
+        self,
+        task: impl Display,
+        mut approve: F,
+    ) -> ThinkBuilder<'bound, Output, R, impl RunWithConnectionTo<R>>
+    where
+        Step: JsonSchema + Serialize + DeserializeOwned + Send + 'static,
+        F: FnMut(Plan<Step>) -> Fut + Send + 'bound,
+        Fut: Future<Output = Plan<Step>> + Send,
+    {
+        let task = task.to_string();
+        let prompt = format!("<task>\n{task}\n</task>\n");
+        self.textln("Break the task below into a sequence of concrete steps and submit them with")
+            .tool(
+                SUBMIT_PLAN,
+                SUBMIT_PLAN_DESCRIPTION,
+                async move |input: PlanSubmission<Step>, _cx| {
+                    let plan = approve(Plan {
+                        task: task.clone(),
+                        steps: input.steps,
+                    })
+                    .await;
+                    debug!(approved = plan.steps.len(), "plan reviewed");
+                    Ok(ApprovedSteps {
+                        approved_steps: plan.steps,
+                    })
+                },
+                agent_client_protocol::tool_fn_mut!(),
+            )
+            .textln("before doing any of the work.")
+            .textln("Then carry out only the approved steps, in order, and do nothing else.")
+            .textln("")
+            .text(&prompt)
+    }
+}
+
+/// A plan as the agent submits it.
+#[derive(Deserialize, JsonSchema)]
+struct PlanSubmission<Step> {
+    /// The steps, in the order they should be carried out.
+    steps: Vec<Step>,
+}
+
+/// What the agent is told once its plan has been reviewed.
+#[derive(Serialize, JsonSchema)]
+struct ApprovedSteps<Step> {
+    /// The steps to carry out, in order. Leave out any step not listed.
+    approved_steps: Vec<Step>,
+}
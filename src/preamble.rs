@@ -0,0 +1,98 @@
+//! The boilerplate that opens every prompt.
+
+/// Instructions placed before every think block's prompt.
+///
+/// The [standard](Self::standard) preamble tells the agent to work
+/// autonomously and to finish by calling `return_result`. Agents with
+/// their own system prompts sometimes respond better to a
+/// [terse](Self::terse) one, or to none at all, and prompts written in
+/// another language read better with a preamble in that language. Set one
+/// per instance with
+/// [`DeterminishticBuilder::preamble`](crate::DeterminishticBuilder::preamble).
+///
+/// The preamble is never trimmed to fit a
+/// [token budget](crate::ThinkBuilder::token_budget).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preamble {
+    text: String,
+}
+
+impl Preamble {
+    /// The default preamble.
+    pub fn standard() -> Self {
+        Self::custom(
+            "Please complete the following task to the best of your ability,\n\
+             No further instructions will be given,\n\
+             so do your best to interpret the instructions without further feedback from the user,\n\
+             making use of the tools you have available.\n\
+             \n\
+             IMPORTANT: When complete, invoke the `return_result` tool with the requested result.",
+        )
+    }
+
+    /// A single sentence, for agents whose system prompt already covers the rest.
+    pub fn terse() -> Self {
+        Self::custom(
+            "Complete the task below using the available tools, \
+             then call `return_result` with the result.",
+        )
+    }
+
+    /// The standard preamble with more explicit guidance, for agents that
+    /// tend to ask questions or answer in prose.
+    pub fn verbose() -> Self {
+        Self::custom(
+            "Please complete the following task to the best of your ability.\n\
+             No further instructions will be given, so interpret the instructions \
+             as best you can without asking the user for clarification.\n\
+             Use the tools you have available whenever they help.\n\
+             \n\
+             IMPORTANT: When complete, invoke the `return_result` tool exactly once \
+             with the requested result, matching its schema. Do not answer in prose; \
+             only the value passed to `return_result` is used.",
+        )
+    }
+
+    /// No preamble: the prompt is exactly what the think block builds.
+    pub fn none() -> Self {
+        Self {
+            text: String::new(),
+        }
+    }
+
+    /// A preamble of your own, such as a translation of the standard one.
+    ///
+    /// A blank line separates it from the prompt.
+    pub fn custom(text: impl Into<String>) -> Self {
+        let text = text.into();
+        let text = text.trim_end();
+        if text.is_empty() {
+            return Self::none();
+        }
+        Self {
+            text: format!("{text}\n\n"),
+        }
+    }
+
+    /// Append a fragment of shared context, such as a glossary, after a
+    /// blank line.
+    pub(crate) fn with_context(mut self, fragment: &str) -> Self {
+        let fragment = fragment.trim();
+        if !fragment.is_empty() {
+            self.text.push_str(fragment);
+            self.text.push_str("\n\n");
+        }
+        self
+    }
+
+    /// The text placed before the prompt, including the separating blank line.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+}
+
+impl Default for Preamble {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
@@ -0,0 +1,364 @@
+//! Running an agent as a child process.
+
+use std::ffi::{OsStr, OsString};
+use std::path::PathBuf;
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use agent_client_protocol::{ByteStreams, Client, ConnectTo};
+use futures::FutureExt;
+use futures::future::{Either, FusedFuture};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+use tracing::debug;
+
+/// How long to wait for the agent to exit once it has closed the connection.
+const EXIT_GRACE: Duration = Duration::from_millis(500);
+
+/// How much of the agent's standard error to keep for error messages.
+const STDERR_TAIL: usize = 8 * 1024;
+
+/// An agent run as a command-line program, speaking ACP on its standard
+/// input and output.
+///
+/// Unlike a component built from a command string, the agent's arguments,
+/// environment, and working directory can all be set, so deployments can
+/// point it at its own configuration or an HTTP proxy. The process inherits
+/// this process's environment apart from the changes made here, and is
+/// killed when the connection ends. Lines it writes to standard error are
+/// logged at debug level and included in the error if it exits early.
+///
+/// Hosts can follow the process with [`on_event`](Self::on_event). Clones
+/// share a count of the processes started, so when a supervisor reconnects
+/// with a clone after the agent died, the new process is reported as
+/// [restarted](AgentEvent::Restarted).
+///
+/// Connect with
+/// [`DeterminishticBuilder::connect_command`](crate::DeterminishticBuilder::connect_command),
+/// which adds the builder's [agent arguments](crate::DeterminishticBuilder::agent_arg),
+/// [environment](crate::DeterminishticBuilder::agent_env), and
+/// [working directory](crate::DeterminishticBuilder::working_dir), or pass
+/// it to any method taking a component.
+///
+/// This type is only available with the `tokio` feature, which is on by
+/// default.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let agent = AgentCommand::new("npx")
+///     .args(["-y", "@zed-industries/claude-code-acp@latest"])
+///     .env("HTTPS_PROXY", "http://proxy.internal:3128");
+/// let d = Determinishtic::builder()
+///     .working_dir("/srv/checkout")
+///     .connect_command(agent)
+///     .await?;
+/// ```
+#[derive(Clone)]
+pub struct AgentCommand {
+    program: OsString,
+    args: Vec<OsString>,
+    /// Variables to set, or to remove where the value is `None`, in order.
+    env: Vec<(OsString, Option<OsString>)>,
+    env_clear: bool,
+    dir: Option<PathBuf>,
+    handlers: Vec<EventHandler>,
+    /// Processes started by this command and its clones.
+    starts: Arc<AtomicU32>,
+    /// Where the end of the agent's standard error is kept for the caller.
+    stderr: Option<StderrTail>,
+}
+
+/// The last few kilobytes an agent wrote to standard error.
+#[derive(Clone, Default)]
+pub(crate) struct StderrTail(Arc<Mutex<String>>);
+
+impl StderrTail {
+    fn push_line(&self, line: &str) {
+        let mut tail = self.lock();
+        if !tail.is_empty() {
+            tail.push('\n');
+        }
+        tail.push_str(line);
+        if tail.len() > STDERR_TAIL {
+            let mut start = tail.len() - STDERR_TAIL;
+            while !tail.is_char_boundary(start) {
+                start += 1;
+            }
+            tail.drain(..start);
+        }
+    }
+
+    /// What has been written so far, if anything.
+    pub(crate) fn get(&self) -> Option<String> {
+        Some(self.lock().clone()).filter(|tail| !tail.is_empty())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, String> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Something that happened to an agent process, reported to the handlers
+/// registered with [`AgentCommand::on_event`] or
+/// [`DeterminishticBuilder::on_agent_event`](crate::DeterminishticBuilder::on_agent_event).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AgentEvent {
+    /// The agent process started.
+    Started {
+        /// The process ID, if the platform reports one.
+        pid: Option<u32>,
+    },
+
+    /// An agent process started after an earlier one from the same
+    /// command, or a clone of it, had ended.
+    Restarted {
+        /// The process ID, if the platform reports one.
+        pid: Option<u32>,
+        /// How many times the agent has been restarted, counting this one.
+        restarts: u32,
+    },
+
+    /// The agent process exited on its own. A process killed because the
+    /// connection ended is not reported.
+    Exited {
+        /// The process ID, if the platform reports one.
+        pid: Option<u32>,
+        /// The exit code, or `None` if the process was killed by a signal.
+        code: Option<i32>,
+        /// Whether the process reported success.
+        success: bool,
+    },
+}
+
+pub(crate) type EventHandler = Arc<dyn Fn(&AgentEvent) + Send + Sync>;
+
+impl std::fmt::Debug for AgentCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AgentCommand")
+            .field("program", &self.program)
+            .field("args", &self.args)
+            .field("env", &self.env)
+            .field("env_clear", &self.env_clear)
+            .field("dir", &self.dir)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AgentCommand {
+    /// Run `program`, looked up on the `PATH` if it isn't a path.
+    pub fn new(program: impl AsRef<OsStr>) -> Self {
+        Self {
+            program: program.as_ref().to_owned(),
+            args: Vec::new(),
+            env: Vec::new(),
+            env_clear: false,
+            dir: None,
+            handlers: Vec::new(),
+            starts: Arc::default(),
+            stderr: None,
+        }
+    }
+
+    /// Add an argument.
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    /// Add several arguments.
+    pub fn args(mut self, args: impl IntoIterator<Item = impl AsRef<OsStr>>) -> Self {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_owned()));
+        self
+    }
+
+    /// Set an environment variable for the agent.
+    pub fn env(mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> Self {
+        self.env
+            .push((key.as_ref().to_owned(), Some(value.as_ref().to_owned())));
+        self
+    }
+
+    /// Don't pass `key` on from this process's environment.
+    pub fn env_remove(mut self, key: impl AsRef<OsStr>) -> Self {
+        self.env.push((key.as_ref().to_owned(), None));
+        self
+    }
+
+    /// Start the agent with only the variables set with [`env`](Self::env),
+    /// instead of a copy of this process's environment.
+    pub fn env_clear(mut self) -> Self {
+        self.env.clear();
+        self.env_clear = true;
+        self
+    }
+
+    /// Run the agent in `dir`.
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    /// Call `handler` when the agent process starts, exits, or is
+    /// restarted, so supervisors can alert on an agent that keeps dying
+    /// instead of inferring it from failed think blocks.
+    ///
+    /// Handlers are called in the order they were added, from the task
+    /// running the connection, so they should return quickly.
+    pub fn on_event(mut self, handler: impl Fn(&AgentEvent) + Send + Sync + 'static) -> Self {
+        self.handlers.push(Arc::new(handler));
+        self
+    }
+
+    /// Add arguments, environment variables, and event handlers configured
+    /// elsewhere, and run in `dir` unless a directory was already set.
+    #[cfg(feature = "conductor")]
+    pub(crate) fn configure<'a>(
+        mut self,
+        args: impl IntoIterator<Item = &'a OsStr>,
+        env: impl IntoIterator<Item = (&'a OsStr, &'a OsStr)>,
+        handlers: &[EventHandler],
+        dir: &std::path::Path,
+    ) -> Self {
+        self.handlers.extend_from_slice(handlers);
+        self.args.extend(args.into_iter().map(OsStr::to_owned));
+        self.env.extend(
+            env.into_iter()
+                .map(|(key, value)| (key.to_owned(), Some(value.to_owned()))),
+        );
+        self.dir.get_or_insert_with(|| dir.to_owned());
+        self
+    }
+
+    /// Keep the end of the next process's standard error where the caller
+    /// can read it.
+    #[cfg(feature = "conductor")]
+    pub(crate) fn capture_stderr(&mut self) -> StderrTail {
+        self.stderr.insert(StderrTail::default()).clone()
+    }
+
+    fn emit(&self, event: AgentEvent) {
+        debug!(?event, "agent process event");
+        for handler in &self.handlers {
+            handler(&event);
+        }
+    }
+
+    fn command(&self) -> tokio::process::Command {
+        let mut command = tokio::process::Command::new(&self.program);
+        command.args(&self.args);
+        if self.env_clear {
+            command.env_clear();
+        }
+        for (key, value) in &self.env {
+            match value {
+                Some(value) => command.env(key, value),
+                None => command.env_remove(key),
+            };
+        }
+        if let Some(dir) = &self.dir {
+            command.current_dir(dir);
+        }
+        command
+    }
+}
+
+impl ConnectTo<Client> for AgentCommand {
+    async fn connect_to(
+        self,
+        client: impl ConnectTo<agent_client_protocol::Agent>,
+    ) -> Result<(), agent_client_protocol::Error> {
+        let program = self.program.to_string_lossy().into_owned();
+        let mut command = self.command();
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        let mut child = command.spawn().map_err(|err| {
+            agent_client_protocol::Error::internal_error()
+                .data(format!("failed to start agent `{program}`: {err}"))
+        })?;
+        let pid = child.id();
+        debug!(program, pid, "started agent process");
+        let restarts = self.starts.fetch_add(1, Ordering::Relaxed);
+        self.emit(if restarts == 0 {
+            AgentEvent::Started { pid }
+        } else {
+            AgentEvent::Restarted { pid, restarts }
+        });
+
+        let (Some(stdin), Some(stdout), Some(stderr)) =
+            (child.stdin.take(), child.stdout.take(), child.stderr.take())
+        else {
+            unreachable!("the agent's standard streams are piped");
+        };
+        let tail = self.stderr.clone().unwrap_or_default();
+        let protocol = ConnectTo::<Client>::connect_to(
+            ByteStreams::new(stdin.compat_write(), stdout.compat()),
+            client,
+        );
+
+        // Read stderr throughout, so it is at hand however the connection ends
+        let mut protocol = std::pin::pin!(protocol.fuse());
+        let mut stderr = std::pin::pin!(collect_stderr(stderr, &tail).fuse());
+        let ended = {
+            let mut wait = std::pin::pin!(child.wait().fuse());
+            loop {
+                futures::select! {
+                    result = protocol => break Either::Left(result),
+                    status = wait => break Either::Right(status),
+                    () = stderr => {}
+                }
+            }
+        };
+        let (result, status) = match ended {
+            // The connection usually ends because the agent is exiting
+            Either::Left(result) => match tokio::time::timeout(EXIT_GRACE, child.wait()).await {
+                Ok(Ok(status)) => (Some(result), status),
+                _ => return result,
+            },
+            Either::Right(status) => (
+                None,
+                status.map_err(agent_client_protocol::Error::into_internal_error)?,
+            ),
+        };
+        if !stderr.is_terminated() {
+            // Let the last of it arrive
+            let _ = tokio::time::timeout(EXIT_GRACE, stderr).await;
+        }
+        self.emit(exited_event(pid, status));
+        if status.success() {
+            return result.unwrap_or(Ok(()));
+        }
+        let message = match tail.get() {
+            Some(stderr) => format!("agent `{program}` exited with {status}: {stderr}"),
+            None => format!("agent `{program}` exited with {status}"),
+        };
+        Err(agent_client_protocol::Error::internal_error().data(message))
+    }
+}
+
+fn exited_event(pid: Option<u32>, status: ExitStatus) -> AgentEvent {
+    AgentEvent::Exited {
+        pid,
+        code: status.code(),
+        success: status.success(),
+    }
+}
+
+/// Log what the agent writes to standard error, keeping the end of it in
+/// `tail`, until the stream closes.
+async fn collect_stderr(stderr: impl AsyncRead + Unpin, tail: &StderrTail) {
+    let mut lines = BufReader::new(stderr).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        debug!(line, "agent stderr");
+        tail.push_line(&line);
+    }
+}
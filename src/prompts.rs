@@ -0,0 +1,141 @@
+//! Prompt templates the agent can discover as task presets.
+
+/// A prompt template served over MCP, which agents that browse server
+/// prompts can offer as a task preset.
+///
+/// The template is plain text with `{name}` placeholders for its arguments;
+/// write `{{` and `}}` for literal braces. Serve templates with
+/// [`ToolSet::prompt`](crate::ToolSet::prompt) or
+/// [`ThinkBuilder::prompt_template`](crate::ThinkBuilder::prompt_template).
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let review = PromptTemplate::new("review", "Review {path} for {focus} issues.")
+///     .description("Review a file")
+///     .argument("path", "The file to review")
+///     .optional_argument("focus", "What to look for, e.g. security");
+///
+/// let tools = ToolSet::new().tool(..).prompt(review);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptTemplate {
+    /// The name the agent requests the prompt by.
+    pub name: String,
+
+    /// What the prompt is for.
+    pub description: Option<String>,
+
+    /// The arguments the template accepts, in the order they are listed.
+    pub arguments: Vec<PromptArgument>,
+
+    /// The template text.
+    pub template: String,
+}
+
+/// An argument of a [`PromptTemplate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptArgument {
+    /// The argument's name, as used in `{name}` placeholders.
+    pub name: String,
+
+    /// What the argument means.
+    pub description: Option<String>,
+
+    /// Whether the agent must supply the argument. Missing optional
+    /// arguments are replaced with nothing.
+    pub required: bool,
+}
+
+impl PromptTemplate {
+    /// A template with no description or arguments.
+    pub fn new(name: impl Into<String>, template: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: None,
+            arguments: Vec::new(),
+            template: template.into(),
+        }
+    }
+
+    /// Set what the prompt is for.
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Add a required argument.
+    pub fn argument(mut self, name: impl Into<String>, description: impl Into<String>) -> Self {
+        self.arguments.push(PromptArgument {
+            name: name.into(),
+            description: Some(description.into()),
+            required: true,
+        });
+        self
+    }
+
+    /// Add an optional argument.
+    pub fn optional_argument(
+        mut self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Self {
+        self.arguments.push(PromptArgument {
+            name: name.into(),
+            description: Some(description.into()),
+            required: false,
+        });
+        self
+    }
+
+    /// Fill in the template, looking arguments up with `lookup`.
+    ///
+    /// Fails if a required argument is missing or the template names an
+    /// argument it doesn't declare.
+    pub fn render<'a>(&self, lookup: impl Fn(&str) -> Option<&'a str>) -> Result<String, String> {
+        for argument in &self.arguments {
+            if argument.required && lookup(&argument.name).is_none() {
+                return Err(format!("missing required argument `{}`", argument.name));
+            }
+        }
+
+        fill(&self.template, |name| {
+            if !self.arguments.iter().any(|a| a.name == name) {
+                return Err(format!("template uses undeclared argument `{name}`"));
+            }
+            Ok(lookup(name).unwrap_or_default().to_string())
+        })
+    }
+}
+
+/// Replace each `{name}` placeholder in `template` with what `value` gives
+/// for it, and `{{` and `}}` with literal braces.
+///
+/// Fails with `value`'s error, or if the template has an unmatched brace.
+pub(crate) fn fill(
+    template: &str,
+    mut value: impl FnMut(&str) -> Result<String, String>,
+) -> Result<String, String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        out.push_str(&rest[..i]);
+        let tail = &rest[i..];
+        if let Some(after) = tail.strip_prefix("{{") {
+            out.push('{');
+            rest = after;
+        } else if let Some(after) = tail.strip_prefix("}}") {
+            out.push('}');
+            rest = after;
+        } else if let Some(after) = tail.strip_prefix('{')
+            && let Some(end) = after.find('}')
+        {
+            out.push_str(&value(&after[..end])?);
+            rest = &after[end + 1..];
+        } else {
+            return Err(format!("unmatched `{}` in template", &tail[..1]));
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
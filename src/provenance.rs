@@ -0,0 +1,79 @@
+//! Where a think block's result came from.
+
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+use agent_client_protocol::schema::Implementation;
+use serde::{Deserialize, Serialize};
+
+/// How a result was produced, for auditing LLM-derived data.
+///
+/// Available from [`ThinkOutcome::provenance`](crate::ThinkOutcome::provenance).
+/// It serializes to JSON, so it can be stored alongside the result.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// The ID the think block was given when it started, under which its
+    /// transcript is kept if a
+    /// [`TranscriptStore`](crate::TranscriptStore) is configured.
+    #[serde(default)]
+    pub think_id: String,
+
+    /// The agent's name, as it reported during initialization.
+    ///
+    /// `None` if the agent didn't say, or if the instance was created with
+    /// [`Determinishtic::from_connection`](crate::Determinishtic::from_connection)
+    /// and so never saw the agent's initialization response.
+    pub agent: Option<String>,
+
+    /// The agent's version, under the same conditions as [`agent`](Self::agent).
+    pub agent_version: Option<String>,
+
+    /// The model the think block ran on, if one was selected with
+    /// [`ThinkBuilder::model`](crate::ThinkBuilder::model) and the agent accepted it.
+    pub model: Option<String>,
+
+    /// The think block's [fingerprint](crate::ThinkBuilder::fingerprint),
+    /// covering the prompt, tools, and output schema.
+    pub prompt_fingerprint: String,
+
+    /// The think block's [labels](crate::ThinkBuilder::label).
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+
+    /// The version of determinishtic that ran the think block.
+    pub crate_version: String,
+
+    /// When the think block started.
+    pub started_at: SystemTime,
+
+    /// When the result was accepted.
+    pub finished_at: SystemTime,
+
+    /// How many times the agent had to try again: results resubmitted
+    /// because they did not match the output schema, plus
+    /// [nudges](crate::ThinkBuilder::nudge_on_no_result).
+    pub retries: u32,
+}
+
+impl Provenance {
+    pub(crate) fn new(
+        think_id: &str,
+        agent: Option<&Implementation>,
+        prompt_fingerprint: String,
+        labels: BTreeMap<String, String>,
+        started_at: SystemTime,
+    ) -> Self {
+        Self {
+            think_id: think_id.to_string(),
+            agent: agent.map(|agent| agent.name.clone()),
+            agent_version: agent.map(|agent| agent.version.clone()),
+            model: None,
+            prompt_fingerprint,
+            labels,
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            started_at,
+            finished_at: started_at,
+            retries: 0,
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! Keeping prompt and result content out of logs.
+
+use std::fmt::{self, Debug, Display};
+
+use agent_client_protocol::BoxFuture;
+use serde::Serialize;
+
+use crate::hash::stable_hash;
+
+/// How prompts, results, session updates, and tool calls appear in logs.
+///
+/// This only changes what determinishtic logs; the agent always gets the
+/// full prompt. Set it with
+/// [`DeterminishticBuilder::log_redaction`](crate::DeterminishticBuilder::log_redaction)
+/// so debug logging can stay on in production without recording customer
+/// data. Under [`SecurityProfile::Strict`](crate::SecurityProfile::Strict),
+/// content is always [omitted](Self::omit).
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let d = Determinishtic::builder()
+///     .log_redaction(LogRedaction::truncate(200).drop_tool_arguments())
+///     .connect(agent)
+///     .await?;
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LogRedaction {
+    text: Text,
+    drop_tool_arguments: bool,
+}
+
+/// What becomes of logged text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Text {
+    #[default]
+    Full,
+    Truncate(usize),
+    Hash,
+    Omit,
+}
+
+/// The keys under which ACP tool calls carry their arguments and output.
+const TOOL_ARGUMENT_KEYS: [&str; 2] = ["rawInput", "rawOutput"];
+
+#[cfg(feature = "tokio")]
+tokio::task_local! {
+    /// The redaction of the instance whose tool call is running, so tools
+    /// that log what the agent sent them can honor it.
+    static TOOL_CALL: LogRedaction;
+}
+
+impl LogRedaction {
+    /// Log everything in full. This is the default.
+    pub fn full() -> Self {
+        Self::default()
+    }
+
+    /// Log at most `max_chars` characters of each prompt, result, and update.
+    pub fn truncate(max_chars: usize) -> Self {
+        Self {
+            text: Text::Truncate(max_chars),
+            ..Self::default()
+        }
+    }
+
+    /// Log a hash of each prompt, result, and update instead of its text,
+    /// so identical content can still be matched up across log lines.
+    pub fn hash() -> Self {
+        Self {
+            text: Text::Hash,
+            ..Self::default()
+        }
+    }
+
+    /// Leave content out of logs; only sizes and kinds are recorded.
+    pub fn omit() -> Self {
+        Self {
+            text: Text::Omit,
+            ..Self::default()
+        }
+    }
+
+    /// Also strip the arguments and raw output of the agent's tool calls
+    /// from logged session updates and permission requests.
+    pub fn drop_tool_arguments(mut self) -> Self {
+        self.drop_tool_arguments = true;
+        self
+    }
+
+    /// Whether everything is logged as it is.
+    pub(crate) fn is_full(&self) -> bool {
+        self.text == Text::Full && !self.drop_tool_arguments
+    }
+
+    /// The redaction of the instance serving the tool call in progress, or
+    /// [full](Self::full) outside one.
+    #[cfg(any(feature = "git", feature = "sqlx"))]
+    pub(crate) fn for_tool_call() -> Self {
+        TOOL_CALL
+            .try_with(|redaction| *redaction)
+            .unwrap_or_default()
+    }
+
+    /// Run a tool call under this redaction.
+    pub(crate) fn scope_tool_call<'a, T: 'a>(self, call: BoxFuture<'a, T>) -> BoxFuture<'a, T> {
+        #[cfg(feature = "tokio")]
+        return Box::pin(TOOL_CALL.scope(self, call));
+        #[cfg(not(feature = "tokio"))]
+        call
+    }
+
+    /// `text` as it may be logged, or `None` if it must be left out.
+    pub(crate) fn text(&self, text: &str) -> Option<String> {
+        match self.text {
+            Text::Full => Some(text.to_string()),
+            Text::Truncate(max_chars) => match text.char_indices().nth(max_chars) {
+                Some((end, _)) => Some(format!(
+                    "{}… ({} chars omitted)",
+                    &text[..end],
+                    text[end..].chars().count()
+                )),
+                None => Some(text.to_string()),
+            },
+            Text::Hash => Some(format!(
+                "<{} chars, hash {:016x}>",
+                text.chars().count(),
+                stable_hash(text.bytes())
+            )),
+            Text::Omit => None,
+        }
+    }
+
+    /// A protocol message as it may be logged, or `None` if it must be left
+    /// out. The message is only formatted if the log line is written.
+    pub(crate) fn message<'a, T>(&'a self, message: &'a T) -> Option<Redacted<'a, T>> {
+        (self.text != Text::Omit).then_some(Redacted {
+            redaction: self,
+            message,
+        })
+    }
+}
+
+/// A protocol message formatted under a [`LogRedaction`].
+pub(crate) struct Redacted<'a, T> {
+    redaction: &'a LogRedaction,
+    message: &'a T,
+}
+
+impl<T: Debug + Serialize> Display for Redacted<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.redaction.is_full() {
+            return write!(f, "{:?}", self.message);
+        }
+        let text = if self.redaction.drop_tool_arguments {
+            let mut value = serde_json::to_value(self.message).map_err(|_| fmt::Error)?;
+            strip_tool_arguments(&mut value);
+            value.to_string()
+        } else {
+            format!("{:?}", self.message)
+        };
+        f.write_str(&self.redaction.text(&text).unwrap_or_default())
+    }
+}
+
+/// Remove tool call arguments and output from `value`, wherever they are.
+fn strip_tool_arguments(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for key in TOOL_ARGUMENT_KEYS {
+                map.remove(key);
+            }
+            map.values_mut().for_each(strip_tool_arguments);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(strip_tool_arguments),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncates_at_a_char_boundary() {
+        let redaction = LogRedaction::truncate(3);
+        assert_eq!(
+            redaction.text("héllo wörld").unwrap(),
+            "hél… (8 chars omitted)"
+        );
+        assert_eq!(redaction.text("hé").unwrap(), "hé");
+        assert_eq!(redaction.text("abc").unwrap(), "abc");
+    }
+
+    #[test]
+    fn hashes_the_same_text_the_same_way() {
+        let redaction = LogRedaction::hash();
+        let logged = redaction.text("the deploy key").unwrap();
+        assert_eq!(logged, redaction.text("the deploy key").unwrap());
+        assert_ne!(logged, redaction.text("the deploy kez").unwrap());
+        assert!(logged.starts_with("<14 chars, hash "), "{logged}");
+        assert!(!logged.contains("deploy"));
+    }
+
+    #[test]
+    fn omits_text_and_messages() {
+        let redaction = LogRedaction::omit();
+        assert_eq!(redaction.text("secret"), None);
+        assert!(redaction.message(&serde_json::json!("secret")).is_none());
+    }
+
+    #[test]
+    fn logs_full_messages_as_they_are() {
+        let message = serde_json::json!({ "rawInput": { "path": "/etc/passwd" } });
+        let logged = LogRedaction::full().message(&message).unwrap().to_string();
+        assert_eq!(logged, format!("{message:?}"));
+    }
+
+    #[test]
+    fn drops_nested_tool_arguments() {
+        let message = serde_json::json!({
+            "update": {
+                "toolCall": {
+                    "title": "Read",
+                    "rawInput": { "path": "/etc/passwd" },
+                    "rawOutput": "root:x:0:0",
+                },
+                "calls": [{ "rawInput": "token=hunter2", "kind": "fetch" }],
+            },
+        });
+        let redaction = LogRedaction::full().drop_tool_arguments();
+        let logged = redaction.message(&message).unwrap().to_string();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&logged).unwrap(),
+            serde_json::json!({
+                "update": {
+                    "toolCall": { "title": "Read" },
+                    "calls": [{ "kind": "fetch" }],
+                },
+            })
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Documents and data the agent can read on demand.
+
+use std::sync::Arc;
+
+use agent_client_protocol::BoxFuture;
+
+use crate::Error;
+
+/// A resource published to the agent, as it appears in the listing.
+///
+/// The agent sees every resource's URI, name, and description up front, and
+/// reads the contents only if it decides it needs them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resource {
+    /// The URI the agent reads the resource with, e.g. `docs://guide.md`.
+    pub uri: String,
+
+    /// A short name for the resource.
+    pub name: String,
+
+    /// What the resource contains, to help the agent decide whether to read it.
+    pub description: Option<String>,
+
+    /// The MIME type of the contents, if known.
+    pub mime_type: Option<String>,
+}
+
+impl Resource {
+    /// A resource with no description or MIME type.
+    pub fn new(uri: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            uri: uri.into(),
+            name: name.into(),
+            description: None,
+            mime_type: None,
+        }
+    }
+
+    /// Set the resource's description.
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Set the MIME type of the resource's contents.
+    pub fn mime_type(mut self, mime_type: impl Into<String>) -> Self {
+        self.mime_type = Some(mime_type.into());
+        self
+    }
+}
+
+/// The contents of a [`Resource`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResourceContents {
+    /// Text, such as a document or CSV data.
+    Text(String),
+
+    /// Binary data; it is base64-encoded on the wire.
+    Blob(Vec<u8>),
+}
+
+impl From<String> for ResourceContents {
+    fn from(text: String) -> Self {
+        ResourceContents::Text(text)
+    }
+}
+
+impl From<&str> for ResourceContents {
+    fn from(text: &str) -> Self {
+        ResourceContents::Text(text.to_string())
+    }
+}
+
+impl From<Vec<u8>> for ResourceContents {
+    fn from(data: Vec<u8>) -> Self {
+        ResourceContents::Blob(data)
+    }
+}
+
+/// A source of resources that are listed and read only when the agent asks.
+///
+/// Publish one to a think block with
+/// [`ThinkBuilder::resource_provider`](crate::ThinkBuilder::resource_provider).
+/// Use this rather than [`ThinkBuilder::resource`](crate::ThinkBuilder::resource)
+/// when there are too many resources to load up front, such as the files in
+/// a directory or the rows of a table.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// struct Notes(PathBuf);
+///
+/// impl ResourceProvider for Notes {
+///     fn list(&self) -> BoxFuture<'_, Result<Vec<Resource>, Error>> {
+///         Box::pin(async move {
+///             Ok(note_names(&self.0)?
+///                 .map(|name| Resource::new(format!("notes://{name}"), name))
+///                 .collect())
+///         })
+///     }
+///
+///     fn read(&self, uri: String) -> BoxFuture<'_, Result<Option<ResourceContents>, Error>> {
+///         Box::pin(async move {
+///             let Some(name) = uri.strip_prefix("notes://") else { return Ok(None) };
+///             Ok(std::fs::read_to_string(self.0.join(name)).ok().map(ResourceContents::Text))
+///         })
+///     }
+/// }
+/// ```
+pub trait ResourceProvider: Send + Sync + 'static {
+    /// The resources available to the agent.
+    fn list(&self) -> BoxFuture<'_, Result<Vec<Resource>, Error>>;
+
+    /// The contents of the resource at `uri`, or `None` if this provider
+    /// doesn't have it.
+    fn read(&self, uri: String) -> BoxFuture<'_, Result<Option<ResourceContents>, Error>>;
+}
+
+impl<P: ResourceProvider + ?Sized> ResourceProvider for Arc<P> {
+    fn list(&self) -> BoxFuture<'_, Result<Vec<Resource>, Error>> {
+        (**self).list()
+    }
+
+    fn read(&self, uri: String) -> BoxFuture<'_, Result<Option<ResourceContents>, Error>> {
+        (**self).read(uri)
+    }
+}
+
+/// The resources published to a think block.
+#[derive(Clone, Default)]
+pub(crate) struct Resources {
+    /// Resources added with their contents, in the order they were added.
+    fixed: Vec<(Resource, ResourceContents)>,
+    providers: Vec<Arc<dyn ResourceProvider>>,
+}
+
+impl Resources {
+    /// Add a resource, replacing any with the same URI.
+    pub(crate) fn add(&mut self, resource: Resource, contents: ResourceContents) {
+        self.fixed.retain(|(r, _)| r.uri != resource.uri);
+        self.fixed.push((resource, contents));
+    }
+
+    pub(crate) fn add_provider(&mut self, provider: impl ResourceProvider) {
+        self.providers.push(Arc::new(provider));
+    }
+
+    /// The resources added with their contents, for rendering.
+    pub(crate) fn fixed(&self) -> impl Iterator<Item = &Resource> {
+        self.fixed.iter().map(|(resource, _)| resource)
+    }
+
+    /// Every resource, fixed ones first, then each provider's in order.
+    pub(crate) async fn list(&self) -> Result<Vec<Resource>, Error> {
+        let mut resources: Vec<Resource> = self.fixed().cloned().collect();
+        for provider in &self.providers {
+            resources.extend(provider.list().await?);
+        }
+        Ok(resources)
+    }
+
+    /// The contents of the resource at `uri`, looking at fixed resources
+    /// first, then asking each provider in order.
+    pub(crate) async fn read(
+        &self,
+        uri: &str,
+    ) -> Result<Option<(Option<String>, ResourceContents)>, Error> {
+        if let Some((resource, contents)) = self.fixed.iter().find(|(r, _)| r.uri == uri) {
+            return Ok(Some((resource.mime_type.clone(), contents.clone())));
+        }
+        for provider in &self.providers {
+            if let Some(contents) = provider.read(uri.to_string()).await? {
+                return Ok(Some((None, contents)));
+            }
+        }
+        Ok(None)
+    }
+}
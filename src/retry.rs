@@ -0,0 +1,132 @@
+//! What the agent is told when its result is rejected.
+
+use std::fmt;
+use std::sync::Arc;
+
+/// A rejected result, passed to a [retry template](crate::ThinkBuilder::retry_template).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attempt {
+    /// Which attempt this was, starting at 1.
+    pub number: u32,
+
+    /// The result the agent submitted, as pretty-printed JSON, cut short if
+    /// the [retry context limit](crate::ThinkBuilder::retry_context_limit)
+    /// doesn't leave room for all of it. Empty once the limit is used up.
+    pub output: String,
+
+    /// Whether [`output`](Self::output) was cut short.
+    pub truncated: bool,
+
+    /// Why the result was rejected.
+    pub reason: String,
+}
+
+/// How much of the agent's rejected results is quoted back to it, in total,
+/// unless overridden.
+pub(crate) const DEFAULT_RETRY_CONTEXT_LIMIT: usize = 8000;
+
+type Template = Arc<dyn Fn(&Attempt) -> String + Send + Sync>;
+
+/// Settings for the feedback sent when a result is rejected.
+#[derive(Clone)]
+pub(crate) struct RetryOptions {
+    template: Option<Template>,
+    context_limit: usize,
+}
+
+impl RetryOptions {
+    pub(crate) fn set_template(
+        &mut self,
+        template: impl Fn(&Attempt) -> String + Send + Sync + 'static,
+    ) {
+        self.template = Some(Arc::new(template));
+    }
+
+    pub(crate) fn set_context_limit(&mut self, chars: usize) {
+        self.context_limit = chars;
+    }
+
+    /// Start tracking feedback for one think block.
+    pub(crate) fn feedback(&self) -> RetryFeedback {
+        RetryFeedback {
+            options: self.clone(),
+            attempts: 0,
+            quoted: 0,
+        }
+    }
+}
+
+impl Default for RetryOptions {
+    fn default() -> Self {
+        Self {
+            template: None,
+            context_limit: DEFAULT_RETRY_CONTEXT_LIMIT,
+        }
+    }
+}
+
+impl fmt::Debug for RetryOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryOptions")
+            .field("template", &self.template.is_some())
+            .field("context_limit", &self.context_limit)
+            .finish()
+    }
+}
+
+/// Builds the feedback for each rejected result in a think block, keeping
+/// the total quoted output within the limit.
+pub(crate) struct RetryFeedback {
+    options: RetryOptions,
+    attempts: u32,
+    /// Characters of rejected output quoted back so far.
+    quoted: usize,
+}
+
+impl RetryFeedback {
+    /// How many results have been rejected.
+    pub(crate) fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Record a rejected result and describe it for the agent.
+    pub(crate) fn reject(&mut self, output: &serde_json::Value, reason: String) -> String {
+        self.attempts += 1;
+        let full =
+            serde_json::to_string_pretty(output).expect("JSON values can always be serialized");
+        let room = self.options.context_limit.saturating_sub(self.quoted);
+        let (output, truncated) = match full.char_indices().nth(room) {
+            Some((end, _)) => (full[..end].to_string(), true),
+            None => (full, false),
+        };
+        self.quoted += output.chars().count();
+        let attempt = Attempt {
+            number: self.attempts,
+            output,
+            truncated,
+            reason,
+        };
+        match &self.options.template {
+            Some(template) => template(&attempt),
+            None => default_template(&attempt),
+        }
+    }
+}
+
+fn default_template(attempt: &Attempt) -> String {
+    let mut out = format!("<previous_attempt number=\"{}\">\n", attempt.number);
+    if !attempt.output.is_empty() {
+        out.push_str("<output>\n```json\n");
+        out.push_str(&attempt.output);
+        if attempt.truncated {
+            out.push_str("\n... (truncated)");
+        }
+        out.push_str("\n```\n</output>\n");
+    }
+    out.push_str(&format!(
+        "<rejection>{}</rejection>\n</previous_attempt>\n",
+        attempt.reason
+    ));
+    out.push_str("Call `return_result` again with a corrected result.");
+    out
+}
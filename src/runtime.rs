@@ -0,0 +1,76 @@
+//! The executor background tasks run on.
+
+use futures::future::BoxFuture;
+
+/// Run `f`, which blocks, without holding up other tasks: on Tokio's
+/// blocking thread pool when running inside a Tokio runtime, otherwise in
+/// place. Panics in `f` are passed on to the caller.
+pub(crate) async fn blocking<T>(f: impl FnOnce() -> T + Send + 'static) -> T
+where
+    T: Send + 'static,
+{
+    #[cfg(feature = "tokio")]
+    if tokio::runtime::Handle::try_current().is_ok() {
+        return match tokio::task::spawn_blocking(f).await {
+            Ok(value) => value,
+            Err(err) => match err.try_into_panic() {
+                Ok(panic) => std::panic::resume_unwind(panic),
+                // The runtime is shutting down and won't poll us again
+                Err(_) => std::future::pending().await,
+            },
+        };
+    }
+    f()
+}
+
+/// Starts the background tasks a [`Determinishtic`](crate::Determinishtic)
+/// instance needs: the connection to the agent, and think blocks run with
+/// [`ThinkBuilder::spawn`](crate::ThinkBuilder::spawn) or in a
+/// [scope](crate::Determinishtic::scope).
+///
+/// With the default `tokio` feature, [`TokioSpawner`] hands tasks to
+/// `tokio::spawn`. To run on another executor, such as async-std, glommio,
+/// or a single-threaded runtime, pass a spawner to
+/// [`DeterminishticBuilder::spawner`](crate::DeterminishticBuilder::spawner);
+/// without the `tokio` feature, a spawner is required.
+/// Any `Fn(BoxFuture<'static, ()>)` closure is a spawner:
+///
+/// ```rust,ignore
+/// let d = Determinishtic::builder()
+///     .spawner(|task| {
+///         async_std::task::spawn(task);
+///     })
+///     .connect(agent)
+///     .await?;
+/// ```
+///
+/// Tasks are stopped by the instance when it is dropped, so the spawner
+/// only has to run them; it doesn't need to return a handle.
+pub trait Spawner: Send + Sync + 'static {
+    /// Run `task` in the background until it completes.
+    fn spawn(&self, task: BoxFuture<'static, ()>);
+}
+
+impl<F> Spawner for F
+where
+    F: Fn(BoxFuture<'static, ()>) + Send + Sync + 'static,
+{
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        self(task)
+    }
+}
+
+/// Spawns tasks on the current Tokio runtime.
+///
+/// This is the default [`Spawner`]. Spawning panics outside a Tokio runtime.
+/// Only available with the `tokio` feature, which is on by default.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioSpawner;
+
+#[cfg(feature = "tokio")]
+impl Spawner for TokioSpawner {
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        tokio::spawn(task);
+    }
+}
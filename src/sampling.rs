@@ -0,0 +1,153 @@
+//! Letting tools ask the model quick questions.
+
+use rmcp::RoleServer;
+use rmcp::model::{CreateMessageRequestParams, SamplingMessage, SamplingMessageContent};
+use rmcp::service::Peer;
+use tracing::debug;
+
+use crate::Error;
+
+/// How many tokens a completion may use unless told otherwise.
+const DEFAULT_MAX_TOKENS: u32 = 1024;
+
+/// A handle for requesting completions from the agent's model, passed to
+/// tools added with [`ToolSet::sampling_tool`](crate::ToolSet::sampling_tool).
+///
+/// Requests go through MCP sampling, so a tool can ask a quick sub-question,
+/// such as "normalize this company name", without opening a new session.
+/// Not every agent supports sampling; check [`is_supported`](Self::is_supported)
+/// and fall back to deterministic code if it doesn't. Tools invoked directly
+/// with [`ToolSet::invoke`](crate::ToolSet::invoke) get a sampler that is never
+/// supported.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let tools = ToolSet::new().sampling_tool(
+///     "lookup_company",
+///     "Look up a company by name",
+///     |input: LookupInput, sampler: Sampler| async move {
+///         let name = if sampler.is_supported() {
+///             sampler
+///                 .max_tokens(50)
+///                 .complete(format!("Reply with only the canonical legal name of: {}", input.name))
+///                 .await
+///                 .unwrap_or(input.name)
+///         } else {
+///             input.name
+///         };
+///         Ok(directory.find(&name))
+///     },
+/// );
+/// ```
+#[derive(Clone)]
+pub struct Sampler {
+    peer: Option<Peer<RoleServer>>,
+    max_tokens: u32,
+    system_prompt: Option<String>,
+    temperature: Option<f32>,
+}
+
+impl Sampler {
+    /// A sampler that sends requests to `peer`.
+    pub(crate) fn new(peer: Peer<RoleServer>) -> Self {
+        Self {
+            peer: Some(peer),
+            ..Self::unavailable()
+        }
+    }
+
+    /// A sampler for tools invoked without an agent.
+    pub(crate) fn unavailable() -> Self {
+        Self {
+            peer: None,
+            max_tokens: DEFAULT_MAX_TOKENS,
+            system_prompt: None,
+            temperature: None,
+        }
+    }
+
+    /// Whether the agent advertised support for sampling.
+    pub fn is_supported(&self) -> bool {
+        self.peer
+            .as_ref()
+            .and_then(|peer| peer.peer_info())
+            .is_some_and(|info| info.capabilities.sampling.is_some())
+    }
+
+    /// Limit completions to `max_tokens` tokens. The default is 1024.
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    /// Send `system_prompt` with each completion request.
+    pub fn system_prompt(mut self, system_prompt: impl Into<String>) -> Self {
+        self.system_prompt = Some(system_prompt.into());
+        self
+    }
+
+    /// Ask for completions sampled at `temperature`.
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Ask the model `prompt` and return the text of its reply.
+    ///
+    /// Fails with [`Error::Sampling`] if the agent doesn't support sampling,
+    /// rejects the request, or replies with something other than text.
+    pub async fn complete(&self, prompt: impl Into<String>) -> Result<String, Error> {
+        let Some(peer) = self.peer.as_ref().filter(|_| self.is_supported()) else {
+            return Err(Error::Sampling {
+                message: "the agent does not support sampling".to_string(),
+            });
+        };
+
+        let mut params = CreateMessageRequestParams::new(
+            vec![SamplingMessage::user_text(prompt)],
+            self.max_tokens,
+        );
+        if let Some(system_prompt) = &self.system_prompt {
+            params = params.with_system_prompt(system_prompt);
+        }
+        if let Some(temperature) = self.temperature {
+            params = params.with_temperature(temperature);
+        }
+
+        debug!(max_tokens = self.max_tokens, "requesting a completion");
+        let result = peer
+            .create_message(params)
+            .await
+            .map_err(|err| Error::Sampling {
+                message: err.to_string(),
+            })?;
+        let text: String = result
+            .message
+            .content
+            .into_vec()
+            .into_iter()
+            .filter_map(|content| match content {
+                SamplingMessageContent::Text(text) => Some(text.text),
+                _ => None,
+            })
+            .collect();
+        if text.is_empty() {
+            return Err(Error::Sampling {
+                message: "the model's reply contained no text".to_string(),
+            });
+        }
+        Ok(text)
+    }
+}
+
+impl std::fmt::Debug for Sampler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sampler")
+            .field("supported", &self.is_supported())
+            .field("max_tokens", &self.max_tokens)
+            .field("system_prompt", &self.system_prompt)
+            .field("temperature", &self.temperature)
+            .finish()
+    }
+}
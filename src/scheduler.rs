@@ -0,0 +1,454 @@
+//! Dispatching think blocks to an agent by priority.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+#[cfg(not(feature = "tokio"))]
+use std::time::Instant;
+
+use tokio::sync::oneshot;
+// Tokio's clock, so the rate limit agrees with the timer that enforces it
+#[cfg(feature = "tokio")]
+use tokio::time::Instant;
+use tracing::debug;
+
+/// Limits how many think blocks run against an agent at once, and how often
+/// they start.
+///
+/// Register with
+/// [`DeterminishticBuilder::scheduler`](crate::DeterminishticBuilder::scheduler).
+/// Think blocks beyond the limits wait in a queue and are dispatched highest
+/// [priority](crate::ThinkBuilder::priority) first, in the order they were
+/// enqueued within a priority. Clones share the same queue, so one scheduler
+/// can throttle several [`Determinishtic`](crate::Determinishtic) instances
+/// talking to the same backend.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let scheduler = Scheduler::new()
+///     .max_concurrent(4)
+///     .rate_limit(60, Duration::from_secs(60));
+/// let d = Determinishtic::builder()
+///     .scheduler(scheduler.clone())
+///     .connect(AcpAgent::zed_claude_code())
+///     .await?;
+///
+/// let summaries = futures::future::join_all(files.iter().map(|file| {
+///     d.think::<Summary>().text("Summarize").display(file)
+/// }))
+/// .await;
+/// println!("mean wait: {:?}", scheduler.metrics().mean_wait());
+/// ```
+#[derive(Clone)]
+pub struct Scheduler {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    max_concurrent: usize,
+    rate_limit: Option<(usize, Duration)>,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    running: usize,
+    waiting: BinaryHeap<Waiter>,
+    next_seq: u64,
+    /// When recent think blocks started, for the rate limit.
+    starts: VecDeque<Instant>,
+    /// Whether a task is already sleeping until the rate limit allows another start.
+    #[cfg(feature = "tokio")]
+    timer_armed: bool,
+    dispatched: u64,
+    completed: u64,
+    total_wait: Duration,
+    max_wait: Duration,
+}
+
+struct Waiter {
+    priority: i32,
+    seq: u64,
+    enqueued: Instant,
+    wake: oneshot::Sender<()>,
+}
+
+/// Queue statistics for a [`Scheduler`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchedulerMetrics {
+    /// Think blocks waiting to be dispatched.
+    pub queued: usize,
+
+    /// Think blocks currently running.
+    pub running: usize,
+
+    /// Think blocks dispatched so far.
+    pub dispatched: u64,
+
+    /// Think blocks that have finished, successfully or not.
+    pub completed: u64,
+
+    /// Total time dispatched think blocks spent in the queue.
+    pub total_wait: Duration,
+
+    /// The longest time a dispatched think block spent in the queue.
+    pub max_wait: Duration,
+}
+
+impl SchedulerMetrics {
+    /// Mean time dispatched think blocks spent in the queue (zero if none were dispatched).
+    pub fn mean_wait(&self) -> Duration {
+        if self.dispatched == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(self.total_wait.as_secs_f64() / self.dispatched as f64)
+        }
+    }
+}
+
+/// Held while a think block runs; frees its slot when dropped.
+pub(crate) struct Permit {
+    inner: Arc<Inner>,
+}
+
+/// A think block waiting in the queue.
+///
+/// If it is dropped while still queued, it leaves the queue; if it is dropped
+/// after being woken but before taking its permit, the slot it was given is
+/// freed.
+struct Queued {
+    woken: oneshot::Receiver<()>,
+    inner: Arc<Inner>,
+    admitted: bool,
+}
+
+impl Scheduler {
+    /// Create a scheduler that runs one think block at a time, with no rate limit.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                max_concurrent: 1,
+                rate_limit: None,
+                state: Mutex::new(State::default()),
+            }),
+        }
+    }
+
+    /// Run at most `n` think blocks at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero, or if the scheduler has already been cloned.
+    pub fn max_concurrent(mut self, n: usize) -> Self {
+        assert!(n > 0, "a scheduler must allow at least one think block");
+        self.configure().max_concurrent = n;
+        self
+    }
+
+    /// Start at most `starts` think blocks in any window of length `per`.
+    ///
+    /// Only available with the `tokio` feature, whose timer wakes the queue
+    /// when the window allows another start.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `starts` is zero, or if the scheduler has already been cloned.
+    #[cfg(feature = "tokio")]
+    pub fn rate_limit(mut self, starts: usize, per: Duration) -> Self {
+        assert!(starts > 0, "a rate limit must allow at least one start");
+        self.configure().rate_limit = Some((starts, per));
+        self
+    }
+
+    /// Current queue depth, concurrency, and wait times.
+    pub fn metrics(&self) -> SchedulerMetrics {
+        let state = self.inner.lock();
+        SchedulerMetrics {
+            queued: state.waiting.len(),
+            running: state.running,
+            dispatched: state.dispatched,
+            completed: state.completed,
+            total_wait: state.total_wait,
+            max_wait: state.max_wait,
+        }
+    }
+
+    fn configure(&mut self) -> &mut Inner {
+        Arc::get_mut(&mut self.inner).expect("a scheduler cannot be configured once shared")
+    }
+
+    /// Wait until a think block with this priority may run.
+    pub(crate) async fn acquire(&self, priority: i32) -> Permit {
+        let mut queued = {
+            let mut state = self.inner.lock();
+            let (wake, woken) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiting.push(Waiter {
+                priority,
+                seq,
+                enqueued: Instant::now(),
+                wake,
+            });
+            Inner::dispatch(&self.inner, &mut state);
+            Queued {
+                woken,
+                inner: self.inner.clone(),
+                admitted: false,
+            }
+        };
+        // Waiters are only removed from the queue by being woken, so the
+        // sender is never dropped without sending.
+        let _ = (&mut queued.woken).await;
+        queued.admitted = true;
+        Permit {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Inner {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Start as many waiting think blocks as the limits allow.
+    fn dispatch(this: &Arc<Inner>, state: &mut State) {
+        while state.running < this.max_concurrent && !state.waiting.is_empty() {
+            let now = Instant::now();
+            if let Some((starts, per)) = this.rate_limit {
+                while state
+                    .starts
+                    .front()
+                    .is_some_and(|&t| now.duration_since(t) >= per)
+                {
+                    state.starts.pop_front();
+                }
+                if state.starts.len() >= starts {
+                    let deadline = state.starts[0] + per;
+                    Self::arm_timer(this, state, deadline);
+                    return;
+                }
+            }
+
+            let waiter = state.waiting.pop().expect("checked above");
+            if waiter.wake.send(()).is_err() {
+                // The think block was dropped while queued
+                continue;
+            }
+            let wait = now.duration_since(waiter.enqueued);
+            debug!(priority = waiter.priority, ?wait, "dispatching think block");
+            state.running += 1;
+            state.dispatched += 1;
+            state.total_wait += wait;
+            state.max_wait = state.max_wait.max(wait);
+            if this.rate_limit.is_some() {
+                state.starts.push_back(now);
+            }
+        }
+    }
+
+    /// Free a slot and hand it to the next waiter. The think block that
+    /// held it is counted as completed only if it `ran`.
+    fn release(this: &Arc<Inner>, ran: bool) {
+        let mut state = this.lock();
+        state.running -= 1;
+        if ran {
+            state.completed += 1;
+        }
+        Inner::dispatch(this, &mut state);
+    }
+
+    /// Dispatch again once the rate limit allows it.
+    #[cfg(feature = "tokio")]
+    fn arm_timer(this: &Arc<Inner>, state: &mut State, deadline: Instant) {
+        if state.timer_armed {
+            return;
+        }
+        state.timer_armed = true;
+        let this = this.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep_until(deadline).await;
+            let mut state = this.lock();
+            state.timer_armed = false;
+            Inner::dispatch(&this, &mut state);
+        });
+    }
+
+    #[cfg(not(feature = "tokio"))]
+    fn arm_timer(_this: &Arc<Inner>, _state: &mut State, _deadline: Instant) {
+        unreachable!("rate limits can only be set with the `tokio` feature")
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        Inner::release(&self.inner, true);
+    }
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        if self.admitted {
+            return;
+        }
+        // Closing first means no dispatch can wake us after we check.
+        self.woken.close();
+        if self.woken.try_recv().is_ok() {
+            Inner::release(&self.inner, false);
+        } else {
+            let mut state = self.inner.lock();
+            state.waiting.retain(|waiter| !waiter.wake.is_closed());
+        }
+    }
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    /// Higher priority first, then first come, first served.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Let spawned tasks run until they block.
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn dispatches_by_priority_then_in_order() {
+        let scheduler = Scheduler::new();
+        let held = scheduler.acquire(0).await;
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let tasks: Vec<_> = [(0, 0), (1, 5), (2, 0), (3, 5)]
+            .into_iter()
+            .map(|(id, priority)| {
+                let scheduler = scheduler.clone();
+                let order = order.clone();
+                tokio::spawn(async move {
+                    let _permit = scheduler.acquire(priority).await;
+                    order.lock().unwrap().push(id);
+                })
+            })
+            .collect();
+        settle().await;
+        assert_eq!(scheduler.metrics().queued, 4);
+
+        drop(held);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), [1, 3, 0, 2]);
+        assert_eq!(scheduler.metrics().completed, 5);
+    }
+
+    #[tokio::test]
+    async fn runs_at_most_max_concurrent() {
+        let scheduler = Scheduler::new().max_concurrent(2);
+        let first = scheduler.acquire(0).await;
+        let _second = scheduler.acquire(0).await;
+        let third = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move {
+                let _permit = scheduler.acquire(0).await;
+            }
+        });
+        settle().await;
+        let metrics = scheduler.metrics();
+        assert_eq!((metrics.running, metrics.queued), (2, 1));
+
+        drop(first);
+        third.await.unwrap();
+        let metrics = scheduler.metrics();
+        assert_eq!((metrics.running, metrics.queued), (1, 0));
+        assert_eq!(metrics.completed, 2);
+    }
+
+    #[tokio::test]
+    async fn a_dropped_waiter_leaves_the_queue() {
+        let scheduler = Scheduler::new();
+        let held = scheduler.acquire(0).await;
+        let mut queued = Box::pin(scheduler.acquire(0));
+        assert!(futures::poll!(&mut queued).is_pending());
+        assert_eq!(scheduler.metrics().queued, 1);
+
+        drop(queued);
+        assert_eq!(scheduler.metrics().queued, 0);
+        drop(held);
+        let metrics = scheduler.metrics();
+        assert_eq!((metrics.running, metrics.completed), (0, 1));
+    }
+
+    #[tokio::test]
+    async fn a_waiter_dropped_after_waking_frees_its_slot_without_completing() {
+        let scheduler = Scheduler::new();
+        let held = scheduler.acquire(0).await;
+        let mut queued = Box::pin(scheduler.acquire(0));
+        assert!(futures::poll!(&mut queued).is_pending());
+
+        // Woken, but dropped before it takes its permit
+        drop(held);
+        assert_eq!(scheduler.metrics().running, 1);
+        drop(queued);
+        let metrics = scheduler.metrics();
+        assert_eq!((metrics.running, metrics.completed), (0, 1));
+
+        let _next = scheduler.acquire(0).await;
+        assert_eq!(scheduler.metrics().running, 1);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn rate_limit_spaces_out_starts() {
+        let per = Duration::from_secs(60);
+        let scheduler = Scheduler::new().max_concurrent(10).rate_limit(2, per);
+        let start = Instant::now();
+        let tasks: Vec<_> = (0..5)
+            .map(|_| {
+                let scheduler = scheduler.clone();
+                tokio::spawn(async move {
+                    let _permit = scheduler.acquire(0).await;
+                    start.elapsed()
+                })
+            })
+            .collect();
+
+        let mut started = Vec::new();
+        for task in tasks {
+            started.push(task.await.unwrap());
+        }
+        assert_eq!(started, [Duration::ZERO, Duration::ZERO, per, per, 2 * per]);
+    }
+}
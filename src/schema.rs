@@ -0,0 +1,215 @@
+//! Rendering JSON schemas as prose for inclusion in prompts.
+
+use serde_json::Value;
+
+/// How deeply nested fields are expanded before we stop describing them.
+/// Guards against infinitely recursive schemas.
+const MAX_DEPTH: usize = 4;
+
+/// Render an output schema as a human-readable prompt section.
+///
+/// Many agents comply more reliably when the expected structure is spelled
+/// out in the prompt rather than only in the tool definition.
+pub(crate) fn describe_output(schema: &Value) -> String {
+    let mut out = String::new();
+    let resolved = resolve(schema, schema);
+    let (object, nullable) = match non_null_variant(resolved, schema) {
+        Some(variant) => (variant, true),
+        None => (resolved, false),
+    };
+
+    if has_properties(object) {
+        if nullable {
+            out.push_str("Your result must be `null` or an object with these fields:\n");
+        } else {
+            out.push_str("Your result must be an object with these fields:\n");
+        }
+        describe_fields(object, schema, 0, &mut out);
+    } else {
+        out.push_str(&format!(
+            "Your result must have type: {}.",
+            type_phrase(resolved, schema)
+        ));
+        if let Some(description) = description(schema, resolved) {
+            out.push_str(&format!(" {description}"));
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+/// If the schema is "X or null", return X.
+fn non_null_variant<'a>(schema: &'a Value, root: &'a Value) -> Option<&'a Value> {
+    let variants = schema
+        .get("anyOf")
+        .or_else(|| schema.get("oneOf"))
+        .and_then(Value::as_array)?;
+    let (nulls, others): (Vec<&Value>, Vec<&Value>) = variants
+        .iter()
+        .map(|v| resolve(v, root))
+        .partition(|v| v.get("type").and_then(Value::as_str) == Some("null"));
+    match (nulls.len(), others.as_slice()) {
+        (1, [other]) => Some(other),
+        _ => None,
+    }
+}
+
+/// Whether a schema explicitly permits `null`, as generated for `Option<T>`.
+pub(crate) fn accepts_null(schema: &Value) -> bool {
+    let schema = resolve(schema, schema);
+    let is_null = |v: &Value| v.get("type").and_then(Value::as_str) == Some("null");
+
+    match schema.get("type") {
+        Some(Value::String(ty)) if ty == "null" => return true,
+        Some(Value::Array(types)) if types.iter().any(|t| t == "null") => return true,
+        _ => {}
+    }
+
+    ["anyOf", "oneOf"].iter().any(|key| {
+        schema
+            .get(key)
+            .and_then(Value::as_array)
+            .is_some_and(|variants| variants.iter().any(is_null))
+    })
+}
+
+/// Describe each property of an object schema as a bullet, recursing into
+/// nested objects and arrays of objects.
+fn describe_fields(schema: &Value, root: &Value, depth: usize, out: &mut String) {
+    if depth > MAX_DEPTH {
+        return;
+    }
+    let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+        return;
+    };
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|r| r.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+
+    let indent = "  ".repeat(depth);
+    for (name, property) in properties {
+        let resolved = resolve(property, root);
+        let requirement = if required.contains(&name.as_str()) {
+            "required"
+        } else {
+            "optional"
+        };
+        out.push_str(&format!(
+            "{indent}- `{name}` ({}, {requirement})",
+            type_phrase(resolved, root)
+        ));
+        if let Some(description) = description(property, resolved) {
+            out.push_str(&format!(": {description}"));
+        }
+        if let Some(examples) = property.get("examples").and_then(Value::as_array) {
+            let examples: Vec<String> = examples.iter().map(|e| format!("`{e}`")).collect();
+            out.push_str(&format!(" (e.g. {})", examples.join(", ")));
+        }
+        out.push('\n');
+
+        if has_properties(resolved) {
+            describe_fields(resolved, root, depth + 1, out);
+        } else if let Some(items) = resolved.get("items") {
+            let items = resolve(items, root);
+            if has_properties(items) {
+                out.push_str(&format!("{indent}  Each item has these fields:\n"));
+                describe_fields(items, root, depth + 2, out);
+            }
+        }
+    }
+}
+
+/// A short phrase describing the type accepted by a schema.
+fn type_phrase(schema: &Value, root: &Value) -> String {
+    if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+        let values: Vec<String> = values.iter().map(|v| format!("`{v}`")).collect();
+        return format!("one of {}", values.join(", "));
+    }
+    if let Some(value) = schema.get("const") {
+        return format!("exactly `{value}`");
+    }
+    for key in ["anyOf", "oneOf"] {
+        if let Some(variants) = schema.get(key).and_then(Value::as_array) {
+            let phrases: Vec<String> = variants
+                .iter()
+                .map(|v| type_phrase(resolve(v, root), root))
+                .collect();
+            return phrases.join(" or ");
+        }
+    }
+
+    match schema.get("type") {
+        Some(Value::String(ty)) => single_type_phrase(ty, schema, root),
+        Some(Value::Array(types)) => types
+            .iter()
+            .filter_map(Value::as_str)
+            .map(|ty| single_type_phrase(ty, schema, root))
+            .collect::<Vec<_>>()
+            .join(" or "),
+        _ if has_properties(schema) => "object".to_string(),
+        _ => "any JSON value".to_string(),
+    }
+}
+
+fn single_type_phrase(ty: &str, schema: &Value, root: &Value) -> String {
+    match ty {
+        "array" => match schema.get("items") {
+            Some(items) => format!("array of {}", type_phrase(resolve(items, root), root)),
+            None => "array".to_string(),
+        },
+        "string" => match schema.get("format").and_then(Value::as_str) {
+            Some(format) => match format_example(format) {
+                Some(example) => format!("string, format `{format}`, e.g. `{example}`"),
+                None => format!("string, format `{format}`"),
+            },
+            None => "string".to_string(),
+        },
+        other => other.to_string(),
+    }
+}
+
+/// An example value for a string format, so the agent can see the exact
+/// layout expected (ISO 8601 dates and times, hyphenated UUIDs).
+fn format_example(format: &str) -> Option<&'static str> {
+    match format {
+        "date-time" => Some("2024-05-01T13:45:00Z"),
+        "partial-date-time" => Some("2024-05-01T13:45:00"),
+        "date" => Some("2024-05-01"),
+        "partial-time" | "time" => Some("13:45:00"),
+        "uuid" => Some("67e55044-10b1-426f-9247-bb680e5fe0c8"),
+        _ => None,
+    }
+}
+
+/// The description for a property, preferring the one attached at the use
+/// site over the one on the referenced definition.
+fn description<'a>(schema: &'a Value, resolved: &'a Value) -> Option<&'a str> {
+    schema
+        .get("description")
+        .or_else(|| resolved.get("description"))
+        .and_then(Value::as_str)
+}
+
+fn has_properties(schema: &Value) -> bool {
+    schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .is_some_and(|p| !p.is_empty())
+}
+
+/// Follow a local `$ref` (e.g. `#/$defs/Name`) to the schema it points at.
+pub(crate) fn resolve<'a>(schema: &'a Value, root: &'a Value) -> &'a Value {
+    let Some(reference) = schema.get("$ref").and_then(Value::as_str) else {
+        return schema;
+    };
+    let Some(pointer) = reference.strip_prefix('#') else {
+        return schema;
+    };
+    match root.pointer(pointer) {
+        Some(target) => resolve(target, root),
+        None => schema,
+    }
+}
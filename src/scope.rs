@@ -0,0 +1,165 @@
+//! Groups of think blocks whose lifetimes are tied to a scope.
+
+use std::sync::{Arc, Mutex};
+
+use agent_client_protocol::Agent;
+use agent_client_protocol::RunWithConnectionTo;
+use agent_client_protocol::role::{HasPeer, Role};
+use futures::future::Either;
+use serde::de::DeserializeOwned;
+use tokio::sync::Notify;
+use tracing::debug;
+
+use crate::handle::TaskAbortHandle;
+use crate::{Determinishtic, Error, ThinkBuilder, ThinkHandle};
+
+/// What a [scope](Determinishtic::scope) does when one of its think blocks
+/// fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnFailure {
+    /// Cancel the other think blocks and fail the whole scope with the
+    /// first error.
+    #[default]
+    CancelOthers,
+
+    /// Let the other think blocks carry on. The error is returned only to
+    /// whoever awaits the failed block's handle.
+    KeepGoing,
+}
+
+/// A group of think blocks running in the background, created by
+/// [`Determinishtic::scope`].
+///
+/// Think blocks [spawned](Self::spawn) into the scope are cancelled when the
+/// scope's body returns, so none outlive it, and, unless the scope was
+/// created with [`OnFailure::KeepGoing`], as soon as any of them fails.
+pub struct ThinkScope {
+    on_failure: OnFailure,
+    tasks: Mutex<Vec<TaskAbortHandle>>,
+    /// The first error from a think block, under [`OnFailure::CancelOthers`].
+    error: Arc<Mutex<Option<Error>>>,
+    failed: Arc<Notify>,
+}
+
+impl ThinkScope {
+    fn new(on_failure: OnFailure) -> Self {
+        Self {
+            on_failure,
+            tasks: Mutex::new(Vec::new()),
+            error: Default::default(),
+            failed: Default::default(),
+        }
+    }
+
+    /// Run `think` in the background as part of the scope.
+    ///
+    /// Await the returned handle for the output. Like
+    /// [`ThinkBuilder::spawn`], the think block's tools must own their
+    /// state. Under [`OnFailure::CancelOthers`], the handle of a think block
+    /// that fails yields [`Error::Cancelled`]; the scope itself returns the
+    /// real error.
+    pub fn spawn<Output, R, Run>(
+        &self,
+        think: ThinkBuilder<'static, Output, R, Run>,
+    ) -> ThinkHandle<Output>
+    where
+        R: Role + HasPeer<Agent>,
+        Output: Send + DeserializeOwned + 'static,
+        Run: RunWithConnectionTo<R> + Send + 'static,
+    {
+        let handle = match self.on_failure {
+            OnFailure::KeepGoing => think.spawn(),
+            OnFailure::CancelOthers => {
+                let error = self.error.clone();
+                let failed = self.failed.clone();
+                think.spawn_then(move |result| {
+                    result.map_err(|err| {
+                        let mut first = error.lock().expect("scope error lock poisoned");
+                        if first.is_none() {
+                            debug!(%err, "think block failed; cancelling its scope");
+                            *first = Some(err);
+                            failed.notify_one();
+                        }
+                        Error::Cancelled
+                    })
+                })
+            }
+        };
+        self.tasks
+            .lock()
+            .expect("scope task lock poisoned")
+            .push(handle.abort_handle());
+        handle
+    }
+
+    /// Cancel every think block still running.
+    fn abort_all(&self) {
+        let tasks = std::mem::take(&mut *self.tasks.lock().expect("scope task lock poisoned"));
+        let running = tasks.iter().filter(|task| !task.is_finished()).count();
+        if running > 0 {
+            debug!(running, "cancelling think blocks at the end of their scope");
+        }
+        for task in tasks {
+            task.abort();
+        }
+    }
+
+    /// The first think block error, if one caused the scope to fail.
+    fn take_error(&self) -> Option<Error> {
+        self.error.lock().expect("scope error lock poisoned").take()
+    }
+}
+
+impl<R: Role> Determinishtic<R>
+where
+    R: HasPeer<Agent>,
+{
+    /// Run several think blocks as a group that cannot outlive `body`.
+    ///
+    /// Spawn think blocks with [`ThinkScope::spawn`] and await their
+    /// handles in `body`. When `body` returns, any think blocks still
+    /// running are cancelled. If any think block fails, the others are
+    /// cancelled too, `body` is dropped, and the scope returns that error;
+    /// use [`scope_with`](Self::scope_with) and [`OnFailure::KeepGoing`] to
+    /// handle failures yourself instead.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let (summary, risks) = d.scope(async |s| {
+    ///     let summary = s.spawn(d.think::<Summary>().text("Summarize").display(&doc));
+    ///     let risks = s.spawn(d.think::<Vec<Risk>>().text("List the risks in").display(&doc));
+    ///     Ok((summary.await?, risks.await?))
+    /// }).await?;
+    /// ```
+    pub async fn scope<T>(
+        &self,
+        body: impl AsyncFnOnce(&ThinkScope) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        self.scope_with(OnFailure::CancelOthers, body).await
+    }
+
+    /// Like [`scope`](Self::scope), choosing what happens when a think
+    /// block fails.
+    pub async fn scope_with<T>(
+        &self,
+        on_failure: OnFailure,
+        body: impl AsyncFnOnce(&ThinkScope) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let scope = ThinkScope::new(on_failure);
+        let result = {
+            let body = std::pin::pin!(body(&scope));
+            let failed = std::pin::pin!(scope.failed.notified());
+            match futures::future::select(body, failed).await {
+                Either::Left((result, _)) => result,
+                Either::Right(((), _)) => Err(Error::Cancelled),
+            }
+        };
+        scope.abort_all();
+        match (result, scope.take_error()) {
+            (Ok(value), _) => Ok(value),
+            (Err(_), Some(error)) => Err(error),
+            (Err(error), None) => Err(error),
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Grounding think blocks in a search index.
+
+use std::sync::Arc;
+
+use agent_client_protocol::BoxFuture;
+use agent_client_protocol::role::Role;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, ToolSet};
+
+/// How many snippets the search tool returns unless the agent asks for a
+/// different number.
+const DEFAULT_RESULTS: usize = 5;
+
+/// The most snippets the agent can ask for in one search.
+const MAX_RESULTS: usize = 20;
+
+/// A search index the agent can query for grounding material.
+///
+/// Offer an index to a think block with
+/// [`ThinkBuilder::tool_search`](crate::ThinkBuilder::tool_search), which
+/// registers a tool that passes the agent's query to [`search`](Self::search)
+/// and returns the snippets. Implement it over whatever you use for
+/// retrieval: a full-text index, a vector database, or a web search API.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// struct Docs(tantivy::Index);
+///
+/// impl Searcher for Docs {
+///     fn search(&self, query: String, limit: usize) -> BoxFuture<'_, Result<Vec<Snippet>, Error>> {
+///         Box::pin(async move { self.top_docs(&query, limit) })
+///     }
+/// }
+///
+/// let answer: Answer = d.think()
+///     .text("Answer the question, citing the documentation found with")
+///     .tool_search(docs)
+///     .text(":")
+///     .display(&question)
+///     .await?;
+/// ```
+pub trait Searcher: Send + Sync + 'static {
+    /// Return up to `limit` snippets relevant to `query`, most relevant first.
+    fn search(&self, query: String, limit: usize) -> BoxFuture<'_, Result<Vec<Snippet>, Error>>;
+
+    /// What the index contains, shown to the agent in the tool's
+    /// description, e.g. "the team's design documents".
+    fn description(&self) -> String {
+        "the available documents".to_string()
+    }
+}
+
+impl<S: Searcher + ?Sized> Searcher for Arc<S> {
+    fn search(&self, query: String, limit: usize) -> BoxFuture<'_, Result<Vec<Snippet>, Error>> {
+        (**self).search(query, limit)
+    }
+
+    fn description(&self) -> String {
+        (**self).description()
+    }
+}
+
+/// A search result returned by a [`Searcher`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Snippet {
+    /// The matching text.
+    pub text: String,
+
+    /// Where the text came from, such as a path or URL, so the agent can
+    /// cite it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+
+    /// How relevant the snippet is; higher is more relevant. The scale
+    /// depends on the index.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+}
+
+impl Snippet {
+    /// A snippet with no source or score.
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            source: None,
+            score: None,
+        }
+    }
+
+    /// Set where the snippet came from.
+    pub fn source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Set the snippet's relevance score.
+    pub fn score(mut self, score: f64) -> Self {
+        self.score = Some(score);
+        self
+    }
+}
+
+/// Input to the search tool.
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SearchInput {
+    /// What to search for.
+    query: String,
+
+    /// How many results to return (default 5, at most 20).
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+/// A tool set with a single tool named `name` that queries `searcher`.
+pub(crate) fn toolset<R: Role>(name: &str, searcher: impl Searcher) -> ToolSet<R> {
+    let description = format!(
+        "Search {} and return the most relevant snippets, each with its source when known.",
+        searcher.description()
+    );
+    let searcher = Arc::new(searcher);
+    let tool = name.to_string();
+    ToolSet::default().tool(name, &description, move |input: SearchInput| {
+        let searcher = searcher.clone();
+        let tool = tool.clone();
+        async move {
+            let limit = input.limit.unwrap_or(DEFAULT_RESULTS).clamp(1, MAX_RESULTS);
+            let mut snippets = searcher.search(input.query, limit).await.map_err(|err| {
+                agent_client_protocol::Error::internal_error()
+                    .data(format!("`{tool}` failed: {err}"))
+            })?;
+            snippets.truncate(limit);
+            Ok(snippets)
+        }
+    })
+}
@@ -0,0 +1,37 @@
+//! Security presets applied when connecting to an agent.
+
+/// How much the agent is trusted.
+///
+/// Set with [`DeterminishticBuilder::security_profile`](crate::DeterminishticBuilder::security_profile).
+/// The profile is fixed when the [`Determinishtic`](crate::Determinishtic)
+/// instance is created and applies to every think block run through it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SecurityProfile {
+    /// Permission requests go to the
+    /// [`on_permission`](crate::DeterminishticBuilder::on_permission) handler,
+    /// or are allowed if there is none, and any client handlers are offered
+    /// to the agent.
+    #[default]
+    Standard,
+
+    /// Deny by default.
+    ///
+    /// - Permission requests are rejected unless the tool was allowlisted with
+    ///   [`allow_permission`](crate::DeterminishticBuilder::allow_permission)
+    ///   or its kind with [`Permissions::allow`](crate::Permissions::allow).
+    /// - The client's file system and terminal handlers are neither
+    ///   advertised nor answered, so the agent gets no built-in tools from us.
+    /// - Prompts, session updates, and tool calls are left out of logs; only
+    ///   their sizes and kinds are recorded.
+    ///
+    /// Tools the agent runs without asking permission are outside the
+    /// client's control.
+    Strict,
+}
+
+impl SecurityProfile {
+    /// Whether prompt and tool content must be kept out of logs.
+    pub(crate) fn redacts_logs(self) -> bool {
+        matches!(self, SecurityProfile::Strict)
+    }
+}
@@ -0,0 +1,329 @@
+//! Serving think blocks to other processes over JSON-RPC.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use agent_client_protocol::Agent;
+use agent_client_protocol::role::{HasPeer, Role};
+use futures::channel::{mpsc, oneshot};
+use futures::future::LocalBoxFuture;
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tracing::{debug, warn};
+
+use crate::{Determinishtic, ToolSet};
+
+/// The error code for a think block that failed, from the range JSON-RPC
+/// reserves for server errors.
+const THINK_FAILED: i32 = -32000;
+
+/// Serves think blocks to a caller in another language over a small
+/// JSON-RPC protocol, so services that aren't written in Rust can use the
+/// crate's session, permission, and result handling as a sidecar.
+///
+/// Messages are JSON-RPC 2.0, one per line. The caller sends `think`
+/// requests with these parameters:
+///
+/// - `prompt`: the prompt text.
+/// - `bindings` (optional): values for `{name}` placeholders; when given,
+///   `prompt` is treated as an f-string style
+///   [template](crate::ThinkBuilder::template).
+/// - `outputSchema` (optional): the JSON Schema the result must match. Any
+///   JSON value is accepted without one.
+/// - `tools` (optional): tools the agent may call, each with a `name`, a
+///   `description`, and an `inputSchema`.
+/// - `labels` (optional): key/value pairs identifying the think block.
+/// - `model` (optional): the model to run the think block on.
+///
+/// The response's result has the agent's `output`, its `provenance`, the
+/// `permissions` it asked for, and `invalidToolCalls`. A think block that
+/// fails is answered with an error whose code is -32000 and whose message
+/// describes the failure.
+///
+/// When the agent calls one of the request's tools, the sidecar sends the
+/// caller a `tool/call` request with the tool's `name` and `arguments`. The
+/// caller answers with the tool's output as the result, or with an error,
+/// which the agent sees as a failed tool call. Several think blocks can run
+/// at once; each is answered when it finishes.
+///
+/// This type is only available with the `sidecar` feature.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let d = Determinishtic::new(AcpAgent::zed_claude_code()).await?;
+/// Sidecar::new(&d).serve_stdio().await?;
+/// ```
+///
+/// A caller then writes lines like this to the sidecar's standard input:
+///
+/// ```json
+/// {"jsonrpc":"2.0","id":1,"method":"think","params":{"prompt":"Summarize order 42, looking it up with lookup_order","tools":[{"name":"lookup_order","description":"Look up an order","inputSchema":{"type":"object","properties":{"id":{"type":"integer"}}}}]}}
+/// ```
+pub struct Sidecar<'d, R: Role = Agent>
+where
+    R: HasPeer<Agent>,
+{
+    d: &'d Determinishtic<R>,
+}
+
+/// The parameters of a `think` request.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ThinkParams {
+    prompt: String,
+    #[serde(default)]
+    bindings: Option<BTreeMap<String, String>>,
+    #[serde(default)]
+    output_schema: Option<Value>,
+    #[serde(default)]
+    tools: Vec<ToolSpec>,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default)]
+    model: Option<String>,
+}
+
+/// A tool the caller implements.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ToolSpec {
+    name: String,
+    #[serde(default)]
+    description: String,
+    input_schema: Value,
+}
+
+impl<'d, R: Role> Sidecar<'d, R>
+where
+    R: HasPeer<Agent>,
+{
+    /// A sidecar running think blocks on `d`.
+    pub fn new(d: &'d Determinishtic<R>) -> Self {
+        Self { d }
+    }
+
+    /// Serve requests from standard input, writing to standard output,
+    /// until standard input closes.
+    pub async fn serve_stdio(self) -> std::io::Result<()> {
+        self.serve(tokio::io::stdin(), tokio::io::stdout()).await
+    }
+
+    /// Serve requests read from `reader`, writing to `writer`, until
+    /// `reader` reaches its end. Think blocks still running then are
+    /// cancelled.
+    pub async fn serve(
+        self,
+        reader: impl AsyncRead + Unpin,
+        mut writer: impl AsyncWrite + Unpin,
+    ) -> std::io::Result<()> {
+        let (outgoing, mut tool_calls) = mpsc::unbounded();
+        let caller = Arc::new(Caller {
+            outgoing,
+            next_id: AtomicU64::new(0),
+            pending: Mutex::default(),
+        });
+        let mut lines = BufReader::new(reader).lines();
+        let mut running = FuturesUnordered::new();
+        loop {
+            futures::select! {
+                line = lines.next_line().fuse() => {
+                    let Some(line) = line? else {
+                        break;
+                    };
+                    if let Some(response) = self.receive(&line, &caller) {
+                        running.push(response);
+                    }
+                }
+                request = tool_calls.select_next_some() => write(&mut writer, &request).await?,
+                response = running.select_next_some() => write(&mut writer, &response).await?,
+            }
+        }
+        debug!(running = running.len(), "sidecar input closed");
+        Ok(())
+    }
+
+    /// Act on a line from the caller: hand a response to the tool call
+    /// waiting for it, or start answering a request.
+    fn receive(&self, line: &str, caller: &Arc<Caller>) -> Option<LocalBoxFuture<'d, Value>> {
+        if line.trim().is_empty() {
+            return None;
+        }
+        let message: Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(err) => {
+                let error = agent_client_protocol::Error::parse_error().data(err.to_string());
+                return Some(
+                    futures::future::ready(response(Value::Null, Err(error))).boxed_local(),
+                );
+            }
+        };
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            caller.answer(&message);
+            return None;
+        };
+        let Some(id) = message.get("id").cloned() else {
+            debug!(method, "ignoring notification");
+            return None;
+        };
+        match method {
+            "think" => {
+                let params = message.get("params").cloned().unwrap_or(Value::Null);
+                let d = self.d;
+                let caller = caller.clone();
+                Some(async move { response(id, think(d, params, caller).await) }.boxed_local())
+            }
+            _ => {
+                let error = agent_client_protocol::Error::method_not_found().data(method);
+                Some(futures::future::ready(response(id, Err(error))).boxed_local())
+            }
+        }
+    }
+}
+
+/// Run the think block described by `params`, calling the caller's tools
+/// through `caller`.
+async fn think<R>(
+    d: &Determinishtic<R>,
+    params: Value,
+    caller: Arc<Caller>,
+) -> Result<Value, agent_client_protocol::Error>
+where
+    R: Role + HasPeer<Agent>,
+{
+    let params: ThinkParams = serde_json::from_value(params)
+        .map_err(|err| agent_client_protocol::Error::invalid_params().data(err.to_string()))?;
+
+    let mut tools = ToolSet::default();
+    for tool in params.tools {
+        if !tool.input_schema.is_object() {
+            return Err(agent_client_protocol::Error::invalid_params().data(format!(
+                "the input schema of tool `{}` is not an object",
+                tool.name
+            )));
+        }
+        let caller = caller.clone();
+        let name = tool.name.clone();
+        tools = tools.dynamic_tool(
+            &tool.name,
+            &tool.description,
+            tool.input_schema,
+            move |arguments| {
+                let caller = caller.clone();
+                let name = name.clone();
+                async move { caller.call_tool(name, arguments).await }
+            },
+        );
+    }
+
+    let mut think = d.think::<Value>().tools(&tools);
+    think = match params.bindings {
+        Some(bindings) => bindings
+            .iter()
+            .fold(think.template(&params.prompt), |think, (name, value)| {
+                think.bind(name, value)
+            }),
+        None => think.text(&params.prompt),
+    };
+    if let Some(schema) = params.output_schema {
+        think = think.output_schema(schema);
+    }
+    if let Some(model) = params.model {
+        think = think.model(model);
+    }
+    for (key, value) in params.labels {
+        think = think.label(key, value);
+    }
+
+    let outcome = think
+        .outcome()
+        .await
+        .map_err(|err| agent_client_protocol::Error::new(THINK_FAILED, err.to_string()))?;
+    Ok(json!({
+        "output": outcome.output,
+        "provenance": outcome.provenance(),
+        "permissions": outcome.permissions,
+        "invalidToolCalls": outcome.invalid_tool_calls,
+    }))
+}
+
+/// The JSON-RPC response to the request with `id`.
+fn response(id: Value, result: Result<Value, agent_client_protocol::Error>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    }
+}
+
+async fn write(writer: &mut (impl AsyncWrite + Unpin), message: &Value) -> std::io::Result<()> {
+    writer.write_all(format!("{message}\n").as_bytes()).await?;
+    writer.flush().await
+}
+
+/// The process driving the sidecar, seen as a provider of tools.
+struct Caller {
+    /// Requests to send the caller.
+    outgoing: mpsc::UnboundedSender<Value>,
+    next_id: AtomicU64,
+    /// Tool calls awaiting the caller's answer, by request ID.
+    pending: Mutex<HashMap<u64, oneshot::Sender<Result<Value, agent_client_protocol::Error>>>>,
+}
+
+impl Caller {
+    /// Ask the caller to run the tool named `name`.
+    async fn call_tool(
+        &self,
+        name: String,
+        arguments: Value,
+    ) -> Result<Value, agent_client_protocol::Error> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (answer, answered) = oneshot::channel();
+        self.lock().insert(id, answer);
+        debug!(tool_name = %name, id, "calling tool on the caller");
+        self.outgoing
+            .unbounded_send(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "tool/call",
+                "params": { "name": name, "arguments": arguments },
+            }))
+            .map_err(agent_client_protocol::Error::into_internal_error)?;
+        answered.await.map_err(|_| {
+            agent_client_protocol::Error::internal_error()
+                .data("the caller stopped before answering the tool call")
+        })?
+    }
+
+    /// Hand the caller's response to the tool call waiting for it.
+    fn answer(&self, message: &Value) {
+        let Some(id) = message.get("id").and_then(Value::as_u64) else {
+            warn!("ignoring a response without a tool call ID");
+            return;
+        };
+        let Some(answer) = self.lock().remove(&id) else {
+            warn!(id, "ignoring a response to no outstanding tool call");
+            return;
+        };
+        let result = match message.get("error") {
+            Some(error) => Err(serde_json::from_value(error.clone()).unwrap_or_else(|_| {
+                agent_client_protocol::Error::internal_error().data(error.clone())
+            })),
+            None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+        };
+        // The think block may have finished without waiting for the answer
+        let _ = answer.send(result);
+    }
+
+    fn lock(
+        &self,
+    ) -> MutexGuard<'_, HashMap<u64, oneshot::Sender<Result<Value, agent_client_protocol::Error>>>>
+    {
+        self.pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -0,0 +1,122 @@
+//! Delivering results to durable storage as soon as they are accepted.
+
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use agent_client_protocol::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+use crate::runtime::blocking;
+use crate::{Error, Provenance};
+
+/// A result handed to a [`ResultSink`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Delivery {
+    /// The result as the agent returned it, after it was accepted.
+    pub output: serde_json::Value,
+
+    /// Where the result came from.
+    pub provenance: Provenance,
+}
+
+/// Somewhere results are written as think blocks complete.
+///
+/// A think block delivers its result to each sink added with
+/// [`ThinkBuilder::deliver_to`](crate::ThinkBuilder::deliver_to) before its
+/// future resolves, so completed work survives if the process crashes
+/// partway through a batch. If a sink fails, the think block fails with
+/// its error, even though other sinks may already have the result; a retry
+/// may then deliver it again, so sinks see each result at least once.
+///
+/// Implementations are provided for [`JsonLinesSink`], Tokio channels of
+/// [`Delivery`], and closures returning a future, such as a database
+/// insert:
+///
+/// ```rust,ignore
+/// let save = move |delivery: Delivery| {
+///     let pool = pool.clone();
+///     async move { insert_result(&pool, delivery).await.map_err(Error::sink) }
+/// };
+///
+/// for doc in docs {
+///     let summary: Summary = d.think().text("Summarize").display(&doc).deliver_to(&save).await?;
+/// }
+/// ```
+pub trait ResultSink: Send + Sync {
+    /// Durably store `delivery`, resolving only once it is safe.
+    fn deliver(&self, delivery: Delivery) -> BoxFuture<'_, Result<(), Error>>;
+}
+
+impl<F, Fut> ResultSink for F
+where
+    F: Fn(Delivery) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<(), Error>> + Send + 'static,
+{
+    fn deliver(&self, delivery: Delivery) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(self(delivery))
+    }
+}
+
+impl ResultSink for tokio::sync::mpsc::Sender<Delivery> {
+    fn deliver(&self, delivery: Delivery) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move { self.send(delivery).await.map_err(Error::sink) })
+    }
+}
+
+impl ResultSink for tokio::sync::mpsc::UnboundedSender<Delivery> {
+    fn deliver(&self, delivery: Delivery) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move { self.send(delivery).map_err(Error::sink) })
+    }
+}
+
+/// A [`ResultSink`] that appends each [`Delivery`] to a file as one line of
+/// JSON, flushing it to disk before the think block returns.
+///
+/// Clones append to the same file.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let sink = JsonLinesSink::open("results.jsonl")?;
+/// for doc in docs {
+///     d.think::<Summary>().text("Summarize").display(&doc).deliver_to(&sink).await?;
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct JsonLinesSink {
+    file: Arc<Mutex<File>>,
+}
+
+impl JsonLinesSink {
+    /// Open `path` for appending, creating it if it doesn't exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(Error::sink)?;
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+}
+
+impl ResultSink for JsonLinesSink {
+    fn deliver(&self, delivery: Delivery) -> BoxFuture<'_, Result<(), Error>> {
+        let file = self.file.clone();
+        Box::pin(async move {
+            let mut line = serde_json::to_vec(&delivery).map_err(Error::sink)?;
+            line.push(b'\n');
+            blocking(move || {
+                let mut file = file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                file.write_all(&line)?;
+                file.sync_data()
+            })
+            .await
+            .map_err(Error::sink)
+        })
+    }
+}
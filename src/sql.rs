@@ -0,0 +1,547 @@
+//! A read-only SQL query tool backed by `sqlx`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use agent_client_protocol::role::Role;
+use base64::Engine;
+use futures::TryStreamExt;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::any::{AnyArguments, AnyRow, AnyTypeInfoKind};
+use sqlx::pool::PoolConnection;
+use sqlx::query::Query;
+use sqlx::{Any, AnyPool, Column, Connection, Row, Transaction, ValueRef};
+use tracing::debug;
+
+use crate::ToolSet;
+use crate::redaction::LogRedaction;
+
+/// Statements a read-only tool accepts, by their first keyword.
+const READ_STATEMENTS: &[&str] = &["select", "with", "values"];
+
+/// Keywords that modify data or schema. A statement containing any of them
+/// after its first keyword is rejected, so that writes can't be smuggled in
+/// through a CTE, `SELECT INTO`, or an allowed `EXPLAIN`.
+const WRITE_KEYWORDS: &[&str] = &[
+    "insert", "update", "delete", "merge", "upsert", "into", "create", "alter", "drop", "truncate",
+    "grant", "revoke", "attach", "detach", "copy", "call", "exec", "execute",
+];
+
+/// Backend names, as reported by `sqlx`, of the databases that can be made
+/// to refuse writes.
+const SQLITE: &str = "SQLite";
+const POSTGRES: &str = "PostgreSQL";
+const MYSQL: &str = "MySQL";
+
+/// A SQL query tool for think blocks that analyze data, so apps don't each
+/// write their own.
+///
+/// The agent sends a statement and a list of parameters, which are bound
+/// rather than spliced into the SQL. Only statements that start with an
+/// allowed keyword (`SELECT`, `WITH`, or `VALUES` for
+/// [`read_only`](Self::read_only)) and contain no keyword that writes
+/// (`INSERT`, `UPDATE`, `DROP`, ...) are run, and only one statement
+/// per call. Results are cut off after [`max_rows`](Self::max_rows) rows
+/// and queries are abandoned after a [`timeout`](Self::timeout).
+///
+/// The statement check is only a fast, lexical first pass; the database
+/// itself refuses writes. Each query runs in a transaction that is rolled
+/// back, which is `READ ONLY` on PostgreSQL and MySQL, and SQLite
+/// connections are switched to `query_only` while it runs. Other databases
+/// only get the rollback, so for untrusted agents, also connect with a
+/// database user that can only read.
+///
+/// A query that runs past the timeout fails with the error code
+/// [`SqlTools::TIMED_OUT`].
+///
+/// Any database `sqlx` supports through [`AnyPool`] works. Remember to call
+/// [`sqlx::any::install_default_drivers`] before connecting. Requires the
+/// `sqlx` feature.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// sqlx::any::install_default_drivers();
+/// let pool = AnyPool::connect("sqlite://sales.db").await?;
+/// let tools = SqlTools::read_only(pool)
+///     .schema("orders(id, customer, total, placed_at)")
+///     .max_rows(50)
+///     .toolset();
+///
+/// let report: Report = d.think()
+///     .text("Which customers ordered the most last month?")
+///     .tools(&tools)
+///     .await?;
+/// ```
+#[derive(Clone)]
+pub struct SqlTools {
+    pool: AnyPool,
+    name: String,
+    schema: Option<String>,
+    statements: Vec<String>,
+    max_rows: usize,
+    timeout: Duration,
+}
+
+/// Input to the query tool.
+#[derive(Debug, Deserialize, JsonSchema)]
+struct QueryInput {
+    /// A single SQL statement. Write values as placeholders (`?` or `$1`,
+    /// depending on the database) and pass them in `params`.
+    sql: String,
+
+    /// Values for the placeholders, in order.
+    #[serde(default)]
+    params: Vec<Value>,
+}
+
+/// Output of the query tool.
+#[derive(Debug, Serialize, JsonSchema)]
+struct QueryOutput {
+    /// Column names, in order.
+    columns: Vec<String>,
+
+    /// Each row's values, in column order. Binary values are base64 encoded.
+    rows: Vec<Vec<Value>>,
+
+    /// Whether more rows matched than were returned.
+    truncated: bool,
+}
+
+impl SqlTools {
+    /// The error code of a query that ran past its timeout, so the agent
+    /// can tell it apart from a query that failed.
+    pub const TIMED_OUT: i32 = -32001;
+
+    /// A tool named `sql_query` that runs read-only statements against `pool`.
+    ///
+    /// Defaults to at most 100 rows per query and a 10 second timeout.
+    pub fn read_only(pool: AnyPool) -> Self {
+        Self {
+            pool,
+            name: "sql_query".to_string(),
+            schema: None,
+            statements: READ_STATEMENTS.iter().map(|s| s.to_string()).collect(),
+            max_rows: 100,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Name the tool, e.g. to offer tools for two databases at once.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Describe the tables the agent can query. Included in the tool's
+    /// description, which saves the agent exploring the schema itself.
+    pub fn schema(mut self, schema: impl Into<String>) -> Self {
+        self.schema = Some(schema.into());
+        self
+    }
+
+    /// Also accept statements starting with `keyword`, such as `explain`
+    /// or `show`. Statements containing a keyword that writes are still
+    /// rejected.
+    pub fn allow_statement(mut self, keyword: impl Into<String>) -> Self {
+        self.statements.push(keyword.into().to_lowercase());
+        self
+    }
+
+    /// Return at most `rows` rows per query. The output says whether any
+    /// were left out.
+    pub fn max_rows(mut self, rows: usize) -> Self {
+        self.max_rows = rows;
+        self
+    }
+
+    /// Abandon queries that run longer than `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// A tool set containing the query tool, to pass to
+    /// [`ThinkBuilder::tools`](crate::ThinkBuilder::tools).
+    pub fn toolset<R: Role>(self) -> ToolSet<R> {
+        let mut description = format!(
+            "Run a read-only SQL query and return the matching rows (at most {}). \
+             Pass values as parameters rather than writing them into the SQL.",
+            self.max_rows
+        );
+        if let Some(schema) = &self.schema {
+            description.push_str(&format!("\n\nSchema:\n{schema}"));
+        }
+        let name = self.name.clone();
+        let tools = Arc::new(self);
+        ToolSet::default().tool(&name, &description, move |input: QueryInput| {
+            let tools = tools.clone();
+            async move { tools.query(input).await }
+        })
+    }
+
+    async fn query(&self, input: QueryInput) -> Result<QueryOutput, agent_client_protocol::Error> {
+        check_statement(&input.sql, &self.statements)
+            .map_err(|reason| agent_client_protocol::Error::invalid_params().data(reason))?;
+        match LogRedaction::for_tool_call().text(&input.sql) {
+            Some(sql) => debug!(tool_name = self.name, sql, "running query"),
+            None => debug!(
+                tool_name = self.name,
+                sql_len = input.sql.len(),
+                "running query"
+            ),
+        }
+
+        let mut query = sqlx::query(&input.sql);
+        for param in &input.params {
+            query = bind(query, param);
+        }
+
+        let mut conn = ReadOnlyConnection::acquire(&self.pool)
+            .await
+            .map_err(query_failed)?;
+        let mut transaction = conn.begin().await.map_err(query_failed)?;
+        let fetch = async {
+            let mut rows = query.fetch(&mut *transaction);
+            let mut output = QueryOutput {
+                columns: Vec::new(),
+                rows: Vec::new(),
+                truncated: false,
+            };
+            while let Some(row) = rows.try_next().await? {
+                if output.rows.len() == self.max_rows {
+                    output.truncated = true;
+                    break;
+                }
+                if output.columns.is_empty() {
+                    output.columns = row.columns().iter().map(|c| c.name().to_string()).collect();
+                }
+                output.rows.push(row_values(&row)?);
+            }
+            Ok::<_, sqlx::Error>(output)
+        };
+
+        let Ok(fetched) = tokio::time::timeout(self.timeout, fetch).await else {
+            // The query may still be running, so don't wait for it to roll back
+            drop(transaction);
+            conn.abandon();
+            return Err(agent_client_protocol::Error::new(
+                Self::TIMED_OUT,
+                format!("query timed out after {:?}", self.timeout),
+            )
+            .data(serde_json::json!({ "timeout_ms": self.timeout.as_millis() })));
+        };
+        // Nothing a query does is ever kept
+        transaction.rollback().await.map_err(query_failed)?;
+        conn.release().await.map_err(query_failed)?;
+        fetched.map_err(query_failed)
+    }
+}
+
+/// A pooled connection that refuses writes for as long as a query runs.
+///
+/// SQLite is switched to `query_only` for the query and back afterwards;
+/// if that doesn't happen, the connection is closed rather than returned
+/// to the pool still refusing writes.
+struct ReadOnlyConnection {
+    conn: PoolConnection<Any>,
+    reusable: bool,
+}
+
+impl ReadOnlyConnection {
+    async fn acquire(pool: &AnyPool) -> Result<Self, sqlx::Error> {
+        let mut conn = Self {
+            conn: pool.acquire().await?,
+            reusable: true,
+        };
+        if conn.conn.backend_name() == SQLITE {
+            conn.reusable = false;
+            sqlx::query("PRAGMA query_only = ON")
+                .execute(&mut *conn.conn)
+                .await?;
+        }
+        Ok(conn)
+    }
+
+    /// Begin the transaction a query runs in, read-only where the database
+    /// supports it.
+    async fn begin(&mut self) -> Result<Transaction<'_, Any>, sqlx::Error> {
+        match self.conn.backend_name() {
+            POSTGRES | MYSQL => self.conn.begin_with("START TRANSACTION READ ONLY").await,
+            _ => self.conn.begin().await,
+        }
+    }
+
+    /// Make the connection writable again, so it can go back to the pool.
+    async fn release(mut self) -> Result<(), sqlx::Error> {
+        if self.conn.backend_name() == SQLITE {
+            sqlx::query("PRAGMA query_only = OFF")
+                .execute(&mut *self.conn)
+                .await?;
+        }
+        self.reusable = true;
+        Ok(())
+    }
+
+    /// Close the connection instead of returning it to the pool.
+    fn abandon(mut self) {
+        self.reusable = false;
+    }
+}
+
+impl Drop for ReadOnlyConnection {
+    fn drop(&mut self) {
+        if !self.reusable {
+            self.conn.close_on_drop();
+        }
+    }
+}
+
+/// Report a failed query back to the agent.
+fn query_failed(err: sqlx::Error) -> agent_client_protocol::Error {
+    agent_client_protocol::Error::invalid_params().data(format!("query failed: {err}"))
+}
+
+impl std::fmt::Debug for SqlTools {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SqlTools")
+            .field("name", &self.name)
+            .field("statements", &self.statements)
+            .field("max_rows", &self.max_rows)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Bind a JSON parameter. Arrays and objects are bound as JSON text.
+fn bind<'q>(
+    query: Query<'q, Any, AnyArguments<'q>>,
+    param: &Value,
+) -> Query<'q, Any, AnyArguments<'q>> {
+    match param {
+        Value::Null => query.bind(None::<String>),
+        Value::Bool(b) => query.bind(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => query.bind(i),
+            None => query.bind(n.as_f64()),
+        },
+        Value::String(s) => query.bind(s.clone()),
+        Value::Array(_) | Value::Object(_) => query.bind(param.to_string()),
+    }
+}
+
+/// Convert a row's values to JSON.
+fn row_values(row: &AnyRow) -> Result<Vec<Value>, sqlx::Error> {
+    (0..row.columns().len())
+        .map(|i| {
+            let raw = row.try_get_raw(i)?;
+            if raw.is_null() {
+                return Ok(Value::Null);
+            }
+            Ok(match raw.type_info().kind() {
+                AnyTypeInfoKind::Null => Value::Null,
+                AnyTypeInfoKind::Bool => Value::from(row.try_get::<bool, _>(i)?),
+                AnyTypeInfoKind::SmallInt | AnyTypeInfoKind::Integer | AnyTypeInfoKind::BigInt => {
+                    Value::from(row.try_get::<i64, _>(i)?)
+                }
+                AnyTypeInfoKind::Real | AnyTypeInfoKind::Double => {
+                    Value::from(row.try_get::<f64, _>(i)?)
+                }
+                AnyTypeInfoKind::Text => Value::from(row.try_get::<String, _>(i)?),
+                AnyTypeInfoKind::Blob => {
+                    let bytes = row.try_get::<Vec<u8>, _>(i)?;
+                    Value::from(base64::engine::general_purpose::STANDARD.encode(bytes))
+                }
+            })
+        })
+        .collect()
+}
+
+/// Check that `sql` is a single statement starting with one of `allowed`
+/// and containing no other keyword that writes.
+///
+/// String literals, quoted identifiers, and comments are skipped, so a
+/// keyword inside them doesn't count.
+fn check_statement(sql: &str, allowed: &[String]) -> Result<(), String> {
+    let words = words(sql)?;
+    let Some(first) = words.first() else {
+        return Err("the query is empty".to_string());
+    };
+    if !allowed.iter().any(|keyword| keyword == first) {
+        return Err(format!(
+            "only statements starting with {} are allowed",
+            allowed
+                .iter()
+                .map(|k| k.to_uppercase())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    if let Some(keyword) = words[1..]
+        .iter()
+        .find(|word| WRITE_KEYWORDS.contains(&word.as_str()))
+    {
+        return Err(format!(
+            "`{}` is not allowed in a read-only query",
+            keyword.to_uppercase()
+        ));
+    }
+    Ok(())
+}
+
+/// The lowercase keywords and identifiers of a single statement, or an
+/// error if it contains more than one.
+fn words(sql: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut chars = sql.chars().peekable();
+    let mut ended = false;
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if ended && c != ';' {
+            return Err("only one statement is allowed per query".to_string());
+        }
+        match c {
+            ';' => ended = true,
+            '\'' | '"' | '`' => {
+                // Quotes are escaped by doubling them, which reads as two
+                // adjacent quoted sections; either way we skip them all
+                for next in chars.by_ref() {
+                    if next == c {
+                        break;
+                    }
+                }
+            }
+            '[' => {
+                for next in chars.by_ref() {
+                    if next == ']' {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                for next in chars.by_ref() {
+                    if next == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for next in chars.by_ref() {
+                    if previous == '*' && next == '/' {
+                        break;
+                    }
+                    previous = next;
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut word = c.to_lowercase().to_string();
+                while let Some(&next) = chars.peek() {
+                    if !(next.is_alphanumeric() || next == '_' || next == '$') {
+                        break;
+                    }
+                    word.extend(next.to_lowercase());
+                    chars.next();
+                }
+                words.push(word);
+            }
+            _ => {}
+        }
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::any::AnyPoolOptions;
+
+    use super::*;
+
+    /// An in-memory SQLite database with one row in table `t`.
+    async fn pool() -> AnyPool {
+        sqlx::any::install_default_drivers();
+        // One connection, so every query sees the same in-memory database
+        let pool = AnyPoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE t (id INTEGER)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO t VALUES (1)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool
+    }
+
+    fn input(sql: &str) -> QueryInput {
+        QueryInput {
+            sql: sql.to_string(),
+            params: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn returns_rows() {
+        let tools = SqlTools::read_only(pool().await);
+        let output = tools.query(input("SELECT id FROM t")).await.unwrap();
+        assert_eq!(output.columns, ["id"]);
+        assert_eq!(output.rows, [[Value::from(1)]]);
+        assert!(!output.truncated);
+    }
+
+    #[tokio::test]
+    async fn the_database_refuses_writes_the_lexer_lets_through() {
+        let pool = pool().await;
+        let tools = SqlTools::read_only(pool.clone()).allow_statement("delete");
+        assert!(check_statement("DELETE FROM t", &tools.statements).is_ok());
+
+        let err = tools.query(input("DELETE FROM t")).await.unwrap_err();
+        assert!(err.data.unwrap().to_string().contains("readonly"));
+
+        // The row is still there, and the connection writable again
+        let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM t")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(rows, 1);
+        sqlx::query("INSERT INTO t VALUES (2)")
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn timeouts_have_their_own_code() {
+        let tools = SqlTools::read_only(pool().await).timeout(Duration::from_millis(1));
+        let err = tools
+            .query(input(
+                "WITH RECURSIVE n(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM n WHERE x < 10000000) \
+                 SELECT COUNT(*) FROM n",
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(i32::from(err.code), SqlTools::TIMED_OUT);
+    }
+
+    #[test]
+    fn rejects_writes_hidden_in_a_cte() {
+        let allowed: Vec<String> = READ_STATEMENTS.iter().map(|s| s.to_string()).collect();
+        assert!(
+            check_statement(
+                "WITH d AS (DELETE FROM t RETURNING 1) SELECT * FROM d",
+                &allowed
+            )
+            .is_err()
+        );
+        assert!(check_statement("SELECT 1; DROP TABLE t", &allowed).is_err());
+    }
+}
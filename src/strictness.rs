@@ -0,0 +1,423 @@
+//! Checking and fixing tool arguments against a tool's input schema.
+
+use serde_json::{Map, Number, Value};
+
+use crate::schema::resolve;
+
+/// How strictly a tool's arguments are held to its input schema, set with
+/// [`ToolSet::strictness`](crate::ToolSet::strictness).
+///
+/// Without a setting, arguments are deserialized with serde's defaults:
+/// unknown fields are silently dropped and mistyped values are rejected.
+/// That hides model confusion, such as a misspelled optional field, which
+/// [`Strict`](Self::Strict) brings to light in evaluation runs; the other
+/// levels go the other way and accept more of what the model sends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgumentStrictness {
+    /// Reject arguments with fields the schema doesn't have, telling the
+    /// agent which ones.
+    Strict,
+    /// Drop unknown fields and convert values to the type the schema
+    /// expects where that is unambiguous, such as `"42"` for an integer.
+    Lenient,
+    /// Try the arguments as they are; if they don't deserialize, make the
+    /// [`Lenient`](Self::Lenient) fixes, also unpack values sent as JSON
+    /// strings and wrap lone values expected in arrays, then try again and
+    /// log a warning listing the repairs.
+    Repair,
+}
+
+/// A problem found, or a fix made, in a tool's arguments.
+#[derive(Debug)]
+pub(crate) struct Issue {
+    /// A JSON pointer to the field concerned.
+    pub(crate) pointer: String,
+    /// What was found or done, such as "unknown field".
+    what: &'static str,
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.pointer.is_empty() {
+            write!(f, "{} the top level", self.what)
+        } else {
+            write!(f, "{} `{}`", self.what, self.pointer)
+        }
+    }
+}
+
+/// Check `value` against `schema`, fixing what `strictness` allows, and
+/// describe each problem found or fix made.
+///
+/// Under [`ArgumentStrictness::Strict`] nothing is changed and only unknown
+/// fields are reported; type errors are left for deserialization to report.
+pub(crate) fn conform(
+    value: &mut Value,
+    schema: &Value,
+    strictness: ArgumentStrictness,
+) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    walk(value, schema, schema, "", strictness, &mut issues);
+    issues
+}
+
+fn walk(
+    value: &mut Value,
+    schema: &Value,
+    root: &Value,
+    path: &str,
+    strictness: ArgumentStrictness,
+    issues: &mut Vec<Issue>,
+) {
+    let schema = resolve(schema, root);
+
+    if let Some(variants) = schema
+        .get("anyOf")
+        .or_else(|| schema.get("oneOf"))
+        .and_then(Value::as_array)
+    {
+        let variants: Vec<&Value> = variants.iter().map(|v| resolve(v, root)).collect();
+        // Prefer a variant the value already fits, then one it can be made to
+        let variant = variants
+            .iter()
+            .find(|variant| fits(value, variant))
+            .or_else(|| {
+                variants
+                    .iter()
+                    .find(|variant| convert(value, variant, strictness).is_some())
+            });
+        if let Some(variant) = variant {
+            walk(value, variant, root, path, strictness, issues);
+        }
+        return;
+    }
+    for part in schema
+        .get("allOf")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        walk(value, part, root, path, strictness, issues);
+    }
+
+    if !fits(value, schema)
+        && let Some((converted, how)) = convert(value, schema, strictness)
+    {
+        issues.push(Issue {
+            pointer: path.to_string(),
+            what: how,
+        });
+        *value = converted;
+    }
+
+    match value {
+        Value::Object(map) => walk_object(map, schema, root, path, strictness, issues),
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items").filter(|items| items.is_object()) {
+                for (index, item) in items.iter_mut().enumerate() {
+                    let path = format!("{path}/{index}");
+                    walk(item, item_schema, root, &path, strictness, issues);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn walk_object(
+    map: &mut Map<String, Value>,
+    schema: &Value,
+    root: &Value,
+    path: &str,
+    strictness: ArgumentStrictness,
+    issues: &mut Vec<Issue>,
+) {
+    let properties = schema.get("properties").and_then(Value::as_object);
+    let additional = schema.get("additionalProperties");
+
+    // Fields are unknown only where the schema lists its fields and doesn't
+    // also allow others
+    if let Some(properties) = properties
+        && !additional.is_some_and(|additional| additional.is_object() || additional == true)
+    {
+        let unknown: Vec<String> = map
+            .keys()
+            .filter(|key| !properties.contains_key(*key))
+            .cloned()
+            .collect();
+        for key in unknown {
+            let pointer = field_path(path, &key);
+            if strictness == ArgumentStrictness::Strict {
+                issues.push(Issue {
+                    pointer,
+                    what: "unknown field",
+                });
+            } else {
+                issues.push(Issue {
+                    pointer,
+                    what: "dropped unknown field",
+                });
+                map.remove(&key);
+            }
+        }
+    }
+
+    for (key, field) in map.iter_mut() {
+        let field_schema = properties
+            .and_then(|properties| properties.get(key))
+            .or(additional.filter(|additional| additional.is_object()));
+        if let Some(field_schema) = field_schema {
+            walk(
+                field,
+                field_schema,
+                root,
+                &field_path(path, key),
+                strictness,
+                issues,
+            );
+        }
+    }
+}
+
+/// Whether `value` has one of the types `schema` allows. Schemas that don't
+/// name a type accept anything.
+fn fits(value: &Value, schema: &Value) -> bool {
+    let types = types(schema);
+    types.is_empty() || types.iter().any(|ty| has_type(value, ty))
+}
+
+fn types(schema: &Value) -> Vec<&str> {
+    match schema.get("type") {
+        Some(Value::String(ty)) => vec![ty.as_str()],
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn has_type(value: &Value, ty: &str) -> bool {
+    match ty {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => false,
+    }
+}
+
+/// `value` converted to a type `schema` allows, with a phrase saying how,
+/// if `strictness` permits a conversion that loses nothing.
+fn convert(
+    value: &Value,
+    schema: &Value,
+    strictness: ArgumentStrictness,
+) -> Option<(Value, &'static str)> {
+    if strictness == ArgumentStrictness::Strict {
+        return None;
+    }
+    let repair = strictness == ArgumentStrictness::Repair;
+    types(schema).into_iter().find_map(|ty| match (ty, value) {
+        ("integer", Value::String(s)) => s
+            .trim()
+            .parse::<i64>()
+            .ok()
+            .map(|n| (Value::from(n), "converted a string to an integer at")),
+        ("integer", Value::Number(n)) => n
+            .as_f64()
+            .filter(|f| f.fract() == 0.0 && f.abs() < i64::MAX as f64)
+            .map(|f| {
+                (
+                    Value::from(f as i64),
+                    "converted a whole float to an integer at",
+                )
+            }),
+        ("number", Value::String(s)) => s
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .and_then(Number::from_f64)
+            .map(|n| (Value::Number(n), "converted a string to a number at")),
+        ("boolean", Value::String(s)) => match s.trim().to_ascii_lowercase().as_str() {
+            "true" => Some((Value::Bool(true), "converted a string to a boolean at")),
+            "false" => Some((Value::Bool(false), "converted a string to a boolean at")),
+            _ => None,
+        },
+        ("string", Value::Number(n)) => Some((
+            Value::String(n.to_string()),
+            "converted a number to a string at",
+        )),
+        ("string", Value::Bool(b)) => Some((
+            Value::String(b.to_string()),
+            "converted a boolean to a string at",
+        )),
+        ("object" | "array", value) if repair => value
+            .as_str()
+            .and_then(|s| serde_json::from_str::<Value>(s).ok())
+            .filter(|parsed| has_type(parsed, ty))
+            .map(|parsed| (parsed, "parsed a JSON string at"))
+            .or_else(|| {
+                (ty == "array" && !value.is_null()).then(|| {
+                    (
+                        Value::Array(vec![value.clone()]),
+                        "wrapped a lone value in an array at",
+                    )
+                })
+            }),
+        _ => None,
+    })
+}
+
+/// The JSON pointer to `key` in the object at `path`.
+pub(crate) fn field_path(path: &str, key: &str) -> String {
+    format!("{path}/{}", key.replace('~', "~0").replace('/', "~1"))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "count": { "type": "integer" },
+                "ratio": { "type": "number" },
+                "dry_run": { "type": "boolean" },
+                "paths": { "type": "array", "items": { "type": "string" } },
+                "filter": { "$ref": "#/$defs/Filter" },
+            },
+            "$defs": {
+                "Filter": {
+                    "type": "object",
+                    "properties": { "pattern": { "type": "string" } },
+                },
+            },
+        })
+    }
+
+    /// Each issue as "what `pointer`".
+    fn described(issues: &[Issue]) -> Vec<String> {
+        issues.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn strict_reports_unknown_fields_and_changes_nothing() {
+        let sent = json!({ "count": "42", "colour": "red", "filter": { "glob": "*.rs" } });
+        let mut value = sent.clone();
+        let issues = conform(&mut value, &schema(), ArgumentStrictness::Strict);
+        assert_eq!(
+            described(&issues),
+            ["unknown field `/colour`", "unknown field `/filter/glob`"]
+        );
+        assert_eq!(value, sent);
+    }
+
+    #[test]
+    fn lenient_drops_unknown_fields_and_converts_values() {
+        let mut value = json!({ "count": 1.0, "ratio": "0.5", "dry_run": "true", "colour": "red" });
+        let issues = conform(&mut value, &schema(), ArgumentStrictness::Lenient);
+        assert_eq!(value, json!({ "count": 1, "ratio": 0.5, "dry_run": true }));
+        assert_eq!(issues.len(), 4, "{:?}", described(&issues));
+
+        let mut value = json!({ "count": "42" });
+        conform(&mut value, &schema(), ArgumentStrictness::Lenient);
+        assert_eq!(value, json!({ "count": 42 }));
+    }
+
+    #[test]
+    fn lenient_leaves_ambiguous_values_alone() {
+        let mut value = json!({ "count": 1.5, "dry_run": "yes", "paths": "src" });
+        let issues = conform(&mut value, &schema(), ArgumentStrictness::Lenient);
+        assert!(issues.is_empty(), "{:?}", described(&issues));
+        assert_eq!(
+            value,
+            json!({ "count": 1.5, "dry_run": "yes", "paths": "src" })
+        );
+    }
+
+    #[test]
+    fn repair_unpacks_json_strings_and_wraps_lone_values() {
+        let mut value = json!({ "filter": "{\"pattern\": \"*.rs\"}", "paths": "src/lib.rs" });
+        let issues = conform(&mut value, &schema(), ArgumentStrictness::Repair);
+        assert_eq!(
+            value,
+            json!({ "filter": { "pattern": "*.rs" }, "paths": ["src/lib.rs"] })
+        );
+        assert_eq!(
+            described(&issues),
+            [
+                "parsed a JSON string at `/filter`",
+                "wrapped a lone value in an array at `/paths`",
+            ]
+        );
+
+        let mut value = json!({ "paths": "[\"a.rs\", \"b.rs\"]" });
+        conform(&mut value, &schema(), ArgumentStrictness::Repair);
+        assert_eq!(value, json!({ "paths": ["a.rs", "b.rs"] }));
+    }
+
+    #[test]
+    fn any_of_prefers_the_variant_the_value_fits() {
+        let schema = json!({
+            "anyOf": [
+                { "type": "integer" },
+                { "type": "string" },
+            ],
+        });
+        let mut value = json!("42");
+        assert!(conform(&mut value, &schema, ArgumentStrictness::Lenient).is_empty());
+        assert_eq!(value, json!("42"));
+
+        // Only converted when no variant fits as sent
+        let schema = json!({
+            "anyOf": [
+                { "type": "null" },
+                { "type": "integer" },
+            ],
+        });
+        let mut value = json!("42");
+        let issues = conform(&mut value, &schema, ArgumentStrictness::Lenient);
+        assert_eq!(value, json!(42));
+        assert_eq!(
+            described(&issues),
+            ["converted a string to an integer at the top level"]
+        );
+    }
+
+    #[test]
+    fn additional_properties_decide_what_is_unknown() {
+        let open = json!({
+            "type": "object",
+            "properties": { "name": { "type": "string" } },
+            "additionalProperties": { "type": "integer" },
+        });
+        let mut value = json!({ "name": "x", "size": "3" });
+        let issues = conform(&mut value, &open, ArgumentStrictness::Lenient);
+        assert_eq!(value, json!({ "name": "x", "size": 3 }));
+        assert_eq!(
+            described(&issues),
+            ["converted a string to an integer at `/size`"]
+        );
+
+        let anything = json!({
+            "type": "object",
+            "properties": { "name": { "type": "string" } },
+            "additionalProperties": true,
+        });
+        let mut value = json!({ "name": "x", "size": "3" });
+        assert!(conform(&mut value, &anything, ArgumentStrictness::Strict).is_empty());
+
+        let closed = json!({
+            "type": "object",
+            "properties": { "name": { "type": "string" } },
+            "additionalProperties": false,
+        });
+        let mut value = json!({ "name": "x", "size": "3" });
+        let issues = conform(&mut value, &closed, ArgumentStrictness::Strict);
+        assert_eq!(described(&issues), ["unknown field `/size`"]);
+    }
+}
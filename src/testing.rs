@@ -0,0 +1,821 @@
+//! A scripted mock agent for exercising think blocks without an LLM.
+//!
+//! [`MockAgent`] speaks ACP and MCP-over-ACP, so the tools registered on a
+//! think block are called through the same machinery a real agent would use.
+//! Each prompt the agent receives consumes one scripted [`Turn`]:
+//!
+//! ```rust,ignore
+//! use determinishtic::testing::{MockAgent, Turn};
+//!
+//! let mock = MockAgent::new().turn(
+//!     Turn::new()
+//!         .expect_prompt("Process the data")
+//!         .call_tool("transform", json!({ "value": 21 }))
+//!         .return_tool_output(),
+//! );
+//!
+//! let d = mock.connect().await?;
+//! let output: TransformOutput = d.think()
+//!     .text("Process the data using")
+//!     .tool("transform", "Double a value", async |input: TransformInput, _cx| {
+//!         Ok(TransformOutput { value: input.value * 2 })
+//!     }, tool_fn_mut!())
+//!     .await?;
+//!
+//! assert_eq!(output.value, 42);
+//! mock.verify();
+//! mock.transcript().assert_tool_called("transform").with_arg("value", 21);
+//! ```
+//!
+//! To check that your retry and validation configuration copes with a
+//! misbehaving model, add [`Chaos`] to inject malformed tool arguments,
+//! invalid results, duplicate calls, and disconnects.
+//!
+//! This module is only available with the `testing` feature.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use agent_client_protocol::schema::{
+    ContentBlock, ContentChunk, Implementation, InitializeRequest, InitializeResponse,
+    McpConnectRequest, McpDisconnectNotification, McpOverAcpMessage, McpServer, NewSessionRequest,
+    NewSessionResponse, PermissionOption, PermissionOptionKind, PromptRequest, PromptResponse,
+    RequestPermissionOutcome, RequestPermissionRequest, SessionId, SessionNotification,
+    SessionUpdate, StopReason, ToolCallUpdate, ToolCallUpdateFields, ToolKind,
+};
+use agent_client_protocol::{
+    Agent, Client, ConnectTo, ConnectionTo, McpAcpTransport, MetaCapabilityExt, UntypedMessage,
+};
+use serde_json::{Value, json};
+use tracing::{debug, warn};
+
+use crate::{Determinishtic, DeterminishticBuilder, Error};
+
+mod transcript;
+
+pub use transcript::{
+    ToolCallAssert, ToolCallView, Transcript, TranscriptAssert, TranscriptEvent, TranscriptRecorder,
+};
+
+/// An ACP agent that follows a script instead of consulting an LLM.
+///
+/// Cloning a `MockAgent` yields a handle to the same script and transcript,
+/// so you can keep one handle to [`verify`](Self::verify) the run after
+/// connecting with another.
+#[derive(Clone, Default)]
+pub struct MockAgent {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Default)]
+struct MockState {
+    turns: VecDeque<Turn>,
+    sessions: HashMap<SessionId, Vec<String>>,
+    next_session: usize,
+    next_tool_call: usize,
+    events: Vec<TranscriptEvent>,
+    failures: Vec<String>,
+    chaos: Option<Chaos>,
+}
+
+/// Misbehavior for a [`MockAgent`] to inject while running its script.
+///
+/// Each kind of misbehavior happens with the given probability, driven by a
+/// seeded generator so that a failing run can be reproduced. Injected
+/// misbehavior is recorded as [`TranscriptEvent::Chaos`].
+///
+/// ```rust,ignore
+/// let mock = MockAgent::new()
+///     .chaos(Chaos::new(7).malformed_arguments(0.5).invalid_results(0.5))
+///     .turn(Turn::new().call_tool("transform", json!({ "value": 21 })).return_tool_output());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Chaos {
+    state: u64,
+    malformed_arguments: f64,
+    invalid_results: f64,
+    duplicate_calls: f64,
+    disconnects: f64,
+}
+
+/// The scripted behavior for a single prompt.
+#[derive(Debug, Clone, Default)]
+pub struct Turn {
+    steps: Vec<Step>,
+    stop_reason: Option<StopReason>,
+}
+
+#[derive(Debug, Clone)]
+enum Step {
+    ExpectPrompt(String),
+    Say(String),
+    Think(String),
+    CallTool { name: String, arguments: Value },
+    ReturnToolOutput,
+    ReturnResult(Value),
+    RequestPermission { title: String, kind: ToolKind },
+}
+
+impl Chaos {
+    /// Create a chaos configuration that injects nothing until probabilities are set.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: seed,
+            malformed_arguments: 0.0,
+            invalid_results: 0.0,
+            duplicate_calls: 0.0,
+            disconnects: 0.0,
+        }
+    }
+
+    /// Before a scripted tool call, first call the tool with arguments of the wrong types.
+    pub fn malformed_arguments(mut self, probability: f64) -> Self {
+        self.malformed_arguments = probability;
+        self
+    }
+
+    /// Before returning the scripted result, first return one that does not match the schema.
+    pub fn invalid_results(mut self, probability: f64) -> Self {
+        self.invalid_results = probability;
+        self
+    }
+
+    /// Repeat a tool call (including `return_result`) with the same arguments.
+    pub fn duplicate_calls(mut self, probability: f64) -> Self {
+        self.duplicate_calls = probability;
+        self
+    }
+
+    /// Drop the connection before a step, without ending the turn.
+    ///
+    /// The connection cannot be used afterwards, so this is best combined
+    /// with a single scripted turn per connection.
+    pub fn disconnects(mut self, probability: f64) -> Self {
+        self.disconnects = probability;
+        self
+    }
+
+    /// Decide whether to inject something with the given probability.
+    fn roll(&mut self, probability: f64) -> bool {
+        if probability <= 0.0 {
+            return false;
+        }
+        // splitmix64
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        ((z >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+/// Replace a value with one of the wrong type, recursing into object fields.
+fn corrupt(value: &Value) -> Value {
+    match value {
+        Value::Object(fields) if !fields.is_empty() => Value::Object(
+            fields
+                .iter()
+                .map(|(k, v)| (k.clone(), corrupt(v)))
+                .collect(),
+        ),
+        Value::Object(_) | Value::Array(_) => Value::String("chaos".to_string()),
+        Value::String(_) => json!(0),
+        Value::Number(_) | Value::Bool(_) | Value::Null => json!({ "chaos": true }),
+    }
+}
+
+/// Why a scripted turn stopped early.
+enum TurnError {
+    /// An expectation failed.
+    Failed(String),
+    /// Chaos decided to drop the connection.
+    Disconnect,
+}
+
+impl From<String> for TurnError {
+    fn from(message: String) -> Self {
+        TurnError::Failed(message)
+    }
+}
+
+impl Turn {
+    /// Create an empty turn, which ends immediately without returning a result.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail the turn unless the prompt contains `text`.
+    pub fn expect_prompt(mut self, text: &str) -> Self {
+        self.steps.push(Step::ExpectPrompt(text.to_string()));
+        self
+    }
+
+    /// Send an agent message to the client.
+    pub fn say(mut self, text: &str) -> Self {
+        self.steps.push(Step::Say(text.to_string()));
+        self
+    }
+
+    /// Stream some reasoning to the client as an agent thought.
+    pub fn think(mut self, text: &str) -> Self {
+        self.steps.push(Step::Think(text.to_string()));
+        self
+    }
+
+    /// Call a tool with the given JSON arguments.
+    ///
+    /// The call goes through MCP, so the arguments are validated and
+    /// deserialized exactly as they would be for a real agent.
+    pub fn call_tool(mut self, name: &str, arguments: Value) -> Self {
+        self.steps.push(Step::CallTool {
+            name: name.to_string(),
+            arguments,
+        });
+        self
+    }
+
+    /// Ask the client for permission to use one of the agent's own tools.
+    ///
+    /// The request offers the usual allow/reject once/always options; the
+    /// chosen option is recorded as a [`TranscriptEvent::Permission`].
+    pub fn request_permission(mut self, title: &str, kind: ToolKind) -> Self {
+        self.steps.push(Step::RequestPermission {
+            title: title.to_string(),
+            kind,
+        });
+        self
+    }
+
+    /// Call `return_result` with the output of the most recent tool call.
+    pub fn return_tool_output(mut self) -> Self {
+        self.steps.push(Step::ReturnToolOutput);
+        self
+    }
+
+    /// Call `return_result` with the given JSON result.
+    pub fn return_result(mut self, result: Value) -> Self {
+        self.steps.push(Step::ReturnResult(result));
+        self
+    }
+
+    /// End the turn with `reason` instead of [`StopReason::EndTurn`].
+    pub fn stop_with(mut self, reason: StopReason) -> Self {
+        self.stop_reason = Some(reason);
+        self
+    }
+}
+
+impl MockAgent {
+    /// Create a mock agent with an empty script.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a turn to the script.
+    pub fn turn(self, turn: Turn) -> Self {
+        self.lock().turns.push_back(turn);
+        self
+    }
+
+    /// Inject misbehavior while running the script.
+    pub fn chaos(self, chaos: Chaos) -> Self {
+        self.lock().chaos = Some(chaos);
+        self
+    }
+
+    /// Connect a [`Determinishtic`] instance to this agent.
+    ///
+    /// The mock agent is connected directly rather than through a conductor,
+    /// so tool calls travel over MCP-over-ACP.
+    pub async fn connect(&self) -> Result<Determinishtic, Error> {
+        self.connect_with(Determinishtic::builder()).await
+    }
+
+    /// Like [`connect`](Self::connect), but with the handlers configured on `builder`.
+    ///
+    /// Conductor settings on the builder are ignored, since no conductor is used.
+    pub async fn connect_with(
+        &self,
+        builder: DeterminishticBuilder,
+    ) -> Result<Determinishtic, Error> {
+        builder.connect_direct(self.clone()).await
+    }
+
+    /// Everything that has happened so far, in order.
+    pub fn transcript(&self) -> Transcript {
+        Transcript::new(self.lock().events.clone())
+    }
+
+    /// How many sessions the client has opened so far.
+    pub fn sessions(&self) -> usize {
+        self.lock().sessions.len()
+    }
+
+    /// Panic if any expectation failed or any scripted turn was not used.
+    #[track_caller]
+    pub fn verify(&self) {
+        let state = self.lock();
+        let mut problems = state.failures.clone();
+        if !state.turns.is_empty() {
+            problems.push(format!(
+                "{} scripted turn(s) were never run",
+                state.turns.len()
+            ));
+        }
+        if !problems.is_empty() {
+            panic!(
+                "mock agent verification failed:\n- {}",
+                problems.join("\n- ")
+            );
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn record(&self, event: TranscriptEvent) {
+        self.lock().events.push(event);
+    }
+
+    /// Roll for a kind of chaos, recording the injection if it happens.
+    fn inject(
+        &self,
+        probability: impl Fn(&Chaos) -> f64,
+        description: impl FnOnce() -> String,
+    ) -> bool {
+        let mut state = self.lock();
+        let Some(chaos) = state.chaos.as_mut() else {
+            return false;
+        };
+        let probability = probability(chaos);
+        if !chaos.roll(probability) {
+            return false;
+        }
+        let description = description();
+        debug!(%description, "mock agent injecting chaos");
+        state.events.push(TranscriptEvent::Chaos(description));
+        true
+    }
+
+    fn fail(&self, message: String) {
+        warn!(%message, "mock agent expectation failed");
+        self.lock().failures.push(message);
+    }
+
+    fn new_session(&self, request: NewSessionRequest) -> NewSessionResponse {
+        let mut state = self.lock();
+        let session_id = SessionId::new(format!("mock-session-{}", state.next_session));
+        state.next_session += 1;
+
+        let acp_urls = request
+            .mcp_servers
+            .into_iter()
+            .filter_map(|server| match server {
+                McpServer::Http(http) if http.url.starts_with("acp:") => Some(http.url),
+                _ => None,
+            })
+            .collect();
+        state.sessions.insert(session_id.clone(), acp_urls);
+
+        NewSessionResponse::new(session_id)
+    }
+
+    /// Run the next scripted turn for a prompt.
+    ///
+    /// Failed expectations are recorded for [`verify`](Self::verify) and end
+    /// the turn early; the prompt itself always completes normally, so the
+    /// think block sees the same outcome a real agent giving up would cause.
+    ///
+    /// Returns `None` if chaos decided to drop the connection.
+    async fn prompt(
+        &self,
+        request: PromptRequest,
+        cx: &ConnectionTo<Client>,
+    ) -> Option<PromptResponse> {
+        let prompt: String = request
+            .prompt
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::Text(text) => Some(text.text.as_str()),
+                _ => None,
+            })
+            .collect();
+        self.record(TranscriptEvent::Prompt(prompt.clone()));
+
+        let (turn, acp_urls) = {
+            let mut state = self.lock();
+            let turn = state.turns.pop_front();
+            let acp_urls = state
+                .sessions
+                .get(&request.session_id)
+                .cloned()
+                .unwrap_or_default();
+            (turn, acp_urls)
+        };
+
+        let stop_reason = turn
+            .as_ref()
+            .and_then(|turn| turn.stop_reason)
+            .unwrap_or(StopReason::EndTurn);
+        match turn {
+            Some(turn) => {
+                let mut mcp = McpClient::new(cx.clone(), acp_urls);
+                let result = self
+                    .run_turn(turn, &prompt, &request.session_id, cx, &mut mcp)
+                    .await;
+                mcp.disconnect();
+                match result {
+                    Ok(()) => {}
+                    Err(TurnError::Failed(message)) => self.fail(message),
+                    Err(TurnError::Disconnect) => return None,
+                }
+            }
+            None => self.fail(format!(
+                "received a prompt but no scripted turns remain: {prompt:?}"
+            )),
+        }
+
+        Some(PromptResponse::new(stop_reason))
+    }
+
+    async fn run_turn(
+        &self,
+        turn: Turn,
+        prompt: &str,
+        session_id: &SessionId,
+        cx: &ConnectionTo<Client>,
+        mcp: &mut McpClient,
+    ) -> Result<(), TurnError> {
+        let mut last_output: Option<Value> = None;
+
+        for step in turn.steps {
+            debug!(?step, "mock agent step");
+            if self.inject(|c| c.disconnects, || format!("disconnect before {step:?}")) {
+                return Err(TurnError::Disconnect);
+            }
+            match step {
+                Step::ExpectPrompt(text) => {
+                    if !prompt.contains(&text) {
+                        return Err(format!(
+                            "expected the prompt to contain {text:?}, got {prompt:?}"
+                        )
+                        .into());
+                    }
+                }
+                Step::Say(text) => {
+                    cx.send_notification(SessionNotification::new(
+                        session_id.clone(),
+                        SessionUpdate::AgentMessageChunk(ContentChunk::new(ContentBlock::from(
+                            text.clone(),
+                        ))),
+                    ))
+                    .map_err(|err| err.to_string())?;
+                    self.record(TranscriptEvent::Message(text));
+                }
+                Step::Think(text) => {
+                    cx.send_notification(SessionNotification::new(
+                        session_id.clone(),
+                        SessionUpdate::AgentThoughtChunk(ContentChunk::new(ContentBlock::from(
+                            text.clone(),
+                        ))),
+                    ))
+                    .map_err(|err| err.to_string())?;
+                    self.record(TranscriptEvent::Reasoning(text));
+                }
+                Step::CallTool { name, arguments } => {
+                    if self.inject(
+                        |c| c.malformed_arguments,
+                        || format!("malformed arguments for `{name}`"),
+                    ) {
+                        let _ = self.call_tool(mcp, &name, corrupt(&arguments)).await;
+                    }
+                    last_output = self.call_tool(mcp, &name, arguments).await.ok();
+                }
+                Step::ReturnToolOutput => {
+                    let Some(output) = last_output.clone() else {
+                        return Err(TurnError::Failed(
+                            "return_tool_output used without a successful tool call before it"
+                                .to_string(),
+                        ));
+                    };
+                    self.return_result(mcp, output).await;
+                }
+                Step::ReturnResult(result) => {
+                    self.return_result(mcp, result).await;
+                }
+                Step::RequestPermission { title, kind } => {
+                    let outcome = self
+                        .request_permission(session_id, cx, &title, kind)
+                        .await
+                        .map_err(|err| err.to_string())?;
+                    self.record(TranscriptEvent::Permission {
+                        title,
+                        kind: Some(kind),
+                        outcome,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Send a permission request, returning the id of the chosen option.
+    async fn request_permission(
+        &self,
+        session_id: &SessionId,
+        cx: &ConnectionTo<Client>,
+        title: &str,
+        kind: ToolKind,
+    ) -> Result<Option<String>, agent_client_protocol::Error> {
+        let next_id = {
+            let mut state = self.lock();
+            state.next_tool_call += 1;
+            state.next_tool_call
+        };
+        let options = [
+            ("allow_once", PermissionOptionKind::AllowOnce),
+            ("allow_always", PermissionOptionKind::AllowAlways),
+            ("reject_once", PermissionOptionKind::RejectOnce),
+            ("reject_always", PermissionOptionKind::RejectAlways),
+        ]
+        .into_iter()
+        .map(|(id, kind)| PermissionOption::new(id, id.replace('_', " "), kind))
+        .collect();
+        let request = RequestPermissionRequest::new(
+            session_id.clone(),
+            ToolCallUpdate::new(
+                format!("mock-tool-call-{next_id}"),
+                ToolCallUpdateFields::new()
+                    .title(title.to_string())
+                    .kind(kind),
+            ),
+            options,
+        );
+        let response = cx.send_request(request).block_task().await?;
+        Ok(match response.outcome {
+            RequestPermissionOutcome::Selected(selected) => Some(selected.option_id.to_string()),
+            _ => None,
+        })
+    }
+
+    /// Call `return_result`, possibly preceded by an invalid result.
+    ///
+    /// A rejected result is not an expectation failure; it surfaces to the
+    /// think block as a missing result.
+    async fn return_result(&self, mcp: &mut McpClient, result: Value) {
+        if self.inject(|c| c.invalid_results, || "invalid result".to_string()) {
+            let _ = self
+                .call_tool(mcp, "return_result", json!({ "result": corrupt(&result) }))
+                .await;
+        }
+        let _ = self
+            .call_tool(mcp, "return_result", json!({ "result": result }))
+            .await;
+    }
+
+    /// Call a tool and record the call. A failing call is not an expectation
+    /// failure: real agents see tool errors too.
+    async fn call_tool(
+        &self,
+        mcp: &mut McpClient,
+        name: &str,
+        arguments: Value,
+    ) -> Result<Value, String> {
+        let output = mcp.call_tool(name, arguments.clone()).await;
+        self.record(TranscriptEvent::ToolCall {
+            name: name.to_string(),
+            arguments: arguments.clone(),
+            output: Some(output.clone()),
+        });
+
+        if self.inject(
+            |c| c.duplicate_calls,
+            || format!("duplicate call to `{name}`"),
+        ) {
+            let duplicate = mcp.call_tool(name, arguments.clone()).await;
+            self.record(TranscriptEvent::ToolCall {
+                name: name.to_string(),
+                arguments,
+                output: Some(duplicate),
+            });
+        }
+
+        output
+    }
+}
+
+impl ConnectTo<Client> for MockAgent {
+    async fn connect_to(
+        self,
+        client: impl ConnectTo<Agent>,
+    ) -> Result<(), agent_client_protocol::Error> {
+        let on_new_session = self.clone();
+        let on_prompt = self.clone();
+
+        Agent
+            .builder()
+            .on_receive_request(
+                async |request: InitializeRequest, responder, _cx| {
+                    responder.respond(
+                        InitializeResponse::new(request.protocol_version)
+                            .agent_info(Implementation::new(
+                                "determinishtic-mock",
+                                env!("CARGO_PKG_VERSION"),
+                            ))
+                            .add_meta_capability(McpAcpTransport),
+                    )
+                },
+                agent_client_protocol::on_receive_request!(),
+            )
+            .on_receive_request(
+                async move |request: NewSessionRequest, responder, _cx| {
+                    responder.respond(on_new_session.new_session(request))
+                },
+                agent_client_protocol::on_receive_request!(),
+            )
+            .on_receive_request(
+                async move |request: PromptRequest, responder, cx: ConnectionTo<Client>| {
+                    // Tool calls round-trip through the client, so the turn must
+                    // run off the dispatch loop.
+                    let mock = on_prompt.clone();
+                    cx.spawn({
+                        let cx = cx.clone();
+                        async move {
+                            match mock.prompt(request, &cx).await {
+                                Some(response) => responder.respond(response),
+                                // Failing the task tears down the whole connection.
+                                None => Err(agent_client_protocol::Error::internal_error()
+                                    .data("mock agent disconnected")),
+                            }
+                        }
+                    })
+                },
+                agent_client_protocol::on_receive_request!(),
+            )
+            .connect_to(client)
+            .await
+    }
+}
+
+/// A minimal MCP client that reaches the session's MCP servers over ACP.
+struct McpClient {
+    cx: ConnectionTo<Client>,
+    acp_urls: Vec<String>,
+    /// Connection ids and the tools each connection serves, once connected.
+    connections: Option<Vec<(String, Vec<String>)>>,
+}
+
+impl McpClient {
+    fn new(cx: ConnectionTo<Client>, acp_urls: Vec<String>) -> Self {
+        Self {
+            cx,
+            acp_urls,
+            connections: None,
+        }
+    }
+
+    /// Call a tool, returning its JSON output or an error message.
+    async fn call_tool(&mut self, name: &str, arguments: Value) -> Result<Value, String> {
+        let connection_id = self
+            .connection_for(name)
+            .await
+            .map_err(|err| err.to_string())?
+            .ok_or_else(|| format!("no MCP server offers a tool named `{name}`"))?;
+
+        let result = self
+            .request(
+                &connection_id,
+                "tools/call",
+                json!({ "name": name, "arguments": arguments }),
+            )
+            .await
+            .map_err(|err| err.to_string())?;
+
+        let text = result["content"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|c| c["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("");
+        if result["isError"].as_bool() == Some(true) {
+            return Err(text);
+        }
+        match result.get("structuredContent") {
+            Some(structured) if !structured.is_null() => Ok(structured.clone()),
+            _ => Ok(serde_json::from_str(&text).unwrap_or(Value::String(text))),
+        }
+    }
+
+    /// Find the connection serving `tool`, connecting to every server first if needed.
+    async fn connection_for(
+        &mut self,
+        tool: &str,
+    ) -> Result<Option<String>, agent_client_protocol::Error> {
+        if self.connections.is_none() {
+            let mut connections = Vec::new();
+            for acp_url in self.acp_urls.clone() {
+                connections.push(self.connect(acp_url).await?);
+            }
+            self.connections = Some(connections);
+        }
+
+        let find = |connections: &Option<Vec<(String, Vec<String>)>>| {
+            connections
+                .iter()
+                .flatten()
+                .find(|(_, tools)| tools.iter().any(|t| t == tool))
+                .map(|(id, _)| id.clone())
+        };
+        if let Some(id) = find(&self.connections) {
+            return Ok(Some(id));
+        }
+
+        // Servers can enable tools mid-session, so list them again before
+        // giving up, as an agent would after `tools/list_changed`
+        let mut connections = self.connections.take().unwrap_or_default();
+        for (connection_id, tools) in &mut connections {
+            *tools = self.list_tools(connection_id).await?;
+        }
+        self.connections = Some(connections);
+        Ok(find(&self.connections))
+    }
+
+    async fn connect(
+        &self,
+        acp_url: String,
+    ) -> Result<(String, Vec<String>), agent_client_protocol::Error> {
+        let response = self
+            .cx
+            .send_request(McpConnectRequest {
+                acp_url,
+                meta: None,
+            })
+            .block_task()
+            .await?;
+        let connection_id = response.connection_id;
+
+        self.request(
+            &connection_id,
+            "initialize",
+            json!({
+                "protocolVersion": "2025-03-26",
+                "capabilities": {},
+                "clientInfo": { "name": "determinishtic-mock", "version": env!("CARGO_PKG_VERSION") },
+            }),
+        )
+        .await?;
+        self.cx.send_notification(McpOverAcpMessage {
+            connection_id: connection_id.clone(),
+            message: UntypedMessage::new("notifications/initialized", json!({}))?,
+            meta: None,
+        })?;
+
+        let tools = self.list_tools(&connection_id).await?;
+        Ok((connection_id, tools))
+    }
+
+    /// The names of the tools `connection_id` currently serves.
+    async fn list_tools(
+        &self,
+        connection_id: &str,
+    ) -> Result<Vec<String>, agent_client_protocol::Error> {
+        let tools = self.request(connection_id, "tools/list", json!({})).await?;
+        Ok(tools["tools"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|t| t["name"].as_str().map(str::to_string))
+            .collect())
+    }
+
+    async fn request(
+        &self,
+        connection_id: &str,
+        method: &str,
+        params: Value,
+    ) -> Result<Value, agent_client_protocol::Error> {
+        self.cx
+            .send_request(McpOverAcpMessage {
+                connection_id: connection_id.to_string(),
+                message: UntypedMessage::new(method, params)?,
+                meta: None,
+            })
+            .block_task()
+            .await
+    }
+
+    fn disconnect(self) {
+        for (connection_id, _) in self.connections.into_iter().flatten() {
+            let _ = self.cx.send_notification(McpDisconnectNotification {
+                connection_id,
+                meta: None,
+            });
+        }
+    }
+}
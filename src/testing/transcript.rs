@@ -0,0 +1,371 @@
+//! Transcripts of agent sessions and assertions over them.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use agent_client_protocol::schema::{
+    ContentBlock, RequestPermissionRequest, SessionNotification, SessionUpdate, ToolCallId,
+    ToolCallStatus, ToolKind,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{PermissionRecord, ThinkObserver};
+
+/// Something that happened during a session.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TranscriptEvent {
+    /// A prompt was sent to the agent.
+    Prompt(String),
+
+    /// The agent sent a message to the client.
+    Message(String),
+
+    /// The agent streamed some of its reasoning, separately from its messages.
+    Reasoning(String),
+
+    /// The agent called a tool.
+    ToolCall {
+        /// The name of the tool. For recorded sessions this is the title the
+        /// agent reported, which is often qualified with the MCP server name.
+        name: String,
+        /// The arguments the tool was called with.
+        arguments: Value,
+        /// The tool's output, or the error message if the call failed.
+        /// `None` if the outcome was never reported.
+        output: Option<Result<Value, String>>,
+    },
+
+    /// The agent asked permission to use one of its own tools.
+    Permission {
+        /// The title of the tool call the agent wants to make.
+        title: String,
+        /// The kind of tool, if the agent said.
+        kind: Option<ToolKind>,
+        /// The id of the option the client chose, or `None` if the request
+        /// was cancelled or the outcome was never seen.
+        outcome: Option<String>,
+    },
+
+    /// Misbehavior was injected by [`Chaos`](super::Chaos).
+    Chaos(String),
+}
+
+/// An ordered record of a session, from a [`MockAgent`](super::MockAgent) or
+/// a [`TranscriptRecorder`].
+///
+/// Use the [`TranscriptAssert`] methods to check it in tests.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Transcript {
+    events: Vec<TranscriptEvent>,
+}
+
+impl Transcript {
+    /// Create a transcript from a list of events.
+    pub fn new(events: Vec<TranscriptEvent>) -> Self {
+        Self { events }
+    }
+
+    /// The events, in order.
+    pub fn events(&self) -> &[TranscriptEvent] {
+        &self.events
+    }
+
+    /// The tool calls, in order.
+    pub fn tool_calls(&self) -> impl Iterator<Item = ToolCallView<'_>> {
+        self.events.iter().filter_map(|event| match event {
+            TranscriptEvent::ToolCall {
+                name,
+                arguments,
+                output,
+            } => Some((name.as_str(), arguments, output.as_ref())),
+            _ => None,
+        })
+    }
+
+    /// The agent's reasoning, one entry per uninterrupted stretch of thought.
+    pub fn reasoning(&self) -> impl Iterator<Item = &str> {
+        self.events.iter().filter_map(|event| match event {
+            TranscriptEvent::Reasoning(text) => Some(text.as_str()),
+            _ => None,
+        })
+    }
+}
+
+/// A borrowed tool call: `(name, arguments, output)`.
+pub type ToolCallView<'t> = (&'t str, &'t Value, Option<&'t Result<Value, String>>);
+
+/// Assertions over a [`Transcript`].
+///
+/// ```rust,ignore
+/// let transcript = mock.transcript();
+/// transcript
+///     .assert_tool_called("transform")
+///     .with_arg("path", "src/lib.rs");
+/// transcript.assert_no_tool("write_file");
+/// transcript.assert_turns_at_most(5);
+/// ```
+///
+/// Tool names match exactly, or as the last component of an MCP-qualified
+/// name such as `mcp__determinishtic__transform`, so the same assertions work on
+/// recorded sessions from real agents.
+pub trait TranscriptAssert {
+    /// Assert that the tool was called at least once.
+    ///
+    /// Returns a [`ToolCallAssert`] for narrowing down which call is meant.
+    #[track_caller]
+    fn assert_tool_called(&self, name: &str) -> ToolCallAssert<'_>;
+
+    /// Assert that the tool was never called.
+    #[track_caller]
+    fn assert_no_tool(&self, name: &str);
+
+    /// Assert that the agent took at most `turns` turns, counting each tool call
+    /// (including the final `return_result`) as a turn.
+    #[track_caller]
+    fn assert_turns_at_most(&self, turns: usize);
+}
+
+impl TranscriptAssert for Transcript {
+    #[track_caller]
+    fn assert_tool_called(&self, name: &str) -> ToolCallAssert<'_> {
+        let calls: Vec<_> = self
+            .tool_calls()
+            .filter(|(called, _, _)| tool_name_matches(called, name))
+            .collect();
+        if calls.is_empty() {
+            panic!(
+                "expected a call to tool `{name}`, but it was never called; tools called: {:?}",
+                self.tool_calls().map(|(n, _, _)| n).collect::<Vec<_>>()
+            );
+        }
+        ToolCallAssert {
+            name: name.to_string(),
+            calls,
+        }
+    }
+
+    #[track_caller]
+    fn assert_no_tool(&self, name: &str) {
+        let count = self
+            .tool_calls()
+            .filter(|(called, _, _)| tool_name_matches(called, name))
+            .count();
+        if count > 0 {
+            panic!("expected no calls to tool `{name}`, but it was called {count} time(s)");
+        }
+    }
+
+    #[track_caller]
+    fn assert_turns_at_most(&self, turns: usize) {
+        let taken = self.tool_calls().count();
+        if taken > turns {
+            panic!("expected at most {turns} turn(s), but the agent took {taken}");
+        }
+    }
+}
+
+/// Narrows down the calls matched by [`TranscriptAssert::assert_tool_called`].
+#[derive(Debug)]
+pub struct ToolCallAssert<'t> {
+    name: String,
+    calls: Vec<ToolCallView<'t>>,
+}
+
+impl ToolCallAssert<'_> {
+    /// Keep only calls whose argument `arg` equals `value`, and assert there is one.
+    #[track_caller]
+    pub fn with_arg(mut self, arg: &str, value: impl Serialize) -> Self {
+        let value = serde_json::to_value(value).expect("argument values must serialize to JSON");
+        let seen: Vec<_> = self
+            .calls
+            .iter()
+            .map(|(_, args, _)| args.get(arg))
+            .collect();
+        self.calls
+            .retain(|(_, args, _)| args.get(arg) == Some(&value));
+        if self.calls.is_empty() {
+            panic!(
+                "expected a call to tool `{}` with `{arg}` = {value}, but saw: {seen:?}",
+                self.name
+            );
+        }
+        self
+    }
+
+    /// Keep only calls that succeeded, and assert there is one.
+    #[track_caller]
+    pub fn succeeded(mut self) -> Self {
+        self.calls
+            .retain(|(_, _, output)| matches!(output, Some(Ok(_))));
+        if self.calls.is_empty() {
+            panic!("expected a successful call to tool `{}`", self.name);
+        }
+        self
+    }
+
+    /// Assert that exactly `count` calls remain.
+    #[track_caller]
+    pub fn times(self, count: usize) -> Self {
+        if self.calls.len() != count {
+            panic!(
+                "expected {count} matching call(s) to tool `{}`, found {}",
+                self.name,
+                self.calls.len()
+            );
+        }
+        self
+    }
+}
+
+fn tool_name_matches(called: &str, expected: &str) -> bool {
+    called == expected
+        || called
+            .strip_suffix(expected)
+            .is_some_and(|prefix| prefix.ends_with("__"))
+}
+
+/// A [`ThinkObserver`] that records a [`Transcript`] of real sessions.
+///
+/// ```rust,ignore
+/// let recorder = Arc::new(TranscriptRecorder::new());
+/// d.set_observer(recorder.clone());
+/// let _: Summary = d.think().text("Summarize").display(&path).await?;
+/// recorder.transcript().assert_no_tool("write_file");
+/// ```
+#[derive(Debug, Default)]
+pub struct TranscriptRecorder {
+    state: Mutex<RecorderState>,
+}
+
+#[derive(Debug, Default)]
+struct RecorderState {
+    events: Vec<TranscriptEvent>,
+    tool_calls: HashMap<ToolCallId, usize>,
+}
+
+impl TranscriptRecorder {
+    /// Create a recorder with an empty transcript.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A snapshot of everything recorded so far.
+    pub fn transcript(&self) -> Transcript {
+        Transcript::new(self.lock().events.clone())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, RecorderState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl ThinkObserver for TranscriptRecorder {
+    fn on_prompt(&self, prompt: &str) {
+        self.lock()
+            .events
+            .push(TranscriptEvent::Prompt(prompt.to_string()));
+    }
+
+    fn on_permission_request(&self, request: &RequestPermissionRequest) {
+        self.lock().events.push(TranscriptEvent::Permission {
+            title: request.tool_call.fields.title.clone().unwrap_or_default(),
+            kind: request.tool_call.fields.kind,
+            outcome: None,
+        });
+    }
+
+    fn on_permission_decision(&self, record: &PermissionRecord) {
+        let mut state = self.lock();
+        let pending = state.events.iter_mut().rev().find_map(|event| match event {
+            TranscriptEvent::Permission { outcome, .. } => Some(outcome),
+            _ => None,
+        });
+        if let Some(outcome) = pending {
+            *outcome = record.option.clone();
+        }
+    }
+
+    fn on_notification(&self, notification: &SessionNotification) {
+        let mut state = self.lock();
+        match &notification.update {
+            SessionUpdate::AgentMessageChunk(chunk) => {
+                let ContentBlock::Text(text) = &chunk.content else {
+                    return;
+                };
+                // Messages are streamed in chunks; merge consecutive ones.
+                if let Some(TranscriptEvent::Message(message)) = state.events.last_mut() {
+                    message.push_str(&text.text);
+                } else {
+                    state
+                        .events
+                        .push(TranscriptEvent::Message(text.text.clone()));
+                }
+            }
+            SessionUpdate::AgentThoughtChunk(chunk) => {
+                let ContentBlock::Text(text) = &chunk.content else {
+                    return;
+                };
+                if let Some(TranscriptEvent::Reasoning(reasoning)) = state.events.last_mut() {
+                    reasoning.push_str(&text.text);
+                } else {
+                    state
+                        .events
+                        .push(TranscriptEvent::Reasoning(text.text.clone()));
+                }
+            }
+            SessionUpdate::ToolCall(call) => {
+                let index = state.events.len();
+                state.events.push(TranscriptEvent::ToolCall {
+                    name: call.title.clone(),
+                    arguments: call.raw_input.clone().unwrap_or(Value::Null),
+                    output: tool_output(Some(call.status), call.raw_output.as_ref()),
+                });
+                state.tool_calls.insert(call.tool_call_id.clone(), index);
+            }
+            SessionUpdate::ToolCallUpdate(update) => {
+                let Some(&index) = state.tool_calls.get(&update.tool_call_id) else {
+                    return;
+                };
+                let Some(TranscriptEvent::ToolCall {
+                    name,
+                    arguments,
+                    output,
+                }) = state.events.get_mut(index)
+                else {
+                    return;
+                };
+                if let Some(title) = &update.fields.title {
+                    *name = title.clone();
+                }
+                if let Some(raw_input) = &update.fields.raw_input {
+                    *arguments = raw_input.clone();
+                }
+                if let Some(new_output) =
+                    tool_output(update.fields.status, update.fields.raw_output.as_ref())
+                {
+                    *output = Some(new_output);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The outcome of a tool call, if its status says it has finished.
+fn tool_output(
+    status: Option<ToolCallStatus>,
+    raw_output: Option<&Value>,
+) -> Option<Result<Value, String>> {
+    let raw_output = raw_output.cloned().unwrap_or(Value::Null);
+    match status? {
+        ToolCallStatus::Completed => Some(Ok(raw_output)),
+        ToolCallStatus::Failed => Some(Err(match raw_output {
+            Value::String(message) => message,
+            other => other.to_string(),
+        })),
+        _ => None,
+    }
+}
@@ -1,22 +1,58 @@
 //! ThinkBuilder for composing prompts with tools.
 
+use std::collections::BTreeMap;
 use std::fmt::{Debug, Display};
+use std::future::Future;
 use std::marker::PhantomData;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 
 use agent_client_protocol::mcp_server::{McpConnectionTo, McpServer, McpServerBuilder};
 use agent_client_protocol::role::{HasPeer, Role};
 use agent_client_protocol::schema::{
-    PermissionOptionKind, RequestPermissionOutcome, RequestPermissionRequest,
-    RequestPermissionResponse, SelectedPermissionOutcome, SessionNotification, StopReason,
+    ContentBlock, CreateTerminalRequest, ErrorCode, Implementation, KillTerminalRequest,
+    NewSessionRequest, ReadTextFileRequest, ReleaseTerminalRequest, RequestPermissionRequest,
+    SessionNotification, SessionUpdate, SetSessionModeRequest, SetSessionModelRequest, StopReason,
+    TerminalOutputRequest, WaitForTerminalExitRequest, WriteTextFileRequest,
 };
 use agent_client_protocol::util::MatchDispatch;
-use agent_client_protocol::{Agent, BoxFuture, ConnectionTo, NullRun, RunWithConnectionTo};
+use agent_client_protocol::{
+    ActiveSession, Agent, BoxFuture, ConnectionTo, NullRun, RunWithConnectionTo,
+};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
-use tracing::{debug, info, trace, warn};
+use tokio::sync::watch;
+use tracing::{Instrument, debug, info, info_span, trace, warn};
 
-use crate::Error;
+use crate::budget::{self, Priority};
+use crate::checkpoint::{CHECKPOINT_DESCRIPTION, CheckpointDecision, CheckpointOutput};
+use crate::client::ClientHandlers;
+use crate::completion::Completion;
+use crate::confidence::{self, CONFIDENCE_GUIDANCE, Confidence, Confident, Elicitation};
+use crate::consistency::SelfConsistency;
+use crate::cost::{CostEstimate, Pricing};
+use crate::examples::Example;
+use crate::experiments;
+use crate::handle::{ProgressObserver, ThinkHandle};
+use crate::hash::stable_hash;
+use crate::hints::{self, Rules, ThinkOutput};
+use crate::judge::{Judge, Judged, Judgement};
+use crate::lint::{LintInput, LintWarning};
+use crate::mcp::InvalidCalls;
+use crate::memory::{self, Memory};
+use crate::search::{self, Searcher};
+use crate::partial::PartialResult;
+use crate::permissions::{PermissionMemory, PermissionRecord};
+use crate::preamble::Preamble;
+use crate::prompts::{self, PromptTemplate};
+use crate::provenance::Provenance;
+use crate::resources::{Resource, ResourceContents, ResourceProvider, Resources};
+use crate::retry::{Attempt, RetryOptions};
+use crate::scheduler::Scheduler;
+use crate::sink::{Delivery, ResultSink};
+use crate::transcripts::{self, TranscriptRecorder};
+use crate::{Error, ToolGate, ToolSet};
 
 /// Observer for session updates during a think block.
 ///
@@ -24,17 +60,84 @@ use crate::Error;
 /// Default implementations are no-ops, so you only need to override
 /// the callbacks you care about.
 pub trait ThinkObserver: Send + Sync {
+    /// Called as each think block is created, to get an observer for that
+    /// think block alone.
+    ///
+    /// Observers set on a [`Determinishtic`](crate::Determinishtic) instance
+    /// see every think block run through it, with nothing to tell them
+    /// apart. Return a fresh observer here to track think blocks
+    /// separately. The default, `None`, sends every callback to `self`.
+    fn for_think(&self) -> Option<Arc<dyn ThinkObserver>> {
+        None
+    }
+
+    /// Called when the think block starts, with its [labels](ThinkBuilder::label).
+    fn on_start(&self, _labels: &BTreeMap<String, String>) {}
+
     /// Called with the assembled prompt text before it is sent to the agent.
     fn on_prompt(&self, _prompt: &str) {}
 
     /// Called for each session notification (agent text, tool calls, etc.).
     fn on_notification(&self, _notification: &SessionNotification) {}
 
+    /// Called with each chunk of reasoning the agent streams as a thought,
+    /// separately from the text it shows the user.
+    ///
+    /// Thoughts also arrive through [`on_notification`](Self::on_notification);
+    /// this is a convenience for observers that only want the text.
+    fn on_reasoning(&self, _text: &str) {}
+
     /// Called when the agent requests permission to use a tool.
     fn on_permission_request(&self, _request: &RequestPermissionRequest) {}
 
+    /// Called after a permission request has been decided.
+    fn on_permission_decision(&self, _record: &PermissionRecord) {}
+
     /// Called when the session stops.
     fn on_stop(&self, _reason: &StopReason) {}
+
+    /// Called once the think block has finished, with the error if it failed.
+    fn on_finish(&self, _error: Option<&Error>) {}
+}
+
+/// The result of a think block along with a record of how it was produced.
+///
+/// Returned by [`ThinkBuilder::outcome`].
+#[derive(Debug, Clone)]
+pub struct ThinkOutcome<T> {
+    /// The value the agent returned.
+    pub output: T,
+
+    /// Every permission request the agent made, and how it was decided, in order.
+    pub permissions: Vec<PermissionRecord>,
+
+    /// How many times the agent called each tool with arguments that didn't
+    /// fit its input schema, by tool name. Tools missing from the map were
+    /// always called correctly; a tool that shows up often has a schema or
+    /// description that confuses the model. Only tools from
+    /// [`ToolSet`]s are counted.
+    pub invalid_tool_calls: BTreeMap<String, u32>,
+
+    /// How confident the agent was in the output, if it was asked with
+    /// [`ThinkBuilder::with_confidence`].
+    pub confidence: Option<Confidence>,
+
+    /// Roughly how many tokens the think block used: its prompts, nudges
+    /// included, and the text and reasoning the agent streamed back,
+    /// estimated as for [`UsageStats`](crate::UsageStats).
+    pub estimated_tokens: u64,
+
+    provenance: Provenance,
+
+    /// The output as the agent sent it, for transcripts.
+    pub(crate) accepted: serde_json::Value,
+}
+
+impl<T> ThinkOutcome<T> {
+    /// Where the output came from: agent, model, prompt, and timing.
+    pub fn provenance(&self) -> &Provenance {
+        &self.provenance
+    }
 }
 
 /// Builder for composing LLM prompts with embedded tools.
@@ -52,17 +155,135 @@ where
     R: HasPeer<Agent>,
 {
     cx: ConnectionTo<R>,
-    segments: Vec<Segment>,
+    closed: Option<watch::Receiver<()>>,
+    client: Arc<ClientHandlers>,
+    segments: Vec<(Segment, Priority)>,
+    /// Values for the placeholders in templates, from [`ThinkBuilder::bind`].
+    bindings: BTreeMap<String, String>,
     server: McpServerBuilder<R, Run>,
     explicit_spacing: bool,
     observer: Option<Arc<dyn ThinkObserver>>,
+    output_schema: serde_json::Value,
+    describe_output: bool,
+    /// Whether to list the tool set's example inputs in the prompt.
+    describe_tool_examples: bool,
+    tools: Vec<ToolInfo>,
+    toolset: ToolSet<R>,
+    /// Tools to leave out of the tool set, from [`ThinkBuilder::without_tool`].
+    excluded_tools: Vec<String>,
+    /// Switches tool set tools on and off mid-session.
+    tool_gate: ToolGate,
+    session: SessionOptions,
+    /// The agent's self-description from initialization, for provenance.
+    agent: Option<Implementation>,
+    scheduler: Option<Scheduler>,
+    priority: i32,
+    /// Estimated tokens the prompt may use before segments are dropped.
+    token_budget: Option<usize>,
+    preamble: Preamble,
+    retry: RetryOptions,
+    /// Earlier versions of the output type, tried in order when a result
+    /// doesn't match the current one.
+    legacy: Vec<Migration<Output>>,
+    /// Field rules from [`ThinkBuilder::output_hints`], checked against
+    /// each result.
+    rules: Rules,
+    /// What to require of the agent's confidence, if it is asked for it
+    /// with [`ThinkBuilder::with_confidence`].
+    elicitation: Option<Elicitation>,
+    /// Memories to recall into the prompt when the think block runs.
+    recall: Vec<Recall>,
+    recall_limit: usize,
+    /// Resources the agent can list and read through the tools server.
+    resources: Resources,
+    /// Where the accepted result is delivered before the think block returns.
+    sinks: Vec<Arc<dyn ResultSink>>,
+    /// Key/value pairs identifying the think block in logs and metrics.
+    labels: BTreeMap<String, String>,
     phantom: PhantomData<fn(&'bound Run) -> Output>,
 }
 
+/// Settings for the session a think block runs in.
+#[derive(Debug, Clone, Default)]
+struct SessionOptions {
+    temperature: Option<f32>,
+    seed: Option<u64>,
+    model: Option<String>,
+    mode: Option<String>,
+    /// How many times to remind an agent that stops without a result.
+    nudges: u32,
+    /// How many times to continue after the agent hits its turn limit.
+    continuations: u32,
+    /// How long the session may run before it is cancelled.
+    #[cfg(feature = "tokio")]
+    timeout: Option<Duration>,
+}
+
+/// The follow-up prompt sent by [`ThinkBuilder::nudge_on_no_result`].
+const NUDGE_PROMPT: &str =
+    "You stopped without calling return_result; please call it now with your answer.";
+
+/// How much of the agent's own messages to carry into a continuation prompt.
+const CONTINUATION_PROGRESS_LIMIT: usize = 4000;
+
+/// The type of [`ThinkBuilder::run_with`]'s closure, named for when there is none.
+pub(crate) type RawSessionFn<R> = for<'s> fn(
+    ActiveSession<'s, R>,
+    String,
+) -> BoxFuture<'s, Result<StopReason, agent_client_protocol::Error>>;
+
+/// Parses a result matching an earlier version of the output type and
+/// migrates it, registered with [`ThinkBuilder::accepts_legacy`].
+type Migration<Output> = Arc<dyn Fn(&serde_json::Value) -> Option<Output> + Send + Sync>;
+
+/// What a session is asked to do.
+struct Task<'a> {
+    prompt: &'a str,
+    /// Set once the agent has called `return_result` with a valid result.
+    returned: &'a AtomicBool,
+    /// The text of the agent's most recent message, for salvaging a result
+    /// if the think block fails.
+    message: &'a Mutex<String>,
+    /// Estimated tokens sent to and streamed back from the agent so far.
+    tokens: &'a AtomicU64,
+}
+
+/// The `_meta` key under which sampling parameters are sent with a new
+/// session, and under which agents advertise support for them in their
+/// capabilities.
+///
+/// ACP has no sampling parameters of its own; this key is a determinishtic
+/// convention, so only agents written to honor it do.
+pub(crate) const SAMPLING_META_KEY: &str = "sampling";
+
 /// A segment of the prompt being built.
+#[derive(Clone)]
 enum Segment {
     Text(String),
     ToolReference(String),
+    Context { label: String, json: String },
+    Example { input: String, output: String },
+    Memory(String),
+    /// An f-string style template, filled in from the bindings.
+    Template(String),
+}
+
+/// A query against a [`Memory`], run when the think block starts.
+#[derive(Clone)]
+struct Recall {
+    memory: Arc<dyn Memory>,
+    query: String,
+    /// Where in the segments the recollections go.
+    position: usize,
+}
+
+/// Metadata about a registered tool, kept for [`ThinkBuilder::render`].
+#[derive(Clone)]
+pub(crate) struct ToolInfo {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    pub(crate) input_schema: serde_json::Value,
+    output_schema: serde_json::Value,
 }
 
 impl<'bound, Output, R: Role> ThinkBuilder<'bound, Output, R, NullRun>
@@ -70,50 +291,160 @@ where
     R: HasPeer<Agent>,
     Output: Send + JsonSchema + DeserializeOwned + 'static,
 {
-    pub(crate) fn new(cx: ConnectionTo<R>, observer: Option<Arc<dyn ThinkObserver>>) -> Self {
+    pub(crate) fn new(
+        cx: ConnectionTo<R>,
+        observer: Option<Arc<dyn ThinkObserver>>,
+        closed: Option<watch::Receiver<()>>,
+        client: Arc<ClientHandlers>,
+        agent: Option<Implementation>,
+        scheduler: Option<Scheduler>,
+        preamble: Preamble,
+    ) -> Self {
         Self {
             cx,
+            closed,
+            client,
             segments: Vec::new(),
-            server: McpServer::builder("patchwork".to_string())
-                .instructions("You have access to tools. Call return_result when done."),
+            bindings: BTreeMap::new(),
+            server: Self::server(),
             explicit_spacing: false,
             observer,
+            output_schema: schemars::schema_for!(Output).to_value(),
+            describe_output: false,
+            describe_tool_examples: false,
+            tools: Vec::new(),
+            toolset: ToolSet::default(),
+            excluded_tools: Vec::new(),
+            tool_gate: ToolGate::default(),
+            session: SessionOptions::default(),
+            agent,
+            scheduler,
+            priority: 0,
+            token_budget: None,
+            preamble,
+            retry: RetryOptions::default(),
+            legacy: Vec::new(),
+            rules: Rules::default(),
+            elicitation: None,
+            recall: Vec::new(),
+            recall_limit: memory::DEFAULT_RECALL_LIMIT,
+            resources: Resources::default(),
+            sinks: Vec::new(),
+            labels: BTreeMap::new(),
             phantom: PhantomData,
         }
-        .textln("Please complete the following task to the best of your ability,")
-        .textln("No further instructions will be given,")
-        .textln(
-            "so do your best to interpret the instructions without further feedback from the user,",
-        )
-        .textln("making use of the tools you have available.")
-        .textln("")
-        .textln(
-            "IMPORTANT: When complete, invoke the `return_result` tool with the requested result.",
-        )
-        .textln("")
+    }
+
+    /// The MCP server serving tools added with [`tool`](Self::tool), before
+    /// any are added.
+    fn server() -> McpServerBuilder<R, NullRun> {
+        McpServer::builder("determinishtic".to_string())
+            .instructions("You have access to tools. Call return_result when done.")
+    }
+
+    /// Run this think block `n` times and keep the answer most of the runs
+    /// agree on.
+    ///
+    /// Sampling a think block several times and voting is a cheap way to
+    /// catch answers a model only gives some of the time, and works best for
+    /// outputs with few possible values, such as enums, numbers, and small
+    /// records. Results are compared as the JSON the agent returned. The
+    /// runs are independent sessions started together, subject to the
+    /// [scheduler](crate::DeterminishticBuilder::scheduler), and each
+    /// counts towards [usage](crate::Determinishtic::stats); a
+    /// [temperature](Self::temperature) above zero gives them room to
+    /// differ.
+    ///
+    /// The returned [`SelfConsistency`] is awaited for the modal answer, or
+    /// run with [`outcome`](SelfConsistency::outcome) for a [`Consensus`](crate::Consensus)
+    /// that also has every sample and the agreement rate, for monitoring.
+    /// Use [`min_agreement`](SelfConsistency::min_agreement) to fail when
+    /// the samples disagree too much. [Result sinks](Self::deliver_to)
+    /// receive only the modal answer.
+    ///
+    /// Only think blocks whose tools come from [`ToolSet`]s can be sampled,
+    /// since tools added with [`tool`](Self::tool) may borrow state that
+    /// can't be shared between runs.
+    ///
+    /// ```rust,ignore
+    /// let consensus = d.think::<Severity>()
+    ///     .text("Classify the severity of this incident")
+    ///     .display(&incident)
+    ///     .temperature(0.7)
+    ///     .self_consistency(5)
+    ///     .outcome()
+    ///     .await?;
+    /// metrics.record_agreement(consensus.agreement);
+    /// let severity = consensus.outcome.output;
+    /// ```
+    pub fn self_consistency(self, n: usize) -> SelfConsistency<'bound, Output, R> {
+        SelfConsistency::new(self, n)
+    }
+
+    /// A copy of this think block, to be run separately.
+    pub(crate) fn duplicate(&self) -> Self {
+        Self {
+            cx: self.cx.clone(),
+            closed: self.closed.clone(),
+            client: self.client.clone(),
+            segments: self.segments.clone(),
+            bindings: self.bindings.clone(),
+            server: Self::server(),
+            explicit_spacing: self.explicit_spacing,
+            observer: self.observer.clone(),
+            output_schema: self.output_schema.clone(),
+            describe_output: self.describe_output,
+            describe_tool_examples: self.describe_tool_examples,
+            tools: self.tools.clone(),
+            toolset: self.toolset.clone(),
+            excluded_tools: self.excluded_tools.clone(),
+            tool_gate: self.tool_gate.clone(),
+            session: self.session.clone(),
+            agent: self.agent.clone(),
+            scheduler: self.scheduler.clone(),
+            priority: self.priority,
+            token_budget: self.token_budget,
+            preamble: self.preamble.clone(),
+            retry: self.retry.clone(),
+            legacy: self.legacy.clone(),
+            rules: self.rules.clone(),
+            elicitation: self.elicitation,
+            recall: self.recall.clone(),
+            recall_limit: self.recall_limit,
+            resources: self.resources.clone(),
+            sinks: self.sinks.clone(),
+            labels: self.labels.clone(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Remove the sinks results would be delivered to, so the caller can
+    /// deliver instead.
+    pub(crate) fn take_sinks(&mut self) -> Vec<Arc<dyn ResultSink>> {
+        std::mem::take(&mut self.sinks)
     }
 }
 
 impl<'bound, Output, R: Role, Run: RunWithConnectionTo<R>> ThinkBuilder<'bound, Output, R, Run>
 where
     R: HasPeer<Agent>,
-    Output: Send + JsonSchema + DeserializeOwned + 'static,
+    Output: Send + DeserializeOwned + 'static,
 {
     /// Add literal text to the prompt.
     pub fn text(mut self, text: &str) -> Self {
-        self.segments.push(Segment::Text(text.to_string()));
+        self.segments.push((Segment::Text(text.to_string()), Priority::Normal));
         self
     }
 
     /// Add literal text to the prompt followed by a newline.
     pub fn textln(mut self, text: &str) -> Self {
-        self.segments.push(Segment::Text(format!("{text}\n")));
+        self.segments.push((Segment::Text(format!("{text}\n")), Priority::Normal));
         self
     }
 
     /// Interpolate a value using its [`Display`] implementation.
     pub fn display(mut self, value: &impl Display) -> Self {
-        self.segments.push(Segment::Text(value.to_string()));
+        self.segments.push((Segment::Text(value.to_string()), Priority::Normal));
         self
     }
 
@@ -122,282 +453,2434 @@ where
     /// Useful for paths, complex types, or when you want to see the
     /// debug representation.
     pub fn debug(mut self, value: &impl Debug) -> Self {
-        self.segments.push(Segment::Text(format!("{:?}", value)));
+        self.segments.push((Segment::Text(format!("{:?}", value)), Priority::Normal));
         self
     }
 
-    /// Disable automatic spacing between segments.
+    /// Add an f-string style template to the prompt, as used by LangChain
+    /// and PromptLayer: `{name}` placeholders are filled with values given to
+    /// [`bind`](Self::bind), and `{{` and `}}` stand for literal braces.
     ///
-    /// By default, the builder inserts spaces between segments unless
-    /// the next segment starts with punctuation. Call this to require
-    /// explicit spacing.
-    pub fn explicit_spacing(mut self) -> Self {
-        self.explicit_spacing = true;
+    /// This lets an existing prompt library move over without rewriting
+    /// each prompt as a builder chain. Placeholder names are taken as
+    /// written, so format specs such as `{price:.2f}` are not supported. A
+    /// think block with an unbound placeholder or an unmatched brace fails
+    /// with [`Error::Prompt`] when it runs; [`lint`](Self::lint) reports both
+    /// beforehand.
+    ///
+    /// ```rust,ignore
+    /// let summary: Summary = d.think()
+    ///     .template(&library["summarize"])
+    ///     .bind("audience", &"executives")
+    ///     .bind("document", &report)
+    ///     .await?;
+    /// ```
+    pub fn template(mut self, template: &str) -> Self {
+        self.segments.push((Segment::Template(template.to_string()), Priority::Normal));
         self
     }
 
-    /// Build the final prompt string with smart spacing.
-    fn build_prompt(&self) -> String {
-        let mut result = String::new();
+    /// Fill the `{name}` placeholders in this think block's
+    /// [templates](Self::template) with a value, using its [`Display`]
+    /// implementation. Binding a name again replaces its value.
+    pub fn bind(mut self, name: &str, value: &impl Display) -> Self {
+        self.bindings.insert(name.to_string(), value.to_string());
+        self
+    }
 
-        for (i, segment) in self.segments.iter().enumerate() {
-            let text = match segment {
-                Segment::Text(t) => t.as_str(),
-                Segment::ToolReference(name) => {
-                    // Tool references are embedded inline
-                    result.push_str(&format!("<mcp_tool>{}</mcp_tool>", name));
-                    continue;
-                }
-            };
+    /// Include an earlier result as a labeled context section.
+    ///
+    /// The value is serialized as pretty-printed JSON inside a fenced block,
+    /// so chained think blocks see prior outputs in a consistent shape:
+    ///
+    /// ```rust,ignore
+    /// let analysis: Analysis = d.think().text("Analyze this crate").await?;
+    /// let plan: Plan = d.think()
+    ///     .context("previous analysis", &analysis)
+    ///     .text("Plan a refactoring based on the previous analysis")
+    ///     .await?;
+    /// ```
+    ///
+    /// If the value can't be serialized, the section says so instead.
+    pub fn context(mut self, label: &str, value: &impl Serialize) -> Self {
+        let json = serde_json::to_string_pretty(value)
+            .unwrap_or_else(|err| format!("(could not serialize: {err})"));
+        self.segments.push((
+            Segment::Context {
+                label: label.to_string(),
+                json,
+            },
+            Priority::Normal,
+        ));
+        self
+    }
 
-            // Smart spacing: insert space before this segment if needed
-            if !self.explicit_spacing && i > 0 && !result.is_empty() {
-                let needs_space = !result.ends_with([' ', '\t', '\n', '(', '[', '{'])
-                    && !text.starts_with(['.', ',', ':', ';', '!', '?']);
-                if needs_space {
-                    result.push(' ');
-                }
+    /// Include a summary of the git repository at `repo`: the branch, the
+    /// `HEAD` commit, how far it is from its upstream, and which files have
+    /// changed.
+    ///
+    /// The summary is a [`context`](Self::context) section labeled
+    /// `git repository`. `git` is run immediately; if it fails, the section
+    /// says so instead. Pair it with [`GitTools`](crate::GitTools) so the
+    /// agent can dig into the history.
+    #[cfg(feature = "git")]
+    pub fn git_context(mut self, repo: impl AsRef<std::path::Path>) -> Self {
+        match crate::git::summary(repo.as_ref()) {
+            Ok(summary) => self.context("git repository", &summary),
+            Err(err) => {
+                self.segments.push((
+                    Segment::Context {
+                        label: "git repository".to_string(),
+                        json: format!("(could not read repository: {err})"),
+                    },
+                    Priority::Normal,
+                ));
+                self
             }
-
-            result.push_str(text);
         }
+    }
 
-        result
+    /// Include worked examples, each showing an input and the output it
+    /// should produce.
+    ///
+    /// Outputs are serialized as pretty-printed JSON, like
+    /// [`context`](Self::context) sections. Examples have
+    /// [`Priority::Low`], so they are the first thing dropped to fit a
+    /// [token budget](Self::token_budget). Pick them per think block from an
+    /// [`ExamplePool`](crate::ExamplePool):
+    ///
+    /// ```rust,ignore
+    /// let review: Review = d.think()
+    ///     .examples(pool.select(&diff, 3))
+    ///     .text("Review this diff")
+    ///     .display(&diff)
+    ///     .await?;
+    /// ```
+    pub fn examples<'e, T>(mut self, examples: impl IntoIterator<Item = &'e Example<T>>) -> Self
+    where
+        T: Serialize + 'e,
+    {
+        for example in examples {
+            let output = serde_json::to_string_pretty(&example.output)
+                .unwrap_or_else(|err| format!("(could not serialize: {err})"));
+            self.segments.push((
+                Segment::Example {
+                    input: example.input.trim_end().to_string(),
+                    output,
+                },
+                Priority::Low,
+            ));
+        }
+        self
     }
 
-    /// Register a tool and embed a reference to it in the prompt.
+    /// Recall the entries in `memory` most relevant to `query` into the
+    /// prompt at this point.
     ///
-    /// The tool closure receives the input and an [`McpConnectionTo`], and returns
-    /// the output. Both input and output must implement [`JsonSchema`] for
-    /// the LLM to understand the expected types.
+    /// The memory is searched when the think block starts, so
+    /// [`render`](Self::render) called beforehand doesn't include what it
+    /// returns. Neither does the [`fingerprint`](Self::fingerprint), here
+    /// or in the result's [provenance](crate::Provenance), so it identifies
+    /// the block the same way whatever was recalled. Each entry becomes a
+    /// `<memory>` section with [`Priority::Low`], so memories are dropped
+    /// before the task itself to fit a [token budget](Self::token_budget).
+    /// At most three entries are recalled from each memory; see
+    /// [`memory_limit`](Self::memory_limit).
     ///
-    /// Tools can capture references from the stack frame, enabling them to
-    /// access and mutate local data during the session. Tool invocations are
-    /// serialized (one at a time) because the closure is `AsyncFnMut`.
+    /// The builder keeps a clone of `memory`, so clones must share their
+    /// entries, as [`InMemoryStore`](crate::InMemoryStore) and typical
+    /// database clients do.
     ///
-    /// Due to Rust compiler limitations, you must pass `agent_client_protocol::tool_fn_mut!()`
-    /// as the final argument.
+    /// ```rust,ignore
+    /// let answer: Answer = d.think()
+    ///     .with_memory(&memory, &question)
+    ///     .text("Answer the question, using what you found earlier if relevant:")
+    ///     .display(&question)
+    ///     .await?;
+    /// ```
+    pub fn with_memory<M>(mut self, memory: &M, query: impl Into<String>) -> Self
+    where
+        M: Memory + Clone + 'static,
+    {
+        self.recall.push(Recall {
+            memory: Arc::new(memory.clone()),
+            query: query.into(),
+            position: self.segments.len(),
+        });
+        self
+    }
+
+    /// Deliver the result to `sink` before the think block returns.
     ///
-    /// # Example
+    /// The result is written as soon as it is accepted, so it survives a
+    /// crash later in a batch; see [`ResultSink`] for the guarantees. Add
+    /// several sinks to deliver to each in turn.
+    pub fn deliver_to<S>(mut self, sink: &S) -> Self
+    where
+        S: ResultSink + Clone + 'static,
+    {
+        self.sinks.push(Arc::new(sink.clone()));
+        self
+    }
+
+    /// Recall at most `n` entries from each memory added with
+    /// [`with_memory`](Self::with_memory).
+    pub fn memory_limit(mut self, n: usize) -> Self {
+        self.recall_limit = n;
+        self
+    }
+
+    /// Add text with a [`Priority`] deciding when it is dropped to fit a
+    /// [token budget](Self::token_budget).
     ///
     /// ```rust,ignore
-    /// let results = Mutex::new(Vec::new());
-    /// patchwork.think()
-    ///     .text("Process the data using")
-    ///     .tool(
-    ///         "transform",
-    ///         "Transform the input data",
-    ///         async |input: TransformInput, _cx| {
-    ///             let output = transform_data(&input);
-    ///             results.lock().unwrap().push(output.clone());
-    ///             Ok(output)
-    ///         },
-    ///         agent_client_protocol::tool_fn_mut!(),
-    ///     )
-    ///     .run()
-    ///     .await?
+    /// let answer: Answer = d.think()
+    ///     .token_budget(8_000)
+    ///     .text_with_priority(&style_guide, Priority::Low)
+    ///     .text_with_priority("Answer the question below.", Priority::High)
+    ///     .display(&question)
+    ///     .await?;
     /// ```
-    pub fn tool<I, O, F, H>(
-        mut self,
-        name: &str,
-        description: &str,
-        func: F,
-        tool_future_hack: H,
-    ) -> ThinkBuilder<'bound, Output, R, impl RunWithConnectionTo<R>>
+    pub fn text_with_priority(mut self, text: &str, priority: Priority) -> Self {
+        self.segments.push((Segment::Text(text.to_string()), priority));
+        self
+    }
+
+    /// Keep the prompt within roughly `tokens` tokens.
+    ///
+    /// If the prompt is estimated to exceed the budget, whole segments are
+    /// dropped until it fits: [`Priority::Low`] segments first, oldest
+    /// first, then [`Priority::Normal`] ones, largest first, so the
+    /// instructions outlast the material they are about. A note tells the
+    /// agent that material was omitted. If
+    /// it still doesn't fit once only [`Priority::High`] segments and tool
+    /// references remain, it is sent as is and a warning is logged.
+    pub fn token_budget(mut self, tokens: usize) -> Self {
+        self.token_budget = Some(tokens);
+        self
+    }
+
+    /// Disable automatic spacing between segments.
+    ///
+    /// By default, the builder inserts spaces between segments unless
+    /// the next segment starts with punctuation. Call this to require
+    /// explicit spacing.
+    pub fn explicit_spacing(mut self) -> Self {
+        self.explicit_spacing = true;
+        self
+    }
+
+    /// Override the JSON schema the LLM is given for the result.
+    ///
+    /// By default the schema is generated from `Output` via [`JsonSchema`].
+    /// Use this to supply a hand-tuned schema (e.g., with `enum` values,
+    /// examples, or tighter constraints). The result returned by the LLM is
+    /// still deserialized into `Output`, so the two must be compatible.
+    pub fn output_schema(mut self, schema: serde_json::Value) -> Self {
+        self.output_schema = schema;
+        self
+    }
+
+    /// Add the examples and constraints from `Output`'s `#[think(...)]`
+    /// field attributes to the schema the LLM is given.
+    ///
+    /// Examples become each field's `examples` and constraints are appended
+    /// to its description, so they show up in the `return_result` tool and,
+    /// with [`describe_output`](Self::describe_output), in the prompt. Call
+    /// this after [`output_schema`](Self::output_schema) if you use both.
+    ///
+    /// Field [rules](crate::Rule) are added to the schema and also checked
+    /// against each result; one that breaks a rule is rejected, telling the
+    /// agent which rules it broke, just like a result of the wrong shape.
+    pub fn output_hints(mut self) -> Self
     where
-        I: JsonSchema + DeserializeOwned + Send + 'static,
-        O: JsonSchema + Serialize + Send + 'static,
-        F: AsyncFnMut(I, McpConnectionTo<R>) -> Result<O, agent_client_protocol::Error> + Send,
-        H: for<'a> Fn(
-                &'a mut F,
-                I,
-                McpConnectionTo<R>,
-            ) -> BoxFuture<'a, Result<O, agent_client_protocol::Error>>
-            + Send
-            + 'static,
+        Output: ThinkOutput,
     {
-        debug!(tool_name = name, "registering tool");
-        self.segments.push(Segment::ToolReference(name.to_string()));
-        ThinkBuilder {
-            cx: self.cx,
-            segments: self.segments,
-            server: self
-                .server
-                .tool_fn_mut(name, description, func, tool_future_hack),
-            explicit_spacing: self.explicit_spacing,
-            observer: self.observer,
-            phantom: PhantomData,
-        }
+        let field_hints = Output::field_hints();
+        hints::apply(&mut self.output_schema, &field_hints);
+        self.rules = Rules::new(&field_hints);
+        self
     }
 
-    /// Register a tool without embedding a reference in the prompt.
+    /// Accept any JSON result and deserialize it into `O`, bypassing [`JsonSchema`].
     ///
-    /// Use this when you want the tool to be available but don't want to
-    /// explicitly mention it at this point in the prompt.
+    /// This is an escape hatch for exploratory scripts where a fixed schema
+    /// isn't known yet, or where the output type cannot implement `JsonSchema`.
+    /// The LLM is told it may return any JSON value, so pair this with prompt
+    /// text (or [`output_schema`](Self::output_schema)) describing the shape you want.
     ///
-    /// Due to Rust compiler limitations, you must pass `agent_client_protocol::tool_fn_mut!()`
-    /// as the final argument.
-    pub fn define_tool<I, O, F, H>(
-        self,
-        name: &str,
-        description: &str,
-        func: F,
-        tool_future_hack: H,
-    ) -> ThinkBuilder<'bound, Output, R, impl RunWithConnectionTo<R>>
+    /// Note that `serde_json::Value` already implements `JsonSchema` and can be
+    /// used as an output type directly.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let data: MyUntypedThing = d.think::<serde_json::Value>()
+    ///     .dynamic_output()
+    ///     .text("List the repositories you know about as JSON")
+    ///     .await?;
+    /// ```
+    pub fn dynamic_output<O>(self) -> ThinkBuilder<'bound, O, R, Run>
     where
-        I: JsonSchema + DeserializeOwned + Send + 'static,
-        O: JsonSchema + Serialize + Send + 'static,
-        F: AsyncFnMut(I, McpConnectionTo<R>) -> Result<O, agent_client_protocol::Error> + Send,
-        H: for<'a> Fn(
-                &'a mut F,
-                I,
-                McpConnectionTo<R>,
-            ) -> BoxFuture<'a, Result<O, agent_client_protocol::Error>>
-            + Send
-            + 'static,
+        O: Send + DeserializeOwned + 'static,
     {
-        debug!(tool_name = name, "defining tool (hidden from prompt)");
         ThinkBuilder {
             cx: self.cx,
+            closed: self.closed,
+            client: self.client,
             segments: self.segments,
-            server: self
-                .server
-                .tool_fn_mut(name, description, func, tool_future_hack),
+            bindings: self.bindings,
+            server: self.server,
             explicit_spacing: self.explicit_spacing,
             observer: self.observer,
+            output_schema: serde_json::json!({}),
+            describe_output: self.describe_output,
+            describe_tool_examples: self.describe_tool_examples,
+            tools: self.tools,
+            toolset: self.toolset,
+            excluded_tools: self.excluded_tools,
+            tool_gate: self.tool_gate,
+            session: self.session,
+            agent: self.agent,
+            scheduler: self.scheduler,
+            priority: self.priority,
+            token_budget: self.token_budget,
+            preamble: self.preamble,
+            retry: self.retry,
+            legacy: Vec::new(),
+            rules: Rules::default(),
+            elicitation: None,
+            recall: self.recall,
+            recall_limit: self.recall_limit,
+            resources: self.resources,
+            sinks: self.sinks,
+            labels: self.labels,
             phantom: PhantomData,
         }
     }
 
-}
+    /// Describe the expected result structure in the prompt itself.
+    ///
+    /// Appends a section listing each field of the output schema along with
+    /// its type and doc-comment description. The schema is always part of the
+    /// `return_result` tool definition, but many agents comply more reliably
+    /// when it is also spelled out in prose.
+    pub fn describe_output(mut self) -> Self {
+        self.describe_output = true;
+        self
+    }
 
-impl<'bound, Output, R: Role, Run: RunWithConnectionTo<R>> IntoFuture for ThinkBuilder<'bound, Output, R, Run>
-where
-    R: HasPeer<Agent>,
-    Output: Send + JsonSchema + DeserializeOwned + 'static,
-    Run: Send,
-{
-    type Output = Result<Output, Error>;
+    /// List the example inputs attached to tool set tools with
+    /// [`ToolSet::with_example`] in the prompt itself.
+    ///
+    /// The examples are always part of the tools' input schemas; spelling
+    /// them out in the prompt as well helps agents that skim schemas.
+    pub fn describe_tool_examples(mut self) -> Self {
+        self.describe_tool_examples = true;
+        self
+    }
 
-    type IntoFuture = BoxFuture<'bound, Result<Output, Error>>;
+    /// Ask the agent to sample with the given temperature.
+    ///
+    /// Lower temperatures reduce output variance, which helps test suites and
+    /// reproducible pipelines. ACP has no standard way to ask for this, so
+    /// the value is forwarded under a `sampling` key in the session's
+    /// `_meta`, a determinishtic convention that agents must opt into;
+    /// others silently ignore it. Check
+    /// [`Determinishtic::supports_sampling`](crate::Determinishtic::supports_sampling)
+    /// if you need to know.
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.session.temperature = Some(temperature);
+        self
+    }
 
-    fn into_future(self) -> Self::IntoFuture {
-        Box::pin(async move {
-            // Build prompt before consuming server
-            let prompt = self.build_prompt();
-            let cx = self.cx;
-            let observer = self.observer;
-
-            // Use a cell to store the result from the return_result tool
-            let mut output: Option<Output> = None;
-
-            // Add the return_result tool
-            let server = self.server.tool_fn_mut(
-                "return_result",
-                "Return the final result. Call this when you have completed the task.",
-                async |input: ReturnResultInput<Output>, _cx| {
-                    debug!("return_result tool invoked");
-                    output = Some(input.result);
-                    Ok(ReturnResultOutput { success: true })
-                },
-                agent_client_protocol::tool_fn_mut!(),
-            );
+    /// Ask the agent to sample with the given seed.
+    ///
+    /// Like [`temperature`](Self::temperature), this is silently ignored by
+    /// agents that don't support it.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.session.seed = Some(seed);
+        self
+    }
 
-            if let Some(observer) = &observer {
-                observer.on_prompt(&prompt);
-            }
+    /// Run this think block on a specific model, e.g. `"claude-sonnet-4"`.
+    ///
+    /// Use [`Determinishtic::models`](crate::Determinishtic::models) to list the
+    /// identifiers the agent accepts. Agents that don't support model selection
+    /// use their default model; an agent that supports it but doesn't know the
+    /// model fails the think block.
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.session.model = Some(model.into());
+        self
+    }
 
-            info!(prompt_len = prompt.len(), "executing think block");
-            trace!(prompt = %prompt, "full prompt");
+    /// Run this think block in one of the agent's session modes, e.g. `"plan"`.
+    ///
+    /// Agents that advertise modes typically offer a read-only planning mode
+    /// alongside one that may edit files and run commands, so restricting
+    /// analysis-only blocks is a cheap safety net. If the agent advertises no
+    /// modes this is ignored; if it advertises modes but not this one, the
+    /// think block fails.
+    pub fn mode(mut self, mode: impl Into<String>) -> Self {
+        self.session.mode = Some(mode.into());
+        self
+    }
 
-            // Create a session with the MCP server and run it
-            let cwd = std::env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from("/"));
+    /// Remind the agent to call `return_result` if it stops without doing so.
+    ///
+    /// Agents sometimes end their turn after answering in prose. Rather than
+    /// failing with [`Error::NoResult`] straight away, send a follow-up prompt
+    /// in the same session, up to `n` times. Sessions that were cancelled or
+    /// refused are not nudged.
+    pub fn nudge_on_no_result(mut self, n: u32) -> Self {
+        self.session.nudges = n;
+        self
+    }
 
-            cx.build_session(&cwd)
-                .with_mcp_server(server.build())?
-                .block_task()
-                .run_until(async |mut session| {
-                    session.send_prompt(&prompt)?;
-                    tracing::info!(?prompt, "sending prompt");
-
-                    // Wait for updates until we get a stop reason
-                    loop {
-                        let update = session.read_update().await?;
-                        trace!(?update, "received session update");
-                        match update {
-                            agent_client_protocol::SessionMessage::StopReason(reason) => {
-                                debug!(?reason, "session stopped");
-                                if let Some(observer) = &observer {
-                                    observer.on_stop(&reason);
+    /// Keep going if the agent hits its turn limit before returning a result.
+    ///
+    /// When the session stops with [`StopReason::MaxTurnRequests`], send a
+    /// fresh prompt in the same session, up to `n` times, restating the task
+    /// along with what the agent has reported so far. Without this, or once
+    /// the continuations are used up, the think block fails with
+    /// [`Error::MaxTurns`].
+    pub fn continue_on_max_turns(mut self, n: u32) -> Self {
+        self.session.continuations = n;
+        self
+    }
+
+    /// Give up on the agent if it hasn't returned a result within `after`.
+    ///
+    /// Once the time is up the session is cancelled and the think block
+    /// fails with [`Error::Timeout`]. Unlike an enclosing
+    /// `tokio::time::timeout`, this leaves
+    /// [`outcome_or_partial`](Self::outcome_or_partial) something to
+    /// salvage: whatever the agent said before it was cut off. Only
+    /// available with the `tokio` feature, which is on by default.
+    #[cfg(feature = "tokio")]
+    pub fn timeout(mut self, after: Duration) -> Self {
+        self.session.timeout = Some(after);
+        self
+    }
+
+    /// Customize the feedback sent when the agent's result is rejected.
+    ///
+    /// By default the agent is shown a `<previous_attempt>` section with the
+    /// attempt number, the result it submitted, and why it was rejected. The
+    /// template receives the same information as an [`Attempt`].
+    ///
+    /// ```rust,ignore
+    /// let report: Report = d.think()
+    ///     .text("Audit the dependencies")
+    ///     .retry_template(|attempt: &Attempt| {
+    ///         format!("Attempt {} was rejected: {}. Fix that field and resubmit.", attempt.number, attempt.reason)
+    ///     })
+    ///     .await?;
+    /// ```
+    pub fn retry_template(
+        mut self,
+        template: impl Fn(&Attempt) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.retry.set_template(template);
+        self
+    }
+
+    /// Quote at most `chars` characters of rejected results back to the agent, in total.
+    ///
+    /// Each rejected result is quoted in the retry feedback so the agent can
+    /// see what to fix; once the limit is used up, later feedback carries only
+    /// the reason, so repeated failures don't bloat the context. The default
+    /// is 8000 characters.
+    pub fn retry_context_limit(mut self, chars: usize) -> Self {
+        self.retry.set_context_limit(chars);
+        self
+    }
+
+    /// Have `judge` evaluate each result before it is accepted, and send
+    /// results it rejects back to the agent with its critique.
+    ///
+    /// The judge is called with every result that matches the output type
+    /// and its [field rules](Self::output_hints), and returns a
+    /// [`Judgement`]. It is usually a second think block, perhaps on a
+    /// cheaper model or [another agent](crate::Determinishtic::think_on),
+    /// but any closure returning a future will do. A rejected result is
+    /// answered like a malformed one: the agent is shown the result and the
+    /// critique, through the [retry template](Self::retry_template), and
+    /// tries again in the same session. After two rejections, or as many as
+    /// set with [`Judged::retries`], the think block fails with
+    /// [`Error::JudgeRejected`]; if the judge itself fails, the think block
+    /// fails with its error.
+    ///
+    /// Call this once the think block is otherwise complete: the returned
+    /// [`Judged`] is awaited like the builder, or run with
+    /// [`outcome`](Judged::outcome). The judge runs while the think block
+    /// holds its [scheduler](crate::DeterminishticBuilder::scheduler) slot,
+    /// so a judge that is a think block on the same scheduler needs a slot
+    /// of its own.
+    ///
+    /// ```rust,ignore
+    /// let summary: Summary = d.think()
+    ///     .text("Summarize")
+    ///     .display(&report)
+    ///     .judged_by(|summary: &Summary| {
+    ///         d.think_on::<Judgement>("fast")
+    ///             .text("Does this summary state every figure in the report correctly?")
+    ///             .debug(summary)
+    ///     })
+    ///     .await?;
+    /// ```
+    pub fn judged_by<J, F>(self, judge: J) -> Judged<'bound, Output, R, Run>
+    where
+        J: Fn(&Output) -> F + Send + Sync + 'bound,
+        F: IntoFuture<Output = Result<Judgement, Error>>,
+        F::IntoFuture: Send + 'bound,
+    {
+        Judged::new(self, judge)
+    }
+
+    /// Have the agent report how confident it is in its result, alongside
+    /// the result itself.
+    ///
+    /// The output schema is wrapped in an object with the result and a
+    /// [`Confidence`] field, and the prompt asks the agent for a calibrated
+    /// probability that its result is correct, with its reasons. A score
+    /// outside 0.0 to 1.0 is sent back like a result of the wrong shape. Use
+    /// [`min_confidence`](Confident::min_confidence) to send results the
+    /// agent is unsure of back to it, failing with
+    /// [`Error::LowConfidence`] if it stays unsure.
+    ///
+    /// Call this once the think block is otherwise complete: the returned
+    /// [`Confident`] is awaited for the output and its confidence, or run
+    /// with [`outcome`](Confident::outcome), which records the confidence
+    /// in [`ThinkOutcome::confidence`]. Self-reported confidence is only a
+    /// hint, so check how well it tracks real accuracy before relying on
+    /// it.
+    ///
+    /// ```rust,ignore
+    /// let (diagnosis, confidence) = d.think::<Diagnosis>()
+    ///     .text("Why did this build fail?")
+    ///     .display(&log)
+    ///     .with_confidence()
+    ///     .min_confidence(0.7)
+    ///     .await?;
+    /// if confidence.score < 0.9 {
+    ///     ticket.add_note(&confidence.rationale);
+    /// }
+    /// ```
+    pub fn with_confidence(mut self) -> Confident<'bound, Output, R, Run> {
+        let schema = std::mem::take(&mut self.output_schema);
+        self.output_schema = confidence::envelope_schema(schema);
+        self.elicitation = Some(Elicitation::default());
+        Confident::new(self)
+    }
+
+    /// What is required of the agent's confidence, once it is asked for.
+    pub(crate) fn elicitation_mut(&mut self) -> &mut Elicitation {
+        self.elicitation.get_or_insert_default()
+    }
+
+    /// Also accept results matching an earlier version of the output type,
+    /// converting them with `migrate`.
+    ///
+    /// The agent is still shown only the current schema, but a result that
+    /// doesn't deserialize into `Output` is tried against each legacy type in
+    /// the order they were registered before being rejected. This keeps
+    /// prompts that describe an older shape, and results recorded before the
+    /// type changed, working while the Rust type evolves. Use
+    /// [`parse_result`](Self::parse_result) to apply the same migrations to
+    /// results stored outside a think block.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// #[derive(Deserialize)]
+    /// struct SummaryV1 { text: String }
+    ///
+    /// let summary: Summary = d.think()
+    ///     .text("Summarize")
+    ///     .display(&contents)
+    ///     .accepts_legacy(|old: SummaryV1| Summary { text: old.text, tags: vec![] })
+    ///     .await?;
+    /// ```
+    pub fn accepts_legacy<Old>(
+        mut self,
+        migrate: impl Fn(Old) -> Output + Send + Sync + 'static,
+    ) -> Self
+    where
+        Old: DeserializeOwned,
+    {
+        self.legacy.push(Arc::new(move |value| {
+            Old::deserialize(value).ok().map(&migrate)
+        }));
+        self
+    }
+
+    /// Deserialize a result into `Output` the way `return_result` does,
+    /// falling back to the types registered with
+    /// [`accepts_legacy`](Self::accepts_legacy).
+    ///
+    /// Use this for results recorded earlier, such as a cache keyed by
+    /// [`fingerprint`](Self::fingerprint), so they go through the same
+    /// migrations as fresh ones.
+    pub fn parse_result(&self, value: &serde_json::Value) -> Result<Output, Error> {
+        deserialize_result(value, &self.legacy).map_err(|source| Error::Deserialization {
+            context: "think block result".to_string(),
+            source,
+        })
+    }
+
+    /// Set this think block's priority in the [`Scheduler`] queue.
+    ///
+    /// Higher priorities are dispatched first; the default is 0. Has no effect
+    /// unless a scheduler was registered with
+    /// [`DeterminishticBuilder::scheduler`](crate::DeterminishticBuilder::scheduler).
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Tag the think block with `key` set to `value`, replacing any earlier
+    /// value for `key`.
+    ///
+    /// Labels are recorded on the tracing span the think block runs in, in
+    /// its [`Provenance`] (and so in what [result sinks](Self::deliver_to)
+    /// receive), and in each [`Completion`](crate::Completion). Usage is
+    /// also totalled per label, for
+    /// [`Determinishtic::label_stats`](crate::Determinishtic::label_stats).
+    ///
+    /// ```rust,ignore
+    /// let summary: String = d.think()
+    ///     .label("task", "summarize")
+    ///     .label("tenant", &tenant_id)
+    ///     .text("Summarize")
+    ///     .display(&document)
+    ///     .await?;
+    ///
+    /// let cost = d.label_stats("tenant", &tenant_id).estimated_tokens;
+    /// ```
+    pub fn label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.labels.insert(key.into(), value.into());
+        self
+    }
+
+    /// Make every tool in a [`ToolSet`] available, without mentioning them in the prompt.
+    ///
+    /// Tools from the set are served alongside the tools registered with
+    /// [`tool`](Self::tool) and [`define_tool`](Self::define_tool). Calling this
+    /// more than once merges the sets; later tools replace earlier ones with the
+    /// same name.
+    pub fn tools(mut self, tools: &ToolSet<R>) -> Self {
+        debug!(tool_names = ?tools.names().collect::<Vec<_>>(), "adding tool set");
+        self.toolset = self.toolset.extend(tools);
+        for name in &self.excluded_tools {
+            self.toolset = self.toolset.without(name);
+        }
+        self
+    }
+
+    /// Leave the tool named `name`, and any deprecated alias for it, out of
+    /// this think block.
+    ///
+    /// This applies to tools from [`ToolSet`]s, including those
+    /// [registered](crate::Determinishtic::register_tools) on the instance,
+    /// whether added before or after this call. Excluded tools are not
+    /// served to the agent at all, so sensitive tools can be kept from
+    /// particular prompts. Tools added with [`tool`](Self::tool) are not
+    /// affected; just don't add them.
+    ///
+    /// ```rust,ignore
+    /// let summary: String = d.think()
+    ///     .without_tool("shell")
+    ///     .text("Summarize this customer email")
+    ///     .display(&email)
+    ///     .await?;
+    /// ```
+    pub fn without_tool(mut self, name: &str) -> Self {
+        debug!(tool_name = name, "excluding tool");
+        self.toolset = self.toolset.without(name);
+        self.excluded_tools.push(name.to_string());
+        self
+    }
+
+    /// Switch tool set tools on and off with `gate` while this think block
+    /// runs, for instance to hold back write tools until a
+    /// [checkpoint](Self::checkpoint) approves a plan. See [`ToolGate`].
+    pub fn tool_gate(mut self, gate: &ToolGate) -> Self {
+        self.tool_gate = gate.clone();
+        self
+    }
+
+    /// Register a tool named `search` that queries `searcher`, and mention
+    /// it in the prompt.
+    ///
+    /// The agent passes a query and optionally how many results it wants,
+    /// and gets back the [`Snippet`](crate::Snippet)s the searcher returns.
+    pub fn tool_search(self, searcher: impl Searcher) -> Self {
+        self.tool_search_named("search", searcher)
+    }
+
+    /// Like [`tool_search`](Self::tool_search), with a tool name of your
+    /// choosing, to offer several indexes in one think block.
+    pub fn tool_search_named(mut self, name: &str, searcher: impl Searcher) -> Self {
+        debug!(tool_name = name, "registering search tool");
+        self.segments.push((Segment::ToolReference(name.to_string()), Priority::High));
+        self.toolset = self.toolset.extend(&search::toolset(name, searcher));
+        self
+    }
+
+    /// Serve a prompt template the agent can discover as a task preset.
+    ///
+    /// The template is served alongside the tools, like one added with
+    /// [`ToolSet::prompt`].
+    pub fn prompt_template(mut self, prompt: PromptTemplate) -> Self {
+        debug!(prompt = %prompt.name, "registering prompt template");
+        self.toolset = self.toolset.prompt(prompt);
+        self
+    }
+
+    /// Publish a resource the agent can read on demand.
+    ///
+    /// Rather than putting a large document in the prompt, publish it as an
+    /// MCP resource: the agent sees its URI and name, and reads it only if it
+    /// needs to. Publishing a resource with the same URI again replaces it.
+    ///
+    /// ```rust,ignore
+    /// let answer: Answer = d.think()
+    ///     .resource("docs://style-guide.md", "Style guide", style_guide)
+    ///     .resource("data://sales.csv", "Quarterly sales", sales_csv)
+    ///     .text("Using the published resources as needed, answer:")
+    ///     .display(&question)
+    ///     .await?;
+    /// ```
+    pub fn resource(
+        self,
+        uri: impl Into<String>,
+        name: impl Into<String>,
+        contents: impl Into<ResourceContents>,
+    ) -> Self {
+        self.resource_with(Resource::new(uri, name), contents)
+    }
+
+    /// Like [`resource`](Self::resource), with a description or MIME type.
+    pub fn resource_with(
+        mut self,
+        resource: Resource,
+        contents: impl Into<ResourceContents>,
+    ) -> Self {
+        debug!(uri = %resource.uri, "publishing resource");
+        self.resources.add(resource, contents.into());
+        self
+    }
+
+    /// Publish every resource from `provider`, listed and read only when the
+    /// agent asks.
+    ///
+    /// Resources added with [`resource`](Self::resource) take precedence
+    /// over a provider's with the same URI, and earlier providers over
+    /// later ones.
+    pub fn resource_provider(mut self, provider: impl ResourceProvider) -> Self {
+        self.resources.add_provider(provider);
+        self
+    }
+
+    /// Render the think block as stable, human-readable text.
+    ///
+    /// The rendering includes the full prompt, every registered tool (including
+    /// `return_result`) with its input and output schemas, any resources published
+    /// with [`resource`](Self::resource), and the output schema.
+    /// Tools are sorted by name and JSON object keys are sorted, so the result is
+    /// deterministic and suitable for snapshot tests (e.g., with `insta`):
+    ///
+    /// ```rust,ignore
+    /// let block = d.think::<Summary>()
+    ///     .text("Summarize")
+    ///     .display(&path)
+    ///     .define_tool("read_file", "Read a file", read_file, tool_fn_mut!());
+    /// insta::assert_snapshot!(block.render());
+    /// ```
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("=== prompt ===\n");
+        out.push_str(&self.build_prompt());
+        if !out.ends_with('\n') {
+            out.push('\n');
+        }
+
+        out.push_str("\n=== tools ===\n");
+        for (tool, _) in self.all_tools() {
+            out.push_str(&format!("\n--- {} ---\n", tool.name));
+            out.push_str(&tool.description);
+            out.push_str("\n\ninput schema:\n");
+            out.push_str(&render_json(&tool.input_schema));
+            out.push_str("\n\noutput schema:\n");
+            out.push_str(&render_json(&tool.output_schema));
+            out.push('\n');
+        }
+
+        let resources: Vec<&Resource> = self.resources.fixed().collect();
+        if !resources.is_empty() {
+            out.push_str("\n=== resources ===\n");
+            for resource in resources {
+                out.push_str(&format!("\n{} ({})\n", resource.uri, resource.name));
+                if let Some(description) = &resource.description {
+                    out.push_str(description);
+                    out.push('\n');
+                }
+            }
+        }
+
+        out.push_str("\n=== output schema ===\n");
+        out.push_str(&render_json(&self.output_schema));
+        out.push('\n');
+
+        out
+    }
+
+    /// A JSON document describing every tool the agent will be offered and
+    /// the result it must return, for security review or for documenting
+    /// what the agent is allowed to do.
+    ///
+    /// Each entry under `tools` gives a tool's name, description, input and
+    /// output schemas, and where it came from: `think` for tools added with
+    /// [`tool`](Self::tool) and its relatives, `toolSet` for tools from a
+    /// [`ToolSet`], or `builtin` for `return_result`. Tools are sorted by
+    /// name; `resultSchema` is the output schema and `fingerprint` the
+    /// think block's [fingerprint](Self::fingerprint). The agent's own tools,
+    /// such as file editing or a shell, are not listed; they are governed by
+    /// [permissions](crate::DeterminishticBuilder::permissions).
+    ///
+    /// ```rust,ignore
+    /// let think = d.think::<Report>().tools(&ops_tools).text("Investigate the outage");
+    /// std::fs::write("review/outage-tools.json", serde_json::to_string_pretty(&think.export_tool_manifest())?)?;
+    /// ```
+    pub fn export_tool_manifest(&self) -> serde_json::Value {
+        let tools: Vec<serde_json::Value> = self
+            .all_tools()
+            .into_iter()
+            .map(|(tool, source)| {
+                serde_json::json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "source": source,
+                    "inputSchema": tool.input_schema,
+                    "outputSchema": tool.output_schema,
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "fingerprint": self.fingerprint(),
+            "crateVersion": env!("CARGO_PKG_VERSION"),
+            "tools": tools,
+            "resultSchema": self.output_schema,
+        })
+    }
+
+    /// Every tool the agent will be offered, sorted by name, with where it
+    /// came from.
+    fn all_tools(&self) -> Vec<(ToolInfo, &'static str)> {
+        let return_result = ToolInfo {
+            name: "return_result".to_string(),
+            description: return_result_description(&self.output_schema),
+            input_schema: return_result_schema(&self.output_schema),
+            output_schema: schemars::schema_for!(ReturnResultOutput).to_value(),
+        };
+        let mut tools: Vec<(ToolInfo, &'static str)> = self
+            .tools
+            .iter()
+            .map(|tool| (tool.clone(), "think"))
+            .chain(self.toolset.entries().map(|tool| (tool, "toolSet")))
+            .chain([(return_result, "builtin")])
+            .collect();
+        tools.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+        tools
+    }
+
+    /// Predict what running this think block will cost, without contacting the agent.
+    ///
+    /// Input tokens are estimated from everything [`render`](Self::render)
+    /// shows (the prompt, tool definitions, and output schema), at about four
+    /// characters per token; output tokens are the figure assumed by
+    /// `pricing`. Agents add their own system prompts and may take several
+    /// turns, so treat the result as a lower bound.
+    ///
+    /// ```rust,ignore
+    /// let pricing = Pricing::builtin().default_model("claude-sonnet-4");
+    /// let think = d.think::<Summary>().text("Summarize").display(&document);
+    /// if think.estimate_cost(&pricing).cost.is_some_and(|cost| cost > 0.50) {
+    ///     return Err(anyhow!("document too expensive to summarize"));
+    /// }
+    /// let summary = think.await?;
+    /// ```
+    pub fn estimate_cost(&self, pricing: &Pricing) -> CostEstimate {
+        let input_tokens = budget::estimate_tokens(&self.render()) as u64;
+        pricing.estimate(self.session.model.as_deref(), input_tokens)
+    }
+
+    /// Check the think block for likely mistakes.
+    ///
+    /// Reports tools that are registered but never mentioned, tools named
+    /// in plain text instead of being referenced with [`tool`](Self::tool),
+    /// prompts with nothing beyond the preamble, duplicate tool names,
+    /// output types with no fields, and [templates](Self::template) with
+    /// unbound placeholders or unmatched braces. The same warnings are logged at debug
+    /// level when the think block runs.
+    ///
+    /// ```rust,ignore
+    /// let think = d.think::<Summary>().text("Summarize the file using read_file");
+    /// for warning in think.lint() {
+    ///     eprintln!("warning: {warning}");
+    /// }
+    /// ```
+    pub fn lint(&self) -> Vec<LintWarning> {
+        let mut texts = Vec::new();
+        let mut references = Vec::new();
+        let mut templates = Vec::new();
+        for (segment, _) in &self.segments {
+            match segment {
+                Segment::Text(text) => texts.push(text.as_str()),
+                Segment::Template(template) => {
+                    texts.push(template.as_str());
+                    templates.push(template.as_str());
+                }
+                Segment::ToolReference(name) => references.push(name.as_str()),
+                // The data in a context section isn't instructions, so only its label counts
+                Segment::Context { label, .. } => texts.push(label.as_str()),
+                Segment::Example { .. } | Segment::Memory(_) => {}
+            }
+        }
+        let toolset: Vec<ToolInfo> = self.toolset.entries().collect();
+        LintInput {
+            texts,
+            references,
+            tools: self.tools.iter().map(|t| t.name.as_str()).collect(),
+            toolset: toolset.iter().map(|t| t.name.as_str()).collect(),
+            output_schema: &self.output_schema,
+            templates,
+            bindings: &self.bindings,
+        }
+        .check()
+    }
+
+    /// A stable hash identifying this think block, as 16 hex digits.
+    ///
+    /// The hash covers everything [`render`](Self::render) shows: the prompt,
+    /// every tool's name, description, and schemas, and the output schema. It
+    /// is the same across runs, processes, and Rust releases, and changes
+    /// whenever any of those do, so it can serve as a cache key, an
+    /// experiment bucket, or a check that a deployed prompt hasn't drifted.
+    ///
+    /// ```rust,ignore
+    /// let block = d.think::<Summary>().text("Summarize").display(&contents);
+    /// if let Some(summary) = cache.get(&block.fingerprint()) {
+    ///     return Ok(summary.clone());
+    /// }
+    /// ```
+    pub fn fingerprint(&self) -> String {
+        format!("{:016x}", stable_hash(self.render().bytes()))
+    }
+
+    /// Fail if a template has an unbound placeholder or an unmatched brace.
+    fn check_templates(&self) -> Result<(), Error> {
+        for (segment, _) in &self.segments {
+            if let Segment::Template(template) = segment {
+                prompts::fill(template, |name| {
+                    self.bindings
+                        .get(name)
+                        .cloned()
+                        .ok_or_else(|| format!("no value bound for `{name}`"))
+                })
+                .map_err(|message| Error::Prompt {
+                    name: "template".to_string(),
+                    message,
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `template` filled in from the bindings. Unbound placeholders are left
+    /// as written, as is a template with an unmatched brace.
+    fn fill_template(&self, template: &str) -> String {
+        prompts::fill(template, |name| {
+            Ok(self
+                .bindings
+                .get(name)
+                .cloned()
+                .unwrap_or_else(|| format!("{{{name}}}")))
+        })
+        .unwrap_or_else(|_| template.to_string())
+    }
+
+    /// Build the final prompt string with smart spacing.
+    fn build_prompt(&self) -> String {
+        let mut omitted = Vec::new();
+        let mut prompt = self.assemble_prompt(&omitted);
+        let Some(budget) = self.token_budget else {
+            return prompt;
+        };
+        if budget::estimate_tokens(&prompt) <= budget {
+            return prompt;
+        }
+
+        // Low segments oldest first, then Normal ones largest first, so a
+        // short instruction outlasts the document it is about
+        let mut candidates: Vec<(Priority, usize, usize)> = self
+            .segments
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, priority))| *priority < Priority::High)
+            .map(|(i, (segment, priority))| match priority {
+                Priority::Low => (*priority, 0, i),
+                _ => (*priority, usize::MAX - self.segment_len(segment), i),
+            })
+            .collect();
+        candidates.sort();
+        for (_, _, i) in candidates {
+            omitted.push(i);
+            prompt = self.assemble_prompt(&omitted);
+            if budget::estimate_tokens(&prompt) <= budget {
+                debug!(omitted = omitted.len(), budget, "trimmed prompt to fit token budget");
+                return prompt;
+            }
+        }
+        warn!(
+            estimate = budget::estimate_tokens(&prompt),
+            budget, "prompt exceeds its token budget even after trimming"
+        );
+        prompt
+    }
+
+    /// Roughly how much of the prompt `segment` takes up, in characters.
+    fn segment_len(&self, segment: &Segment) -> usize {
+        match segment {
+            Segment::Text(text) | Segment::Memory(text) => text.len(),
+            Segment::Template(template) => self.fill_template(template).len(),
+            Segment::ToolReference(name) => name.len(),
+            Segment::Context { label, json } => label.len() + json.len(),
+            Segment::Example { input, output } => input.len() + output.len(),
+        }
+    }
+
+    /// Render the segments, skipping those at the indices in `omitted`.
+    fn assemble_prompt(&self, omitted: &[usize]) -> String {
+        let mut result = self.preamble.as_str().to_string();
+
+        for (i, (segment, _)) in self.segments.iter().enumerate() {
+            if omitted.contains(&i) {
+                continue;
+            }
+            let filled;
+            let text = match segment {
+                Segment::Text(t) => t.as_str(),
+                Segment::Template(template) => {
+                    filled = self.fill_template(template);
+                    filled.as_str()
+                }
+                Segment::ToolReference(name) => {
+                    // Tool references are embedded inline
+                    result.push_str(&format!("<mcp_tool>{}</mcp_tool>", name));
+                    continue;
+                }
+                Segment::Context { label, json } => {
+                    // Context sections always stand on their own lines
+                    if !result.is_empty() && !result.ends_with('\n') {
+                        result.push('\n');
+                    }
+                    result.push_str(&format!(
+                        "<context label={label:?}>\n```json\n{json}\n```\n</context>\n"
+                    ));
+                    continue;
+                }
+                Segment::Example { input, output } => {
+                    if !result.is_empty() && !result.ends_with('\n') {
+                        result.push('\n');
+                    }
+                    result.push_str(&format!(
+                        "<example>\n<input>\n{input}\n</input>\n\
+                         <output>\n```json\n{output}\n```\n</output>\n</example>\n"
+                    ));
+                    continue;
+                }
+                Segment::Memory(text) => {
+                    if !result.is_empty() && !result.ends_with('\n') {
+                        result.push('\n');
+                    }
+                    result.push_str(&format!("<memory>\n{text}\n</memory>\n"));
+                    continue;
+                }
+            };
+
+            // Smart spacing: insert space before this segment if needed
+            if !self.explicit_spacing && i > 0 && !result.is_empty() {
+                let needs_space = !result.ends_with([' ', '\t', '\n', '(', '[', '{'])
+                    && !text.starts_with(['.', ',', ':', ';', '!', '?']);
+                if needs_space {
+                    result.push(' ');
+                }
+            }
+
+            result.push_str(text);
+        }
+
+        if !omitted.is_empty() {
+            if !result.ends_with('\n') {
+                result.push('\n');
+            }
+            result.push_str(&budget::omission_note(omitted.len()));
+        }
+
+        if crate::schema::accepts_null(&self.output_schema) {
+            if !result.ends_with('\n') {
+                result.push('\n');
+            }
+            result.push_str(NULL_RESULT_GUIDANCE);
+            result.push('\n');
+        }
+
+        if self.elicitation.is_some() {
+            if !result.ends_with('\n') {
+                result.push('\n');
+            }
+            result.push_str(CONFIDENCE_GUIDANCE);
+            result.push('\n');
+        }
+
+        if self.describe_tool_examples {
+            for tool in self.toolset.entries() {
+                let Some(serde_json::Value::Array(examples)) = tool.input_schema.get("examples")
+                else {
+                    continue;
+                };
+                for example in examples {
+                    if !result.ends_with('\n') {
+                        result.push('\n');
+                    }
+                    result.push_str(&format!(
+                        "<tool_example tool={:?}>\n```json\n{example}\n```\n</tool_example>\n",
+                        tool.name
+                    ));
+                }
+            }
+        }
+
+        if self.describe_output {
+            if !result.ends_with('\n') {
+                result.push('\n');
+            }
+            result.push('\n');
+            result.push_str(&crate::schema::describe_output(&self.output_schema));
+        }
+
+        result
+    }
+
+    /// Register a tool and embed a reference to it in the prompt.
+    ///
+    /// The tool closure receives the input and an [`McpConnectionTo`], and returns
+    /// the output. Both input and output must implement [`JsonSchema`] for
+    /// the LLM to understand the expected types.
+    ///
+    /// Tools can capture references from the stack frame, enabling them to
+    /// access and mutate local data during the session. Tool invocations are
+    /// serialized (one at a time) because the closure is `AsyncFnMut`.
+    ///
+    /// Due to Rust compiler limitations, you must pass `agent_client_protocol::tool_fn_mut!()`
+    /// as the final argument.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let results = Mutex::new(Vec::new());
+    /// d.think()
+    ///     .text("Process the data using")
+    ///     .tool(
+    ///         "transform",
+    ///         "Transform the input data",
+    ///         async |input: TransformInput, _cx| {
+    ///             let output = transform_data(&input);
+    ///             results.lock().unwrap().push(output.clone());
+    ///             Ok(output)
+    ///         },
+    ///         agent_client_protocol::tool_fn_mut!(),
+    ///     )
+    ///     .run()
+    ///     .await?
+    /// ```
+    pub fn tool<I, O, F, H>(
+        mut self,
+        name: &str,
+        description: &str,
+        func: F,
+        tool_future_hack: H,
+    ) -> ThinkBuilder<'bound, Output, R, impl RunWithConnectionTo<R>>
+    where
+        I: JsonSchema + DeserializeOwned + Send + 'static,
+        O: JsonSchema + Serialize + Send + 'static,
+        F: AsyncFnMut(I, McpConnectionTo<R>) -> Result<O, agent_client_protocol::Error> + Send,
+        H: for<'a> Fn(
+                &'a mut F,
+                I,
+                McpConnectionTo<R>,
+            ) -> BoxFuture<'a, Result<O, agent_client_protocol::Error>>
+            + Send
+            + 'static,
+    {
+        debug!(tool_name = name, "registering tool");
+        self.segments.push((Segment::ToolReference(name.to_string()), Priority::High));
+        let mut server = self
+            .server
+            .tool_fn_mut(name, description, func, tool_future_hack);
+        match self.client.denied_by(Some(name)) {
+            Some(profile) => {
+                warn!(tool_name = name, profile, "guardrails deny the tool; not serving it");
+                server = server.disable_tool(name).expect("the tool was just added");
+            }
+            None => self.tools.push(ToolInfo::new::<I, O>(name, description)),
+        }
+        ThinkBuilder {
+            cx: self.cx,
+            closed: self.closed,
+            client: self.client,
+            segments: self.segments,
+            bindings: self.bindings,
+            server,
+            explicit_spacing: self.explicit_spacing,
+            observer: self.observer,
+            output_schema: self.output_schema,
+            describe_output: self.describe_output,
+            describe_tool_examples: self.describe_tool_examples,
+            tools: self.tools,
+            toolset: self.toolset,
+            excluded_tools: self.excluded_tools,
+            tool_gate: self.tool_gate,
+            session: self.session,
+            agent: self.agent,
+            scheduler: self.scheduler,
+            priority: self.priority,
+            token_budget: self.token_budget,
+            preamble: self.preamble,
+            retry: self.retry,
+            legacy: self.legacy,
+            rules: self.rules,
+            elicitation: self.elicitation,
+            recall: self.recall,
+            recall_limit: self.recall_limit,
+            resources: self.resources,
+            sinks: self.sinks,
+            labels: self.labels,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Register a tool without embedding a reference in the prompt.
+    ///
+    /// Use this when you want the tool to be available but don't want to
+    /// explicitly mention it at this point in the prompt.
+    ///
+    /// Due to Rust compiler limitations, you must pass `agent_client_protocol::tool_fn_mut!()`
+    /// as the final argument.
+    pub fn define_tool<I, O, F, H>(
+        mut self,
+        name: &str,
+        description: &str,
+        func: F,
+        tool_future_hack: H,
+    ) -> ThinkBuilder<'bound, Output, R, impl RunWithConnectionTo<R>>
+    where
+        I: JsonSchema + DeserializeOwned + Send + 'static,
+        O: JsonSchema + Serialize + Send + 'static,
+        F: AsyncFnMut(I, McpConnectionTo<R>) -> Result<O, agent_client_protocol::Error> + Send,
+        H: for<'a> Fn(
+                &'a mut F,
+                I,
+                McpConnectionTo<R>,
+            ) -> BoxFuture<'a, Result<O, agent_client_protocol::Error>>
+            + Send
+            + 'static,
+    {
+        debug!(tool_name = name, "defining tool (hidden from prompt)");
+        let mut server = self
+            .server
+            .tool_fn_mut(name, description, func, tool_future_hack);
+        match self.client.denied_by(Some(name)) {
+            Some(profile) => {
+                warn!(tool_name = name, profile, "guardrails deny the tool; not serving it");
+                server = server.disable_tool(name).expect("the tool was just added");
+            }
+            None => self.tools.push(ToolInfo::new::<I, O>(name, description)),
+        }
+        ThinkBuilder {
+            cx: self.cx,
+            closed: self.closed,
+            client: self.client,
+            segments: self.segments,
+            bindings: self.bindings,
+            server,
+            explicit_spacing: self.explicit_spacing,
+            observer: self.observer,
+            output_schema: self.output_schema,
+            describe_output: self.describe_output,
+            describe_tool_examples: self.describe_tool_examples,
+            tools: self.tools,
+            toolset: self.toolset,
+            excluded_tools: self.excluded_tools,
+            tool_gate: self.tool_gate,
+            session: self.session,
+            agent: self.agent,
+            scheduler: self.scheduler,
+            priority: self.priority,
+            token_budget: self.token_budget,
+            preamble: self.preamble,
+            retry: self.retry,
+            legacy: self.legacy,
+            rules: self.rules,
+            elicitation: self.elicitation,
+            recall: self.recall,
+            recall_limit: self.recall_limit,
+            resources: self.resources,
+            sinks: self.sinks,
+            labels: self.labels,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Register a tool that hands the agent's work to host code for a decision.
+    ///
+    /// The agent calls the tool with a value of type `I` (a plan, a draft,
+    /// a proposed change) and waits while `func` decides whether it may
+    /// proceed or must revise. `func` can take as long as it likes, so this
+    /// is the place to pause for human approval; the agent then continues in
+    /// the same session. Like [`tool`](Self::tool), a reference to the tool is
+    /// embedded in the prompt.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let report: Report = d.think()
+    ///     .text("Draft a migration plan and submit it with")
+    ///     .checkpoint("review_plan", |plan: Plan| async move {
+    ///         if ask_user(&format!("Approve {} steps?", plan.steps.len())).await {
+    ///             CheckpointDecision::Proceed
+    ///         } else {
+    ///             CheckpointDecision::Revise("Split the database migration into smaller steps".into())
+    ///         }
+    ///     })
+    ///     .text("before carrying it out.")
+    ///     .await?;
+    /// ```
+    pub fn checkpoint<I, F, Fut>(
+        self,
+        name: &'bound str,
+        mut func: F,
+    ) -> ThinkBuilder<'bound, Output, R, impl RunWithConnectionTo<R>>
+    where
+        I: JsonSchema + DeserializeOwned + Send + 'static,
+        F: FnMut(I) -> Fut + Send + 'bound,
+        Fut: Future<Output = CheckpointDecision> + Send,
+    {
+        debug!(tool_name = name, "registering checkpoint");
+        self.tool(
+            name,
+            CHECKPOINT_DESCRIPTION,
+            async move |input: I, _cx| Ok(CheckpointOutput::from(func(input).await)),
+            agent_client_protocol::tool_fn_mut!(),
+        )
+    }
+}
+
+impl<'bound, Output, R: Role, Run: RunWithConnectionTo<R>> ThinkBuilder<'bound, Output, R, Run>
+where
+    R: HasPeer<Agent>,
+    Output: Send + DeserializeOwned + 'static,
+    Run: Send,
+{
+    /// Run the think block, returning the output along with a record of how
+    /// it was produced.
+    ///
+    /// Awaiting the builder directly is equivalent to awaiting this and
+    /// keeping only [`ThinkOutcome::output`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let outcome = d.think::<Summary>().text("Summarize").display(&path).outcome().await?;
+    /// for record in &outcome.permissions {
+    ///     println!("{:?} {:?}: {:?} by {:?}", record.kind, record.tool, record.decision, record.decided_by);
+    /// }
+    /// ```
+    pub fn outcome(self) -> BoxFuture<'bound, Result<ThinkOutcome<Output>, Error>> {
+        let outcome = self.run(None::<RawSessionFn<R>>, None);
+        Box::pin(async move { outcome.await.map_err(Error::from) })
+    }
+
+    /// Like [`outcome`](Self::outcome), but if the think block fails, also
+    /// return whatever the agent produced before it did.
+    ///
+    /// If the agent is cancelled, runs out of turns, or stops without calling
+    /// `return_result`, its last message may still hold a usable answer. The
+    /// returned [`PartialResult`] carries the error, that message, and the
+    /// output parsed from it if it contained JSON of the right shape. Only
+    /// what the agent sent before the session ended can be salvaged: if the
+    /// future is dropped (for instance by an enclosing timeout, or
+    /// [`ThinkHandle::abort`]) there is nothing left to return, so bound the
+    /// time with [`timeout`](Self::timeout) instead.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let summary = match d.think::<Summary>().text("Summarize").display(&log).outcome_or_partial().await {
+    ///     Ok(outcome) => outcome.output,
+    ///     Err(PartialResult { output: Some(summary), error, .. }) => {
+    ///         tracing::warn!(%error, "keeping a partial summary");
+    ///         summary
+    ///     }
+    ///     Err(partial) => return Err(partial.into()),
+    /// };
+    /// ```
+    pub fn outcome_or_partial(
+        self,
+    ) -> BoxFuture<'bound, Result<ThinkOutcome<Output>, PartialResult<Output>>> {
+        self.run(None::<RawSessionFn<R>>, None)
+    }
+
+    /// Run the think block, driving the session with your own code.
+    ///
+    /// The prompt and MCP server (including `return_result`) are set up as
+    /// usual, and the session's model, mode, and sampling are configured, but
+    /// instead of the built-in loop `op` receives the session and the prompt.
+    /// It must send the prompt, handle whatever updates and requests it cares
+    /// about, and return the stop reason once it is done. Use this to handle
+    /// updates the crate doesn't model yet; nudges, continuations, and the
+    /// client handlers configured on the builder are bypassed.
+    ///
+    /// The output is whatever the agent passed to `return_result`; if it never
+    /// did, the stop reason decides the error, as for a normal think block.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let summary: Summary = d.think()
+    ///     .text("Summarize the changes")
+    ///     .run_with(|mut session, prompt| Box::pin(async move {
+    ///         session.send_prompt(prompt)?;
+    ///         loop {
+    ///             match session.read_update().await? {
+    ///                 SessionMessage::StopReason(reason) => return Ok(reason),
+    ///                 SessionMessage::SessionMessage(dispatch) => handle_custom(dispatch).await?,
+    ///                 _ => {}
+    ///             }
+    ///         }
+    ///     }))
+    ///     .await?;
+    /// ```
+    pub fn run_with<F>(self, op: F) -> BoxFuture<'bound, Result<Output, Error>>
+    where
+        F: for<'s> FnOnce(
+                ActiveSession<'s, R>,
+                String,
+            ) -> BoxFuture<'s, Result<StopReason, agent_client_protocol::Error>>
+            + Send
+            + 'bound,
+    {
+        let outcome = self.run(Some(op), None);
+        Box::pin(async move { Ok(outcome.await?.output) })
+    }
+
+    /// Run the think block, with `raw` (if any) in place of the built-in
+    /// session loop, checking results with `judge` if there is one.
+    pub(crate) fn run<F>(
+        mut self,
+        raw: Option<F>,
+        judge: Option<Judge<'bound, Output>>,
+    ) -> BoxFuture<'bound, Result<ThinkOutcome<Output>, PartialResult<Output>>>
+    where
+        F: for<'s> FnOnce(
+                ActiveSession<'s, R>,
+                String,
+            ) -> BoxFuture<'s, Result<StopReason, agent_client_protocol::Error>>
+            + Send
+            + 'bound,
+    {
+        let span = info_span!("think", labels = ?self.labels);
+        Box::pin(
+            async move {
+                let started = Instant::now();
+                let observer = self.observer.clone();
+                let client = self.client.clone();
+                // Taken before memories are recalled into the prompt, so it
+                // matches what `fingerprint` returned when the block was built
+                let fingerprint = self.fingerprint();
+                let labels = self.labels.clone();
+                let think_id = transcripts::new_think_id();
+                let recorder = client.transcript_store().map(|store| {
+                    let recorder = Arc::new(TranscriptRecorder::new(
+                        self.observer.take(),
+                        &think_id,
+                        labels.clone(),
+                        fingerprint.clone(),
+                    ));
+                    self.observer = Some(recorder.clone());
+                    (store, recorder)
+                });
+                let observer = recorder
+                    .as_ref()
+                    .map(|(_, recorder)| recorder.clone() as Arc<dyn ThinkObserver>)
+                    .or(observer);
+                if let Some(observer) = &observer {
+                    observer.on_start(&labels);
+                }
+                let message = Mutex::new(String::new());
+                let tokens = AtomicU64::new(0);
+                let result = self
+                    .run_session(raw, judge, &message, &tokens, &think_id, &fingerprint)
+                    .await;
+                experiments::count_tokens(tokens.load(Ordering::Relaxed));
+                if let Some(observer) = &observer {
+                    observer.on_finish(result.as_ref().err());
+                }
+                if let Some((store, recorder)) = recorder
+                    && let Err(err) = store.put(recorder.finish(&result)).await
+                {
+                    warn!(%err, think_id, "cannot store transcript");
+                }
+                if client.wants_completion() {
+                    client
+                        .complete(Completion::new(
+                            fingerprint,
+                            labels,
+                            &result,
+                            started.elapsed(),
+                        ))
+                        .await;
+                }
+                result.map_err(|error| {
+                    let message =
+                        message.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner());
+                    PartialResult::salvage(error, message, |output| {
+                        client
+                            .guardrails()
+                            .iter()
+                            .all(|guardrails| guardrails.check_result(output).is_ok())
+                    })
+                })
+            }
+            .instrument(span),
+        )
+    }
+
+    /// Everything [`run`](Self::run) does except report that it finished.
+    ///
+    /// The agent's most recent message is kept in `message`, and the
+    /// tokens used so far in `tokens`.
+    async fn run_session<F>(
+        mut self,
+        raw: Option<F>,
+        judge: Option<Judge<'bound, Output>>,
+        message: &Mutex<String>,
+        tokens: &AtomicU64,
+        think_id: &str,
+        fingerprint: &str,
+    ) -> Result<ThinkOutcome<Output>, Error>
+    where
+        F: for<'s> FnOnce(
+                ActiveSession<'s, R>,
+                String,
+            ) -> BoxFuture<'s, Result<StopReason, agent_client_protocol::Error>>
+            + Send
+            + 'bound,
+    {
+        self.check_templates()?;
+        let _permit = match &self.scheduler {
+            Some(scheduler) => Some(scheduler.acquire(self.priority).await),
+            None => None,
+        };
+        // Later positions first, so earlier ones stay valid as we insert
+        for recall in std::mem::take(&mut self.recall).into_iter().rev() {
+            let recollections = recall.memory.retrieve(recall.query, self.recall_limit).await?;
+            debug!(count = recollections.len(), "recalled memories");
+            self.segments.splice(
+                recall.position..recall.position,
+                recollections
+                    .into_iter()
+                    .map(|recollection| (Segment::Memory(recollection.text), Priority::Low)),
+            );
+        }
+        for warning in self.lint() {
+            debug!(%warning, "prompt lint");
+        }
+        let started_at = SystemTime::now();
+
+        let mut provenance = Provenance::new(
+            think_id,
+            self.agent.as_ref(),
+            fingerprint.to_string(),
+            self.labels.clone(),
+            started_at,
+        );
+
+        // Build prompt before consuming server
+        let prompt = self.build_prompt();
+        let cx = self.cx;
+        let observer = self.observer;
+        let description = return_result_description(&self.output_schema);
+
+        // Use a cell to store the result from the return_result tool
+        let mut output: Option<Output> = None;
+        // The accepted result as the agent sent it, for result sinks
+        let mut accepted: Option<serde_json::Value> = None;
+        let returned = AtomicBool::new(false);
+        let mut feedback = self.retry.feedback();
+        let redaction = self.client.log_redaction();
+        let legacy = self.legacy;
+        let rules = self.rules;
+        let elicitation = self.elicitation;
+        // The agent's confidence in the accepted result, if it was asked
+        let mut confidence: Option<Confidence> = None;
+        let mut unsure = 0;
+        let mut judge_rejections = 0;
+        // Why the judge or the agent's doubts ended the think block, once
+        // they have
+        let mut judge_failure: Option<Error> = None;
+        let guardrails = self.client.guardrails();
+        // The last guardrail a result broke, reported if the agent never
+        // returns one that passes
+        let mut violation: Option<Error> = None;
+
+        // Serve the return_result tool alongside the tool set, offering the
+        // (possibly overridden) output schema as its input schema, and
+        // handle its calls here, one at a time, while the session runs.
+        let (calls, mut returns) = tokio::sync::mpsc::channel::<ReturnCall>(1);
+        self.toolset = std::mem::take(&mut self.toolset).dynamic_tool(
+            "return_result",
+            &description,
+            return_result_schema(&self.output_schema),
+            move |arguments| {
+                let calls = calls.clone();
+                async move {
+                    let finished = || {
+                        agent_client_protocol::Error::internal_error()
+                            .data("the think block is no longer running")
+                    };
+                    let (reply, replied) = tokio::sync::oneshot::channel();
+                    calls.send((arguments, reply)).await.map_err(|_| finished())?;
+                    replied.await.map_err(|_| finished())?
+                }
+            },
+        );
+
+        // The result is still checked here, as raw JSON, since agents don't
+        // all hold arguments to the schema; a result that does not
+        // deserialize is reported back to the LLM as a tool error, quoting
+        // what it sent, so it can try again.
+        let mut return_result = async |input: ReturnResultInput| {
+            debug!("return_result tool invoked");
+            // Asked for its confidence, the agent wraps the result with it
+            let (value, stated) = match elicitation {
+                Some(_) => match confidence::split(&input.result) {
+                    Ok((value, stated)) => (value, Some(stated)),
+                    Err(err) => {
+                        warn!("return_result invoked without a valid confidence");
+                        let reason = format!("result does not match the expected schema: {err}");
+                        return Err(agent_client_protocol::Error::invalid_params()
+                            .data(feedback.reject(&input.result, reason)));
+                    }
+                },
+                None => (input.result, None),
+            };
+            match deserialize_result(&value, &legacy) {
+                Ok(result) => match rules.check(&value) {
+                    Ok(()) => {
+                        if let Some(err) = guardrails
+                            .iter()
+                            .find_map(|guardrails| guardrails.check_result(&value).err())
+                        {
+                            let reason = err.to_string();
+                            violation = Some(err);
+                            return Err(agent_client_protocol::Error::invalid_params()
+                                .data(feedback.reject(&value, reason)));
+                        }
+                        if let Some(Elicitation { min_confidence: Some(required), retries }) = elicitation
+                            && let Some(stated) = &stated
+                            && stated.score < required
+                        {
+                            unsure += 1;
+                            if unsure > retries {
+                                warn!(score = stated.score, "the agent is unsure of the result; giving up");
+                                judge_failure = Some(Error::LowConfidence {
+                                    confidence: stated.score,
+                                    required,
+                                });
+                                returned.store(true, Ordering::Relaxed);
+                                return Ok(ReturnResultOutput { success: true });
+                            }
+                            debug!(score = stated.score, "the agent is unsure of the result");
+                            let reason = format!(
+                                "your confidence of {} is below the required {required}. Look into what you were \
+                                 unsure of, then return the result again with your honest confidence",
+                                stated.score
+                            );
+                            return Err(agent_client_protocol::Error::invalid_params()
+                                .data(feedback.reject(&value, reason)));
+                        }
+                        if let Some(judge) = &judge {
+                            let critique = match (judge.check)(&result).await {
+                                Ok(judgement) if judgement.accepted => None,
+                                Ok(judgement) => Some(judgement.critique),
+                                Err(err) => {
+                                    warn!(%err, "judge failed");
+                                    judge_failure = Some(err);
+                                    None
+                                }
+                            };
+                            if let Some(critique) = critique {
+                                judge_rejections += 1;
+                                if judge_rejections > judge.retries {
+                                    warn!(judge_rejections, "judge rejected the result; giving up");
+                                    judge_failure = Some(Error::JudgeRejected {
+                                        attempts: judge_rejections,
+                                        critique,
+                                    });
+                                } else {
+                                    debug!(judge_rejections, "judge rejected the result");
+                                    let reason = format!("the result was judged inadequate: {critique}");
+                                    return Err(agent_client_protocol::Error::invalid_params()
+                                        .data(feedback.reject(&value, reason)));
                                 }
-                                break;
                             }
-                            agent_client_protocol::SessionMessage::SessionMessage(dispatch) => {
-                                MatchDispatch::new(dispatch)
-                                    .if_notification(async |notification: SessionNotification| {
-                                        tracing::debug!(?notification, "received session notification");
-                                        if let Some(observer) = &observer {
-                                            observer.on_notification(&notification);
-                                        }
-                                        Ok(())
-                                    })
-                                    .await
-                                    .if_request(
-                                        async |request: RequestPermissionRequest, responder| {
-                                            tracing::debug!(
-                                                ?request,
-                                                "received tool use permission request"
-                                            );
-                                            if let Some(observer) = &observer {
-                                                observer.on_permission_request(&request);
-                                            }
-                                            // approve all tool usage
-                                            let option =
-                                                request.options.iter().find(|o| match o.kind {
-                                                    PermissionOptionKind::AllowOnce
-                                                    | PermissionOptionKind::AllowAlways => true,
-                                                    PermissionOptionKind::RejectOnce
-                                                    | PermissionOptionKind::RejectAlways => false,
-                                                    _ => false,
-                                                });
-                                            let outcome = option
-                                                .map(|o| {
-                                                    RequestPermissionOutcome::Selected(
-                                                        SelectedPermissionOutcome::new(
-                                                            o.option_id.clone(),
-                                                        ),
-                                                    )
-                                                })
-                                                .unwrap_or(RequestPermissionOutcome::Cancelled);
-                                            responder.respond(RequestPermissionResponse::new(outcome))
-                                        },
-                                    )
-                                    .await
-                                    .otherwise_ignore()?
+                            if judge_failure.is_some() {
+                                // Let the agent stop; the think block fails once it has
+                                returned.store(true, Ordering::Relaxed);
+                                return Ok(ReturnResultOutput { success: true });
                             }
-                            _ => continue,
                         }
+                        output = Some(result);
+                        accepted = Some(value);
+                        confidence = stated;
+                        returned.store(true, Ordering::Relaxed);
+                        Ok(ReturnResultOutput { success: true })
                     }
-                    Ok(())
+                    Err(violations) => {
+                        match redaction.text(&violations.to_string()) {
+                            Some(violations) => warn!(%violations, "return_result invoked with a result that breaks field rules"),
+                            None => warn!("return_result invoked with a result that breaks field rules"),
+                        }
+                        let reason = format!("result breaks field rules: {violations}");
+                        Err(agent_client_protocol::Error::invalid_params()
+                            .data(feedback.reject(&value, reason)))
+                    }
+                },
+                Err(err) => {
+                    match redaction.text(&err.to_string()) {
+                        Some(err) => warn!(%err, "return_result invoked with a malformed result"),
+                        None => warn!("return_result invoked with a malformed result"),
+                    }
+                    let reason = format!("result does not match the expected schema: {err}");
+                    Err(agent_client_protocol::Error::invalid_params()
+                        .data(feedback.reject(&value, reason)))
+                }
+            }
+        };
+        let serve_return_result = async {
+            while let Some((arguments, reply)) = returns.recv().await {
+                let response = match serde_json::from_value(arguments) {
+                    Ok(input) => return_result(input).await.map(|output| serde_json::json!(output)),
+                    Err(err) => Err(agent_client_protocol::Error::invalid_params()
+                        .data(format!("invalid return_result arguments: {err}"))),
+                };
+                let _ = reply.send(response);
+            }
+        };
+
+        if let Some(observer) = &observer {
+            observer.on_prompt(&prompt);
+        }
+        count_tokens(tokens, &prompt);
+
+        info!(prompt_len = prompt.len(), "executing think block");
+        if let Some(prompt) = redaction.text(&prompt) {
+            trace!(%prompt, "full prompt");
+        }
+
+        // Create a session with the MCP server and run it
+        let mut request = NewSessionRequest::new(self.client.working_dir());
+        if let Some(sampling) = self.session.sampling() {
+            debug!(?sampling, "requesting sampling parameters");
+            request = request.meta(serde_json::Map::from_iter([(
+                SAMPLING_META_KEY.to_string(),
+                sampling,
+            )]));
+        }
+
+        let session = cx
+            .build_session_from(request)
+            .with_mcp_server(self.server.build())?;
+        let observer = observer.as_deref();
+        let mut closed = self.closed;
+        let mut permissions = PermissionMemory::default();
+        let invalid_calls = InvalidCalls::default();
+        let task = Task {
+            prompt: &prompt,
+            returned: &returned,
+            message,
+            tokens,
+        };
+        let run = async |session: ActiveSession<'_, R>| match raw {
+            Some(op) => {
+                let model = self.session.configure(&session).await?;
+                let stop_reason = op(session, prompt.clone()).await?;
+                Ok(SessionEnd {
+                    stop_reason,
+                    model,
+                    nudges: 0,
+                    continuations: 0,
                 })
-                .await?;
+            }
+            None => {
+                drive_session(
+                    session,
+                    &self.session,
+                    &self.client,
+                    &mut permissions,
+                    &task,
+                    observer,
+                    closed.as_mut(),
+                )
+                .await
+            }
+        };
+        // Set if the session ran out of time and was cancelled
+        #[cfg_attr(not(feature = "tokio"), allow(unused_mut))]
+        let mut timed_out: Option<Duration> = None;
+        let drive = async |session: ActiveSession<'_, R>| {
+            let cancel = (session.connection(), session.session_id().clone());
+            let run = std::pin::pin!(run(session));
+            let serve = std::pin::pin!(serve_return_result);
+            let driven = async {
+                match futures::future::select(run, serve).await {
+                    futures::future::Either::Left((end, _)) => end,
+                    futures::future::Either::Right(((), run)) => run.await,
+                }
+            };
+            #[cfg(feature = "tokio")]
+            if let Some(after) = self.session.timeout {
+                return match tokio::time::timeout(after, driven).await {
+                    Ok(end) => end,
+                    Err(_) => {
+                        warn!(?after, "think block timed out; cancelling the session");
+                        timed_out = Some(after);
+                        let (connection, session_id) = cancel;
+                        if let Err(err) =
+                            connection.send_notification_to(Agent, agent_client_protocol::schema::CancelNotification::new(session_id))
+                        {
+                            warn!(%err, "cannot cancel the session");
+                        }
+                        Err(agent_client_protocol::Error::internal_error().data("the think block timed out"))
+                    }
+                };
+            }
+            let _ = cancel;
+            driven.await
+        };
+        let result = session
+            .with_mcp_server(self.toolset.server(
+                "tools",
+                self.resources,
+                self.tool_gate,
+                invalid_calls.clone(),
+                self.client.log_redaction(),
+            ))?
+            .block_task()
+            .run_until(drive)
+            .await;
+        if let Some(after) = timed_out {
+            return Err(Error::Timeout {
+                operation: "think block".to_string(),
+                after,
+            });
+        }
+        let SessionEnd {
+            stop_reason,
+            model,
+            nudges,
+            continuations,
+        } =
+            result.map_err(|source| session_error(source, closed.as_ref()))?;
+        if let Some(err) = judge_failure {
+            return Err(err);
+        }
 
-            if output.is_some() {
+        match output {
+            Some(output) => {
                 info!("think block completed successfully");
-            } else {
-                warn!("think block completed but no result was returned");
+                provenance.model = model;
+                provenance.retries = feedback.attempts() + nudges + continuations;
+                provenance.finished_at = SystemTime::now();
+                if let Some(accepted) = &accepted {
+                    for sink in &self.sinks {
+                        debug!("delivering result to sink");
+                        sink.deliver(Delivery {
+                            output: accepted.clone(),
+                            provenance: provenance.clone(),
+                        })
+                        .await?;
+                    }
+                }
+                Ok(ThinkOutcome {
+                    output,
+                    permissions: permissions.into_records(),
+                    invalid_tool_calls: invalid_calls.counts(),
+                    confidence,
+                    estimated_tokens: tokens.load(Ordering::Relaxed),
+                    provenance,
+                    accepted: accepted.unwrap_or_default(),
+                })
+            }
+            None => {
+                warn!(?stop_reason, "think block completed but no result was returned");
+                if let Some(err) = violation {
+                    return Err(err);
+                }
+                Err(match stop_reason {
+                    StopReason::Cancelled => Error::Cancelled,
+                    StopReason::Refusal => Error::Refused,
+                    StopReason::MaxTurnRequests => Error::MaxTurns,
+                    _ => Error::NoResult,
+                })
+            }
+        }
+    }
+}
+
+impl<'bound, Output, R: Role, Run: RunWithConnectionTo<R>> IntoFuture for ThinkBuilder<'bound, Output, R, Run>
+where
+    R: HasPeer<Agent>,
+    Output: Send + DeserializeOwned + 'static,
+    Run: Send,
+{
+    type Output = Result<Output, Error>;
+
+    type IntoFuture = BoxFuture<'bound, Result<Output, Error>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move { Ok(self.outcome().await?.output) })
+    }
+}
+
+impl<Output, R: Role, Run: RunWithConnectionTo<R>> ThinkBuilder<'static, Output, R, Run>
+where
+    R: HasPeer<Agent>,
+    Output: Send + DeserializeOwned + 'static,
+    Run: Send + 'static,
+{
+    /// Run the think block in the background.
+    ///
+    /// The returned [`ThinkHandle`] can be awaited for the output, polled for
+    /// [progress](ThinkHandle::progress), or [aborted](ThinkHandle::abort).
+    /// Because the think block may outlive the caller's stack frame, its tools
+    /// must own their state: use `move` closures over owned or `Arc`-shared
+    /// data, or a [`ToolSet`], rather than borrowing locals.
+    ///
+    /// The think block runs on the instance's [`Spawner`](crate::Spawner);
+    /// with the default, [`TokioSpawner`](crate::TokioSpawner), this must be
+    /// called from within a Tokio runtime.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no spawner, which happens only when the `tokio`
+    /// feature is off and none was set.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let handle = d.think::<Report>().text("Audit the dependencies").spawn();
+    /// while !handle.is_finished() {
+    ///     println!("{} tool calls so far", handle.progress().tool_calls);
+    ///     tokio::time::sleep(Duration::from_secs(5)).await;
+    /// }
+    /// let report = handle.await?;
+    /// ```
+    pub fn spawn(self) -> ThinkHandle<Output> {
+        self.spawn_then(std::convert::identity)
+    }
+
+    /// Like [`spawn`](Self::spawn), passing the result through `finish` in
+    /// the background task before the handle sees it.
+    pub(crate) fn spawn_then(
+        mut self,
+        finish: impl FnOnce(Result<ThinkOutcome<Output>, Error>) -> Result<ThinkOutcome<Output>, Error>
+        + Send
+        + 'static,
+    ) -> ThinkHandle<Output> {
+        let spawner = self
+            .client
+            .spawner()
+            .expect("spawning a think block needs the `tokio` feature or a `spawner`");
+        let progress = Arc::new(ProgressObserver::new(self.observer.take()));
+        self.observer = Some(progress.clone());
+        let outcome = self.outcome();
+        ThinkHandle::spawn(&*spawner, async move { finish(outcome.await) }, progress)
+    }
+}
+
+impl SessionOptions {
+    /// The sampling parameters to send, if any were set.
+    fn sampling(&self) -> Option<serde_json::Value> {
+        if self.temperature.is_none() && self.seed.is_none() {
+            return None;
+        }
+        let mut sampling = serde_json::Map::new();
+        if let Some(temperature) = self.temperature {
+            sampling.insert("temperature".to_string(), temperature.into());
+        }
+        if let Some(seed) = self.seed {
+            sampling.insert("seed".to_string(), seed.into());
+        }
+        Some(sampling.into())
+    }
+
+    /// Apply the settings that are made after the session is created.
+    ///
+    /// Returns the model the agent accepted, if one was selected.
+    async fn configure<R>(
+        &self,
+        session: &ActiveSession<'_, R>,
+    ) -> Result<Option<String>, agent_client_protocol::Error>
+    where
+        R: Role + HasPeer<Agent>,
+    {
+        let mut selected_model = None;
+        if let Some(model) = &self.model {
+            debug!(model, "selecting model");
+            let result = session
+                .connection()
+                .send_request_to(
+                    Agent,
+                    SetSessionModelRequest::new(session.session_id().clone(), model.clone()),
+                )
+                .block_task()
+                .await;
+            match result {
+                Ok(_) => selected_model = Some(model.clone()),
+                Err(err) if err.code == ErrorCode::MethodNotFound => {
+                    warn!(model, "agent does not support model selection; using its default");
+                }
+                Err(err) => return Err(err),
             }
+        }
 
-            output.ok_or(Error::NoResult)
-        })
+        if let Some(mode) = &self.mode {
+            let Some(modes) = session.modes() else {
+                warn!(mode, "agent does not advertise session modes; ignoring mode");
+                return Ok(selected_model);
+            };
+            if !modes.available_modes.iter().any(|m| &*m.id.0 == mode) {
+                let available: Vec<_> =
+                    modes.available_modes.iter().map(|m| m.id.to_string()).collect();
+                return Err(agent_client_protocol::Error::invalid_params().data(format!(
+                    "agent has no session mode `{mode}`; available modes: {available:?}"
+                )));
+            }
+            if &*modes.current_mode_id.0 != mode {
+                debug!(mode, "selecting session mode");
+                session
+                    .connection()
+                    .send_request_to(
+                        Agent,
+                        SetSessionModeRequest::new(session.session_id().clone(), mode.clone()),
+                    )
+                    .block_task()
+                    .await?;
+            }
+        }
+        Ok(selected_model)
+    }
+}
+
+/// How a session ended.
+struct SessionEnd {
+    stop_reason: StopReason,
+    /// The model the agent accepted, if one was selected.
+    model: Option<String>,
+    /// How many times the agent was nudged to return a result.
+    nudges: u32,
+    /// How many times the session continued past the agent's turn limit.
+    continuations: u32,
+}
+
+/// Add the estimated tokens in `text` to `tokens`.
+fn count_tokens(tokens: &AtomicU64, text: &str) {
+    tokens.fetch_add(budget::estimate_tokens(text) as u64, Ordering::Relaxed);
+}
+
+/// The prompt sent by [`ThinkBuilder::continue_on_max_turns`].
+///
+/// It restates the task, since the agent may have lost track of it, along
+/// with the tail of what the agent said before running out of turns.
+fn continuation_prompt(prompt: &str, progress: &str) -> String {
+    let mut start = progress.len().saturating_sub(CONTINUATION_PROGRESS_LIMIT);
+    while !progress.is_char_boundary(start) {
+        start += 1;
+    }
+    let progress = progress[start..].trim();
+    let progress = if progress.is_empty() {
+        "(nothing)"
+    } else {
+        progress
+    };
+    format!(
+        "You reached the turn limit before calling return_result. The original task was:\n\n\
+         {prompt}\n\n\
+         Here is what you reported so far:\n\n\
+         {progress}\n\n\
+         Continue from where you left off, and call return_result when done."
+    )
+}
+
+/// Classify an error that ended a session.
+fn session_error(
+    source: agent_client_protocol::Error,
+    closed: Option<&watch::Receiver<()>>,
+) -> Error {
+    // The sender is dropped when the connection task ends.
+    if closed.is_some_and(|closed| closed.has_changed().is_err()) {
+        Error::Connection {
+            message: "connection closed during the session".to_string(),
+            source: Some(source),
+        }
+    } else {
+        Error::Protocol { source }
+    }
+}
+
+/// Send the prompt and process session updates until the agent stops,
+/// returning why it stopped.
+async fn drive_session<R>(
+    mut session: ActiveSession<'_, R>,
+    options: &SessionOptions,
+    client: &ClientHandlers,
+    permissions: &mut PermissionMemory,
+    task: &Task<'_>,
+    observer: Option<&dyn ThinkObserver>,
+    mut closed: Option<&mut watch::Receiver<()>>,
+) -> Result<SessionEnd, agent_client_protocol::Error>
+where
+    R: Role + HasPeer<Agent>,
+{
+    let model = options.configure(&session).await?;
+    let connection = session.connection();
+    let redaction = client.log_redaction();
+    let prompt = task.prompt;
+    let mut nudges = 0;
+    let mut continuations = 0;
+    // The agent's messages since the last prompt, for continuations
+    let mut progress = String::new();
+    // Whether the agent's next message chunk continues the current message
+    let mut in_message = false;
+
+    session.send_prompt(prompt)?;
+    match redaction.text(prompt) {
+        Some(prompt) => tracing::info!(?prompt, "sending prompt"),
+        None => tracing::info!(prompt_len = prompt.len(), "sending prompt"),
+    }
+
+    // Wait for updates until we get a stop reason
+    loop {
+        // If the connection goes away mid-turn, no stop reason will ever arrive.
+        let update = match closed.as_deref_mut() {
+            Some(closed) => {
+                let read = std::pin::pin!(session.read_update());
+                let closed = std::pin::pin!(closed.changed());
+                match futures::future::select(read, closed).await {
+                    futures::future::Either::Left((update, _)) => update?,
+                    futures::future::Either::Right(_) => {
+                        warn!("connection closed before the session stopped");
+                        return Err(agent_client_protocol::Error::internal_error()
+                            .data("connection closed before the session stopped"));
+                    }
+                }
+            }
+            None => session.read_update().await?,
+        };
+        if redaction.is_full() {
+            trace!(?update, "received session update");
+        }
+        match update {
+            agent_client_protocol::SessionMessage::StopReason(reason) => {
+                debug!(?reason, "session stopped");
+                if let Some(observer) = observer {
+                    observer.on_stop(&reason);
+                }
+                let returned = task.returned.load(Ordering::Relaxed);
+                if !returned
+                    && reason == StopReason::MaxTurnRequests
+                    && continuations < options.continuations
+                {
+                    continuations += 1;
+                    debug!(continuations, "agent hit its turn limit; continuing");
+                    let prompt = continuation_prompt(prompt, &progress);
+                    progress.clear();
+                    in_message = false;
+                    if let Some(observer) = observer {
+                        observer.on_prompt(&prompt);
+                    }
+                    count_tokens(task.tokens, &prompt);
+                    session.send_prompt(prompt)?;
+                    continue;
+                }
+                let stopped_early = matches!(reason, StopReason::Cancelled | StopReason::Refusal);
+                if !stopped_early && !returned && nudges < options.nudges {
+                    nudges += 1;
+                    debug!(nudges, "agent stopped without a result; nudging");
+                    if let Some(observer) = observer {
+                        observer.on_prompt(NUDGE_PROMPT);
+                    }
+                    count_tokens(task.tokens, NUDGE_PROMPT);
+                    in_message = false;
+                    session.send_prompt(NUDGE_PROMPT)?;
+                    continue;
+                }
+                return Ok(SessionEnd {
+                    stop_reason: reason,
+                    model,
+                    nudges,
+                    continuations,
+                });
+            }
+            agent_client_protocol::SessionMessage::SessionMessage(dispatch) => {
+                MatchDispatch::new(dispatch)
+                    .if_notification(async |notification: SessionNotification| {
+                        match redaction.message(&notification) {
+                            Some(notification) => {
+                                tracing::debug!(%notification, "received session notification")
+                            }
+                            None => tracing::debug!("received session notification"),
+                        }
+                        match &notification.update {
+                            SessionUpdate::AgentMessageChunk(chunk) => {
+                                if let ContentBlock::Text(text) = &chunk.content {
+                                    progress.push_str(&text.text);
+                                    let mut message = task
+                                        .message
+                                        .lock()
+                                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+                                    if !in_message {
+                                        message.clear();
+                                        in_message = true;
+                                    }
+                                    message.push_str(&text.text);
+                                }
+                            }
+                            SessionUpdate::AgentThoughtChunk(_) => {}
+                            _ => in_message = false,
+                        }
+                        if let SessionUpdate::AgentMessageChunk(chunk)
+                        | SessionUpdate::AgentThoughtChunk(chunk) = &notification.update
+                            && let ContentBlock::Text(text) = &chunk.content
+                        {
+                            count_tokens(task.tokens, &text.text);
+                        }
+                        if let Some(observer) = observer {
+                            observer.on_notification(&notification);
+                            if let SessionUpdate::AgentThoughtChunk(chunk) = &notification.update
+                                && let ContentBlock::Text(text) = &chunk.content
+                            {
+                                observer.on_reasoning(&text.text);
+                            }
+                        }
+                        Ok(())
+                    })
+                    .await
+                    .if_request(
+                        async |request: RequestPermissionRequest, responder| {
+                            match redaction.message(&request) {
+                                Some(request) => tracing::debug!(
+                                    %request,
+                                    "received tool use permission request"
+                                ),
+                                None => tracing::debug!(
+                                    kind = ?request.tool_call.fields.kind,
+                                    "received tool use permission request"
+                                ),
+                            }
+                            if let Some(observer) = observer {
+                                observer.on_permission_request(&request);
+                            }
+                            let (response, record) = permissions.decide(client, request).await;
+                            if let Some(observer) = observer {
+                                observer.on_permission_decision(&record);
+                            }
+                            responder.respond(response)
+                        },
+                    )
+                    .await
+                    .if_request(async |request: ReadTextFileRequest, responder| {
+                        client.read_text_file(&connection, request, responder)
+                    })
+                    .await
+                    .if_request(async |request: WriteTextFileRequest, responder| {
+                        client.write_text_file(&connection, request, responder)
+                    })
+                    .await
+                    .if_request(async |request: CreateTerminalRequest, responder| {
+                        client.create_terminal(&connection, request, responder)
+                    })
+                    .await
+                    .if_request(async |request: TerminalOutputRequest, responder| {
+                        client.terminal_output(&connection, request, responder)
+                    })
+                    .await
+                    .if_request(async |request: WaitForTerminalExitRequest, responder| {
+                        client.wait_for_terminal_exit(&connection, request, responder)
+                    })
+                    .await
+                    .if_request(async |request: KillTerminalRequest, responder| {
+                        client.kill_terminal(&connection, request, responder)
+                    })
+                    .await
+                    .if_request(async |request: ReleaseTerminalRequest, responder| {
+                        client.release_terminal(&connection, request, responder)
+                    })
+                    .await
+                    .otherwise(async |dispatch| client.unhandled(dispatch).await)
+                    .await?
+            }
+            _ => continue,
+        }
+    }
+}
+
+impl ToolInfo {
+    pub(crate) fn new<I: JsonSchema, O: JsonSchema>(name: &str, description: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            input_schema: schemars::schema_for!(I).to_value(),
+            output_schema: schemars::schema_for!(O).to_value(),
+        }
+    }
+
+    /// A tool with a schema given as data, which may return any JSON.
+    pub(crate) fn dynamic(name: &str, description: &str, input_schema: serde_json::Value) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            input_schema,
+            output_schema: serde_json::json!({ "description": "any JSON value" }),
+        }
+    }
+
+    /// A tool returning [`ToolOutput`](crate::ToolOutput), which has no schema.
+    pub(crate) fn content<I: JsonSchema>(name: &str, description: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            input_schema: schemars::schema_for!(I).to_value(),
+            output_schema: serde_json::json!({
+                "description": "MCP content: text, images, or resource links"
+            }),
+        }
+    }
+}
+
+/// Pretty-print JSON with object keys sorted, regardless of how the map
+/// type orders them (e.g., when `serde_json/preserve_order` is enabled).
+fn render_json(value: &serde_json::Value) -> String {
+    fn sorted(value: &serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::Object(map) => {
+                let mut entries: Vec<_> = map.iter().collect();
+                entries.sort_by_key(|(k, _)| *k);
+                serde_json::Value::Object(
+                    entries.into_iter().map(|(k, v)| (k.clone(), sorted(v))).collect(),
+                )
+            }
+            serde_json::Value::Array(items) => {
+                serde_json::Value::Array(items.iter().map(sorted).collect())
+            }
+            other => other.clone(),
+        }
+    }
+    serde_json::to_string_pretty(&sorted(value)).expect("JSON values can always be serialized")
+}
+
+/// Guidance given when the output type admits `null` (e.g., `Option<T>`), so that
+/// extraction tasks get a clean "not found" signal instead of invented values.
+const NULL_RESULT_GUIDANCE: &str = "If the requested information does not exist, \
+    call `return_result` with `null` as the result rather than inventing a value.";
+
+/// Describe the return_result tool. The schema the result must match is the
+/// tool's input schema, so it is not repeated here.
+fn return_result_description(output_schema: &serde_json::Value) -> String {
+    let mut description = "Return the final result. Call this when you have completed the \
+        task, passing the result as `result`, in the shape the input schema gives."
+        .to_string();
+    if crate::schema::accepts_null(output_schema) {
+        description.push_str("\n\n");
+        description.push_str(NULL_RESULT_GUIDANCE);
+    }
+    description
+}
+
+/// The input schema of the return_result tool: `result`, matching the output
+/// schema, whose definitions move to the root where its references point.
+fn return_result_schema(output_schema: &serde_json::Value) -> serde_json::Value {
+    let mut result = output_schema.clone();
+    let defs = result
+        .as_object_mut()
+        .and_then(|schema| schema.remove("$defs"));
+    let meta = result
+        .as_object_mut()
+        .and_then(|schema| schema.remove("$schema"));
+    let mut schema = serde_json::json!({
+        "type": "object",
+        "properties": { "result": result },
+    });
+    // A missing result is taken as `null`, so it is only optional when
+    // `null` is allowed
+    if !crate::schema::accepts_null(output_schema) {
+        schema["required"] = serde_json::json!(["result"]);
     }
+    if let Some(meta) = meta {
+        schema["$schema"] = meta;
+    }
+    if let Some(defs) = defs {
+        schema["$defs"] = defs;
+    }
+    schema
+}
+
+/// Deserialize a result into `Output`, falling back to the legacy versions
+/// registered with [`ThinkBuilder::accepts_legacy`].
+///
+/// If nothing matches, the error is the one for the current version.
+fn deserialize_result<Output: DeserializeOwned>(
+    value: &serde_json::Value,
+    legacy: &[Migration<Output>],
+) -> Result<Output, serde_json::Error> {
+    Output::deserialize(value).or_else(|err| {
+        let migrated = legacy.iter().find_map(|migrate| migrate(value));
+        if migrated.is_some() {
+            debug!("accepted a result matching a legacy output type");
+        }
+        migrated.ok_or(err)
+    })
 }
 
-/// Input schema for the return_result tool.
+/// A call to the return_result tool, and where to send its response.
+type ReturnCall = (
+    serde_json::Value,
+    tokio::sync::oneshot::Sender<Result<serde_json::Value, agent_client_protocol::Error>>,
+);
+
+/// Arguments to the return_result tool.
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
-struct ReturnResultInput<T> {
-    /// The result value to return.
-    result: T,
+struct ReturnResultInput {
+    /// The result value to return, matching the output schema.
+    ///
+    /// A missing result is treated as `null`.
+    #[serde(default)]
+    result: serde_json::Value,
 }
 
 /// Output schema for the return_result tool.
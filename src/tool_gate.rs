@@ -0,0 +1,161 @@
+//! Enabling and disabling tools while a session runs.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use rmcp::RoleServer;
+use rmcp::service::Peer;
+use tracing::{debug, warn};
+
+use crate::toolset::ErasedTool;
+
+/// A switch for hiding and revealing tool set tools in the middle of a
+/// session.
+///
+/// Attach a gate to a think block with
+/// [`ThinkBuilder::tool_gate`](crate::ThinkBuilder::tool_gate), disable the
+/// tools the agent shouldn't have yet, and keep a clone of the gate in the
+/// checkpoint or tool that decides when they become available. Disabled
+/// tools are left out of `tools/list` and refuse to be called; whenever the
+/// set of enabled tools changes, the agent is sent `tools/list_changed` so
+/// it can fetch the list again.
+///
+/// The gate covers tools from [`ToolSet`](crate::ToolSet)s, including
+/// deprecated aliases of a disabled tool, but not tools added with
+/// [`ThinkBuilder::tool`](crate::ThinkBuilder::tool). A gate shared by
+/// several think blocks switches tools in all of them at once.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let gate = ToolGate::new();
+/// gate.disable("write_file").await;
+///
+/// let report: Report = d.think()
+///     .tools(&file_tools)
+///     .tool_gate(&gate)
+///     .text("Plan the refactoring, submit it with")
+///     .checkpoint("review_plan", |plan: Plan| {
+///         let gate = gate.clone();
+///         async move {
+///             if approve(&plan).await {
+///                 gate.enable("write_file").await;
+///                 CheckpointDecision::Proceed
+///             } else {
+///                 CheckpointDecision::Revise("Keep the public API unchanged".into())
+///             }
+///         }
+///     })
+///     .text("and carry it out once it is approved.")
+///     .await?;
+/// ```
+#[derive(Clone, Default)]
+pub struct ToolGate {
+    state: Arc<Mutex<GateState>>,
+}
+
+#[derive(Default)]
+struct GateState {
+    disabled: HashSet<String>,
+    /// The agents connected to servers behind this gate, by connection.
+    peers: Vec<(u64, Peer<RoleServer>)>,
+    next_connection: u64,
+}
+
+impl ToolGate {
+    /// A gate with every tool enabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make the tool named `name` available again, telling connected agents
+    /// if that changes their tool list.
+    pub async fn enable(&self, name: &str) {
+        let changed = self.lock().disabled.remove(name);
+        if changed {
+            debug!(tool_name = name, "enabling tool");
+            self.notify().await;
+        }
+    }
+
+    /// Hide the tool named `name` and refuse calls to it, telling connected
+    /// agents if that changes their tool list.
+    pub async fn disable(&self, name: &str) {
+        let changed = self.lock().disabled.insert(name.to_string());
+        if changed {
+            debug!(tool_name = name, "disabling tool");
+            self.notify().await;
+        }
+    }
+
+    /// Whether the tool named `name` is enabled.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        !self.lock().disabled.contains(name)
+    }
+
+    /// Whether `tool` may be listed and called: neither it nor the tool it
+    /// is an alias for is disabled.
+    pub(crate) fn allows(&self, tool: &dyn ErasedTool) -> bool {
+        let state = self.lock();
+        !state.disabled.contains(tool.name())
+            && tool
+                .alias_of()
+                .is_none_or(|name| !state.disabled.contains(name))
+    }
+
+    /// Tell `peer` about changes until the returned guard is dropped.
+    pub(crate) fn attach(&self, peer: Peer<RoleServer>) -> Attached {
+        let mut state = self.lock();
+        let connection = state.next_connection;
+        state.next_connection += 1;
+        state.peers.push((connection, peer));
+        Attached {
+            gate: self.clone(),
+            connection,
+        }
+    }
+
+    /// Send `tools/list_changed` to every connected agent.
+    async fn notify(&self) {
+        let peers: Vec<Peer<RoleServer>> = self
+            .lock()
+            .peers
+            .iter()
+            .map(|(_, peer)| peer.clone())
+            .collect();
+        for peer in peers {
+            if let Err(err) = peer.notify_tool_list_changed().await {
+                warn!(%err, "cannot tell the agent its tools changed");
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, GateState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl std::fmt::Debug for ToolGate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ToolGate")
+            .field("disabled", &self.lock().disabled)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Keeps an agent attached to a [`ToolGate`]; detaches it when dropped.
+pub(crate) struct Attached {
+    gate: ToolGate,
+    connection: u64,
+}
+
+impl Drop for Attached {
+    fn drop(&mut self) {
+        self.gate
+            .lock()
+            .peers
+            .retain(|(connection, _)| *connection != self.connection);
+    }
+}
@@ -0,0 +1,151 @@
+//! Rich content returned by tools.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use rmcp::model::{Content, RawResource};
+use serde::Serialize;
+
+/// What a tool hands back to the agent, when plain JSON isn't enough.
+///
+/// Tools registered with [`ToolSet::tool`](crate::ToolSet::tool) return any
+/// [`Serialize`] type, which the agent receives as JSON text. Register a tool
+/// with [`ToolSet::content_tool`](crate::ToolSet::content_tool) to return a
+/// `ToolOutput` instead, so it can give the agent Markdown, an image, or a
+/// link to a resource it can read on demand.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let tools = ToolSet::new().content_tool(
+///     "plot",
+///     "Plot a series and return the chart as a PNG",
+///     |input: PlotInput| async move {
+///         let png = render_chart(&input.points)?;
+///         Ok(ToolOutput::text(format!("Chart of {} points:", input.points.len()))
+///             .and(ToolOutput::image(png, "image/png")))
+///     },
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolOutput {
+    /// A JSON value, sent as text like the output of an ordinary tool.
+    Json(serde_json::Value),
+
+    /// Plain text or Markdown, sent as is rather than as a JSON string.
+    Text(String),
+
+    /// An image.
+    Image {
+        /// The raw image bytes; they are base64-encoded on the wire.
+        data: Vec<u8>,
+        /// The MIME type of the image (e.g., `image/png`).
+        mime_type: String,
+    },
+
+    /// A link to a resource the agent can fetch if it needs it.
+    ResourceLink {
+        /// The resource's URI.
+        uri: String,
+        /// A short name for the resource.
+        name: String,
+        /// The MIME type of the resource, if known.
+        mime_type: Option<String>,
+    },
+
+    /// Several pieces of content, in order.
+    Multiple(Vec<ToolOutput>),
+}
+
+impl ToolOutput {
+    /// Serialize `value` as JSON.
+    pub fn json(value: &impl Serialize) -> Result<Self, serde_json::Error> {
+        serde_json::to_value(value).map(ToolOutput::Json)
+    }
+
+    /// Plain text or Markdown.
+    pub fn text(text: impl Into<String>) -> Self {
+        ToolOutput::Text(text.into())
+    }
+
+    /// An image with the given MIME type.
+    pub fn image(data: impl Into<Vec<u8>>, mime_type: impl Into<String>) -> Self {
+        ToolOutput::Image {
+            data: data.into(),
+            mime_type: mime_type.into(),
+        }
+    }
+
+    /// A link to the resource at `uri`.
+    pub fn resource_link(uri: impl Into<String>, name: impl Into<String>) -> Self {
+        ToolOutput::ResourceLink {
+            uri: uri.into(),
+            name: name.into(),
+            mime_type: None,
+        }
+    }
+
+    /// Follow this content with `other`.
+    pub fn and(self, other: ToolOutput) -> Self {
+        match self {
+            ToolOutput::Multiple(mut parts) => {
+                parts.push(other);
+                ToolOutput::Multiple(parts)
+            }
+            first => ToolOutput::Multiple(vec![first, other]),
+        }
+    }
+
+    /// The output as JSON, for [`ToolSet::invoke`](crate::ToolSet::invoke).
+    ///
+    /// JSON output is returned as is; anything else becomes the array of MCP
+    /// content blocks the agent would receive.
+    pub(crate) fn into_json(self) -> serde_json::Value {
+        match self {
+            ToolOutput::Json(value) => value,
+            other => serde_json::to_value(other.into_contents())
+                .expect("MCP content can always be serialized"),
+        }
+    }
+
+    /// The MCP content blocks for this output.
+    pub(crate) fn into_contents(self) -> Vec<Content> {
+        match self {
+            ToolOutput::Json(value) => vec![Content::text(value.to_string())],
+            ToolOutput::Text(text) => vec![Content::text(text)],
+            ToolOutput::Image { data, mime_type } => {
+                vec![Content::image(STANDARD.encode(data), mime_type)]
+            }
+            ToolOutput::ResourceLink {
+                uri,
+                name,
+                mime_type,
+            } => {
+                let mut resource = RawResource::new(uri, name);
+                resource.mime_type = mime_type;
+                vec![Content::resource_link(resource)]
+            }
+            ToolOutput::Multiple(parts) => parts
+                .into_iter()
+                .flat_map(ToolOutput::into_contents)
+                .collect(),
+        }
+    }
+}
+
+impl From<serde_json::Value> for ToolOutput {
+    fn from(value: serde_json::Value) -> Self {
+        ToolOutput::Json(value)
+    }
+}
+
+impl From<String> for ToolOutput {
+    fn from(text: String) -> Self {
+        ToolOutput::Text(text)
+    }
+}
+
+impl From<&str> for ToolOutput {
+    fn from(text: &str) -> Self {
+        ToolOutput::Text(text.to_string())
+    }
+}
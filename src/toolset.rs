@@ -0,0 +1,768 @@
+//! Reusable sets of tools that can be invoked directly in tests.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use agent_client_protocol::mcp_server::McpServer;
+use agent_client_protocol::role::Role;
+use agent_client_protocol::{Agent, BoxFuture, NullRun};
+use rmcp::handler::server::tool::{schema_for_output, schema_for_type};
+use schemars::JsonSchema;
+use serde::{Serialize, de::DeserializeOwned};
+use serde_path_to_error::Segment;
+use tracing::{debug, warn};
+
+use crate::mcp::{InvalidCalls, Server};
+use crate::prompts::PromptTemplate;
+use crate::redaction::LogRedaction;
+use crate::resources::Resources;
+use crate::sampling::Sampler;
+use crate::strictness::{self, ArgumentStrictness};
+use crate::think::ToolInfo;
+use crate::tool_gate::ToolGate;
+use crate::{Error, ToolOutput};
+
+/// A reusable collection of `'static` tools.
+///
+/// Unlike tools registered with [`ThinkBuilder::tool`](crate::ThinkBuilder::tool),
+/// which may borrow from the stack, a `ToolSet` owns its tools. That makes it
+/// cheap to share across many think blocks (via
+/// [`ThinkBuilder::tools`](crate::ThinkBuilder::tools)) and lets tests call the
+/// tools directly with JSON input, without an agent:
+///
+/// ```rust,ignore
+/// let tools = ToolSet::new().tool(
+///     "word_count",
+///     "Count the words in some text",
+///     |input: WordCountInput| async move { Ok(input.text.split_whitespace().count()) },
+/// );
+///
+/// let output = tools.invoke("word_count", json!({ "text": "one two three" })).await?;
+/// assert_eq!(output, json!(3));
+/// ```
+///
+/// The type parameter `R` is the role of the connection the tools will be
+/// served over; it matches the `R` of the [`Determinishtic`](crate::Determinishtic)
+/// instance. Use [`ToolSet::default`] to create a set for a role other than `Agent`.
+pub struct ToolSet<R: Role = Agent> {
+    tools: Vec<Arc<dyn ErasedTool>>,
+    prompts: Vec<PromptTemplate>,
+    phantom: PhantomData<fn() -> R>,
+}
+
+impl ToolSet<Agent> {
+    /// Create an empty tool set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<R: Role> ToolSet<R> {
+    /// Add a tool.
+    ///
+    /// If a tool with the same name is already in the set, it is replaced.
+    pub fn tool<I, O, F, Fut>(self, name: &str, description: &str, func: F) -> Self
+    where
+        I: JsonSchema + DeserializeOwned + Send + 'static,
+        O: JsonSchema + Serialize + Send + 'static,
+        F: Fn(I) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<O, agent_client_protocol::Error>> + Send + 'static,
+    {
+        self.sampling_tool(name, description, move |input, _sampler| func(input))
+    }
+
+    /// Add a tool that can ask the agent's model quick questions through a
+    /// [`Sampler`].
+    ///
+    /// The sampler only works with agents that support MCP sampling, so
+    /// check [`Sampler::is_supported`] and have a fallback. If a tool with
+    /// the same name is already in the set, it is replaced.
+    pub fn sampling_tool<I, O, F, Fut>(self, name: &str, description: &str, func: F) -> Self
+    where
+        I: JsonSchema + DeserializeOwned + Send + 'static,
+        O: JsonSchema + Serialize + Send + 'static,
+        F: Fn(I, Sampler) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<O, agent_client_protocol::Error>> + Send + 'static,
+    {
+        self.insert(TypedTool {
+            name: name.to_string(),
+            description: description.to_string(),
+            func: Arc::new(func),
+            phantom: PhantomData,
+        })
+    }
+
+    /// Add a tool that returns rich content, such as Markdown or images,
+    /// rather than JSON.
+    ///
+    /// See [`ToolOutput`] for what the tool can return. If a tool with the
+    /// same name is already in the set, it is replaced.
+    pub fn content_tool<I, F, Fut>(self, name: &str, description: &str, func: F) -> Self
+    where
+        I: JsonSchema + DeserializeOwned + Send + 'static,
+        F: Fn(I) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<ToolOutput, agent_client_protocol::Error>> + Send + 'static,
+    {
+        self.insert(ContentTool {
+            name: name.to_string(),
+            description: description.to_string(),
+            func: Arc::new(func),
+            phantom: PhantomData,
+        })
+    }
+
+    /// Add a tool whose input schema is only known at run time, taking and
+    /// returning JSON.
+    ///
+    /// Use this for tools defined outside Rust, whose schemas arrive as
+    /// data. Arguments are passed on without being checked against
+    /// `input_schema`; add a [`strictness`](Self::strictness) level to hold
+    /// them to it. If a tool with the same name is already in the set, it
+    /// is replaced.
+    ///
+    /// ```rust,ignore
+    /// let tools = ToolSet::new().dynamic_tool(
+    ///     "lookup_order",
+    ///     "Look up an order by its ID",
+    ///     json!({ "type": "object", "properties": { "id": { "type": "string" } } }),
+    ///     |arguments| async move { Ok(orders_service.get(&arguments["id"]).await) },
+    /// );
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `input_schema` is not a JSON object.
+    pub fn dynamic_tool<F, Fut>(
+        self,
+        name: &str,
+        description: &str,
+        input_schema: serde_json::Value,
+        func: F,
+    ) -> Self
+    where
+        F: Fn(serde_json::Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<serde_json::Value, agent_client_protocol::Error>>
+            + Send
+            + 'static,
+    {
+        let serde_json::Value::Object(input_schema) = input_schema else {
+            panic!("the input schema of tool `{name}` must be a JSON object");
+        };
+        self.insert(DynamicTool {
+            name: name.to_string(),
+            description: description.to_string(),
+            input_schema: Arc::new(input_schema),
+            func: Arc::new(func),
+        })
+    }
+
+    /// Attach an example input to the most recently added tool.
+    ///
+    /// Examples are listed under `examples` in the tool's input schema,
+    /// which helps the agent call tools whose arguments have a non-obvious
+    /// shape. Call this several times to add several examples. To also show
+    /// them in the prompt, use
+    /// [`ThinkBuilder::describe_tool_examples`](crate::ThinkBuilder::describe_tool_examples).
+    ///
+    /// ```rust,ignore
+    /// let tools = ToolSet::new()
+    ///     .tool("schedule", "Schedule a meeting", schedule)
+    ///     .with_example(json!({ "title": "Standup", "when": { "weekday": "mon", "time": "09:30" } }));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the set has no tools.
+    pub fn with_example(mut self, input: serde_json::Value) -> Self {
+        let tool = self
+            .tools
+            .pop()
+            .expect("`with_example` needs a tool to attach the example to");
+        self.tools.push(Arc::new(WithExample {
+            tool,
+            example: input,
+        }));
+        self
+    }
+
+    /// Set how strictly the most recently added tool's arguments are held to
+    /// its input schema.
+    ///
+    /// By default serde silently drops fields the tool doesn't have; see
+    /// [`ArgumentStrictness`] for the alternatives. Rejected and repaired
+    /// arguments are logged, so evaluation runs can count how often the
+    /// model gets a tool's arguments wrong.
+    ///
+    /// ```rust,ignore
+    /// let tools = ToolSet::new()
+    ///     .tool("transfer", "Move money between accounts", transfer)
+    ///     .strictness(ArgumentStrictness::Strict);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the set has no tools.
+    pub fn strictness(mut self, strictness: ArgumentStrictness) -> Self {
+        let tool = self
+            .tools
+            .pop()
+            .expect("`strictness` needs a tool to apply to");
+        self.tools
+            .push(Arc::new(WithStrictness { tool, strictness }));
+        self
+    }
+
+    /// Also offer the tool named `target` under its old name `alias`, marked
+    /// deprecated.
+    ///
+    /// After renaming a tool, this keeps prompts and recorded transcripts
+    /// that use the old name working. The alias's description tells the
+    /// agent to use `target` instead, so new calls drift to the new name.
+    ///
+    /// ```rust,ignore
+    /// let tools = ToolSet::new()
+    ///     .tool("search_docs", "Search the documentation", search_docs)
+    ///     .deprecated_alias("find_docs", "search_docs");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the set has no tool named `target`.
+    pub fn deprecated_alias(self, alias: &str, target: &str) -> Self {
+        let tool = self
+            .tools
+            .iter()
+            .find(|t| t.name() == target)
+            .unwrap_or_else(|| panic!("no tool named `{target}` to alias as `{alias}`"))
+            .clone();
+        self.insert(Alias {
+            name: alias.to_string(),
+            tool,
+        })
+    }
+
+    fn insert(mut self, tool: impl ErasedTool + 'static) -> Self {
+        self.tools.retain(|t| t.name() != tool.name());
+        self.tools.push(Arc::new(tool));
+        self
+    }
+
+    /// Add every tool from `other`, replacing tools with the same name.
+    pub fn extend(mut self, other: &ToolSet<R>) -> Self {
+        for tool in &other.tools {
+            self.tools.retain(|t| t.name() != tool.name());
+            self.tools.push(tool.clone());
+        }
+        for prompt in &other.prompts {
+            self = self.prompt(prompt.clone());
+        }
+        self
+    }
+
+    /// Serve a prompt template alongside the tools.
+    ///
+    /// Agents that browse server prompts can offer it as a task preset. If a
+    /// template with the same name is already in the set, it is replaced.
+    pub fn prompt(mut self, prompt: PromptTemplate) -> Self {
+        self.prompts.retain(|p| p.name != prompt.name);
+        self.prompts.push(prompt);
+        self
+    }
+
+    /// Fill in the prompt template named `name` as the agent would receive it.
+    pub fn get_prompt(
+        &self,
+        name: &str,
+        arguments: &HashMap<String, String>,
+    ) -> Result<String, Error> {
+        let prompt = self
+            .prompts
+            .iter()
+            .find(|p| p.name == name)
+            .ok_or_else(|| Error::Prompt {
+                name: name.to_string(),
+                message: "no prompt with that name".to_string(),
+            })?;
+        prompt
+            .render(|argument| arguments.get(argument).map(String::as_str))
+            .map_err(|message| Error::Prompt {
+                name: name.to_string(),
+                message,
+            })
+    }
+
+    /// The names of the tools in the set, in the order they were added.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.tools.iter().map(|t| t.name())
+    }
+
+    /// Whether the set contains no tools.
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// Invoke a tool by name with JSON input, returning its JSON output.
+    ///
+    /// The input is deserialized exactly as it would be for a call from the
+    /// agent, so this exercises the same code path minus the transport.
+    /// Content from a [`content_tool`](Self::content_tool) is returned as
+    /// the MCP content blocks the agent would receive; use
+    /// [`invoke_output`](Self::invoke_output) to get the [`ToolOutput`].
+    pub async fn invoke(
+        &self,
+        name: &str,
+        input: serde_json::Value,
+    ) -> Result<serde_json::Value, Error> {
+        Ok(self.invoke_output(name, input).await?.into_json())
+    }
+
+    /// Invoke a tool by name with JSON input, returning what it would hand
+    /// the agent.
+    ///
+    /// Tools added with [`tool`](Self::tool) return [`ToolOutput::Json`].
+    pub async fn invoke_output(
+        &self,
+        name: &str,
+        input: serde_json::Value,
+    ) -> Result<ToolOutput, Error> {
+        let tool = self
+            .tools
+            .iter()
+            .find(|t| t.name() == name)
+            .ok_or_else(|| Error::ToolFailure {
+                tool: name.to_string(),
+                source: agent_client_protocol::Error::method_not_found()
+                    .data(format!("no tool named `{name}`")),
+            })?;
+        debug!(tool_name = name, "invoking tool directly");
+        tool.call(input, Sampler::unavailable()).await
+    }
+
+    pub(crate) fn entries(&self) -> impl Iterator<Item = ToolInfo> + '_ {
+        self.tools.iter().map(|t| t.entry())
+    }
+
+    /// An MCP server named `name` serving every tool in the set, along
+    /// with `resources`, with tools switched on and off by `gate`, calls
+    /// with bad arguments counted in `invalid_calls`, and tools logging
+    /// under `redaction`.
+    pub(crate) fn server(
+        &self,
+        name: &str,
+        resources: Resources,
+        gate: ToolGate,
+        invalid_calls: InvalidCalls,
+        redaction: LogRedaction,
+    ) -> McpServer<R> {
+        let server = Server::new(
+            name,
+            &self.tools,
+            self.prompts.clone(),
+            resources,
+            gate,
+            invalid_calls,
+            redaction,
+        );
+        McpServer::new(server, NullRun)
+    }
+
+    /// The set without the tool named `name` or any alias for it.
+    pub(crate) fn without(mut self, name: &str) -> Self {
+        self.tools
+            .retain(|t| t.name() != name && t.alias_of() != Some(name));
+        self
+    }
+}
+
+impl<R: Role> Default for ToolSet<R> {
+    fn default() -> Self {
+        Self {
+            tools: Vec::new(),
+            prompts: Vec::new(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<R: Role> Clone for ToolSet<R> {
+    fn clone(&self) -> Self {
+        Self {
+            tools: self.tools.clone(),
+            prompts: self.prompts.clone(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Type-erased view of a tool in a [`ToolSet`].
+pub(crate) trait ErasedTool: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn entry(&self) -> ToolInfo;
+
+    /// The tool as advertised to the agent.
+    fn model(&self) -> rmcp::model::Tool;
+
+    /// The name of the tool this one is an alias for, if it is one.
+    fn alias_of(&self) -> Option<&str> {
+        None
+    }
+
+    fn call(
+        &self,
+        input: serde_json::Value,
+        sampler: Sampler,
+    ) -> BoxFuture<'_, Result<ToolOutput, Error>>;
+}
+
+struct TypedTool<I, O, F, Fut> {
+    name: String,
+    description: String,
+    func: Arc<F>,
+    phantom: PhantomData<fn(I) -> (O, Fut)>,
+}
+
+impl<I, O, F, Fut> ErasedTool for TypedTool<I, O, F, Fut>
+where
+    I: JsonSchema + DeserializeOwned + Send + 'static,
+    O: JsonSchema + Serialize + Send + 'static,
+    F: Fn(I, Sampler) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<O, agent_client_protocol::Error>> + Send + 'static,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn entry(&self) -> ToolInfo {
+        ToolInfo::new::<I, O>(&self.name, &self.description)
+    }
+
+    fn model(&self) -> rmcp::model::Tool {
+        let tool = rmcp::model::Tool::new(
+            self.name.clone(),
+            self.description.clone(),
+            schema_for_type::<I>(),
+        );
+        // Only object outputs can be sent as structured content
+        match schema_for_output::<O>() {
+            Ok(schema) => tool.with_raw_output_schema(schema),
+            Err(_) => tool,
+        }
+    }
+
+    fn call(
+        &self,
+        input: serde_json::Value,
+        sampler: Sampler,
+    ) -> BoxFuture<'_, Result<ToolOutput, Error>> {
+        Box::pin(async move {
+            let input = deserialize_input::<I>(&self.name, input)?;
+            let output =
+                (self.func)(input, sampler)
+                    .await
+                    .map_err(|source| Error::ToolFailure {
+                        tool: self.name.clone(),
+                        source,
+                    })?;
+            let output = serde_json::to_value(output).map_err(|err| Error::ToolFailure {
+                tool: self.name.clone(),
+                source: agent_client_protocol::Error::internal_error()
+                    .data(format!("output could not be serialized: {err}")),
+            })?;
+            Ok(ToolOutput::Json(output))
+        })
+    }
+}
+
+struct ContentTool<I, F, Fut> {
+    name: String,
+    description: String,
+    func: Arc<F>,
+    phantom: PhantomData<fn(I) -> Fut>,
+}
+
+impl<I, F, Fut> ErasedTool for ContentTool<I, F, Fut>
+where
+    I: JsonSchema + DeserializeOwned + Send + 'static,
+    F: Fn(I) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<ToolOutput, agent_client_protocol::Error>> + Send + 'static,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn entry(&self) -> ToolInfo {
+        ToolInfo::content::<I>(&self.name, &self.description)
+    }
+
+    fn model(&self) -> rmcp::model::Tool {
+        rmcp::model::Tool::new(
+            self.name.clone(),
+            self.description.clone(),
+            schema_for_type::<I>(),
+        )
+    }
+
+    fn call(
+        &self,
+        input: serde_json::Value,
+        _sampler: Sampler,
+    ) -> BoxFuture<'_, Result<ToolOutput, Error>> {
+        Box::pin(async move {
+            let input = deserialize_input::<I>(&self.name, input)?;
+            (self.func)(input)
+                .await
+                .map_err(|source| Error::ToolFailure {
+                    tool: self.name.clone(),
+                    source,
+                })
+        })
+    }
+}
+
+/// A tool whose input schema was given as data.
+struct DynamicTool<F> {
+    name: String,
+    description: String,
+    input_schema: Arc<serde_json::Map<String, serde_json::Value>>,
+    func: Arc<F>,
+}
+
+impl<F, Fut> ErasedTool for DynamicTool<F>
+where
+    F: Fn(serde_json::Value) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<serde_json::Value, agent_client_protocol::Error>> + Send + 'static,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn entry(&self) -> ToolInfo {
+        ToolInfo::dynamic(
+            &self.name,
+            &self.description,
+            serde_json::Value::Object(self.input_schema.as_ref().clone()),
+        )
+    }
+
+    fn model(&self) -> rmcp::model::Tool {
+        rmcp::model::Tool::new(
+            self.name.clone(),
+            self.description.clone(),
+            self.input_schema.clone(),
+        )
+    }
+
+    fn call(
+        &self,
+        input: serde_json::Value,
+        _sampler: Sampler,
+    ) -> BoxFuture<'_, Result<ToolOutput, Error>> {
+        Box::pin(async move {
+            let output = (self.func)(input)
+                .await
+                .map_err(|source| Error::ToolFailure {
+                    tool: self.name.clone(),
+                    source,
+                })?;
+            Ok(ToolOutput::Json(output))
+        })
+    }
+}
+
+/// A tool with an example input added to its schema.
+struct WithExample {
+    tool: Arc<dyn ErasedTool>,
+    example: serde_json::Value,
+}
+
+impl ErasedTool for WithExample {
+    fn name(&self) -> &str {
+        self.tool.name()
+    }
+
+    fn entry(&self) -> ToolInfo {
+        let mut entry = self.tool.entry();
+        if let serde_json::Value::Object(schema) = &mut entry.input_schema {
+            add_example(schema, &self.example);
+        }
+        entry
+    }
+
+    fn model(&self) -> rmcp::model::Tool {
+        let mut model = self.tool.model();
+        add_example(Arc::make_mut(&mut model.input_schema), &self.example);
+        model
+    }
+
+    fn call(
+        &self,
+        input: serde_json::Value,
+        sampler: Sampler,
+    ) -> BoxFuture<'_, Result<ToolOutput, Error>> {
+        self.tool.call(input, sampler)
+    }
+}
+
+/// A tool whose arguments are checked or fixed before it is called.
+struct WithStrictness {
+    tool: Arc<dyn ErasedTool>,
+    strictness: ArgumentStrictness,
+}
+
+impl ErasedTool for WithStrictness {
+    fn name(&self) -> &str {
+        self.tool.name()
+    }
+
+    fn entry(&self) -> ToolInfo {
+        self.tool.entry()
+    }
+
+    fn model(&self) -> rmcp::model::Tool {
+        self.tool.model()
+    }
+
+    fn alias_of(&self) -> Option<&str> {
+        self.tool.alias_of()
+    }
+
+    fn call(
+        &self,
+        mut input: serde_json::Value,
+        sampler: Sampler,
+    ) -> BoxFuture<'_, Result<ToolOutput, Error>> {
+        let schema = serde_json::Value::Object(self.tool.model().input_schema.as_ref().clone());
+        Box::pin(async move {
+            match self.strictness {
+                ArgumentStrictness::Strict => {
+                    let issues = strictness::conform(&mut input, &schema, self.strictness);
+                    if !issues.is_empty() {
+                        warn!(tool = self.name(), ?issues, "rejected tool arguments");
+                        let message: Vec<String> = issues.iter().map(ToString::to_string).collect();
+                        return Err(Error::ToolArguments {
+                            tool: self.name().to_string(),
+                            pointer: issues[0].pointer.clone(),
+                            message: message.join("; "),
+                        });
+                    }
+                    self.tool.call(input, sampler).await
+                }
+                ArgumentStrictness::Lenient => {
+                    let issues = strictness::conform(&mut input, &schema, self.strictness);
+                    if !issues.is_empty() {
+                        debug!(tool = self.name(), ?issues, "adjusted tool arguments");
+                    }
+                    self.tool.call(input, sampler).await
+                }
+                ArgumentStrictness::Repair => {
+                    // Only a failure to deserialize calls for repairs, and in
+                    // that case the tool itself never ran
+                    let error = match self.tool.call(input.clone(), sampler.clone()).await {
+                        Err(error @ Error::ToolArguments { .. }) => error,
+                        result => return result,
+                    };
+                    let issues = strictness::conform(&mut input, &schema, self.strictness);
+                    if issues.is_empty() {
+                        return Err(error);
+                    }
+                    warn!(tool = self.name(), ?issues, "repaired tool arguments");
+                    self.tool.call(input, sampler).await
+                }
+            }
+        })
+    }
+}
+
+/// A deprecated name for another tool.
+struct Alias {
+    name: String,
+    tool: Arc<dyn ErasedTool>,
+}
+
+impl Alias {
+    fn description(&self, description: &str) -> String {
+        format!(
+            "Deprecated: use `{}` instead. {description}",
+            self.tool.name()
+        )
+    }
+}
+
+impl ErasedTool for Alias {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn entry(&self) -> ToolInfo {
+        let mut entry = self.tool.entry();
+        entry.description = self.description(&entry.description);
+        entry.name = self.name.clone();
+        entry
+    }
+
+    fn model(&self) -> rmcp::model::Tool {
+        let mut model = self.tool.model();
+        let description = self.description(model.description.as_deref().unwrap_or_default());
+        model.description = Some(description.into());
+        model.name = self.name.clone().into();
+        model
+    }
+
+    fn alias_of(&self) -> Option<&str> {
+        Some(self.tool.name())
+    }
+
+    fn call(
+        &self,
+        input: serde_json::Value,
+        sampler: Sampler,
+    ) -> BoxFuture<'_, Result<ToolOutput, Error>> {
+        warn!(
+            alias = %self.name,
+            tool = self.tool.name(),
+            "deprecated tool alias called"
+        );
+        self.tool.call(input, sampler)
+    }
+}
+
+/// Append `example` to the `examples` array of `schema`.
+fn add_example(
+    schema: &mut serde_json::Map<String, serde_json::Value>,
+    example: &serde_json::Value,
+) {
+    let examples = schema
+        .entry("examples")
+        .or_insert_with(|| serde_json::Value::Array(Vec::new()));
+    if let serde_json::Value::Array(examples) = examples {
+        examples.push(example.clone());
+    }
+}
+
+/// Deserialize the input to the tool named `tool`, pointing at the
+/// offending field if it doesn't fit.
+fn deserialize_input<I: DeserializeOwned>(
+    tool: &str,
+    input: serde_json::Value,
+) -> Result<I, Error> {
+    serde_path_to_error::deserialize(input).map_err(|err| {
+        let mut pointer = String::new();
+        for segment in err.path().iter() {
+            match segment {
+                Segment::Seq { index } => pointer.push_str(&format!("/{index}")),
+                Segment::Map { key } | Segment::Enum { variant: key } => {
+                    pointer = strictness::field_path(&pointer, key)
+                }
+                Segment::Unknown => break,
+            }
+        }
+        Error::ToolArguments {
+            tool: tool.to_string(),
+            pointer,
+            message: err.into_inner().to_string(),
+        }
+    })
+}
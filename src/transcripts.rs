@@ -0,0 +1,377 @@
+//! Keeping transcripts of think blocks for auditing and debugging.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use agent_client_protocol::BoxFuture;
+use agent_client_protocol::schema::{
+    RequestPermissionRequest, SessionNotification, SessionUpdate, StopReason,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::runtime::blocking;
+use crate::think::{ThinkObserver, ThinkOutcome};
+use crate::{Error, PermissionRecord};
+
+/// Think blocks started by this process, for unique IDs.
+static THINK_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// A record of one think block: the prompts sent, everything the agent
+/// reported, the permission decisions made, and how it ended.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredTranscript {
+    /// The think block's ID, also found in its
+    /// [`Provenance::think_id`](crate::Provenance::think_id).
+    pub id: String,
+
+    /// The think block's [labels](crate::ThinkBuilder::label).
+    pub labels: BTreeMap<String, String>,
+
+    /// The think block's [fingerprint](crate::ThinkBuilder::fingerprint).
+    pub prompt_fingerprint: String,
+
+    /// When the think block started.
+    pub started_at: SystemTime,
+
+    /// When the think block finished.
+    pub finished_at: SystemTime,
+
+    /// What happened, in order.
+    pub entries: Vec<TranscriptEntry>,
+
+    /// The result as the agent returned it, if the think block succeeded.
+    pub output: Option<serde_json::Value>,
+
+    /// Why the think block failed, if it did.
+    pub error: Option<String>,
+}
+
+/// Something that happened during a think block.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TranscriptEntry {
+    /// A prompt was sent to the agent.
+    Prompt {
+        /// The prompt text.
+        text: String,
+    },
+
+    /// The agent reported progress: message text, a tool call, a plan.
+    Update {
+        /// The update as the agent sent it.
+        update: SessionUpdate,
+    },
+
+    /// A permission request was decided.
+    Permission {
+        /// The decision and what it was about.
+        record: PermissionRecord,
+    },
+
+    /// The session stopped.
+    Stop {
+        /// Why the agent stopped.
+        reason: StopReason,
+    },
+}
+
+/// Somewhere transcripts of finished think blocks are kept.
+///
+/// Configure a store with
+/// [`DeterminishticBuilder::transcript_store`](crate::DeterminishticBuilder::transcript_store)
+/// and every think block run through the instance puts its transcript there
+/// when it finishes, whether it succeeded or not. Transcripts are keyed by
+/// think ID, which is recorded in each result's
+/// [`Provenance`](crate::Provenance), so a stored result can be traced back
+/// to the session that produced it.
+///
+/// [`InMemoryTranscriptStore`] and [`FileTranscriptStore`] are provided.
+/// Implement this trait to keep transcripts in object storage or a
+/// database instead, reporting failures with [`Error::transcript_store`].
+pub trait TranscriptStore: Send + Sync {
+    /// Store `transcript` under its ID, replacing any transcript already
+    /// there.
+    fn put(&self, transcript: StoredTranscript) -> BoxFuture<'_, Result<(), Error>>;
+
+    /// The transcript with ID `id`, if there is one.
+    fn get(&self, id: &str) -> BoxFuture<'_, Result<Option<StoredTranscript>, Error>>;
+
+    /// The IDs of every stored transcript, oldest first.
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, Error>>;
+}
+
+/// A [`TranscriptStore`] kept in process, for tests and short-lived tools.
+///
+/// Clones share the same transcripts.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryTranscriptStore {
+    transcripts: Arc<Mutex<BTreeMap<String, StoredTranscript>>>,
+}
+
+impl InMemoryTranscriptStore {
+    /// An empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, StoredTranscript>> {
+        self.transcripts
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl TranscriptStore for InMemoryTranscriptStore {
+    fn put(&self, transcript: StoredTranscript) -> BoxFuture<'_, Result<(), Error>> {
+        self.lock().insert(transcript.id.clone(), transcript);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn get(&self, id: &str) -> BoxFuture<'_, Result<Option<StoredTranscript>, Error>> {
+        let transcript = self.lock().get(id).cloned();
+        Box::pin(async move { Ok(transcript) })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, Error>> {
+        let ids = self.lock().keys().cloned().collect();
+        Box::pin(async move { Ok(ids) })
+    }
+}
+
+/// A [`TranscriptStore`] that writes each transcript to a JSON file named
+/// after its ID in a directory.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let store = FileTranscriptStore::new("transcripts");
+/// let d = Determinishtic::builder()
+///     .transcript_store(store.clone())
+///     .connect(agent)
+///     .await?;
+///
+/// let outcome = d.think::<Summary>().text("Summarize").display(&doc).outcome().await?;
+/// let transcript = store.get(&outcome.provenance().think_id).await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct FileTranscriptStore {
+    dir: PathBuf,
+}
+
+impl FileTranscriptStore {
+    /// Keep transcripts in `dir`, which is created when the first one is
+    /// stored.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The file for the transcript with ID `id`, refusing IDs that could
+    /// name a file outside the directory.
+    fn path(&self, id: &str) -> Result<PathBuf, Error> {
+        let safe = !id.is_empty()
+            && !id.starts_with('.')
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !safe {
+            return Err(Error::transcript_store(format!(
+                "`{id}` is not a valid transcript ID"
+            )));
+        }
+        Ok(self.dir.join(format!("{id}.json")))
+    }
+}
+
+impl TranscriptStore for FileTranscriptStore {
+    fn put(&self, transcript: StoredTranscript) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            let path = self.path(&transcript.id)?;
+            let json = serde_json::to_vec_pretty(&transcript).map_err(Error::transcript_store)?;
+            let dir = self.dir.clone();
+            blocking(move || {
+                std::fs::create_dir_all(&dir)?;
+                // Write to the side first so readers never see half a file
+                let partial = path.with_extension("json.partial");
+                std::fs::write(&partial, json)?;
+                std::fs::rename(&partial, &path)
+            })
+            .await
+            .map_err(Error::transcript_store)
+        })
+    }
+
+    fn get(&self, id: &str) -> BoxFuture<'_, Result<Option<StoredTranscript>, Error>> {
+        let path = self.path(id);
+        Box::pin(async move {
+            let path = path?;
+            let json = match blocking(move || std::fs::read(path)).await {
+                Ok(json) => json,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                Err(err) => return Err(Error::transcript_store(err)),
+            };
+            serde_json::from_slice(&json)
+                .map(Some)
+                .map_err(Error::transcript_store)
+        })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, Error>> {
+        let dir = self.dir.clone();
+        Box::pin(async move {
+            blocking(move || list_ids(&dir))
+                .await
+                .map_err(Error::transcript_store)
+        })
+    }
+}
+
+/// The IDs of the transcripts in `dir`; IDs sort in the order they were
+/// given out.
+fn list_ids(dir: &Path) -> std::io::Result<Vec<String>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut ids = Vec::new();
+    for entry in entries {
+        let name = entry?.file_name();
+        if let Some(id) = name.to_str().and_then(|name| name.strip_suffix(".json")) {
+            ids.push(id.to_string());
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
+
+/// A new think ID: the start time in milliseconds, the process ID, and a
+/// count of think blocks in this process, so IDs are unique across workers
+/// and sort by when the think block started.
+pub(crate) fn new_think_id() -> String {
+    let millis = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let count = THINK_COUNT.fetch_add(1, Ordering::Relaxed);
+    format!("{millis:013}-{}-{count}", std::process::id())
+}
+
+/// Records a think block's transcript while passing everything on to the
+/// observer it wraps.
+pub(crate) struct TranscriptRecorder {
+    inner: Option<Arc<dyn ThinkObserver>>,
+    id: String,
+    labels: BTreeMap<String, String>,
+    prompt_fingerprint: String,
+    started_at: SystemTime,
+    entries: Mutex<Vec<TranscriptEntry>>,
+}
+
+impl TranscriptRecorder {
+    pub(crate) fn new(
+        inner: Option<Arc<dyn ThinkObserver>>,
+        id: &str,
+        labels: BTreeMap<String, String>,
+        prompt_fingerprint: String,
+    ) -> Self {
+        Self {
+            inner,
+            id: id.to_string(),
+            labels,
+            prompt_fingerprint,
+            started_at: SystemTime::now(),
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The transcript of the think block that ended with `result`.
+    pub(crate) fn finish<T>(&self, result: &Result<ThinkOutcome<T>, Error>) -> StoredTranscript {
+        let (output, error) = match result {
+            Ok(outcome) => (Some(outcome.accepted.clone()), None),
+            Err(err) => (None, Some(err.to_string())),
+        };
+        StoredTranscript {
+            id: self.id.clone(),
+            labels: self.labels.clone(),
+            prompt_fingerprint: self.prompt_fingerprint.clone(),
+            started_at: self.started_at,
+            finished_at: SystemTime::now(),
+            entries: std::mem::take(&mut *self.lock()),
+            output,
+            error,
+        }
+    }
+
+    fn record(&self, entry: TranscriptEntry) {
+        self.lock().push(entry);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<TranscriptEntry>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl ThinkObserver for TranscriptRecorder {
+    fn on_start(&self, labels: &BTreeMap<String, String>) {
+        if let Some(inner) = &self.inner {
+            inner.on_start(labels);
+        }
+    }
+
+    fn on_prompt(&self, prompt: &str) {
+        self.record(TranscriptEntry::Prompt {
+            text: prompt.to_string(),
+        });
+        if let Some(inner) = &self.inner {
+            inner.on_prompt(prompt);
+        }
+    }
+
+    fn on_notification(&self, notification: &SessionNotification) {
+        self.record(TranscriptEntry::Update {
+            update: notification.update.clone(),
+        });
+        if let Some(inner) = &self.inner {
+            inner.on_notification(notification);
+        }
+    }
+
+    fn on_reasoning(&self, text: &str) {
+        if let Some(inner) = &self.inner {
+            inner.on_reasoning(text);
+        }
+    }
+
+    fn on_permission_request(&self, request: &RequestPermissionRequest) {
+        if let Some(inner) = &self.inner {
+            inner.on_permission_request(request);
+        }
+    }
+
+    fn on_permission_decision(&self, record: &PermissionRecord) {
+        self.record(TranscriptEntry::Permission {
+            record: record.clone(),
+        });
+        if let Some(inner) = &self.inner {
+            inner.on_permission_decision(record);
+        }
+    }
+
+    fn on_stop(&self, reason: &StopReason) {
+        self.record(TranscriptEntry::Stop { reason: *reason });
+        if let Some(inner) = &self.inner {
+            inner.on_stop(reason);
+        }
+    }
+
+    fn on_finish(&self, error: Option<&Error>) {
+        if let Some(inner) = &self.inner {
+            inner.on_finish(error);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Cumulative usage per [`Determinishtic`](crate::Determinishtic) instance.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use agent_client_protocol::schema::{
+    ContentBlock, RequestPermissionRequest, SessionNotification, SessionUpdate, StopReason,
+};
+use serde::Serialize;
+
+use crate::Error;
+use crate::budget;
+use crate::permissions::PermissionRecord;
+use crate::think::ThinkObserver;
+
+/// Totals across every think block run on a [`Determinishtic`](crate::Determinishtic)
+/// instance, from [`Determinishtic::stats`](crate::Determinishtic::stats).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct UsageStats {
+    /// Think blocks that have finished, successfully or not.
+    pub think_blocks: u64,
+
+    /// Think blocks that failed.
+    pub failures: u64,
+
+    /// Prompts sent to the agent, including nudges and continuations.
+    pub prompts: u64,
+
+    /// Estimated tokens in the prompts sent and the text the agent streamed
+    /// back, at about four characters per token.
+    pub estimated_tokens: u64,
+
+    /// Tool calls the agent reported making.
+    pub tool_calls: u64,
+
+    /// Total time from each think block's first prompt until it finished.
+    pub wall_time: Duration,
+}
+
+/// Shared counters behind [`UsageStats`].
+#[derive(Debug, Default)]
+pub(crate) struct Usage {
+    tallies: Mutex<Tallies>,
+}
+
+#[derive(Debug, Default)]
+struct Tallies {
+    total: UsageStats,
+    /// Totals for think blocks carrying each label, keyed by its key and value.
+    by_label: BTreeMap<(String, String), UsageStats>,
+}
+
+impl Usage {
+    pub(crate) fn snapshot(&self) -> UsageStats {
+        self.lock().total.clone()
+    }
+
+    pub(crate) fn snapshot_label(&self, key: &str, value: &str) -> UsageStats {
+        self.lock()
+            .by_label
+            .get(&(key.to_string(), value.to_string()))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn reset(&self) -> UsageStats {
+        std::mem::take(&mut *self.lock()).total
+    }
+
+    /// Apply `update` to the totals and to those of each of `labels`.
+    fn record(&self, labels: &BTreeMap<String, String>, update: impl Fn(&mut UsageStats)) {
+        let mut tallies = self.lock();
+        update(&mut tallies.total);
+        for (key, value) in labels {
+            update(
+                tallies
+                    .by_label
+                    .entry((key.clone(), value.clone()))
+                    .or_default(),
+            );
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Tallies> {
+        self.tallies
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Adds one think block's activity to the instance's [`Usage`], forwarding
+/// everything to the instance's observer, if any.
+pub(crate) struct UsageObserver {
+    inner: Option<Arc<dyn ThinkObserver>>,
+    usage: Arc<Usage>,
+    /// The think block's labels, once it has started.
+    labels: Mutex<BTreeMap<String, String>>,
+    /// When the first prompt was sent.
+    started: Mutex<Option<Instant>>,
+}
+
+impl UsageObserver {
+    pub(crate) fn new(inner: Option<Arc<dyn ThinkObserver>>, usage: Arc<Usage>) -> Self {
+        Self {
+            inner,
+            usage,
+            labels: Mutex::new(BTreeMap::new()),
+            started: Mutex::new(None),
+        }
+    }
+
+    fn started(&self) -> MutexGuard<'_, Option<Instant>> {
+        self.started
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn record(&self, update: impl Fn(&mut UsageStats)) {
+        let labels = self
+            .labels
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.usage.record(&labels, update);
+    }
+}
+
+impl ThinkObserver for UsageObserver {
+    fn on_start(&self, labels: &BTreeMap<String, String>) {
+        *self
+            .labels
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = labels.clone();
+        if let Some(inner) = &self.inner {
+            inner.on_start(labels);
+        }
+    }
+
+    fn on_prompt(&self, prompt: &str) {
+        self.started().get_or_insert_with(Instant::now);
+        let tokens = budget::estimate_tokens(prompt) as u64;
+        self.record(|stats| {
+            stats.prompts += 1;
+            stats.estimated_tokens += tokens;
+        });
+        if let Some(inner) = &self.inner {
+            inner.on_prompt(prompt);
+        }
+    }
+
+    fn on_notification(&self, notification: &SessionNotification) {
+        match &notification.update {
+            SessionUpdate::AgentMessageChunk(chunk) | SessionUpdate::AgentThoughtChunk(chunk) => {
+                if let ContentBlock::Text(text) = &chunk.content {
+                    let tokens = budget::estimate_tokens(&text.text) as u64;
+                    self.record(|stats| stats.estimated_tokens += tokens);
+                }
+            }
+            SessionUpdate::ToolCall(_) => self.record(|stats| stats.tool_calls += 1),
+            _ => {}
+        }
+        if let Some(inner) = &self.inner {
+            inner.on_notification(notification);
+        }
+    }
+
+    fn on_reasoning(&self, text: &str) {
+        if let Some(inner) = &self.inner {
+            inner.on_reasoning(text);
+        }
+    }
+
+    fn on_permission_request(&self, request: &RequestPermissionRequest) {
+        if let Some(inner) = &self.inner {
+            inner.on_permission_request(request);
+        }
+    }
+
+    fn on_permission_decision(&self, record: &PermissionRecord) {
+        if let Some(inner) = &self.inner {
+            inner.on_permission_decision(record);
+        }
+    }
+
+    fn on_stop(&self, reason: &StopReason) {
+        if let Some(inner) = &self.inner {
+            inner.on_stop(reason);
+        }
+    }
+
+    fn on_finish(&self, error: Option<&Error>) {
+        let elapsed = self.started().map(|started| started.elapsed());
+        self.record(|stats| {
+            stats.think_blocks += 1;
+            if error.is_some() {
+                stats.failures += 1;
+            }
+            stats.wall_time += elapsed.unwrap_or_default();
+        });
+        if let Some(inner) = &self.inner {
+            inner.on_finish(error);
+        }
+    }
+}
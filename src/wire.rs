@@ -0,0 +1,246 @@
+//! Capturing the raw JSON-RPC frames exchanged with an agent.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use agent_client_protocol::jsonrpcmsg::Message;
+use agent_client_protocol::role::Role;
+use agent_client_protocol::{Channel, ConnectTo};
+use futures::StreamExt;
+use futures::channel::mpsc;
+use futures::future::{BoxFuture, Either};
+use serde_json::{Value, json};
+use tracing::warn;
+
+/// Frames that belong to no session go to this file.
+const CONNECTION_FILE: &str = "connection.jsonl";
+
+/// Edits a frame before it is written; see [`WireCapture::redact`].
+type Redactor = Arc<dyn Fn(&mut Value) + Send + Sync>;
+
+/// Wraps a component and tees every JSON-RPC frame it exchanges to files,
+/// for debugging interop problems between the conductor and an agent.
+///
+/// Frames are written as JSON lines to one file per ACP session, named
+/// after the session ID, in the capture directory; frames outside any
+/// session, such as `initialize`, go to `connection.jsonl`. Each line
+/// records whether the frame was `sent` to the wrapped component or
+/// `received` from it, the time in seconds since the Unix epoch, and the
+/// frame itself. Frames are passed on unchanged; only what is written is
+/// [redacted](Self::redact). Failing to write a frame is logged, not
+/// treated as an error.
+///
+/// This type is only available with the `wire-debug` feature.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let agent = WireCapture::new(AcpAgent::zed_claude_code(), "target/acp-frames")
+///     .redact(|frame| {
+///         if let Some(prompt) = frame.pointer_mut("/params/prompt") {
+///             *prompt = json!("<redacted>");
+///         }
+///     });
+/// let d = Determinishtic::new(agent).await?;
+/// ```
+pub struct WireCapture<C> {
+    inner: C,
+    dir: PathBuf,
+    redactors: Vec<Redactor>,
+}
+
+impl<C> WireCapture<C> {
+    /// Capture the frames exchanged with `inner` into files in `dir`,
+    /// which is created if needed.
+    pub fn new(inner: C, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            dir: dir.into(),
+            redactors: Vec::new(),
+        }
+    }
+
+    /// Edit each frame before it is written, for example to blank out
+    /// prompts or file contents. Hooks run in the order they were added.
+    pub fn redact(mut self, redactor: impl Fn(&mut Value) + Send + Sync + 'static) -> Self {
+        self.redactors.push(Arc::new(redactor));
+        self
+    }
+}
+
+impl<R: Role, C: ConnectTo<R>> ConnectTo<R> for WireCapture<C> {
+    async fn connect_to(
+        self,
+        client: impl ConnectTo<R::Counterpart>,
+    ) -> Result<(), agent_client_protocol::Error> {
+        let (channel, serve_self) = ConnectTo::<R>::into_channel_and_future(self);
+        match futures::future::select(Box::pin(client.connect_to(channel)), serve_self).await {
+            Either::Left((result, _)) | Either::Right((result, _)) => result,
+        }
+    }
+
+    fn into_channel_and_future(
+        self,
+    ) -> (
+        Channel,
+        BoxFuture<'static, Result<(), agent_client_protocol::Error>>,
+    ) {
+        let (inner, serve_inner) = self.inner.into_channel_and_future();
+        let (caller, ours) = Channel::duplex();
+        let log = Arc::new(FrameLog {
+            dir: self.dir,
+            redactors: self.redactors,
+            state: Mutex::default(),
+        });
+        let future = Box::pin(async move {
+            futures::try_join!(
+                forward(ours.rx, inner.tx, &log, Direction::Sent),
+                forward(inner.rx, ours.tx, &log, Direction::Received),
+                serve_inner,
+            )?;
+            Ok(())
+        });
+        (caller, future)
+    }
+}
+
+/// Which way a frame travelled, from the caller's side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Direction {
+    Sent,
+    Received,
+}
+
+impl Direction {
+    fn name(self) -> &'static str {
+        match self {
+            Direction::Sent => "sent",
+            Direction::Received => "received",
+        }
+    }
+
+    fn reverse(self) -> Self {
+        match self {
+            Direction::Sent => Direction::Received,
+            Direction::Received => Direction::Sent,
+        }
+    }
+}
+
+/// Pass frames from `rx` to `tx`, writing each to `log`.
+async fn forward(
+    mut rx: mpsc::UnboundedReceiver<Result<Message, agent_client_protocol::Error>>,
+    tx: mpsc::UnboundedSender<Result<Message, agent_client_protocol::Error>>,
+    log: &FrameLog,
+    direction: Direction,
+) -> Result<(), agent_client_protocol::Error> {
+    while let Some(frame) = rx.next().await {
+        if let Ok(message) = &frame {
+            log.write(message, direction);
+        }
+        tx.unbounded_send(frame)
+            .map_err(agent_client_protocol::Error::into_internal_error)?;
+    }
+    Ok(())
+}
+
+/// The files frames are written to.
+struct FrameLog {
+    dir: PathBuf,
+    redactors: Vec<Redactor>,
+    state: Mutex<FrameLogState>,
+}
+
+#[derive(Default)]
+struct FrameLogState {
+    /// Open files, by session ID, or `None` for the connection file.
+    files: HashMap<Option<String>, File>,
+    /// The session of each request awaiting a response, by the direction
+    /// it was sent in and its ID, so the response lands beside it.
+    pending: HashMap<(Direction, String), String>,
+}
+
+impl FrameLog {
+    fn write(&self, message: &Message, direction: Direction) {
+        let Ok(mut frame) = serde_json::to_value(message) else {
+            return;
+        };
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let id = frame
+            .get("id")
+            .filter(|id| !id.is_null())
+            .map(Value::to_string);
+        let session = session_id(&frame).or_else(|| {
+            let id = id.clone()?;
+            state.pending.remove(&(direction.reverse(), id))
+        });
+        if let (Some(id), Some(session), Some(_)) = (&id, &session, frame.get("method")) {
+            state
+                .pending
+                .insert((direction, id.clone()), session.clone());
+        }
+
+        for redactor in &self.redactors {
+            redactor(&mut frame);
+        }
+        let at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let line = json!({ "direction": direction.name(), "at": at, "frame": frame });
+
+        let file = match state.files.entry(session.clone()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let name = match &session {
+                    Some(session) => format!("{}.jsonl", file_name(session)),
+                    None => CONNECTION_FILE.to_string(),
+                };
+                let path = self.dir.join(name);
+                let file = std::fs::create_dir_all(&self.dir)
+                    .and_then(|()| File::options().create(true).append(true).open(&path));
+                match file {
+                    Ok(file) => entry.insert(file),
+                    Err(err) => {
+                        warn!(%err, path = %path.display(), "cannot open wire capture file");
+                        return;
+                    }
+                }
+            }
+        };
+        if let Err(err) = writeln!(file, "{line}") {
+            warn!(%err, "cannot write to wire capture file");
+        }
+    }
+}
+
+/// The session a frame belongs to, if it names one.
+fn session_id(frame: &Value) -> Option<String> {
+    ["/params/sessionId", "/result/sessionId"]
+        .into_iter()
+        .find_map(|pointer| frame.pointer(pointer)?.as_str())
+        .map(str::to_string)
+}
+
+/// `session` with anything that isn't safe in a file name replaced.
+fn file_name(session: &str) -> String {
+    session
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
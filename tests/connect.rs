@@ -0,0 +1,22 @@
+//! Connecting to agents started as processes.
+
+#![cfg(all(feature = "conductor", unix))]
+
+use determinishtic::{AgentCommand, Determinishtic, Error};
+
+#[tokio::test]
+async fn an_agent_exiting_during_initialize_fails_the_handshake() {
+    // Reads the initialize request, then gives up
+    let agent =
+        AgentCommand::new("sh").args(["-c", "read -r request; echo 'missing API key' >&2; exit 3"]);
+
+    let result = Determinishtic::builder().connect_command(agent).await;
+
+    match result {
+        Err(Error::HandshakeFailed { detail }) => {
+            assert!(detail.contains("missing API key"), "{detail}")
+        }
+        Err(err) => panic!("expected a failed handshake, got {err:?}"),
+        Ok(_) => panic!("expected a failed handshake"),
+    }
+}
@@ -0,0 +1,101 @@
+//! Planning a task and carrying out the approved steps against the
+//! scripted mock agent.
+
+#![cfg(feature = "testing")]
+
+use std::sync::Mutex;
+
+use determinishtic::Plan;
+use determinishtic::testing::{MockAgent, TranscriptEvent, Turn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// A step of a plan.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+struct Step {
+    /// What to do.
+    action: String,
+    /// Whether the step deletes anything.
+    destructive: bool,
+}
+
+fn steps() -> serde_json::Value {
+    json!([
+        {"action": "back up the database", "destructive": false},
+        {"action": "drop the old tables", "destructive": true},
+        {"action": "run the migrations", "destructive": false},
+    ])
+}
+
+#[tokio::test]
+async fn executes_only_the_approved_steps() {
+    let agent = MockAgent::new()
+        .turn(
+            Turn::new()
+                .expect_prompt("Do not carry out any of the steps yet")
+                .return_result(steps()),
+        )
+        .turn(
+            Turn::new()
+                .expect_prompt("run the migrations")
+                .return_result(json!("migrated")),
+        );
+    let d = agent.connect().await.unwrap();
+
+    let plan: Plan<Step> = d.plan("Migrate the database").await.unwrap();
+    let plan = plan.retain(|step| !step.destructive);
+    let report: String = d.think().execute(&plan).await.unwrap();
+
+    assert_eq!(report, "migrated");
+    let transcript = agent.transcript();
+    let prompts: Vec<_> = transcript
+        .events()
+        .iter()
+        .filter_map(|event| match event {
+            TranscriptEvent::Prompt(prompt) => Some(prompt),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(prompts.len(), 2);
+    assert!(prompts[1].contains("back up the database"));
+    assert!(!prompts[1].contains("drop the old tables"));
+    agent.verify();
+}
+
+#[tokio::test]
+async fn plans_and_executes_in_one_session() {
+    let agent = MockAgent::new().turn(
+        Turn::new()
+            .expect_prompt("Migrate the database")
+            .call_tool("submit_plan", json!({"steps": steps()}))
+            .return_tool_output()
+            .return_result(json!("migrated")),
+    );
+    let d = agent.connect().await.unwrap();
+
+    let reviewed = Mutex::new(Vec::new());
+    let report: String = d
+        .think()
+        .plan_then_execute("Migrate the database", |plan: Plan<Step>| {
+            reviewed.lock().unwrap().push(plan.steps.len());
+            async move { plan.retain(|step| !step.destructive) }
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(report, "migrated");
+    assert_eq!(*reviewed.lock().unwrap(), [3]);
+    let transcript = agent.transcript();
+    let (name, _, output) = transcript.tool_calls().next().unwrap();
+    assert_eq!(name, "submit_plan");
+    let approved = &output.unwrap().as_ref().unwrap()["approved_steps"];
+    assert_eq!(
+        approved,
+        &json!([
+            {"action": "back up the database", "destructive": false},
+            {"action": "run the migrations", "destructive": false},
+        ])
+    );
+    agent.verify();
+}
@@ -0,0 +1,404 @@
+//! Think blocks run against the scripted mock agent.
+
+#![cfg(feature = "testing")]
+
+use determinishtic::testing::{MockAgent, TranscriptEvent, Turn};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use agent_client_protocol::schema::StopReason;
+use determinishtic::{Confidence, Error, InMemoryStore, Memory, OnFailure};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::Notify;
+
+#[derive(Debug, PartialEq, Deserialize, JsonSchema)]
+struct Invoice {
+    number: String,
+    lines: Vec<Line>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, JsonSchema)]
+struct Line {
+    item: String,
+    cents: u64,
+}
+
+#[tokio::test]
+async fn return_result_takes_the_output_schema() {
+    let d = MockAgent::new().connect().await.unwrap();
+    let think = d.think::<Invoice>().text("Read the invoice");
+    let manifest = think.export_tool_manifest();
+
+    let tools = manifest["tools"].as_array().unwrap();
+    let return_result = tools
+        .iter()
+        .find(|tool| tool["name"] == "return_result")
+        .unwrap();
+    let schema = &return_result["inputSchema"];
+    assert_eq!(schema["required"], json!(["result"]));
+    let result = &schema["properties"]["result"];
+    assert_eq!(result["required"], json!(["number", "lines"]));
+    // `Line` is defined at the root, where the reference to it points
+    assert_eq!(
+        result["properties"]["lines"]["items"]["$ref"],
+        "#/$defs/Line"
+    );
+    assert!(schema["$defs"]["Line"].is_object());
+    assert!(result.get("$defs").is_none());
+}
+
+#[tokio::test]
+async fn optional_results_may_be_left_out() {
+    let d = MockAgent::new().connect().await.unwrap();
+    let manifest = d
+        .think::<Option<String>>()
+        .text("Find the order number")
+        .export_tool_manifest();
+
+    let tools = manifest["tools"].as_array().unwrap();
+    let return_result = tools
+        .iter()
+        .find(|tool| tool["name"] == "return_result")
+        .unwrap();
+    assert!(return_result["inputSchema"].get("required").is_none());
+}
+
+#[tokio::test]
+async fn returns_the_result() {
+    let agent = MockAgent::new().turn(Turn::new().return_result(json!({
+        "number": "INV-7",
+        "lines": [{ "item": "paper", "cents": 450 }],
+    })));
+    let d = agent.connect().await.unwrap();
+
+    let invoice: Invoice = d.think().text("Read the invoice").await.unwrap();
+
+    assert_eq!(
+        invoice,
+        Invoice {
+            number: "INV-7".to_string(),
+            lines: vec![Line {
+                item: "paper".to_string(),
+                cents: 450,
+            }],
+        }
+    );
+    agent.verify();
+}
+
+#[tokio::test]
+async fn the_instruction_outlasts_the_document_over_budget() {
+    let agent = MockAgent::new().turn(Turn::new().return_result(json!("a summary")));
+    let d = agent.connect().await.unwrap();
+    let document = "lorem ipsum ".repeat(1_000);
+
+    let _: String = d
+        .think()
+        .token_budget(500)
+        .text("Summarize the document below in one sentence.")
+        .display(&document)
+        .await
+        .unwrap();
+
+    let transcript = agent.transcript();
+    let prompt = transcript
+        .events()
+        .iter()
+        .find_map(|event| match event {
+            TranscriptEvent::Prompt(prompt) => Some(prompt),
+            _ => None,
+        })
+        .unwrap();
+    assert!(prompt.contains("Summarize the document below in one sentence."));
+    assert!(!prompt.contains("lorem ipsum"));
+    assert!(prompt.contains("omitted to fit the prompt budget"));
+    agent.verify();
+}
+
+#[tokio::test]
+async fn times_out_with_what_the_agent_said() {
+    let agent = MockAgent::new().turn(
+        Turn::new()
+            .say("\"the build is red\"")
+            .call_tool("wait_for_ci", json!(null)),
+    );
+    let d = agent.connect().await.unwrap();
+
+    let partial = d
+        .think::<String>()
+        .text("Why is the build failing?")
+        .timeout(Duration::from_millis(200))
+        .tool(
+            "wait_for_ci",
+            "Wait for CI to finish",
+            async |_: (), _cx| -> Result<(), agent_client_protocol::Error> {
+                std::future::pending().await
+            },
+            agent_client_protocol::tool_fn_mut!(),
+        )
+        .outcome_or_partial()
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(partial.error, Error::Timeout { after, .. } if after == Duration::from_millis(200)),
+        "{:?}",
+        partial.error
+    );
+    assert_eq!(partial.output.as_deref(), Some("the build is red"));
+}
+
+#[tokio::test]
+async fn confidence_outside_zero_to_one_is_sent_back() {
+    let agent = MockAgent::new().turn(
+        Turn::new()
+            .return_result(json!({
+                "result": "42",
+                "confidence": { "score": 1.5, "rationale": "very sure" },
+            }))
+            .return_result(json!({
+                "result": "42",
+                "confidence": { "score": 0.9, "rationale": "checked twice" },
+            })),
+    );
+    let d = agent.connect().await.unwrap();
+
+    let (answer, confidence): (String, _) = d
+        .think()
+        .text("What is six times seven?")
+        .with_confidence()
+        .await
+        .unwrap();
+
+    assert_eq!(answer, "42");
+    assert_eq!(confidence.score, 0.9);
+    let transcript = agent.transcript();
+    let (_, _, rejected) = transcript.tool_calls().next().unwrap();
+    let rejected = rejected.unwrap().as_ref().unwrap_err();
+    assert!(rejected.contains("not between 0.0 and 1.0"), "{rejected}");
+    agent.verify();
+}
+
+#[test]
+fn confidence_schema_bounds_the_score() {
+    let schema = schemars::schema_for!(Confidence).to_value();
+
+    let score = &schema["properties"]["score"];
+    assert_eq!(score["minimum"], 0.0);
+    assert_eq!(score["maximum"], 1.0);
+}
+
+#[tokio::test]
+async fn capabilities_and_models_share_one_probe() {
+    let agent = MockAgent::new();
+    let d = agent.connect().await.unwrap();
+
+    let capabilities = d.capabilities().await.unwrap();
+    assert_eq!(d.capabilities().await.unwrap(), capabilities);
+    assert!(d.models().await.unwrap().is_empty());
+    assert!(d.models().await.unwrap().is_empty());
+
+    assert_eq!(agent.sessions(), 1);
+}
+
+#[tokio::test]
+async fn dynamic_output_keeps_memories() {
+    let memory = InMemoryStore::new();
+    memory
+        .store("The deploy key rotates on Mondays.".to_string())
+        .await
+        .unwrap();
+    memory
+        .store("The office plants are watered on Fridays.".to_string())
+        .await
+        .unwrap();
+    let agent = MockAgent::new().turn(
+        Turn::new()
+            .expect_prompt("The deploy key rotates on Mondays.")
+            .return_result(json!({ "day": "Monday" })),
+    );
+    let d = agent.connect().await.unwrap();
+
+    let answer: serde_json::Value = d
+        .think::<()>()
+        .with_memory(&memory, "when does the deploy key rotate")
+        .memory_limit(1)
+        .text("When does the deploy key rotate?")
+        .dynamic_output()
+        .await
+        .unwrap();
+
+    assert_eq!(answer, json!({ "day": "Monday" }));
+    agent.verify();
+    let prompts: Vec<_> = agent
+        .transcript()
+        .events()
+        .iter()
+        .filter_map(|event| match event {
+            TranscriptEvent::Prompt(prompt) => Some(prompt.clone()),
+            _ => None,
+        })
+        .collect();
+    assert!(!prompts[0].contains("office plants"));
+}
+
+#[tokio::test]
+async fn recalled_memories_leave_the_fingerprint_alone() {
+    let memory = InMemoryStore::new();
+    memory
+        .store("Invoices are numbered INV-n.".to_string())
+        .await
+        .unwrap();
+    let agent = MockAgent::new().turn(
+        Turn::new()
+            .expect_prompt("Invoices are numbered INV-n.")
+            .return_result(json!("INV-7")),
+    );
+    let d = agent.connect().await.unwrap();
+
+    let think = d
+        .think::<String>()
+        .with_memory(&memory, "how are invoices numbered")
+        .text("What is the next invoice number?");
+    let fingerprint = think.fingerprint();
+    let outcome = think.outcome().await.unwrap();
+
+    assert_eq!(outcome.provenance().prompt_fingerprint, fingerprint);
+    agent.verify();
+}
+
+#[tokio::test]
+async fn experiment_trials_count_think_block_tokens() {
+    let agent = MockAgent::new().turn(
+        Turn::new()
+            .say("Counting the words now.")
+            .return_result(json!("three")),
+    );
+    let d = agent.connect().await.unwrap();
+    let experiment = determinishtic::experiments::Experiment::new("count")
+        .variant("plain", "How many words are in `one two three`?");
+
+    let outcome = experiment
+        .run("words", async |question| {
+            d.think::<String>().text(question).outcome().await
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(outcome.output, "three");
+    assert!(outcome.estimated_tokens > 0);
+    let report = &experiment.report()[0];
+    assert_eq!(report.mean_tokens, Some(outcome.estimated_tokens as f64));
+    agent.verify();
+}
+
+#[tokio::test]
+async fn scope_fails_with_the_first_error_and_cancels_the_rest() {
+    let agent = MockAgent::new()
+        .turn(Turn::new().call_tool("wait", json!(null)))
+        .turn(Turn::new().stop_with(StopReason::Refusal));
+    let d = agent.connect().await.unwrap();
+    let called = Arc::new(Notify::new());
+    let dropped = Arc::new(AtomicBool::new(false));
+
+    let result = tokio::time::timeout(
+        Duration::from_secs(5),
+        d.scope(async |s| {
+            let slow = s.spawn(d.think::<String>().text("Wait for the deploy").tool(
+                "wait",
+                "Wait for the deploy to finish",
+                {
+                    let called = called.clone();
+                    let dropped = dropped.clone();
+                    async move |_: (), _cx| -> Result<(), agent_client_protocol::Error> {
+                        let _guard = SetOnDrop(dropped.clone());
+                        called.notify_one();
+                        std::future::pending().await
+                    }
+                },
+                agent_client_protocol::tool_fn_mut!(),
+            ));
+            called.notified().await;
+            let fast = s.spawn(d.think::<String>().text("Summarize the deploy"));
+            Ok((slow.await?, fast.await?))
+        }),
+    )
+    .await
+    .expect("the failure ends the scope");
+
+    assert!(matches!(result, Err(Error::Refused)), "{result:?}");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(
+        dropped.load(Ordering::Relaxed),
+        "the waiting block was cancelled"
+    );
+}
+
+#[tokio::test]
+async fn scope_keeps_going_after_a_failure_when_asked() {
+    let agent = MockAgent::new()
+        .turn(
+            Turn::new()
+                .call_tool("wait", json!(null))
+                .return_result(json!("deployed")),
+        )
+        .turn(Turn::new().stop_with(StopReason::Refusal));
+    let d = agent.connect().await.unwrap();
+    let called = Arc::new(Notify::new());
+    let open = Arc::new(Notify::new());
+
+    let (slow, fast) = d
+        .scope_with(OnFailure::KeepGoing, async |s| {
+            let slow = s.spawn(d.think::<String>().text("Wait for the deploy").tool(
+                "wait",
+                "Wait for the deploy to finish",
+                {
+                    let called = called.clone();
+                    let open = open.clone();
+                    async move |_: (), _cx| -> Result<(), agent_client_protocol::Error> {
+                        called.notify_one();
+                        open.notified().await;
+                        Ok(())
+                    }
+                },
+                agent_client_protocol::tool_fn_mut!(),
+            ));
+            called.notified().await;
+            let fast = s
+                .spawn(d.think::<String>().text("Summarize the deploy"))
+                .await;
+            open.notify_one();
+            Ok((slow.await, fast))
+        })
+        .await
+        .unwrap();
+
+    assert!(matches!(fast, Err(Error::Refused)), "{fast:?}");
+    assert_eq!(slow.unwrap(), "deployed");
+    agent.verify();
+}
+
+/// Sets its flag when dropped.
+struct SetOnDrop(Arc<AtomicBool>);
+
+impl Drop for SetOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+#[tokio::test]
+async fn thinking_on_an_unknown_agent_fails() {
+    let d = MockAgent::new().connect().await.unwrap();
+
+    let result = d.try_think_on::<String>("fast");
+
+    assert!(
+        matches!(&result, Err(Error::UnknownAgent { name }) if name == "fast"),
+        "{:?}",
+        result.err()
+    );
+}